@@ -0,0 +1,557 @@
+//! Key bindings.
+//!
+//! Bindings map key sequences to [`Action`]s separately for each [`Mode`].
+//! A sequence is one or more chords separated by spaces, such as `ctrl+p`
+//! or `g d`. The defaults can be changed with a YAML keymap file:
+//!
+//! ```yaml
+//! insert:
+//!   ctrl+k ctrl+p: open_palette
+//!   ctrl+p: ~   # unbind
+//! explorer:
+//!   q: toggle_sidebar
+//! ```
+//!
+//! Problems in the file, such as unknown modes, unknown keys or bindings
+//! hidden by other bindings, are reported as [`Issue`]s instead of failing
+//! the whole file.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Error;
+use serde::Deserialize;
+
+/// Input modes with their own bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Modal editing outside of insert mode.
+    Normal,
+    /// Typing text into the editor.
+    Insert,
+    /// The quick open palette is open.
+    Palette,
+    /// The sidebar has the focus.
+    Explorer,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::Normal, Mode::Insert, Mode::Palette, Mode::Explorer];
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+            Mode::Palette => "palette",
+            Mode::Explorer => "explorer",
+        })
+    }
+}
+
+/// Something a key sequence can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Save,
+    OpenPalette,
+    ToggleFold,
+    FollowLink,
+    Back,
+    Home,
+    Search,
+    NewNote,
+    Settings,
+    ToggleSidebar,
+    SelectPrevious,
+    SelectNext,
+    Accept,
+    Close,
+    Undo,
+    Redo,
+    History,
+    CancelLoad,
+    ToggleHidden,
+}
+
+/// Keys without a modifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Char(char),
+    F(u8),
+    Enter,
+    Esc,
+    Tab,
+    BackTab,
+    Backspace,
+    Delete,
+    Insert,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+const NAMES: [(&str, KeyCode); 17] = [
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("space", KeyCode::Char(' ')),
+    ("escape", KeyCode::Esc),
+];
+
+/// A key with its modifiers, such as `ctrl+p`.
+///
+/// Shift is part of the character for character keys, so `shift+g` and
+/// `G` are the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl Key {
+    /// A key, normalising shift on character keys.
+    pub fn new(code: KeyCode, ctrl: bool, alt: bool, shift: bool) -> Self {
+        match code {
+            KeyCode::Char(c) => Key {
+                code: KeyCode::Char(if shift { c.to_ascii_uppercase() } else { c }),
+                ctrl,
+                alt,
+                shift: false,
+            },
+            code => Key {
+                code,
+                ctrl,
+                alt,
+                shift,
+            },
+        }
+    }
+
+    /// A key without modifiers.
+    pub fn plain(code: KeyCode) -> Self {
+        Key::new(code, false, false, false)
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (mut ctrl, mut alt, mut shift) = (false, false, false);
+        let mut parts: Vec<&str> = text.split('+').collect();
+        // `+` and `ctrl++` bind the plus key.
+        if text == "+" || text.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let name = parts.pop().unwrap_or_default();
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ctrl = true,
+                "alt" | "meta" => alt = true,
+                "shift" => shift = true,
+                _ => return Err(format!("unknown modifier `{modifier}` in `{text}`")),
+            }
+        }
+        let mut chars = name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let lower = name.to_ascii_lowercase();
+                match NAMES.iter().find(|(n, _)| *n == lower) {
+                    Some((_, code)) => *code,
+                    None => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(n @ 1..=24) => KeyCode::F(n),
+                        _ => return Err(format!("unknown key `{name}` in `{text}`")),
+                    },
+                }
+            }
+        };
+        Ok(Key::new(code, ctrl, alt, shift))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            f.write_str("ctrl+")?;
+        }
+        if self.alt {
+            f.write_str("alt+")?;
+        }
+        if self.shift {
+            f.write_str("shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "f{n}"),
+            code => {
+                let name = NAMES
+                    .iter()
+                    .find(|(_, c)| *c == code)
+                    .map_or("?", |(n, _)| n);
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// Keys pressed one after the other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sequence(pub Vec<Key>);
+
+impl FromStr for Sequence {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let keys = text
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<Key>, _>>()?;
+        if keys.is_empty() {
+            return Err("empty key sequence".into());
+        }
+        Ok(Sequence(keys))
+    }
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{key}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A problem found while loading a keymap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Mode of the bindings with the problem, `None` when the mode itself is
+    /// unknown.
+    pub mode: Option<Mode>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Some(mode) => write!(f, "{mode} mode: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Outcome of looking up the keys pressed so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    /// The keys start a longer sequence.
+    Prefix,
+    Unbound,
+}
+
+/// Bindings of all modes.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: BTreeMap<Mode, Vec<(Sequence, Action)>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let editing = [
+            ("ctrl+q", Action::Quit),
+            ("ctrl+s", Action::Save),
+            ("ctrl+p", Action::OpenPalette),
+            ("ctrl+o", Action::ToggleFold),
+            ("enter", Action::FollowLink),
+            ("alt+left", Action::Back),
+            ("ctrl+t", Action::ToggleSidebar),
+            ("ctrl+z", Action::Undo),
+            ("ctrl+y", Action::Redo),
+            ("alt+h", Action::History),
+        ];
+        let palette = [
+            ("up", Action::SelectPrevious),
+            ("ctrl+p", Action::SelectPrevious),
+            ("down", Action::SelectNext),
+            ("ctrl+n", Action::SelectNext),
+            ("enter", Action::Accept),
+            ("esc", Action::Close),
+        ];
+        let explorer = [
+            ("h", Action::Home),
+            ("f", Action::Search),
+            ("n", Action::NewNote),
+            ("b", Action::Back),
+            ("s", Action::Settings),
+            ("ctrl+t", Action::ToggleSidebar),
+            ("tab", Action::ToggleSidebar),
+            ("esc", Action::ToggleSidebar),
+            ("ctrl+q", Action::Quit),
+            ("ctrl+c", Action::CancelLoad),
+            ("alt+.", Action::ToggleHidden),
+        ];
+        let mut keymap = Keymap {
+            bindings: BTreeMap::new(),
+        };
+        let modes: [(Mode, &[(&str, Action)]); 4] = [
+            (Mode::Normal, &editing),
+            (Mode::Insert, &editing),
+            (Mode::Palette, &palette),
+            (Mode::Explorer, &explorer),
+        ];
+        for (mode, bindings) in modes {
+            for (keys, action) in bindings {
+                keymap.bind(mode, keys.parse().unwrap(), Some(*action));
+            }
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Bind `sequence` in `mode`, replacing its previous binding. `None`
+    /// removes the binding.
+    pub fn bind(&mut self, mode: Mode, sequence: Sequence, action: Option<Action>) {
+        let bindings = self.bindings.entry(mode).or_default();
+        bindings.retain(|(s, _)| *s != sequence);
+        if let Some(action) = action {
+            bindings.push((sequence, action));
+        }
+    }
+
+    /// Bindings of `mode` in the order they were made.
+    pub fn bindings(&self, mode: Mode) -> &[(Sequence, Action)] {
+        self.bindings.get(&mode).map_or(&[], Vec::as_slice)
+    }
+
+    /// What the keys pressed so far in `mode` do.
+    pub fn lookup(&self, mode: Mode, keys: &[Key]) -> Lookup {
+        let mut prefix = false;
+        for (sequence, action) in self.bindings(mode) {
+            if sequence.0 == keys {
+                return Lookup::Action(*action);
+            }
+            prefix |= sequence.0.starts_with(keys);
+        }
+        if prefix {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// Bindings that can never trigger because a shorter binding starts
+    /// the same way.
+    pub fn conflicts(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        for (mode, bindings) in &self.bindings {
+            for (short, short_action) in bindings {
+                for (long, long_action) in bindings {
+                    if long.0.len() > short.0.len() && long.0.starts_with(&short.0) {
+                        issues.push(Issue {
+                            mode: Some(*mode),
+                            message: format!(
+                                "`{long}` ({long_action:?}) is hidden by `{short}` ({short_action:?})"
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        issues
+    }
+}
+
+/// Apply the keymap file at `path` to the default bindings.
+///
+/// Fails when the file cannot be read or is not YAML. Bad entries and
+/// conflicting bindings are skipped and reported.
+pub fn load_keymap(path: &Path) -> Result<(Keymap, Vec<Issue>), Error> {
+    let content = fs::read_to_string(path)?;
+    let file: serde_yaml::Mapping = serde_yaml::from_str(&content)?;
+    let mut keymap = Keymap::default();
+    let mut issues = Vec::new();
+    for (mode, entries) in file {
+        let mode: Mode = match serde_yaml::from_value(mode.clone()) {
+            Ok(mode) => mode,
+            Err(_) => {
+                let name = mode
+                    .as_str()
+                    .map_or_else(|| format!("{mode:?}"), str::to_string);
+                issues.push(Issue {
+                    mode: None,
+                    message: format!("unknown mode `{name}`, its bindings are skipped"),
+                });
+                continue;
+            }
+        };
+        let entries: serde_yaml::Mapping = match serde_yaml::from_value(entries) {
+            Ok(entries) => entries,
+            Err(err) => {
+                issues.push(Issue {
+                    mode: Some(mode),
+                    message: format!("bindings are not a mapping: {err}"),
+                });
+                continue;
+            }
+        };
+        let mut seen: Vec<(Sequence, String)> = Vec::new();
+        for (keys, action) in entries {
+            let mut problem = |message: String| {
+                issues.push(Issue {
+                    mode: Some(mode),
+                    message,
+                });
+            };
+            let Some(keys) = keys.as_str() else {
+                problem(format!("key sequence {keys:?} is not text"));
+                continue;
+            };
+            let sequence: Sequence = match keys.parse() {
+                Ok(sequence) => sequence,
+                Err(err) => {
+                    problem(err);
+                    continue;
+                }
+            };
+            let action: Option<Action> = match serde_yaml::from_value(action) {
+                Ok(action) => action,
+                Err(err) => {
+                    problem(format!("`{keys}`: {err}"));
+                    continue;
+                }
+            };
+            if let Some((_, first)) = seen.iter().find(|(s, _)| *s == sequence) {
+                problem(format!("`{keys}` is bound twice, also as `{first}`"));
+            }
+            seen.push((sequence.clone(), keys.to_string()));
+            keymap.bind(mode, sequence, action);
+        }
+    }
+    issues.extend(keymap.conflicts());
+    Ok((keymap, issues))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn parses_and_prints_keys() {
+        let key: Key = "Ctrl+Alt+x".parse().unwrap();
+        assert_eq!(key, Key::new(KeyCode::Char('x'), true, true, false));
+        assert_eq!(key.to_string(), "ctrl+alt+x");
+        assert_eq!("shift+g".parse::<Key>(), "G".parse::<Key>());
+        assert_eq!("ctrl++".parse::<Key>().unwrap().code, KeyCode::Char('+'));
+        assert_eq!("+".parse::<Key>(), Ok(Key::plain(KeyCode::Char('+'))));
+        assert_eq!("f5".parse::<Key>().unwrap().code, KeyCode::F(5));
+        assert!("hyper+x".parse::<Key>().is_err());
+        let sequence: Sequence = "g  space".parse().unwrap();
+        assert_eq!(sequence.to_string(), "g space");
+    }
+
+    #[test]
+    fn looks_up_chords() {
+        let mut keymap = Keymap::default();
+        keymap.bind(
+            Mode::Normal,
+            "g d".parse().unwrap(),
+            Some(Action::FollowLink),
+        );
+        let g = "g".parse::<Key>().unwrap();
+        let d = "d".parse::<Key>().unwrap();
+        assert_eq!(keymap.lookup(Mode::Normal, &[g]), Lookup::Prefix);
+        assert_eq!(
+            keymap.lookup(Mode::Normal, &[g, d]),
+            Lookup::Action(Action::FollowLink)
+        );
+        assert_eq!(keymap.lookup(Mode::Insert, &[g]), Lookup::Unbound);
+        assert_eq!(
+            keymap.lookup(Mode::Explorer, &[Key::plain(KeyCode::Char('h'))]),
+            Lookup::Action(Action::Home)
+        );
+        assert_eq!(
+            keymap.lookup(Mode::Insert, &["ctrl+s".parse().unwrap()]),
+            Lookup::Action(Action::Save)
+        );
+        assert_eq!(
+            keymap.lookup(Mode::Explorer, &["ctrl+c".parse().unwrap()]),
+            Lookup::Action(Action::CancelLoad)
+        );
+        assert_eq!(
+            keymap.lookup(Mode::Explorer, &["alt+.".parse().unwrap()]),
+            Lookup::Action(Action::ToggleHidden)
+        );
+        assert!(Keymap::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn loads_keymap_files_and_reports_problems() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "insert:\n  ctrl+k ctrl+p: open_palette\n  ctrl+p: ~\n  ctrl+k: settings\n  \
+             control+k: home\n  hyper+x: quit\n  ctrl+x: fly\n  +: home\nvisual:\n  v: quit\n\
+             explorer:\n  q: toggle_sidebar"
+        )
+        .unwrap();
+        let (keymap, issues) = load_keymap(file.path()).unwrap();
+        let ctrl_p = "ctrl+p".parse::<Key>().unwrap();
+        assert_eq!(keymap.lookup(Mode::Insert, &[ctrl_p]), Lookup::Unbound);
+        assert_eq!(
+            keymap.lookup(Mode::Insert, &[Key::plain(KeyCode::Char('+'))]),
+            Lookup::Action(Action::Home)
+        );
+        assert_eq!(
+            keymap.lookup(Mode::Explorer, &["q".parse().unwrap()]),
+            Lookup::Action(Action::ToggleSidebar)
+        );
+        let messages: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        assert_eq!(messages.len(), 5, "{messages:?}");
+        assert!(messages[0].contains("bound twice"));
+        assert!(messages[1].contains("unknown modifier"));
+        assert!(messages[2].contains("fly"));
+        assert_eq!(
+            messages[3],
+            "unknown mode `visual`, its bindings are skipped"
+        );
+        assert_eq!(
+            messages[4],
+            "insert mode: `ctrl+k ctrl+p` (OpenPalette) is hidden by `ctrl+k` (Home)"
+        );
+    }
+}
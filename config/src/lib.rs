@@ -1,3 +1,5 @@
+pub mod keymap;
+
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
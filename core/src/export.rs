@@ -0,0 +1,41 @@
+//! Running the `export` tool of the workspace from the interfaces.
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The `export` tool installed next to the running program. Another
+/// program named `export` on the `PATH` is never run in its place.
+pub fn tool() -> io::Result<PathBuf> {
+    let exe = env::current_exe()?;
+    let tool = exe.with_file_name(format!("export{}", env::consts::EXE_SUFFIX));
+    if tool.is_file() {
+        Ok(tool)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no export tool at {}", tool.display()),
+        ))
+    }
+}
+
+/// Export the note at `note` to HTML in the folder `out`, styled with
+/// `theme`.
+pub fn export_note(note: &Path, out: &Path, theme: &str) -> io::Result<()> {
+    let status = Command::new(tool()?)
+        .arg(note)
+        .arg("-o")
+        .arg(out)
+        .arg("--theme")
+        .arg(theme)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("export failed: {status}")))
+    }
+}
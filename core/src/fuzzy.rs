@@ -0,0 +1,149 @@
+//! Fuzzy matching of short queries against names and paths.
+//!
+//! A query matches a candidate when its characters appear in the candidate
+//! in order, ignoring case. Among the possible alignments the best scoring
+//! one is chosen: runs of consecutive characters and characters at the start
+//! of a word or path component score higher, gaps between them lower.
+
+/// Score for every matched character.
+const MATCH: i64 = 16;
+/// Extra score when the previous query character matched the previous
+/// candidate character.
+const CONSECUTIVE: i64 = 12;
+/// Extra score for a match at the start of a word.
+const WORD_START: i64 = 10;
+/// Extra score when the case matches exactly.
+const SAME_CASE: i64 = 1;
+
+/// Outcome of matching a query against one candidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    /// Char indices of the candidate characters matched by the query.
+    pub positions: Vec<usize>,
+}
+
+fn word_start(chars: &[char], i: usize) -> bool {
+    match i.checked_sub(1).map(|p| chars[p]) {
+        None => true,
+        Some(prev) => {
+            matches!(prev, ' ' | '/' | '\\' | '_' | '-' | '.' | '#')
+                || (prev.is_lowercase() && chars[i].is_uppercase())
+        }
+    }
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Match `query` against `candidate`, or `None` when not all characters
+/// of the query occur in order.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<Match> {
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).collect();
+    if query.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let chars: Vec<char> = candidate.chars().collect();
+    let (m, n) = (query.len(), chars.len());
+    if m > n {
+        return None;
+    }
+    const NONE: i64 = i64::MIN / 4;
+    // best[i][j]: best score with query[i] matched at chars[j].
+    let mut best = vec![vec![NONE; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+    for (i, &q) in query.iter().enumerate() {
+        // Best `best[i - 1][k] + k` over k < j - 1, for gapped matches.
+        let mut gapped = (NONE, 0);
+        for j in i..n {
+            if i > 0 && j >= 2 && best[i - 1][j - 2] != NONE {
+                let value = best[i - 1][j - 2] + (j - 2) as i64;
+                if value > gapped.0 {
+                    gapped = (value, j - 2);
+                }
+            }
+            if fold(chars[j]) != fold(q) {
+                continue;
+            }
+            let mut bonus = MATCH;
+            if word_start(&chars, j) {
+                bonus += WORD_START;
+            }
+            if chars[j] == q {
+                bonus += SAME_CASE;
+            }
+            if i == 0 {
+                // Matches late in the candidate are slightly worse.
+                best[0][j] = bonus - (j as i64).min(8);
+                continue;
+            }
+            let consecutive = best[i - 1][j - 1];
+            if consecutive != NONE && consecutive + CONSECUTIVE >= gapped.0 - (j as i64 - 1) {
+                best[i][j] = consecutive + CONSECUTIVE + bonus;
+                from[i][j] = j - 1;
+            } else if gapped.0 != NONE {
+                // One point per skipped character.
+                best[i][j] = gapped.0 - (j as i64 - 1) + bonus;
+                from[i][j] = gapped.1;
+            }
+        }
+    }
+    let (end, score) = best[m - 1]
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| **s != NONE)
+        .max_by_key(|(j, s)| (**s, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![end];
+    for i in (1..m).rev() {
+        positions.push(from[i][positions[positions.len() - 1]]);
+    }
+    positions.reverse();
+    // Prefer shorter candidates among equal matches.
+    let score = score - (n as i64 - m as i64) / 8;
+    Some(Match { score, positions })
+}
+
+/// Indices of the `items` matching `query` with their matches, best first.
+/// Ties keep the order of `items`.
+pub fn rank<T>(query: &str, items: &[T], key: impl Fn(&T) -> &str) -> Vec<(usize, Match)> {
+    let mut ranked: Vec<(usize, Match)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_match(query, key(item)).map(|m| (i, m)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.score.cmp(&a.1.score).then(a.0.cmp(&b.0)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences_ignoring_case() {
+        assert!(fuzzy_match("wrk", "Work/Plans.md").is_some());
+        assert!(fuzzy_match("krw", "Work/Plans.md").is_none());
+        assert_eq!(fuzzy_match("", "x").unwrap().score, 0);
+        assert_eq!(fuzzy_match("wp", "work/plans").unwrap().positions, [0, 5]);
+    }
+
+    #[test]
+    fn prefers_word_starts_and_runs() {
+        let notes = [
+            "banana/apple.md",
+            "notes/new note.md",
+            "nn.md",
+            "inbox/Plans.md",
+        ];
+        let ranked = rank("nn", &notes, |n| n);
+        assert_eq!(ranked[0].0, 2);
+        assert_eq!(ranked[1].0, 1);
+        let ranked = rank("plan", &notes, |n| n);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].1.positions, [6, 7, 8, 9]);
+    }
+}
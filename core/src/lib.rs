@@ -1,4 +1,8 @@
 pub mod events;
+pub mod export;
+pub mod fuzzy;
+pub mod navigation;
+pub mod palette;
 pub mod state;
 
 /// Simple addition function used for unit tests.
@@ -0,0 +1,118 @@
+//! Moving between notes.
+//!
+//! Following a link remembers where it was followed from in a [`History`],
+//! which [`SidebarAction::Back`](crate::events::SidebarAction::Back) walks
+//! back through. Links leaving the vault are handed to an external command.
+
+use std::io;
+use std::process::{Command, Stdio};
+
+/// Number of places remembered.
+const LIMIT: usize = 100;
+
+/// Places visited before the current one, most recent last.
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    entries: Vec<T>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> History<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember `from` when leaving it. The oldest place is forgotten once
+    /// the history is full.
+    pub fn visit(&mut self, from: T) {
+        if self.entries.len() == LIMIT {
+            self.entries.remove(0);
+        }
+        self.entries.push(from);
+    }
+
+    /// The place to go back to, if any.
+    pub fn back(&mut self) -> Option<T> {
+        self.entries.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Program and arguments opening `url`.
+///
+/// `command` is split on whitespace and each `{url}` in it is replaced by
+/// the URL; without a placeholder the URL is passed as the last argument.
+/// Without a command the desktop's default handler is used. The URL is
+/// always a single argument and never goes through a shell.
+pub fn open_command(url: &str, command: Option<&str>) -> Vec<String> {
+    let template: Vec<&str> = match command.map(str::trim).filter(|c| !c.is_empty()) {
+        Some(command) => command.split_whitespace().collect(),
+        None if cfg!(target_os = "macos") => vec!["open"],
+        // `cmd /C start` would run what follows `&` in the URL.
+        None if cfg!(windows) => vec!["rundll32", "url.dll,FileProtocolHandler"],
+        None => vec!["xdg-open"],
+    };
+    let mut args: Vec<String> = template.iter().map(|a| a.replace("{url}", url)).collect();
+    if !template.iter().any(|a| a.contains("{url}")) {
+        args.push(url.to_string());
+    }
+    args
+}
+
+/// Open `url` with [`open_command`] without waiting for the program.
+pub fn open_external(url: &str, command: Option<&str>) -> io::Result<()> {
+    let args = open_command(url, command);
+    Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_goes_back_in_order() {
+        let mut history = History::new();
+        for i in 0..LIMIT + 2 {
+            history.visit(i);
+        }
+        assert_eq!(history.len(), LIMIT);
+        assert_eq!(history.back(), Some(LIMIT + 1));
+        assert_eq!(history.back(), Some(LIMIT));
+        let mut history = History::<&str>::default();
+        assert!(history.back().is_none());
+    }
+
+    #[test]
+    fn builds_open_commands() {
+        let url = "https://example.com";
+        assert_eq!(
+            open_command(url, Some("firefox --new-tab")),
+            ["firefox", "--new-tab", url]
+        );
+        assert_eq!(
+            open_command(url, Some("browse --url={url} -q")),
+            ["browse", "--url=https://example.com", "-q"]
+        );
+        assert_eq!(open_command(url, None).last().unwrap(), url);
+    }
+}
@@ -0,0 +1,382 @@
+//! Model of the quick open palette shared by the interfaces.
+//!
+//! The palette searches the notes of the vault and the registered commands
+//! with [`fuzzy`](crate::fuzzy). Recently opened notes rank higher and are
+//! listed first while the query is empty. A query starting with `>` only
+//! searches commands. The recently opened notes are kept in
+//! `.notes2/recent` inside the vault.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fuzzy;
+
+/// Number of recently opened notes remembered.
+const RECENT_LIMIT: usize = 20;
+/// Extra score of the most recently opened note, decreasing with age.
+const RECENT_BONUS: i64 = 40;
+/// Number of results kept for a query.
+const MAX_RESULTS: usize = 100;
+
+/// Commands an interface can offer in the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    NewNote,
+    ToggleSidebar,
+    SwitchTheme,
+    Export,
+    Settings,
+    /// Commits of the current note, when the vault is kept in git.
+    NoteLog,
+    /// Who last changed each line of the current note.
+    Blame,
+    ResolveConflicts,
+    /// Saved snapshots of the current note, compared with its text.
+    Snapshots,
+}
+
+impl Command {
+    pub const ALL: [Command; 9] = [
+        Command::NewNote,
+        Command::ToggleSidebar,
+        Command::SwitchTheme,
+        Command::Export,
+        Command::Settings,
+        Command::NoteLog,
+        Command::Blame,
+        Command::ResolveConflicts,
+        Command::Snapshots,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Command::NewNote => "New note",
+            Command::ToggleSidebar => "Toggle sidebar",
+            Command::SwitchTheme => "Switch theme",
+            Command::Export => "Export note",
+            Command::Settings => "Open settings",
+            Command::NoteLog => "Show git log",
+            Command::Blame => "Show git blame",
+            Command::ResolveConflicts => "Resolve conflicts",
+            Command::Snapshots => "Show snapshots",
+        }
+    }
+}
+
+/// What a palette entry stands for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Vault-relative path of a note.
+    Note(PathBuf),
+    Command(Command),
+}
+
+/// One result of the palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub item: Item,
+    pub label: String,
+    /// Title of a note, or empty.
+    pub detail: String,
+    /// Char indices of `label` matched by the query.
+    pub positions: Vec<usize>,
+}
+
+struct Note {
+    path: PathBuf,
+    label: String,
+    title: Option<String>,
+}
+
+/// Notes, commands and the current query of the palette.
+#[derive(Default)]
+pub struct Palette {
+    notes: Vec<Note>,
+    commands: Vec<Command>,
+    /// Most recently opened first.
+    recent: Vec<PathBuf>,
+    query: String,
+    results: Vec<Entry>,
+    selected: usize,
+}
+
+/// Vault-relative path for a new untitled note that does not exist yet in
+/// the vault at `vault`.
+pub fn untitled(vault: &Path) -> PathBuf {
+    (1..)
+        .map(|n| match n {
+            1 => PathBuf::from("Untitled.md"),
+            n => PathBuf::from(format!("Untitled {n}.md")),
+        })
+        .find(|p| !vault.join(p).exists())
+        .unwrap_or_default()
+}
+
+/// Text of a new note at `path`: a heading naming it.
+pub fn new_note_text(path: &Path) -> String {
+    format!(
+        "# {}\n",
+        path.file_stem().unwrap_or_default().to_string_lossy()
+    )
+}
+
+fn recent_file(vault: &Path) -> PathBuf {
+    vault.join(".notes2").join("recent")
+}
+
+fn label(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offer `command` in the palette.
+    pub fn register(&mut self, command: Command) {
+        if !self.commands.contains(&command) {
+            self.commands.push(command);
+        }
+    }
+
+    /// Replace the searched notes with vault-relative paths and titles.
+    pub fn set_notes(&mut self, notes: impl IntoIterator<Item = (PathBuf, Option<String>)>) {
+        self.notes = notes
+            .into_iter()
+            .map(|(path, title)| Note {
+                label: label(&path),
+                path,
+                title,
+            })
+            .collect();
+        self.notes.sort_by(|a, b| a.label.cmp(&b.label));
+        self.refresh();
+    }
+
+    /// Recently opened notes, most recent first.
+    pub fn recent(&self) -> &[PathBuf] {
+        &self.recent
+    }
+
+    /// Restore recently opened notes, most recent first.
+    pub fn set_recent(&mut self, recent: Vec<PathBuf>) {
+        self.recent = recent;
+        self.recent.truncate(RECENT_LIMIT);
+        self.refresh();
+    }
+
+    /// Restore the recently opened notes kept in the vault at `vault`.
+    pub fn load_recent(&mut self, vault: &Path) {
+        let recent = fs::read_to_string(recent_file(vault)).unwrap_or_default();
+        self.set_recent(recent.lines().map(PathBuf::from).collect());
+    }
+
+    /// Keep the recently opened notes in the vault at `vault`.
+    pub fn save_recent(&self, vault: &Path) -> io::Result<()> {
+        let file = recent_file(vault);
+        let recent: Vec<String> = self
+            .recent
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written aside and renamed, so a crash never leaves half a list.
+        let tmp = file.with_extension("tmp");
+        fs::write(&tmp, recent.join("\n"))?;
+        fs::rename(tmp, file)
+    }
+
+    /// Remember that the note at `path` was opened.
+    pub fn opened(&mut self, path: &Path) {
+        self.recent.retain(|p| p != path);
+        self.recent.insert(0, path.to_path_buf());
+        self.recent.truncate(RECENT_LIMIT);
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Search for `query`, selecting the best result.
+    pub fn set_query(&mut self, query: &str) {
+        self.query = query.to_string();
+        self.refresh();
+    }
+
+    pub fn results(&self) -> &[Entry] {
+        &self.results
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.results.get(self.selected)
+    }
+
+    /// Move the selection by `delta` entries, wrapping around.
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.results.len() as isize;
+        if len > 0 {
+            self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    fn recency(&self, path: &Path) -> i64 {
+        self.recent.iter().position(|p| p == path).map_or(0, |i| {
+            RECENT_BONUS * (RECENT_LIMIT - i) as i64 / RECENT_LIMIT as i64
+        })
+    }
+
+    /// Recompute the results for the current query.
+    fn refresh(&mut self) {
+        self.selected = 0;
+        let (query, commands_only) = match self.query.strip_prefix('>') {
+            Some(rest) => (rest.trim(), true),
+            None => (self.query.trim(), false),
+        };
+        // Score, order among equal scores, entry.
+        let mut scored: Vec<(i64, usize, Entry)> = Vec::new();
+        if query.is_empty() && !commands_only {
+            for (i, path) in self.recent.iter().enumerate() {
+                if let Some(note) = self.notes.iter().find(|n| n.path == *path) {
+                    scored.push((0, i, self.note_entry(note, Vec::new())));
+                }
+            }
+        }
+        for (i, command) in self.commands.iter().enumerate() {
+            if let Some(m) = fuzzy::fuzzy_match(query, command.title()) {
+                scored.push((
+                    m.score,
+                    RECENT_LIMIT + i,
+                    Entry {
+                        item: Item::Command(*command),
+                        label: command.title().to_string(),
+                        detail: String::new(),
+                        positions: m.positions,
+                    },
+                ));
+            }
+        }
+        if !query.is_empty() && !commands_only {
+            for (i, note) in self.notes.iter().enumerate() {
+                let by_label = fuzzy::fuzzy_match(query, &note.label);
+                let by_title = note
+                    .title
+                    .as_deref()
+                    .and_then(|t| fuzzy::fuzzy_match(query, t));
+                let score = match (&by_label, &by_title) {
+                    (Some(l), Some(t)) => l.score.max(t.score),
+                    (Some(l), None) => l.score,
+                    (None, Some(t)) => t.score,
+                    (None, None) => continue,
+                };
+                // Highlight the path only when it matched.
+                let positions = by_label.map(|m| m.positions).unwrap_or_default();
+                let score = score + self.recency(&note.path);
+                let order = RECENT_LIMIT + self.commands.len() + i;
+                scored.push((score, order, self.note_entry(note, positions)));
+            }
+        }
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        if query.is_empty() {
+            // Keep recent notes first, then the commands in registration order.
+            scored.sort_by_key(|(_, order, _)| *order);
+        }
+        self.results = scored
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|(_, _, entry)| entry)
+            .collect();
+    }
+
+    fn note_entry(&self, note: &Note, positions: Vec<usize>) -> Entry {
+        Entry {
+            item: Item::Note(note.path.clone()),
+            label: note.label.clone(),
+            detail: note.title.clone().unwrap_or_default(),
+            positions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        let mut palette = Palette::new();
+        palette.register(Command::NewNote);
+        palette.register(Command::ToggleSidebar);
+        palette.set_notes([
+            ("work/plans.md".into(), Some("Road map".into())),
+            ("home/plants.md".into(), None),
+            ("inbox.md".into(), None),
+        ]);
+        palette
+    }
+
+    fn labels(palette: &Palette) -> Vec<&str> {
+        palette.results().iter().map(|e| e.label.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_by_match_and_recency() {
+        let mut palette = palette();
+        palette.set_query("plans");
+        assert_eq!(labels(&palette), ["work/plans.md", "home/plants.md"]);
+        palette.opened(Path::new("home/plants.md"));
+        palette.set_query("plans");
+        assert_eq!(labels(&palette), ["home/plants.md", "work/plans.md"]);
+
+        palette.set_query("road");
+        assert_eq!(labels(&palette), ["work/plans.md"]);
+        assert!(palette.selected().unwrap().positions.is_empty());
+
+        palette.set_query("new");
+        assert_eq!(
+            palette.selected().map(|e| &e.item),
+            Some(&Item::Command(Command::NewNote))
+        );
+    }
+
+    #[test]
+    fn empty_query_lists_recent_notes_and_commands() {
+        let mut palette = palette();
+        palette.opened(Path::new("inbox.md"));
+        palette.opened(Path::new("work/plans.md"));
+        palette.set_query("");
+        assert_eq!(
+            labels(&palette),
+            ["work/plans.md", "inbox.md", "New note", "Toggle sidebar"]
+        );
+        palette.set_query(">");
+        assert_eq!(labels(&palette), ["New note", "Toggle sidebar"]);
+        palette.move_selection(-1);
+        assert_eq!(palette.selected().unwrap().label, "Toggle sidebar");
+    }
+
+    #[test]
+    fn keeps_recent_notes_in_the_vault() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut palette = palette();
+        palette.opened(Path::new("work/plans.md"));
+        palette.save_recent(vault.path()).unwrap();
+        let mut palette = Palette::new();
+        palette.load_recent(vault.path());
+        assert_eq!(palette.recent(), [PathBuf::from("work/plans.md")]);
+
+        assert_eq!(untitled(vault.path()), Path::new("Untitled.md"));
+        std::fs::write(vault.path().join("Untitled.md"), "").unwrap();
+        assert_eq!(untitled(vault.path()), Path::new("Untitled 2.md"));
+        assert_eq!(new_note_text(Path::new("work/Plans.md")), "# Plans\n");
+    }
+}
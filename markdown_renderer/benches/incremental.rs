@@ -0,0 +1,54 @@
+//! Compares full and incremental re-parsing of a 10 MB note.
+//!
+//! Run with `cargo bench -p markdown_renderer`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use markdown_renderer::incremental::{Document, Edit};
+use markdown_renderer::render_markdown;
+
+const SIZE: usize = 10 * 1024 * 1024;
+const EDITS: usize = 200;
+
+const SECTION: &str = "## Section\n\nSome running text with *emphasis*, a [link](note://x) and $e^{i\\pi} + 1 = 0$.\n\n- first item\n- second item\n\n```rust\nfn main() {\n    println!(\"hello\");\n}\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n";
+
+fn report(name: &str, total: Duration, runs: usize) {
+    println!(
+        "{name:<32} {:>12.3?} per run ({runs} runs)",
+        total / runs as u32
+    );
+}
+
+fn main() {
+    let text = SECTION.repeat(SIZE / SECTION.len() + 1);
+    println!("document: {} bytes", text.len());
+
+    let started = Instant::now();
+    black_box(render_markdown(&text));
+    report("full parse", started.elapsed(), 1);
+
+    let started = Instant::now();
+    let mut doc = black_box(Document::new(text.clone()));
+    report("Document::new", started.elapsed(), 1);
+
+    // Type a character at positions spread over the document.
+    let mut total = Duration::ZERO;
+    for i in 0..EDITS {
+        let at = doc.text().len() / EDITS * i;
+        let at = (at..).find(|p| doc.text().is_char_boundary(*p)).unwrap();
+        let edit = Edit::new(at..at, "x");
+        let started = Instant::now();
+        black_box(doc.apply(&edit));
+        total += started.elapsed();
+    }
+    report("insert one character", total, EDITS);
+
+    // Open and close a fence, which re-parses everything after it.
+    let at = doc.text().len() / 2;
+    let at = at + doc.text()[at..].find("\n\n").unwrap() + 2;
+    let started = Instant::now();
+    black_box(doc.apply(&Edit::new(at..at, "~~~\n")));
+    black_box(doc.apply(&Edit::new(at..at + 4, "")));
+    report("open and close a fence", started.elapsed(), 2);
+}
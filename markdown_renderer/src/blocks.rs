@@ -0,0 +1,339 @@
+//! Block-boundary index for rendering windows of large notes.
+//!
+//! A byte range picked by a scroll position may cut a multi-byte character
+//! or start in the middle of a code fence or table. The index records the
+//! offsets where a window can safely begin: the first line of every
+//! top-level block the parser finds outside of `<details>` elements, plus
+//! periodic line starts inside long code fences and table rows. Windows are
+//! widened to these offsets, and the fence's opening line or the table's
+//! header is replayed in front of a window starting inside them. Other
+//! blocks, such as lists, are never split, however long they are.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+
+use crate::{Widget, render_markdown};
+
+/// Distance in bytes between checkpoints inside a long code fence or table.
+const CHECKPOINT: u64 = 64 * 1024;
+
+/// An offset where rendering can begin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStart {
+    /// Byte offset of the first line of the window.
+    pub offset: u64,
+    /// Bytes reopening the block open at `offset`, if any: the opening line
+    /// of a code fence or the header and delimiter rows of a table.
+    pub reopen: Option<Range<u64>>,
+}
+
+impl BlockStart {
+    fn new(offset: usize, reopen: Option<Range<usize>>) -> Self {
+        Self {
+            offset: offset as u64,
+            reopen: reopen.map(|r| r.start as u64..r.end as u64),
+        }
+    }
+}
+
+/// Net change in `<details>` nesting caused by `line`.
+pub(crate) fn details_depth(line: &str) -> isize {
+    let lower = line.to_ascii_lowercase();
+    lower.matches("<details").count() as isize - lower.matches("</details>").count() as isize
+}
+
+/// Start of the line holding `offset`.
+fn line_start(text: &str, offset: usize) -> usize {
+    text[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// Opening line of a code fence: its character and length.
+pub(crate) fn fence_open(line: &[u8]) -> Option<(u8, usize)> {
+    let indent = line.iter().take_while(|b| **b == b' ').count();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let ch = *rest.first().filter(|c| **c == b'`' || **c == b'~')?;
+    let len = rest.iter().take_while(|b| **b == ch).count();
+    // Backtick fences may not contain backticks in their info string.
+    let info_ok = ch == b'~' || !rest[len..].contains(&b'`');
+    (len >= 3 && info_ok).then_some((ch, len))
+}
+
+pub(crate) fn closes_fence(line: &[u8], ch: u8, len: usize) -> bool {
+    let trimmed = line.trim_ascii();
+    trimmed.len() >= len && trimmed.iter().all(|b| *b == ch)
+}
+
+/// Offsets of the blocks of a markdown file.
+#[derive(Debug, Clone, Default)]
+pub struct BlockIndex {
+    starts: Vec<BlockStart>,
+    len: u64,
+}
+
+impl BlockIndex {
+    /// Parse the note read from `reader` and index its top-level blocks.
+    /// The note is only held while it is indexed.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Ok(Self::new(&text))
+    }
+
+    /// Index the top-level blocks of `text`.
+    pub fn new(text: &str) -> Self {
+        let mut starts: Vec<BlockStart> = Vec::new();
+        let mut push = |start: BlockStart| {
+            if starts.last().is_none_or(|last| last.offset < start.offset) {
+                starts.push(start);
+            }
+        };
+        let mut level = 0usize;
+        let mut details = 0isize;
+        // End of the last line of HTML, to tell a new HTML block apart.
+        let mut html_end = None;
+        // Start of the table being read and the end of its header rows.
+        let mut table: Option<(usize, Option<usize>)> = None;
+        let mut last = 0usize;
+        for (event, range) in Parser::new_ext(text, Options::all()).into_offset_iter() {
+            let start = line_start(text, range.start);
+            let top = level == 0;
+            match event {
+                Event::Start(tag) => {
+                    level += 1;
+                    match tag {
+                        _ if !top => {
+                            if let (Tag::TableRow, 2, Some((open, head))) = (tag, level, &mut table)
+                            {
+                                let head = *head.get_or_insert(start);
+                                if start - last >= CHECKPOINT as usize {
+                                    push(BlockStart::new(start, Some(*open..head)));
+                                    last = start;
+                                }
+                            }
+                        }
+                        _ if details > 0 => {}
+                        Tag::CodeBlock(CodeBlockKind::Fenced(_)) => {
+                            push(BlockStart::new(start, None));
+                            let opening = start
+                                + text[start..]
+                                    .find('\n')
+                                    .map_or(text.len() - start, |i| i + 1);
+                            let mut line = opening;
+                            last = start;
+                            for piece in text[opening..range.end].split_inclusive('\n') {
+                                if line - last >= CHECKPOINT as usize {
+                                    push(BlockStart::new(line, Some(start..opening)));
+                                    last = line;
+                                }
+                                line += piece.len();
+                            }
+                        }
+                        Tag::Table(_) => {
+                            push(BlockStart::new(start, None));
+                            table = Some((start, None));
+                            last = start;
+                        }
+                        _ => push(BlockStart::new(start, None)),
+                    }
+                }
+                Event::End(tag) => {
+                    level -= 1;
+                    if let (0, Tag::Table(_)) = (level, tag) {
+                        table = None;
+                    }
+                }
+                Event::Html(line) if top => {
+                    if details <= 0 && html_end != Some(start) {
+                        push(BlockStart::new(start, None));
+                    }
+                    details += details_depth(&line);
+                    html_end = Some(range.end);
+                    continue;
+                }
+                Event::Rule if top && details <= 0 => push(BlockStart::new(start, None)),
+                _ => {}
+            }
+            if top {
+                html_end = None;
+            }
+        }
+        Self {
+            starts,
+            len: text.len() as u64,
+        }
+    }
+
+    pub fn build(path: &Path) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Length of the indexed file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The last safe start at or before `offset`.
+    pub fn snap_start(&self, offset: u64) -> BlockStart {
+        let i = self.starts.partition_point(|s| s.offset <= offset);
+        i.checked_sub(1)
+            .map(|i| self.starts[i].clone())
+            .unwrap_or(BlockStart {
+                offset: 0,
+                reopen: None,
+            })
+    }
+
+    /// The first block boundary at or after `offset`, or the end of the file.
+    pub fn snap_end(&self, offset: u64) -> u64 {
+        let i = self.starts.partition_point(|s| s.offset < offset);
+        self.starts.get(i).map_or(self.len, |s| s.offset)
+    }
+}
+
+/// Renders windows of one file, keeping its block index between calls.
+#[derive(Debug)]
+pub struct Viewport {
+    path: PathBuf,
+    index: BlockIndex,
+    stamp: Option<(u64, SystemTime)>,
+}
+
+fn stamp(meta: &fs::Metadata) -> io::Result<(u64, SystemTime)> {
+    Ok((meta.len(), meta.modified()?))
+}
+
+impl Viewport {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let stamp = stamp(&file.metadata()?)?;
+        let index = BlockIndex::from_reader(file)?;
+        Ok(Self {
+            path,
+            index,
+            stamp: Some(stamp),
+        })
+    }
+
+    pub fn index(&self) -> &BlockIndex {
+        &self.index
+    }
+
+    /// The rendered file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Markdown source of the window covering `start..end`, widened to block
+    /// boundaries. A fence or table open at the start of the window is
+    /// reopened. The index is read again when the file changed since the
+    /// last call, as told by the size and time of the opened file.
+    pub fn source(&mut self, start: u64, end: u64) -> io::Result<String> {
+        let mut file = BufReader::new(File::open(&self.path)?);
+        let current = stamp(&file.get_ref().metadata()?)?;
+        if self.stamp != Some(current) {
+            self.index = BlockIndex::from_reader(&mut file)?;
+            self.stamp = Some(current);
+        }
+        let from = self.index.snap_start(start);
+        let to = self.index.snap_end(end.max(start)).max(from.offset);
+        let mut buf = Vec::new();
+        if let Some(reopen) = from.reopen {
+            file.seek(SeekFrom::Start(reopen.start))?;
+            (&mut file)
+                .take(reopen.end - reopen.start)
+                .read_to_end(&mut buf)?;
+        }
+        file.seek(SeekFrom::Start(from.offset))?;
+        file.take(to - from.offset).read_to_end(&mut buf)?;
+        // Boundaries are line starts, so this only fails on files that are
+        // not UTF-8 to begin with.
+        Ok(String::from_utf8(buf)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+    }
+
+    /// Render the blocks overlapping `start..end`.
+    pub fn render(&mut self, start: u64, end: u64) -> io::Result<Vec<Widget>> {
+        Ok(render_markdown(&self.source(start, end)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(content: &str) -> (tempfile::NamedTempFile, Viewport) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), content).unwrap();
+        let viewport = Viewport::open(file.path()).unwrap();
+        (file, viewport)
+    }
+
+    #[test]
+    fn snaps_to_block_starts() {
+        let (_file, mut view) =
+            viewport("# Über\n\nfirst ünïcode\nline\n\n| a | b |\n|---|---|\n| 1 | 2 |\n");
+        // Inside the multi-byte `Ü` and in the middle of the paragraph.
+        assert_eq!(view.source(3, 4).unwrap(), "# Über\n\n");
+        assert_eq!(view.source(20, 21).unwrap(), "first ünïcode\nline\n\n");
+        let table = view.render(45, 46).unwrap();
+        assert!(matches!(table.as_slice(), [Widget::Table(_)]), "{table:?}");
+    }
+
+    #[test]
+    fn reopens_fences_in_long_code_blocks() {
+        let body: String = (0..20_000).map(|i| format!("line {i} $x$\n")).collect();
+        let (_file, mut view) = viewport(&format!("intro\n\n~~~~rust\n{body}~~~~\n\nafter\n"));
+        let middle = view.index().len() / 2;
+        let start = view.index().snap_start(middle);
+        assert_eq!(start.reopen, Some(7..16));
+        let source = view.source(middle, middle + 10).unwrap();
+        assert!(source.starts_with("~~~~rust\nline "));
+        assert!(source.len() <= 2 * CHECKPOINT as usize);
+        let widgets = view.render(middle, middle + 10).unwrap();
+        assert!(matches!(
+            widgets.as_slice(),
+            [Widget::CodeBlock { lang: Some(lang), .. }] if lang == "rust"
+        ));
+    }
+
+    #[test]
+    fn splits_long_tables_at_rows() {
+        let rows: String = (0..10_000).map(|i| format!("| {i} | row |\n")).collect();
+        let (_file, mut view) =
+            viewport(&format!("intro\n\n| n | r |\n|---|---|\n{rows}\nafter\n"));
+        let middle = view.index().len() / 2;
+        let start = view.index().snap_start(middle);
+        assert_eq!(start.reopen, Some(7..27));
+        let source = view.source(middle, middle + 10).unwrap();
+        assert!(source.starts_with("| n | r |\n|---|---|\n| "));
+        assert!(source.len() <= 2 * CHECKPOINT as usize);
+        let widgets = view.render(middle, middle + 10).unwrap();
+        let [Widget::Table(rows)] = widgets.as_slice() else {
+            panic!("{widgets:?}");
+        };
+        assert!(rows.len() > 2 && rows.len() < 10_001);
+    }
+
+    #[test]
+    fn keeps_nested_blocks_whole() {
+        let (_file, mut view) = viewport(
+            "- item\n\n  more of the item\n\n      code in the item\n\n<details>\n\ninside\n\n</details>\n\nafter\n",
+        );
+        let starts: Vec<u64> = view.index().starts.iter().map(|s| s.offset).collect();
+        assert_eq!(starts, [0, 52, 83]);
+        assert!(view.source(20, 21).unwrap().starts_with("- item\n"));
+    }
+}
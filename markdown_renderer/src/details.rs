@@ -0,0 +1,160 @@
+//! Collapsible `<details>` blocks.
+//!
+//! The markdown parser hands out every line of raw HTML as a separate event
+//! without pairing tags, so open `<details>` elements are tracked on a stack
+//! while the surrounding events are parsed.
+
+use crate::Widget;
+
+/// A `<details>` element whose closing tag has not been seen yet.
+#[derive(Debug, Default)]
+pub(crate) struct Frame {
+    summary: String,
+    open: bool,
+    in_summary: bool,
+    pub(crate) children: Vec<Widget>,
+}
+
+impl Frame {
+    fn into_widget(self) -> Widget {
+        Widget::Details {
+            summary: self
+                .summary
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            open: self.open,
+            children: self.children,
+        }
+    }
+}
+
+/// Piece of an HTML chunk that matters for `<details>` blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece<'a> {
+    DetailsOpen { open: bool },
+    DetailsClose,
+    SummaryOpen,
+    SummaryClose,
+    Text(&'a str),
+}
+
+fn tag_name(tag: &str) -> (String, &str) {
+    let end = tag
+        .find(|c: char| c.is_whitespace() || c == '>')
+        .unwrap_or(tag.len());
+    (
+        tag[..end].trim_end_matches('/').to_ascii_lowercase(),
+        &tag[end..],
+    )
+}
+
+/// Whether an attribute list contains the boolean attribute `name`.
+fn has_attribute(attrs: &str, name: &str) -> bool {
+    attrs
+        .split_whitespace()
+        .map(|a| {
+            a.split('=')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+        })
+        .any(|a| a.eq_ignore_ascii_case(name))
+}
+
+/// Split raw HTML into `details`/`summary` tags and the text between tags.
+/// Other tags are dropped.
+fn scan(html: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = html;
+    while let Some(lt) = rest.find('<') {
+        let Some(len) = rest[lt..].find('>') else {
+            break;
+        };
+        if lt > 0 {
+            pieces.push(Piece::Text(&rest[..lt]));
+        }
+        let (name, attrs) = tag_name(&rest[lt + 1..lt + len]);
+        match name.as_str() {
+            "details" => pieces.push(Piece::DetailsOpen {
+                open: has_attribute(attrs, "open"),
+            }),
+            "/details" => pieces.push(Piece::DetailsClose),
+            "summary" => pieces.push(Piece::SummaryOpen),
+            "/summary" => pieces.push(Piece::SummaryClose),
+            _ => {}
+        }
+        rest = &rest[lt + len + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn close(stack: &mut Vec<Frame>, widgets: &mut Vec<Widget>) {
+    if let Some(frame) = stack.pop() {
+        let widget = frame.into_widget();
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(widget),
+            None => widgets.push(widget),
+        }
+    }
+}
+
+/// Feed an HTML event into the open `<details>` elements. HTML outside of
+/// any `<details>` element is ignored.
+pub(crate) fn handle_html(html: &str, stack: &mut Vec<Frame>, widgets: &mut Vec<Widget>) {
+    for piece in scan(html) {
+        match piece {
+            Piece::DetailsOpen { open } => stack.push(Frame {
+                open,
+                ..Frame::default()
+            }),
+            Piece::DetailsClose => close(stack, widgets),
+            Piece::SummaryOpen | Piece::SummaryClose => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.in_summary = piece == Piece::SummaryOpen;
+                }
+            }
+            Piece::Text(text) => match stack.last_mut() {
+                Some(frame) if frame.in_summary => frame.summary.push_str(&decode_entities(text)),
+                Some(frame) if !text.trim().is_empty() => {
+                    frame
+                        .children
+                        .push(Widget::Text(decode_entities(text.trim())));
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Summary text that arrives as a markdown text event, e.g. when the
+/// summary is separated from its tags by blank lines. Returns `false` when
+/// no summary is being read.
+pub(crate) fn push_summary(text: &str, stack: &mut [Frame]) -> bool {
+    match stack.last_mut() {
+        Some(frame) if frame.in_summary => {
+            frame.summary.push_str(text);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Close elements left open at the end of the document.
+pub(crate) fn finish(mut stack: Vec<Frame>, widgets: &mut Vec<Widget>) {
+    while !stack.is_empty() {
+        close(&mut stack, widgets);
+    }
+}
@@ -0,0 +1,267 @@
+//! Static HTML rendering of notes.
+//!
+//! The output needs no JavaScript: math is drawn as inline SVG by the
+//! [`math`](crate::math) layout engine and Mermaid flowcharts by
+//! [`mermaid`](crate::mermaid). Links to other notes, included notes and
+//! images are resolved through an [`HtmlContext`], which lets the caller
+//! decide where they end up.
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, html};
+
+use crate::inline_math::{self, Piece};
+use crate::{Widget, links, math, mermaid, parse_macro};
+
+/// Resolves references from a note while it is rendered.
+pub trait HtmlContext {
+    /// URL for a `note://` link to `target`.
+    fn note_link(&mut self, target: &str) -> String {
+        format!("note://{target}")
+    }
+
+    /// URL for a `[[target#anchor]]` wikilink, whose target may also be a
+    /// note title.
+    fn wiki_link(&mut self, target: &str, anchor: Option<&str>) -> String {
+        match anchor {
+            Some(anchor) => self.note_link(&format!("{target}#{anchor}")),
+            None => self.note_link(target),
+        }
+    }
+
+    /// Rendered HTML of the note included with `{% include-note target %}`,
+    /// or `None` when it cannot be included.
+    fn include(&mut self, _target: &str) -> Option<String> {
+        None
+    }
+
+    /// URL under which the image at `url` is reachable from the output.
+    fn image(&mut self, url: &str) -> String {
+        url.to_string()
+    }
+}
+
+/// Context that leaves every reference untouched.
+pub struct Unresolved;
+
+impl HtmlContext for Unresolved {}
+
+/// Escape text for use in HTML content and attribute values.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// SVG for `tex`, or the escaped source marked as an error.
+fn math_html(tex: &str, display: bool) -> String {
+    let class = if display { "math display" } else { "math" };
+    match math::layout(tex, display) {
+        Ok(layout) => format!(
+            "<span class=\"{class}\" title=\"{}\">{}</span>",
+            escape(tex.trim()),
+            layout.to_svg()
+        ),
+        Err(err) => format!(
+            "<code class=\"math-error\" title=\"{}\">{}</code>",
+            escape(&err.to_string()),
+            escape(tex.trim())
+        ),
+    }
+}
+
+fn push_text<'a>(text: &str, ctx: &mut dyn HtmlContext, out: &mut Vec<Event<'a>>) {
+    for piece in inline_math::unprotect(text) {
+        match piece {
+            Piece::Text(t) => out.push(Event::Text(t.into())),
+            Piece::Math { tex, display } => out.push(Event::Html(math_html(&tex, display).into())),
+            Piece::WikiLink(inner) => {
+                let Widget::NoteLink {
+                    target,
+                    alias,
+                    anchor,
+                } = links::wikilink(&inner)
+                else {
+                    continue;
+                };
+                let href = ctx.wiki_link(&target, anchor.as_deref());
+                let text = links::label(&target, alias.as_deref(), anchor.as_deref());
+                let html = format!("<a href=\"{}\">{}</a>", escape(&href), escape(&text));
+                out.push(Event::Html(html.into()));
+            }
+        }
+    }
+}
+
+/// HTML for a paragraph that consists of a single macro.
+fn macro_html(text: &str, ctx: &mut dyn HtmlContext) -> Option<String> {
+    if let Some(target) = parse_macro(text, "include-note") {
+        return Some(match ctx.include(target) {
+            Some(body) => format!("<section class=\"include\">\n{body}</section>\n"),
+            None => format!(
+                "<p class=\"missing\">Missing note: {}</p>\n",
+                escape(target)
+            ),
+        });
+    }
+    let url = parse_macro(text, "image")?;
+    Some(format!(
+        "<p><img src=\"{}\" alt=\"\"></p>\n",
+        escape(&ctx.image(url))
+    ))
+}
+
+/// Render `markdown` to an HTML fragment.
+pub fn to_html(markdown: &str, ctx: &mut dyn HtmlContext) -> String {
+    let input = inline_math::protect(markdown);
+    let events: Vec<Event> = Parser::new_ext(&input, Options::all()).collect();
+    let mut out = Vec::with_capacity(events.len());
+    let mut i = 0;
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::Paragraph) => {
+                if let (Some(Event::Text(text)), Some(Event::End(Tag::Paragraph))) =
+                    (events.get(i + 1), events.get(i + 2))
+                    && let Some(html) = macro_html(text, ctx)
+                {
+                    out.push(Event::Html(html.into()));
+                    i += 3;
+                    continue;
+                }
+                out.push(events[i].clone());
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
+                if matches!(lang.as_ref(), "math" | "mermaid") =>
+            {
+                let mut body = String::new();
+                i += 1;
+                while let Some(event) = events.get(i) {
+                    match event {
+                        Event::End(Tag::CodeBlock(_)) => break,
+                        Event::Text(t) => body.push_str(&inline_math::restore(t)),
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                let html = if lang.as_ref() == "math" {
+                    format!(
+                        "<div class=\"math-block\">{}</div>\n",
+                        math_html(&body, true)
+                    )
+                } else {
+                    match mermaid::to_svg(&body) {
+                        Some(svg) => format!("<figure class=\"mermaid\">{svg}</figure>\n"),
+                        None => format!(
+                            "<pre class=\"mermaid\"><code>{}</code></pre>\n",
+                            escape(&body)
+                        ),
+                    }
+                };
+                out.push(Event::Html(html.into()));
+            }
+            Event::Text(text) => push_text(text, ctx, &mut out),
+            // Headings get the id that links to them point at.
+            Event::Start(Tag::Heading(level, id, classes)) => {
+                let mut text = String::new();
+                for event in &events[i + 1..] {
+                    match event {
+                        Event::End(Tag::Heading(..)) => break,
+                        Event::Text(t) | Event::Code(t) => text.push_str(&inline_math::restore(t)),
+                        _ => {}
+                    }
+                }
+                let id = id.map_or_else(|| links::slug(&text), String::from);
+                let class = if classes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" class=\"{}\"", escape(&classes.join(" ")))
+                };
+                let html = format!("<{level} id=\"{}\"{class}>", escape(&id));
+                out.push(Event::Html(html.into()));
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                out.push(Event::Html(format!("</{level}>\n").into()));
+            }
+            Event::Start(Tag::Link(kind, url, title)) => {
+                let url = match url.strip_prefix("note://") {
+                    Some(target) => CowStr::from(ctx.note_link(target)),
+                    None => url.clone(),
+                };
+                out.push(Event::Start(Tag::Link(*kind, url, title.clone())));
+            }
+            Event::Start(Tag::Image(kind, url, title)) => {
+                let url = CowStr::from(ctx.image(url));
+                out.push(Event::Start(Tag::Image(*kind, url, title.clone())));
+            }
+            event => out.push(event.clone()),
+        }
+        i += 1;
+    }
+    let mut html = String::new();
+    html::push_html(&mut html, out.into_iter());
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Vault;
+
+    impl HtmlContext for Vault {
+        fn note_link(&mut self, target: &str) -> String {
+            format!("{}.html", target.trim_end_matches(".md"))
+        }
+
+        fn wiki_link(&mut self, target: &str, anchor: Option<&str>) -> String {
+            format!("{target}.html#{}", anchor.unwrap_or_default())
+        }
+
+        fn include(&mut self, target: &str) -> Option<String> {
+            (target == "part.md").then(|| to_html("included *text*", self))
+        }
+
+        fn image(&mut self, url: &str) -> String {
+            format!("assets/{url}")
+        }
+    }
+
+    #[test]
+    fn resolves_links_includes_and_images() {
+        let html = to_html(
+            "See [a](note://dir/a.md), [[dir/a#Top|A]] and ![x](x.png).\n\n{% include-note part.md %}\n\n{% include-note gone.md %}",
+            &mut Vault,
+        );
+        assert!(html.contains("<a href=\"dir/a.html\">a</a>"), "{html}");
+        assert!(html.contains("<a href=\"dir/a.html#Top\">A</a>"), "{html}");
+        assert!(
+            html.contains("<img src=\"assets/x.png\" alt=\"x\" />"),
+            "{html}"
+        );
+        assert!(html.contains("<section class=\"include\">\n<p>included <em>text</em></p>"));
+        assert!(html.contains("Missing note: gone.md"));
+
+        let html = to_html("# Road *map* `v2`\n\n## Named {#custom}\n", &mut Unresolved);
+        assert!(
+            html.contains("<h1 id=\"road-map-v2\">Road <em>map</em> <code>v2</code></h1>"),
+            "{html}"
+        );
+        assert!(html.contains("<h2 id=\"custom\">Named</h2>"), "{html}");
+    }
+
+    #[test]
+    fn renders_math_and_diagrams_as_svg() {
+        let html = to_html(
+            "Area $\\pi r^2$, cost $5.\n\n```math\n\\frac{1}{2}\n```\n\n```mermaid\ngraph TD\nA-->B\n```\n\n```mermaid\npie\n```",
+            &mut Unresolved,
+        );
+        assert!(
+            html.contains("<p>Area <span class=\"math\" title=\"\\pi r^2\"><svg"),
+            "{html}"
+        );
+        assert!(html.contains(", cost $5.</p>"));
+        assert!(html.contains("<div class=\"math-block\"><span class=\"math display\""));
+        assert!(html.contains("<figure class=\"mermaid\"><svg"));
+        assert!(html.contains("<pre class=\"mermaid\"><code>pie\n</code></pre>"));
+        assert!(!html.contains("<script"));
+    }
+}
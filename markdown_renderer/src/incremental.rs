@@ -0,0 +1,513 @@
+//! Incremental re-parsing of edited documents.
+//!
+//! A [`Document`] splits its text into top-level blocks and parses each of
+//! them on its own. An [`Edit`] only re-parses the blocks it touches: block
+//! boundaries are recomputed from the block before the edit until they line
+//! up with the old boundaries again, which also covers edits that open or
+//! close a code fence. The change is reported as a [`WidgetDiff`] so
+//! frontends can patch their views instead of rebuilding them.
+//!
+//! Reference links may be defined in any block of the document. The
+//! document keeps a table of all definitions that blocks look up links they
+//! do not define themselves in, and re-parses the blocks using a label
+//! whose definition an edit added, changed or removed.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use pulldown_cmark::{BrokenLink, CowStr, Options, Parser};
+
+use crate::blocks::{closes_fence, details_depth, fence_open};
+use crate::{Widget, inline_math, parse_events};
+
+/// Replacement of the bytes in `range` by `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Edit {
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            range,
+            text: text.into(),
+        }
+    }
+
+    /// The edit turning `old` into `new`, replacing the part between their
+    /// common start and end.
+    pub fn between(old: &str, new: &str) -> Self {
+        let mut start = old
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut same_end = old[start..]
+            .bytes()
+            .rev()
+            .zip(new[start..].bytes().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(old.len() - same_end) {
+            same_end -= 1;
+        }
+        Self::new(
+            start..old.len() - same_end,
+            &new[start..new.len() - same_end],
+        )
+    }
+}
+
+/// Change to the flat widget list of a document: the widgets at
+/// `start..start + removed` were replaced by `inserted`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WidgetDiff {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: Vec<Widget>,
+}
+
+impl WidgetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.removed == 0 && self.inserted.is_empty()
+    }
+
+    /// Apply the diff to a copy of the widget list it was computed for.
+    pub fn apply(&self, widgets: &mut Vec<Widget>) {
+        widgets.splice(
+            self.start..self.start + self.removed,
+            self.inserted.iter().cloned(),
+        );
+    }
+}
+
+fn is_list_item(line: &str) -> bool {
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &line[digits..];
+    if digits > 0 {
+        return rest.starts_with(". ") || rest.starts_with(") ");
+    }
+    ["- ", "* ", "+ "].iter().any(|m| rest.starts_with(m))
+}
+
+/// End of the block starting at `start`: the next line that begins a new
+/// top-level block, i.e. an unindented line after a blank line that is not
+/// inside a fence or `<details>` element and does not continue a list.
+fn block_end(text: &str, start: usize) -> usize {
+    let mut offset = start;
+    let mut fence: Option<(u8, usize)> = None;
+    let mut depth = 0isize;
+    let mut blank_before = false;
+    let mut list = false;
+    for line in text[start..].split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if offset > start
+            && fence.is_none()
+            && depth <= 0
+            && blank_before
+            && !blank
+            && !line.starts_with(char::is_whitespace)
+            && !(list && is_list_item(line))
+        {
+            return offset;
+        }
+        if offset == start {
+            list = is_list_item(line);
+        }
+        match fence {
+            Some((ch, len)) if closes_fence(line.as_bytes(), ch, len) => fence = None,
+            Some(_) => {}
+            None => {
+                fence = fence_open(line.as_bytes());
+                if fence.is_none() {
+                    depth += details_depth(line);
+                }
+            }
+        }
+        blank_before = blank;
+        offset += line.len();
+    }
+    text.len()
+}
+
+/// Destination and title of a reference definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Definition {
+    url: String,
+    title: String,
+}
+
+/// A top-level block parsed on its own.
+#[derive(Debug, Clone)]
+struct Block {
+    widgets: Vec<Widget>,
+    /// Reference definitions in the block, by [`label`].
+    defines: Vec<(String, Definition)>,
+    /// Labels of the reference links the block looked up in the document.
+    uses: Vec<String>,
+}
+
+/// Reference labels match case-insensitively and with runs of whitespace
+/// collapsed.
+fn label(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Parse `source`, looking up reference links it does not define in
+/// `definitions`.
+fn parse(source: &str, definitions: &HashMap<String, Definition>) -> Block {
+    let input = inline_math::protect(source);
+    let mut uses = Vec::new();
+    let mut lookup = |link: BrokenLink| {
+        let key = label(&link.reference);
+        let found = definitions
+            .get(&key)
+            .map(|d| (CowStr::from(d.url.clone()), CowStr::from(d.title.clone())));
+        uses.push(key);
+        found
+    };
+    let parser = Parser::new_with_broken_link_callback(&input, Options::all(), Some(&mut lookup));
+    let defines = parser
+        .reference_definitions()
+        .iter()
+        .map(|(key, def)| {
+            let title = def.title.as_deref().unwrap_or_default();
+            (
+                label(key),
+                Definition {
+                    url: def.dest.to_string(),
+                    title: title.to_string(),
+                },
+            )
+        })
+        .collect();
+    let widgets = parse_events(&mut parser.peekable());
+    Block {
+        widgets,
+        defines,
+        uses,
+    }
+}
+
+/// The definitions of `blocks`, the first one winning for each label.
+fn definitions(blocks: &[Block]) -> HashMap<String, Definition> {
+    let mut table = HashMap::new();
+    for (key, def) in blocks.iter().flat_map(|b| &b.defines) {
+        table.entry(key.clone()).or_insert_with(|| def.clone());
+    }
+    table
+}
+
+/// A document kept parsed block by block.
+#[derive(Debug, Clone)]
+pub struct Document {
+    text: String,
+    /// Start offsets of the blocks, the first one is always 0.
+    starts: Vec<usize>,
+    blocks: Vec<Block>,
+    /// Reference definitions of all blocks.
+    definitions: HashMap<String, Definition>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Document {
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut doc = Self {
+            text,
+            starts: Vec::new(),
+            blocks: Vec::new(),
+            definitions: HashMap::new(),
+        };
+        let mut start = 0;
+        while start < doc.text.len() || doc.starts.is_empty() {
+            let end = block_end(&doc.text, start);
+            doc.starts.push(start);
+            doc.blocks
+                .push(parse(&doc.text[start..end], &doc.definitions));
+            if end == start {
+                break;
+            }
+            start = end;
+        }
+        // Links to definitions further down were parsed before the table
+        // knew them.
+        doc.definitions = definitions(&doc.blocks);
+        for i in 0..doc.blocks.len() {
+            if doc.blocks[i]
+                .uses
+                .iter()
+                .any(|key| doc.definitions.contains_key(key))
+            {
+                doc.blocks[i] = parse(doc.source(i), &doc.definitions);
+            }
+        }
+        doc
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Number of top-level blocks.
+    pub fn block_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// All widgets of the document, in order.
+    pub fn widgets(&self) -> impl Iterator<Item = &Widget> {
+        self.blocks.iter().flat_map(|b| &b.widgets)
+    }
+
+    /// Source text and widgets of each top-level block.
+    pub fn blocks(&self) -> impl Iterator<Item = (&str, &[Widget])> {
+        (0..self.blocks.len()).map(|i| (self.source(i), self.blocks[i].widgets.as_slice()))
+    }
+
+    fn source(&self, block: usize) -> &str {
+        let end = self
+            .starts
+            .get(block + 1)
+            .copied()
+            .unwrap_or(self.text.len());
+        &self.text[self.starts[block]..end]
+    }
+
+    fn block_of(&self, offset: usize) -> usize {
+        self.starts
+            .partition_point(|s| *s <= offset)
+            .saturating_sub(1)
+    }
+
+    /// Apply `edit` and re-parse the blocks it affects, along with the
+    /// blocks using a reference definition it changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or does not lie on character
+    /// boundaries, like [`String::replace_range`].
+    pub fn apply(&mut self, edit: &Edit) -> WidgetDiff {
+        let Range { start, end } = edit.range;
+        assert!(start <= end, "edit range starts after its end");
+        self.text.replace_range(start..end, &edit.text);
+        let delta = edit.text.len() as isize - (end - start) as isize;
+        let edited_end = start + edit.text.len();
+
+        // The edit may join its first line with the block before it.
+        let first = self.block_of(start).saturating_sub(1);
+        let mut new_starts = Vec::new();
+        let mut pos = self.starts[first];
+        let resume = loop {
+            new_starts.push(pos);
+            pos = block_end(&self.text, pos);
+            if pos >= self.text.len() {
+                break self.starts.len();
+            }
+            if pos >= edited_end {
+                // Old boundaries after the edit are still valid once a new
+                // boundary coincides with one of them.
+                let old = (pos as isize - delta) as usize;
+                if old >= end
+                    && let Ok(k) = self.starts.binary_search(&old)
+                {
+                    break k;
+                }
+            }
+        };
+
+        for s in &mut self.starts[resume..] {
+            *s = (*s as isize + delta) as usize;
+        }
+        let edited = first..first + new_starts.len();
+        self.starts.splice(first..resume, new_starts);
+        let new_blocks: Vec<Block> = edited
+            .clone()
+            .map(|i| parse(self.source(i), &self.definitions))
+            .collect();
+        let old_blocks: Vec<Block> = self.blocks.splice(first..resume, new_blocks).collect();
+
+        // Blocks outside the edit parsed again for a changed definition,
+        // with their old widgets.
+        let mut dependents: Vec<(usize, Vec<Widget>)> = Vec::new();
+        let old_defines = old_blocks.iter().flat_map(|b| &b.defines);
+        let new_defines = self.blocks[edited.clone()].iter().flat_map(|b| &b.defines);
+        if !old_defines.eq(new_defines) {
+            let table = definitions(&self.blocks);
+            let changed: HashSet<&String> = table
+                .keys()
+                .chain(self.definitions.keys())
+                .filter(|key| table.get(*key) != self.definitions.get(*key))
+                .collect();
+            let stale: Vec<usize> = (0..self.blocks.len())
+                .filter(|i| self.blocks[*i].uses.iter().any(|key| changed.contains(key)))
+                .collect();
+            self.definitions = table;
+            for i in stale {
+                let block = parse(self.source(i), &self.definitions);
+                let old = std::mem::replace(&mut self.blocks[i], block);
+                if !edited.contains(&i) {
+                    dependents.push((i, old.widgets));
+                }
+            }
+        }
+
+        // The diff spans the edited blocks and all dependents.
+        let lo = dependents.first().map_or(first, |(i, _)| first.min(*i));
+        let hi = dependents
+            .last()
+            .map_or(edited.end, |(i, _)| edited.end.max(i + 1));
+        let prefix: usize = self.blocks[..lo].iter().map(|b| b.widgets.len()).sum();
+        let mut removed: Vec<Widget> = Vec::new();
+        let mut dependents = dependents.into_iter().peekable();
+        for i in lo..hi {
+            if i == first {
+                removed.extend(old_blocks.iter().flat_map(|b| b.widgets.iter().cloned()));
+            }
+            if edited.contains(&i) {
+                continue;
+            }
+            match dependents.next_if(|(j, _)| *j == i) {
+                Some((_, old)) => removed.extend(old),
+                None => removed.extend(self.blocks[i].widgets.iter().cloned()),
+            }
+        }
+        let inserted: Vec<Widget> = self.blocks[lo..hi]
+            .iter()
+            .flat_map(|b| b.widgets.iter().cloned())
+            .collect();
+
+        // Report only the widgets that actually changed.
+        let same_head = removed
+            .iter()
+            .zip(&inserted)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let same_tail = removed[same_head..]
+            .iter()
+            .rev()
+            .zip(inserted[same_head..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        WidgetDiff {
+            start: prefix + same_head,
+            removed: removed.len() - same_head - same_tail,
+            inserted: inserted[same_head..inserted.len() - same_tail].to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_markdown;
+
+    const SAMPLE: &str = "# Title\n\nintro with $x^2$\n\n- one\n\n- two\n\n```rust\nfn main() {}\n\nlet x = 1;\n```\n\n<details>\n<summary>More</summary>\n\nhidden\n\n</details>\n\nend\n";
+
+    fn check(doc: &mut Document, edit: Edit) {
+        let mut widgets: Vec<Widget> = doc.widgets().cloned().collect();
+        let diff = doc.apply(&edit);
+        diff.apply(&mut widgets);
+        let fresh = Document::new(doc.text());
+        assert_eq!(doc.starts, fresh.starts, "after {edit:?}");
+        assert_eq!(widgets, fresh.widgets().cloned().collect::<Vec<_>>());
+        assert_eq!(widgets, render_markdown(doc.text()), "after {edit:?}");
+    }
+
+    #[test]
+    fn splits_top_level_blocks() {
+        let doc = Document::new(SAMPLE);
+        assert_eq!(doc.block_count(), 6);
+        let (source, widgets) = doc.blocks().nth(2).unwrap();
+        assert_eq!(source, "- one\n\n- two\n\n");
+        assert_eq!(widgets.len(), 2);
+        assert_eq!(
+            doc.widgets().cloned().collect::<Vec<_>>(),
+            render_markdown(SAMPLE)
+        );
+    }
+
+    #[test]
+    fn reparses_only_touched_blocks() {
+        let mut doc = Document::new(SAMPLE);
+        let at = SAMPLE.find("intro").unwrap();
+        let diff = doc.apply(&Edit::new(at..at + 5, "outro"));
+        assert_eq!(diff.start, 1);
+        assert_eq!(diff.removed, 1);
+        assert_eq!(diff.inserted, vec![Widget::Text("outro with ".into())]);
+    }
+
+    #[test]
+    fn matches_a_full_parse_after_edits() {
+        let mut doc = Document::new(SAMPLE);
+        // Opening a fence swallows the rest of the document, closing it again
+        // restores the old blocks.
+        let at = SAMPLE.find("- two").unwrap();
+        check(&mut doc, Edit::new(at..at, "~~~\n"));
+        check(&mut doc, Edit::new(at..at + 4, ""));
+        // Joining and splitting blocks.
+        let at = doc.text().find("\n\nend").unwrap();
+        check(&mut doc, Edit::new(at..at + 2, "\n"));
+        check(&mut doc, Edit::new(at..at + 1, "\n\n"));
+        check(&mut doc, Edit::new(0..2, ""));
+        let len = doc.text().len();
+        check(&mut doc, Edit::new(len..len, "\n\nmore $$y$$\n"));
+        check(&mut doc, Edit::new(0..len, ""));
+    }
+
+    #[test]
+    fn resolves_references_defined_in_other_blocks() {
+        let text = "See [the docs][Docs] and a note[^1].\n\n[^1]: The footnote.\n\nmore\n\n[docs]: https://example.com\n";
+        let mut doc = Document::new(text);
+        assert_eq!(doc.block_count(), 4);
+        assert_eq!(
+            doc.widgets().cloned().collect::<Vec<_>>(),
+            render_markdown(text)
+        );
+        let link = |doc: &Document| {
+            doc.widgets().find_map(|w| match w {
+                Widget::Link { url, .. } => Some(url.clone()),
+                _ => None,
+            })
+        };
+        assert_eq!(link(&doc).as_deref(), Some("https://example.com"));
+
+        // Editing the definition re-parses the link far above it.
+        let at = doc.text().find("example").unwrap();
+        check(&mut doc, Edit::new(at..at + 7, "example.org/x"));
+        assert_eq!(link(&doc).as_deref(), Some("https://example.org/x.com"));
+        let at = doc.text().find("[docs]:").unwrap();
+        let len = doc.text().len();
+        check(&mut doc, Edit::new(at..len, ""));
+        assert_eq!(link(&doc), None);
+        check(&mut doc, Edit::new(at..at, "[DOCS]: https://docs.rs\n"));
+        assert_eq!(link(&doc).as_deref(), Some("https://docs.rs"));
+        // The footnote is defined before its reference now.
+        let at = doc.text().find("more").unwrap();
+        check(&mut doc, Edit::new(0..0, "[^1]: First.\n\n"));
+        check(&mut doc, Edit::new(at..at + 4, "more [docs]"));
+    }
+
+    #[test]
+    fn finds_the_edit_between_two_texts() {
+        assert_eq!(Edit::between("abcd", "abXd"), Edit::new(2..3, "X"));
+        assert_eq!(Edit::between("abab", "ab"), Edit::new(2..4, ""));
+        assert_eq!(Edit::between("same", "same"), Edit::new(4..4, ""));
+        // Never splits a character.
+        assert_eq!(Edit::between("aé", "aè"), Edit::new(1..3, "è"));
+        let mut doc = Document::new("# Old\n\ntext\n");
+        let diff = doc.apply(&Edit::between(doc.text(), "# New\n\ntext\n"));
+        assert_eq!(diff.inserted, vec![Widget::Header(1, "New".into())]);
+    }
+}
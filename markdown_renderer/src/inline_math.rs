@@ -0,0 +1,452 @@
+//! Detection of `$..$` and `$$..$$` math spans and `[[wikilinks]]` inside
+//! running text.
+//!
+//! Both have to be found in the markdown source: the parser unescapes `\$`,
+//! reads `*` or `_` inside formulas as emphasis and splits brackets into
+//! separate text events. [`protect`] therefore replaces every span with a
+//! placeholder made of private-use and hex characters that pass through the
+//! parser untouched, and [`unprotect`] turns the placeholders in text events
+//! back into math and links.
+
+use crate::blocks::{closes_fence, fence_open};
+
+/// A piece of text produced by [`tokenize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineSpan<'a> {
+    Text(&'a str),
+    Math { tex: &'a str, display: bool },
+    /// The text between `[[` and `]]`.
+    WikiLink(&'a str),
+}
+
+/// Split `text` into plain text and math spans.
+///
+/// Escaped dollars (`\$`) and code spans are never math. An inline span
+/// must open with a non-space character and close on a `$` that follows a
+/// non-space character and is not followed by a digit, which keeps currency
+/// such as `$5 and $10` as text. Neither kind of span crosses a blank line.
+/// Wikilinks stay on one line, and `![[embeds]]` are left as text.
+pub fn tokenize(text: &str) -> Vec<InlineSpan<'_>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => i = skip_code_span(bytes, i),
+            b'!' if bytes[i + 1..].starts_with(b"[[") => i += 3,
+            b'[' if bytes[i + 1..].starts_with(b"[") => match wikilink_at(text, i) {
+                Some(end) => {
+                    if text_start < i {
+                        spans.push(InlineSpan::Text(&text[text_start..i]));
+                    }
+                    spans.push(InlineSpan::WikiLink(&text[i + 2..end - 2]));
+                    i = end;
+                    text_start = end;
+                }
+                None => i += 2,
+            },
+            b'$' => match math_at(bytes, i) {
+                Some((tex_start, tex_end, end)) => {
+                    if text_start < i {
+                        spans.push(InlineSpan::Text(&text[text_start..i]));
+                    }
+                    spans.push(InlineSpan::Math {
+                        tex: &text[tex_start..tex_end],
+                        display: tex_start - i == 2,
+                    });
+                    i = end;
+                    text_start = end;
+                }
+                None => {
+                    while bytes.get(i) == Some(&b'$') {
+                        i += 1;
+                    }
+                }
+            },
+            _ => i += 1,
+        }
+    }
+    if text_start < bytes.len() {
+        spans.push(InlineSpan::Text(&text[text_start..]));
+    }
+    spans
+}
+
+fn skip_code_span(bytes: &[u8], start: usize) -> usize {
+    let run = bytes[start..].iter().take_while(|&&b| b == b'`').count();
+    let mut i = start + run;
+    while i < bytes.len() {
+        if bytes[i] == b'`' {
+            let len = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+            if len == run {
+                return i + len;
+            }
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+    start + run
+}
+
+/// End of the wikilink opening at `start`, after its closing `]]`.
+fn wikilink_at(text: &str, start: usize) -> Option<usize> {
+    let inner = &text[start + 2..];
+    let close = inner.find("]]")?;
+    let target = &inner[..close];
+    let valid = !target.trim().is_empty() && !target.contains(['[', ']', '\n']);
+    valid.then_some(start + 2 + close + 2)
+}
+
+fn blank_line_at(bytes: &[u8], i: usize) -> bool {
+    bytes[i] == b'\n'
+        && bytes[i + 1..]
+            .iter()
+            .take_while(|&&b| b != b'\n')
+            .all(|b| b.is_ascii_whitespace())
+        && bytes[i + 1..].contains(&b'\n')
+}
+
+/// Try to read a math span starting at the `$` at `start`. Returns the
+/// range of the TeX source and the end of the whole span.
+fn math_at(bytes: &[u8], start: usize) -> Option<(usize, usize, usize)> {
+    let run = bytes[start..].iter().take_while(|&&b| b == b'$').count();
+    let tex_start = start + run;
+    match run {
+        1 => {
+            if bytes.get(tex_start).is_none_or(|b| b.is_ascii_whitespace()) {
+                return None;
+            }
+            let mut i = tex_start;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'\n' if blank_line_at(bytes, i) => return None,
+                    b'$' => {
+                        let closes = !bytes[i - 1].is_ascii_whitespace()
+                            && !bytes
+                                .get(i + 1)
+                                .is_some_and(|b| b.is_ascii_digit() || *b == b'$');
+                        return closes.then_some((tex_start, i, i + 1));
+                    }
+                    _ => i += 1,
+                }
+            }
+            None
+        }
+        2 => {
+            let mut i = tex_start;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'\n' if blank_line_at(bytes, i) => return None,
+                    b'$' if bytes.get(i + 1) == Some(&b'$') => {
+                        let empty = bytes[tex_start..i].iter().all(u8::is_ascii_whitespace);
+                        return (!empty).then_some((tex_start, i, i + 2));
+                    }
+                    _ => i += 1,
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+const MARK_START: char = '\u{E000}';
+const MARK_END: char = '\u{E001}';
+
+/// Placeholder for `text`, tagged `d` for display math, `i` for inline math
+/// and `w` for a wikilink.
+fn encode(kind: char, text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2 + 8);
+    out.push(MARK_START);
+    out.push(kind);
+    for b in text.bytes() {
+        out.push_str(&format!("{b:02x}"));
+    }
+    out.push(MARK_END);
+    out
+}
+
+fn decode(hex: &str) -> Option<String> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Replace math spans outside code blocks with placeholders.
+///
+/// A fence only closes on a run of its character at least as long as the
+/// one that opened it. Lines indented by four spaces or a tab after a blank
+/// line are indented code, except inside list items where they continue the
+/// item. The private use characters placeholders are made of are dropped
+/// from `input`, so they cannot be mistaken for one.
+pub(crate) fn protect(input: &str) -> String {
+    let input = input.replace([MARK_START, MARK_END], "");
+    let mut out = String::with_capacity(input.len());
+    let mut chunk = String::new();
+    // Character and length of the open fence.
+    let mut fence: Option<(u8, usize)> = None;
+    let mut indented = false;
+    let mut blank_before = true;
+    let mut in_list = false;
+    for line in input.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if let Some((ch, len)) = fence {
+            out.push_str(line);
+            if closes_fence(line.as_bytes(), ch, len) {
+                fence = None;
+            }
+        } else if let Some(open) = fence_open(line.as_bytes()) {
+            protect_chunk(&chunk, &mut out);
+            chunk.clear();
+            fence = Some(open);
+            indented = false;
+            out.push_str(line);
+        } else if (indented && blank)
+            || (!blank && !in_list && (indented || blank_before) && code_indent(line))
+        {
+            if !indented {
+                protect_chunk(&chunk, &mut out);
+                chunk.clear();
+                indented = true;
+            }
+            out.push_str(line);
+        } else {
+            indented = false;
+            if list_item(line) {
+                in_list = true;
+            } else if !line.starts_with([' ', '\t']) && !blank {
+                in_list = false;
+            }
+            chunk.push_str(line);
+        }
+        blank_before = blank;
+    }
+    protect_chunk(&chunk, &mut out);
+    out
+}
+
+/// Whether `line` is indented as far as an indented code block.
+fn code_indent(line: &str) -> bool {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 4 - width % 4,
+            _ => break,
+        }
+        if width >= 4 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether `line` starts a list item.
+fn list_item(line: &str) -> bool {
+    let rest = line.trim_start_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return false;
+    }
+    let rest = match rest.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            match rest[digits..].strip_prefix(['.', ')']) {
+                Some(rest) if (1..10).contains(&digits) => rest,
+                _ => return false,
+            }
+        }
+    };
+    rest.is_empty() || rest.starts_with([' ', '\t', '\n', '\r'])
+}
+
+fn protect_chunk(chunk: &str, out: &mut String) {
+    for span in tokenize(chunk) {
+        match span {
+            InlineSpan::Text(t) => out.push_str(t),
+            InlineSpan::Math { tex, display } => {
+                out.push_str(&encode(if display { 'd' } else { 'i' }, tex))
+            }
+            InlineSpan::WikiLink(inner) => out.push_str(&encode('w', inner)),
+        }
+    }
+}
+
+/// Owned counterpart of [`InlineSpan`] recovered from protected text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Piece {
+    Text(String),
+    Math { tex: String, display: bool },
+    WikiLink(String),
+}
+
+/// Split a text event of protected markdown back into text and math.
+pub(crate) fn unprotect(text: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(MARK_START) {
+        let Some(len) = rest[start..].find(MARK_END) else {
+            break;
+        };
+        let mut marker = rest[start..start + len].chars();
+        marker.next();
+        let (Some(kind), Some(text)) = (marker.next(), decode(marker.as_str())) else {
+            break;
+        };
+        if start > 0 {
+            pieces.push(Piece::Text(rest[..start].to_string()));
+        }
+        pieces.push(match kind {
+            'w' => Piece::WikiLink(text),
+            kind => Piece::Math {
+                tex: text,
+                display: kind == 'd',
+            },
+        });
+        rest = &rest[start + len + MARK_END.len_utf8()..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest.to_string()));
+    }
+    pieces
+}
+
+/// Put the original `$..$` and `[[..]]` source back in place of the
+/// placeholders, for contexts that only keep plain text such as headings.
+pub(crate) fn restore(text: &str) -> String {
+    unprotect(text)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(t) => t,
+            Piece::Math { tex, display: true } => format!("$${tex}$$"),
+            Piece::Math {
+                tex,
+                display: false,
+            } => format!("${tex}$"),
+            Piece::WikiLink(inner) => format!("[[{inner}]]"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InlineSpan::{Math, Text};
+
+    #[test]
+    fn finds_math_inside_text() {
+        assert_eq!(
+            tokenize(r"the area is $\pi r^2$ units"),
+            vec![
+                Text("the area is "),
+                Math {
+                    tex: r"\pi r^2",
+                    display: false
+                },
+                Text(" units"),
+            ]
+        );
+        assert_eq!(
+            tokenize("so $$E = mc^2$$."),
+            vec![
+                Text("so "),
+                Math {
+                    tex: "E = mc^2",
+                    display: true
+                },
+                Text(".")
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_currency_and_escapes_alone() {
+        assert_eq!(tokenize("$5 and $10"), vec![Text("$5 and $10")]);
+        assert_eq!(
+            tokenize(r"costs \$5 or $x$"),
+            vec![
+                Text(r"costs \$5 or "),
+                Math {
+                    tex: "x",
+                    display: false
+                },
+            ]
+        );
+        assert_eq!(tokenize("`$x$` and $ y$"), vec![Text("`$x$` and $ y$")]);
+        assert_eq!(tokenize("$a\n\nb$"), vec![Text("$a\n\nb$")]);
+    }
+
+    #[test]
+    fn finds_wikilinks() {
+        assert_eq!(
+            tokenize("see [[A|b]] and ![[c]] or [[]] `[[d]]`"),
+            vec![
+                Text("see "),
+                InlineSpan::WikiLink("A|b"),
+                Text(" and ![[c]] or [[]] `[[d]]`"),
+            ]
+        );
+        let protected = protect("x [[Note *1*]] y");
+        assert_eq!(
+            unprotect(&protected)[1],
+            Piece::WikiLink("Note *1*".into())
+        );
+        assert_eq!(restore(&protected), "x [[Note *1*]] y");
+    }
+
+    #[test]
+    fn placeholders_round_trip() {
+        let protected = protect("a $x_1*y*$ b\n```\n$code$\n```\n");
+        assert!(protected.contains("$code$"));
+        let line = protected.lines().next().unwrap();
+        assert_eq!(
+            unprotect(line),
+            vec![
+                Piece::Text("a ".into()),
+                Piece::Math {
+                    tex: "x_1*y*".into(),
+                    display: false
+                },
+                Piece::Text(" b".into()),
+            ]
+        );
+        assert_eq!(restore(line), "a $x_1*y*$ b");
+    }
+
+    #[test]
+    fn drops_marker_characters_of_the_input() {
+        let math = Piece::Math {
+            tex: "x".into(),
+            display: false,
+        };
+        for input in ["a \u{E000}\u{E001} $x$", "\u{E000}é $x$\u{E001}"] {
+            let pieces = unprotect(&protect(input));
+            assert!(pieces.contains(&math), "{input:?}");
+            assert!(!restore(&protect(input)).contains(MARK_START));
+            crate::render_markdown(input);
+        }
+        assert_eq!(
+            unprotect("\u{E000}\u{E001}"),
+            [Piece::Text("\u{E000}\u{E001}".into())]
+        );
+        assert_eq!(unprotect("\u{E000}é\u{E001}").len(), 1);
+    }
+
+    #[test]
+    fn skips_long_fences_and_indented_code() {
+        let protected = protect("````\n```\n$a$\n```\n$b$\n````\n$c$\n");
+        assert!(protected.contains("$a$") && protected.contains("$b$"));
+        assert!(!protected.contains("$c$"));
+        let protected = protect("text\n\n    $a$\n\n\t$b$\nnext $c$\n\n- item\n\n    $d$\n");
+        assert!(protected.contains("$a$") && protected.contains("$b$"));
+        assert!(!protected.contains("$c$") && !protected.contains("$d$"));
+        assert!(!protect("text\n    $e$\n").contains("$e$"));
+    }
+}
@@ -1,22 +1,44 @@
+pub mod blocks;
+mod details;
+pub mod html;
+pub mod incremental;
+pub mod inline_math;
+pub mod links;
+pub mod math;
+pub mod mermaid;
+
 use std::path::Path;
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use std::sync::Mutex;
 
 use pulldown_cmark::{Parser, Options, Event, Tag, CodeBlockKind};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Widget {
     Header(u32, String),
     Bold(String),
     Italic(String),
     CodeBlock { lang: Option<String>, code: String },
-    Latex(String),
+    /// TeX math, either inline (`$..$`) or display (`$$..$$` and `math` fences).
+    Latex { tex: String, display: bool },
     Image { alt: String, url: String },
+    /// A table whose children are [`Widget::TableRow`]s, header row first.
     Table(Vec<Widget>),
+    /// Text of the cells of one table row.
+    TableRow(Vec<String>),
     Mermaid(String),
-    Details(Vec<Widget>),
+    /// A collapsible `<details>` block. `open` reflects the HTML attribute.
+    Details { summary: String, open: bool, children: Vec<Widget> },
     Text(String),
-    NoteLink(String),
+    /// Link to another note, see [`links`] for the accepted syntax and how
+    /// `target` is resolved.
+    NoteLink {
+        target: String,
+        alias: Option<String>,
+        anchor: Option<String>,
+    },
+    /// Markdown link with a URL scheme other than `note://`, opened outside
+    /// the application.
+    Link { text: String, url: String },
     IncludeNote(String),
     CustomImage(String),
 }
@@ -34,19 +56,19 @@ fn parse_macro<'a>(text: &'a str, name: &str) -> Option<&'a str> {
     }
 }
 
-fn parse_latex_inline(text: &str) -> Option<String> {
-    if text.starts_with('$') && text.ends_with('$') && text.len() >= 2 {
-        let start = text.chars().take_while(|&c| c == '$').count();
-        let end = text.chars().rev().take_while(|&c| c == '$').count();
-        if start == end && (start == 1 || start == 2) {
-            return Some(text[start..text.len() - end].to_string());
+fn push_text(text: &str, widgets: &mut Vec<Widget>) {
+    for piece in inline_math::unprotect(text) {
+        match piece {
+            inline_math::Piece::Text(t) => widgets.push(Widget::Text(t)),
+            inline_math::Piece::Math { tex, display } => widgets.push(Widget::Latex { tex, display }),
+            inline_math::Piece::WikiLink(inner) => widgets.push(links::wikilink(&inner)),
         }
     }
-    None
 }
 
 pub fn render_markdown(input: &str) -> Vec<Widget> {
-    let parser = Parser::new_ext(input, Options::all());
+    let input = inline_math::protect(input);
+    let parser = Parser::new_ext(&input, Options::all());
     let mut iter = parser.peekable();
     parse_events(&mut iter)
 }
@@ -55,9 +77,22 @@ fn parse_events<'a, I>(events: &mut std::iter::Peekable<I>) -> Vec<Widget>
 where
     I: Iterator<Item = Event<'a>>,
 {
-    
     let mut widgets = Vec::new();
+    let mut open_details = Vec::new();
     while let Some(event) = events.next() {
+        if let Event::Html(html) = &event {
+            details::handle_html(html, &mut open_details, &mut widgets);
+            continue;
+        }
+        if let Event::Text(text) = &event
+            && details::push_summary(text, &mut open_details)
+        {
+            continue;
+        }
+        let widgets = match open_details.last_mut() {
+            Some(frame) => &mut frame.children,
+            None => &mut widgets,
+        };
         match event {
             Event::Start(Tag::Heading(level, ..)) => {
                 let text = collect_text(events, Tag::Heading(level, None, Vec::new()));
@@ -80,14 +115,27 @@ where
                 if lang.as_deref() == Some("mermaid") {
                     widgets.push(Widget::Mermaid(text));
                 } else if lang.as_deref() == Some("math") {
-                    widgets.push(Widget::Latex(text));
+                    widgets.push(Widget::Latex { tex: text, display: true });
                 } else {
                     widgets.push(Widget::CodeBlock { lang, code: text });
                 }
             }
             Event::Start(Tag::Table(_aligns)) => {
-                let inner = parse_until(events, |e| matches!(e, Event::End(Tag::Table(_))));
-                widgets.push(Widget::Table(inner));
+                let mut rows = Vec::new();
+                let mut cells = Vec::new();
+                while let Some(event) = events.next() {
+                    match event {
+                        Event::End(Tag::Table(_)) => break,
+                        Event::Start(Tag::TableCell) => {
+                            cells.push(collect_text(events, Tag::TableCell));
+                        }
+                        Event::End(Tag::TableHead | Tag::TableRow) => {
+                            rows.push(Widget::TableRow(std::mem::take(&mut cells)));
+                        }
+                        _ => {}
+                    }
+                }
+                widgets.push(Widget::Table(rows));
             }
             Event::Start(Tag::Image(_link, url, title)) => {
                 widgets.push(Widget::Image {
@@ -95,21 +143,13 @@ where
                     url: url.to_string(),
                 });
             }
-            Event::Start(Tag::Link(_link, url, _title)) => {
+            Event::Start(Tag::Link(kind, url, title)) => {
                 if url.starts_with("note://") {
-                    // consume inner text
-                    let _ = parse_until(events, |e| matches!(e, Event::End(Tag::Link(_, _, _))));
-                    widgets.push(Widget::NoteLink(url.to_string()));
-                }
-            }
-            Event::Html(html) => {
-                let trimmed = html.trim();
-                if trimmed.starts_with("<details") {
-                    let inner = parse_until(events, |e| match e {
-                        Event::Html(h) => h.trim().starts_with("</details"),
-                        _ => false,
-                    });
-                    widgets.push(Widget::Details(inner));
+                    let text = collect_text(events, Tag::Link(kind, url.clone(), title));
+                    widgets.push(links::note_url(&url, &text));
+                } else if let links::LinkTarget::External(external) = links::LinkTarget::from_url(&url) {
+                    let text = collect_text(events, Tag::Link(kind, url.clone(), title));
+                    widgets.push(Widget::Link { text, url: external });
                 }
             }
             Event::Text(text) => {
@@ -118,15 +158,14 @@ where
                     widgets.push(Widget::IncludeNote(arg.to_string()));
                 } else if let Some(arg) = parse_macro(t, "image") {
                     widgets.push(Widget::CustomImage(arg.to_string()));
-                } else if let Some(latex) = parse_latex_inline(t) {
-                    widgets.push(Widget::Latex(latex));
                 } else {
-                    widgets.push(Widget::Text(text.to_string()));
+                    push_text(&text, widgets);
                 }
             }
             _ => {}
         }
     }
+    details::finish(open_details, &mut widgets);
     widgets
 }
 
@@ -138,38 +177,28 @@ where
     while let Some(event) = events.next() {
         match &event {
             Event::End(tag) if tag == &until => break,
-            Event::Text(t) => text.push_str(t),
+            Event::Text(t) => text.push_str(&inline_math::restore(t)),
+            Event::Code(code) => text.push_str(code),
             _ => {}
         }
     }
     text
 }
 
-fn parse_until<'a, I, F>(events: &mut std::iter::Peekable<I>, end: F) -> Vec<Widget>
-where
-    I: Iterator<Item = Event<'a>>,
-    F: Fn(&Event<'a>) -> bool,
-{
-    let mut collected = Vec::new();
-    while let Some(event) = events.next() {
-        if end(&event) {
-            break;
-        } else {
-            let mut single = vec![event];
-            let mut it = single.into_iter().peekable();
-            collected.extend(parse_events(&mut it));
-        }
-    }
-    collected
-}
+/// Viewport of the file last rendered by [`render_viewport`].
+static VIEWPORT: Mutex<Option<blocks::Viewport>> = Mutex::new(None);
 
+/// Render the blocks of the file at `path` overlapping the byte range
+/// `start..end`. The range is widened to block boundaries, see
+/// [`blocks::Viewport`]. The index of the file is kept until another file
+/// is rendered, and only rebuilt when the file changes.
 pub fn render_viewport<P: AsRef<Path>>(path: P, start: u64, end: u64) -> std::io::Result<Vec<Widget>> {
-    let mut file = File::open(path)?;
-    file.seek(SeekFrom::Start(start))?;
-    let mut buf = vec![0u8; (end - start) as usize];
-    file.read_exact(&mut buf)?;
-    let content = String::from_utf8_lossy(&buf);
-    Ok(render_markdown(&content))
+    let path = path.as_ref();
+    let mut last = VIEWPORT.lock().unwrap_or_else(|e| e.into_inner());
+    match last.as_mut() {
+        Some(viewport) if viewport.path() == path => viewport.render(start, end),
+        _ => last.insert(blocks::Viewport::open(path)?).render(start, end),
+    }
 }
 
 #[cfg(test)]
@@ -186,5 +215,77 @@ mod tests {
         let res = render_viewport(path, 0, 9).unwrap();
         assert!(!res.is_empty());
     }
+
+    #[test]
+    fn parses_table_rows() {
+        let widgets = render_markdown("| a | *b* |\n|---|---|\n| 1 | `2` |\n");
+        let row = |cells: &[&str]| Widget::TableRow(cells.iter().map(|c| c.to_string()).collect());
+        assert_eq!(widgets, vec![Widget::Table(vec![row(&["a", "b"]), row(&["1", "2"])])]);
+    }
+
+    #[test]
+    fn keeps_external_links() {
+        let widgets = render_markdown("[site](https://example.com/?a=1&b=2) [x](other.md)");
+        assert_eq!(
+            widgets,
+            vec![
+                Widget::Link { text: "site".into(), url: "https://example.com/?a=1&b=2".into() },
+                Widget::Text(" ".into()),
+                Widget::Text("x".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_inline_and_display_math_distinct() {
+        let widgets = render_markdown("$x^2$\n\n$$\\frac{1}{2}$$\n\n```math\ny\n```");
+        let math: Vec<_> = widgets
+            .iter()
+            .filter_map(|w| match w {
+                Widget::Latex { tex, display } => Some((tex.as_str(), *display)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(math, vec![("x^2", false), ("\\frac{1}{2}", true), ("y\n", true)]);
+    }
+
+    #[test]
+    fn parses_nested_details() {
+        let input = "<details open>\n<summary>Outer <b>x</b></summary>\n\ntext\n\n\
+                     <details>\n<summary>Inner</summary>\n\ninner\n\n</details>\n\n\
+                     </details>\n\nafter";
+        let widgets = render_markdown(input);
+        let [Widget::Details { summary, open, children }, Widget::Text(after)] = &widgets[..]
+        else {
+            panic!("unexpected widgets: {widgets:?}");
+        };
+        assert_eq!((summary.as_str(), *open, after.as_str()), ("Outer x", true, "after"));
+        let [Widget::Text(text), Widget::Details { summary, open, children }] = &children[..]
+        else {
+            panic!("unexpected children: {children:?}");
+        };
+        assert_eq!((text.as_str(), summary.as_str(), *open), ("text", "Inner", false));
+        assert!(matches!(&children[..], [Widget::Text(t)] if t == "inner"));
+    }
+
+    #[test]
+    fn parses_single_line_details() {
+        let widgets = render_markdown("<details><summary>S</summary>body</details>");
+        assert!(matches!(
+            widgets.as_slice(),
+            [Widget::Details { summary, open: false, children }]
+                if summary == "S" && matches!(children.as_slice(), [Widget::Text(b)] if b == "body")
+        ));
+    }
+
+    #[test]
+    fn splits_paragraphs_into_text_and_math() {
+        let widgets = render_markdown("the area is $\\pi r^2$ units, $5 and $10");
+        assert!(matches!(
+            widgets.as_slice(),
+            [Widget::Text(a), Widget::Latex { tex, display: false }, Widget::Text(b)]
+                if a == "the area is " && tex == "\\pi r^2" && b == " units, $5 and $10"
+        ));
+    }
 }
 pub fn placeholder() {}
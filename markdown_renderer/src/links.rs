@@ -0,0 +1,584 @@
+//! Links between notes.
+//!
+//! Notes link to each other either with `[text](note://path#anchor)` or with
+//! wikilinks: `[[Note Name]]`, `[[Note Name|alias]]` and `[[Note#Heading]]`.
+//! Both become [`Widget::NoteLink`]s. A wikilink target may be a path, a file
+//! name or the title of a note; [`NoteIndex`] resolves it and reports when
+//! several notes match.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::Widget;
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Widget for the text between `[[` and `]]`, as found by
+/// [`tokenize`](crate::inline_math::tokenize).
+pub fn wikilink(inner: &str) -> Widget {
+    let (target, alias) = match inner.split_once('|') {
+        Some((target, alias)) => (target, non_empty(alias)),
+        None => (inner, None),
+    };
+    let (target, anchor) = match target.split_once('#') {
+        Some((target, anchor)) => (target, non_empty(anchor)),
+        None => (target, None),
+    };
+    Widget::NoteLink {
+        target: target.trim().to_string(),
+        alias,
+        anchor,
+    }
+}
+
+/// Widget for a `note://` link with the link text `text`. Its path and
+/// anchor are percent-decoded.
+pub(crate) fn note_url(url: &str, text: &str) -> Widget {
+    let path = url.strip_prefix("note://").unwrap_or(url);
+    let (target, anchor) = match path.split_once('#') {
+        Some((target, anchor)) => (target, non_empty(&percent_decode(anchor))),
+        None => (path, None),
+    };
+    Widget::NoteLink {
+        target: percent_decode(target),
+        alias: non_empty(text),
+        anchor,
+    }
+}
+
+/// Path with `/` separators, as written in links and macros.
+pub fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `path` for a `note://` URL: [`slash_path`] with the characters that
+/// would end the URL, start its anchor or read as an escape
+/// percent-encoded, as [`note_url`] decodes them.
+pub fn url_path(path: &Path) -> String {
+    let mut out = String::new();
+    for c in slash_path(path).chars() {
+        match c {
+            ' ' | '%' | '#' | '(' | ')' | '<' | '>' | '[' | ']' | '\\' => {
+                out.push_str(&format!("%{:02X}", c as u8))
+            }
+            c if c.is_control() => {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    out.push_str(&format!("%{b:02X}"));
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `text` with `%XX` escapes replaced by the bytes they stand for.
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(b) = text
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+        {
+            out.push(b);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Text shown for a link: its alias, or the target and heading.
+pub fn label(target: &str, alias: Option<&str>, anchor: Option<&str>) -> String {
+    match (alias, anchor) {
+        (Some(alias), _) => alias.to_string(),
+        (None, Some(anchor)) if target.is_empty() => anchor.to_string(),
+        (None, Some(anchor)) => format!("{target} > {anchor}"),
+        (None, None) => target.to_string(),
+    }
+}
+
+/// Anchor for a heading in `note://` URLs: lowercase, with spaces turned
+/// into dashes and punctuation dropped.
+pub fn slug(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            c if c.is_alphanumeric() || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A heading of a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    /// Index of the source line.
+    pub line: usize,
+}
+
+/// ATX headings of a note, skipping fenced code blocks.
+pub fn headings(source: &str) -> Vec<Heading> {
+    let mut out = Vec::new();
+    let mut fence = false;
+    for (line, text) in source.lines().enumerate() {
+        let trimmed = text.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = !fence;
+        } else if !fence {
+            let level = trimmed.bytes().take_while(|b| *b == b'#').count();
+            if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+                out.push(Heading {
+                    level,
+                    text: trimmed[level..].trim().to_string(),
+                    line,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// Source line of the heading an anchor points at. Anchors may be written
+/// as the heading text, as in wikilinks, or as its [`slug`].
+pub fn heading_line(source: &str, anchor: &str) -> Option<usize> {
+    let wanted = slug(anchor);
+    headings(source)
+        .into_iter()
+        .find(|h| h.text.eq_ignore_ascii_case(anchor.trim()) || slug(&h.text) == wanted)
+        .map(|h| h.line)
+}
+
+/// Where a link points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// Another note, resolved with [`NoteIndex::resolve`]. An empty target
+    /// is the linking note itself.
+    Note {
+        target: String,
+        anchor: Option<String>,
+    },
+    /// Anything with a URL scheme, opened outside the application.
+    External(String),
+}
+
+impl LinkTarget {
+    /// Where the URL of a Markdown link points.
+    pub fn from_url(url: &str) -> Self {
+        let url = url.trim();
+        let scheme = url
+            .split_once(':')
+            .is_some_and(|(s, _)| s.len() > 1 && s.chars().all(|c| c.is_ascii_alphanumeric()));
+        if url.starts_with("note://") || !scheme {
+            match note_url(url, "") {
+                Widget::NoteLink { target, anchor, .. } => LinkTarget::Note { target, anchor },
+                _ => unreachable!(),
+            }
+        } else {
+            LinkTarget::External(url.to_string())
+        }
+    }
+}
+
+/// The link under the char column `col` of a source line: a wikilink, a
+/// Markdown link or a bare `http(s)` URL.
+pub fn link_at(line: &str, col: usize) -> Option<LinkTarget> {
+    let at = line.char_indices().nth(col).map_or(line.len(), |(i, _)| i);
+    let mut from = 0;
+    while let Some(start) = line[from..].find("[[").map(|i| from + i) {
+        let Some(end) = line[start..].find("]]").map(|i| start + i + 2) else {
+            break;
+        };
+        if (start..end).contains(&at)
+            && !line[..start].ends_with('!')
+            && let Widget::NoteLink { target, anchor, .. } = wikilink(&line[start + 2..end - 2])
+        {
+            return Some(LinkTarget::Note { target, anchor });
+        }
+        from = end;
+    }
+    let mut from = 0;
+    while let Some(mid) = line[from..].find("](").map(|i| from + i) {
+        let Some(end) = line[mid..].find(')').map(|i| mid + i + 1) else {
+            break;
+        };
+        let start = line[..mid].rfind('[').unwrap_or(mid);
+        if (start..end).contains(&at) && !line[..start].ends_with('!') {
+            return Some(LinkTarget::from_url(&line[mid + 2..end - 1]));
+        }
+        from = end;
+    }
+    let start = line[..at].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let end = line[at..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |i| at + i);
+    let word = line[start..end].trim_matches(['<', '>', '(', ')', ',', '.']);
+    (word.starts_with("http://") || word.starts_with("https://"))
+        .then(|| LinkTarget::External(word.to_string()))
+}
+
+/// Vault-relative path of a note created for a link to `target` from the
+/// note `from`. Targets without a folder go next to the linking note.
+/// `None` when the path would leave the vault.
+pub fn new_note_path(target: &str, from: Option<&Path>) -> Option<PathBuf> {
+    let target = target.trim().trim_start_matches('/');
+    let mut path = if target.contains('/') {
+        PathBuf::from(target)
+    } else {
+        from.and_then(Path::parent)
+            .unwrap_or(Path::new(""))
+            .join(target)
+    };
+    if path.extension().is_none_or(|e| e != "md") {
+        let name = format!(
+            "{}.md",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        path.set_file_name(name);
+    }
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then_some(path)
+}
+
+/// Title of a note: the `title` in its front matter or its first level-one
+/// heading.
+pub fn note_title(source: &str) -> Option<String> {
+    if let Some(rest) = source.strip_prefix("---\n") {
+        for line in rest.lines().take_while(|l| l.trim_end() != "---") {
+            if let Some(title) = line.strip_prefix("title:") {
+                let title = title.trim().trim_matches(['"', '\'']);
+                if !title.is_empty() {
+                    return Some(title.to_string());
+                }
+            }
+        }
+    }
+    source
+        .lines()
+        .find_map(|l| l.strip_prefix("# "))
+        .and_then(non_empty)
+}
+
+/// Outcome of resolving a link target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Found(PathBuf),
+    /// Several notes match, sorted by path.
+    Ambiguous(Vec<PathBuf>),
+    Missing,
+}
+
+/// A link whose target did not resolve to exactly one note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkProblem {
+    pub target: String,
+    /// Matching notes, empty when there is none.
+    pub candidates: Vec<PathBuf>,
+}
+
+fn lookup_key(text: &str) -> String {
+    text.trim()
+        .trim_start_matches('/')
+        .replace('\\', "/")
+        .to_lowercase()
+}
+
+/// Notes of a vault by path, file name and title.
+#[derive(Debug, Clone, Default)]
+pub struct NoteIndex {
+    /// Vault-relative paths of all notes.
+    paths: Vec<PathBuf>,
+    /// Title of each note in `paths`.
+    note_titles: Vec<Option<String>>,
+    /// Lowercased path to its index into `paths`, the first note winning.
+    keys: HashMap<String, usize>,
+    /// Lowercased file stem to indices into `paths`.
+    names: HashMap<String, Vec<usize>>,
+    /// Lowercased title to indices into `paths`.
+    titles: HashMap<String, Vec<usize>>,
+}
+
+impl NoteIndex {
+    /// Index the Markdown files below `vault`, skipping hidden entries.
+    pub fn scan(vault: &Path) -> io::Result<Self> {
+        Self::scan_with(vault, |path, _| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+    }
+
+    /// Index the Markdown files below `vault`, skipping the entries for
+    /// which `skip` returns true. It is given the vault-relative path of
+    /// each entry and whether it is a folder.
+    pub fn scan_with(vault: &Path, skip: impl Fn(&Path, bool) -> bool) -> io::Result<Self> {
+        let mut index = Self::default();
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(vault.join(&dir))? {
+                let entry = entry?;
+                let rel = dir.join(entry.file_name());
+                let is_dir = entry.file_type()?.is_dir();
+                if skip(&rel, is_dir) {
+                    continue;
+                }
+                if is_dir {
+                    dirs.push(rel);
+                } else if rel.extension().is_some_and(|e| e == "md") {
+                    let source = fs::read_to_string(entry.path()).unwrap_or_default();
+                    index.insert(rel, note_title(&source).as_deref());
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Add the note at the vault-relative `path`.
+    pub fn insert(&mut self, path: PathBuf, title: Option<&str>) {
+        let i = self.paths.len();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        self.names.entry(lookup_key(&stem)).or_default().push(i);
+        self.keys
+            .entry(lookup_key(&path.to_string_lossy()))
+            .or_insert(i);
+        if let Some(title) = title {
+            self.titles.entry(lookup_key(title)).or_default().push(i);
+        }
+        self.paths.push(path);
+        self.note_titles.push(title.map(String::from));
+    }
+
+    /// Paths and titles of all notes.
+    pub fn notes(&self) -> impl Iterator<Item = (&Path, Option<&str>)> {
+        self.paths
+            .iter()
+            .zip(&self.note_titles)
+            .map(|(path, title)| (path.as_path(), title.as_deref()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    fn matches(&self, indices: &[usize]) -> Resolution {
+        let mut paths: Vec<PathBuf> = indices.iter().map(|i| self.paths[*i].clone()).collect();
+        paths.sort();
+        match paths.len() {
+            0 => Resolution::Missing,
+            1 => Resolution::Found(paths.remove(0)),
+            _ => Resolution::Ambiguous(paths),
+        }
+    }
+
+    /// Resolve `target` as written in a link from the note `from`.
+    ///
+    /// A path relative to the linking note or to the vault wins, then a
+    /// unique file name, then a unique title. Path suffixes such as
+    /// `folder/Note` narrow down file names.
+    pub fn resolve(&self, target: &str, from: Option<&Path>) -> Resolution {
+        let key = lookup_key(target);
+        if key.is_empty() {
+            return from.map_or(Resolution::Missing, |f| Resolution::Found(f.to_path_buf()));
+        }
+        let with_ext = if key.ends_with(".md") {
+            key.clone()
+        } else {
+            format!("{key}.md")
+        };
+        let sibling = from
+            .and_then(Path::parent)
+            .map(|dir| lookup_key(&dir.join(&with_ext).to_string_lossy()));
+        for wanted in sibling.iter().chain([&with_ext]) {
+            if let Some(i) = self.keys.get(wanted) {
+                return Resolution::Found(self.paths[*i].clone());
+            }
+        }
+        let (dir, name) = match with_ext.rsplit_once('/') {
+            Some((dir, name)) => (Some(dir), name),
+            None => (None, with_ext.as_str()),
+        };
+        let stem = name.trim_end_matches(".md");
+        let by_name: Vec<usize> = self
+            .names
+            .get(stem)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|i| {
+                dir.is_none_or(|dir| {
+                    let parent = self.paths[*i].parent().unwrap_or(Path::new(""));
+                    Path::new(&lookup_key(&parent.to_string_lossy())).ends_with(dir)
+                })
+            })
+            .collect();
+        if !by_name.is_empty() {
+            return self.matches(&by_name);
+        }
+        self.matches(self.titles.get(&key).map_or(&[], Vec::as_slice))
+    }
+
+    /// Links in `widgets` that do not resolve to exactly one note.
+    pub fn problems(&self, widgets: &[Widget], from: Option<&Path>) -> Vec<LinkProblem> {
+        let mut problems = Vec::new();
+        for widget in widgets {
+            match widget {
+                Widget::NoteLink { target, .. } => {
+                    let candidates = match self.resolve(target, from) {
+                        Resolution::Found(_) => continue,
+                        Resolution::Ambiguous(paths) => paths,
+                        Resolution::Missing => Vec::new(),
+                    };
+                    problems.push(LinkProblem {
+                        target: target.clone(),
+                        candidates,
+                    });
+                }
+                Widget::Details { children, .. } | Widget::Table(children) => {
+                    problems.extend(self.problems(children, from));
+                }
+                _ => {}
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_markdown;
+
+    fn link(target: &str, alias: Option<&str>, anchor: Option<&str>) -> Widget {
+        Widget::NoteLink {
+            target: target.into(),
+            alias: alias.map(Into::into),
+            anchor: anchor.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn parses_wikilinks_and_note_urls() {
+        let widgets = render_markdown(
+            "See [[Note Name]], [[Note Name|alias]], [[Note#Heading]] and \
+             [text](note://dir/a.md#top) [b](note://my%20notes/b%28v2%29.md#to%20do).\n\n\
+             `[[code]]` ![[embed]]",
+        );
+        let links: Vec<&Widget> = widgets
+            .iter()
+            .filter(|w| matches!(w, Widget::NoteLink { .. }))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                &link("Note Name", None, None),
+                &link("Note Name", Some("alias"), None),
+                &link("Note", None, Some("Heading")),
+                &link("dir/a.md", Some("text"), Some("top")),
+                &link("my notes/b(v2).md", Some("b"), Some("to do")),
+            ]
+        );
+        assert_eq!(label("Note", None, Some("Heading")), "Note > Heading");
+        let path = url_path(Path::new("my notes/b(v2) 100%.md"));
+        assert_eq!(path, "my%20notes/b%28v2%29%20100%25.md");
+        assert!(
+            matches!(note_url(&path, ""), Widget::NoteLink { target, .. } if target == "my notes/b(v2) 100%.md")
+        );
+    }
+
+    #[test]
+    fn resolves_by_path_name_and_title() {
+        let mut index = NoteIndex::default();
+        index.insert("inbox/Plans.md".into(), Some("Road map"));
+        index.insert("work/Plans.md".into(), None);
+        index.insert("work/Other.md".into(), Some("Road map"));
+        index.insert("Solo.md".into(), Some("Only one"));
+        index.insert("homework/Memo.md".into(), None);
+        let found = |p: &str| Resolution::Found(p.into());
+
+        assert_eq!(index.resolve("work/Plans", None), found("work/Plans.md"));
+        assert_eq!(
+            index.resolve("Plans", Some(Path::new("work/x.md"))),
+            found("work/Plans.md")
+        );
+        assert_eq!(
+            index.resolve("plans", None),
+            Resolution::Ambiguous(vec!["inbox/Plans.md".into(), "work/Plans.md".into()])
+        );
+        assert_eq!(index.resolve("inbox/plans", None), found("inbox/Plans.md"));
+        assert_eq!(index.resolve("work/Memo", None), Resolution::Missing);
+        assert_eq!(
+            index.resolve("homework/Memo", None),
+            found("homework/Memo.md")
+        );
+        assert_eq!(index.resolve("only one", None), found("Solo.md"));
+        assert!(
+            matches!(index.resolve("Road map", None), Resolution::Ambiguous(p) if p.len() == 2)
+        );
+        assert_eq!(index.resolve("Nope", None), Resolution::Missing);
+
+        let problems = index.problems(&render_markdown("[[Plans]] [[Solo]] [[Nope]]"), None);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].candidates.len(), 2);
+        assert!(problems[1].candidates.is_empty());
+    }
+
+    #[test]
+    fn finds_links_and_headings_under_the_cursor() {
+        let line = "See [[Plans#Next step|next]], [x](note://a.md#top) or https://example.com.";
+        let note = |target: &str, anchor: &str| LinkTarget::Note {
+            target: target.into(),
+            anchor: Some(anchor.into()),
+        };
+        assert_eq!(link_at(line, 6), Some(note("Plans", "Next step")));
+        assert_eq!(link_at(line, 3), None);
+        assert_eq!(link_at(line, 31), Some(note("a.md", "top")));
+        assert_eq!(
+            link_at(line, 60),
+            Some(LinkTarget::External("https://example.com".into()))
+        );
+        assert_eq!(
+            link_at("[site](mailto:a@b.c)", 2),
+            Some(LinkTarget::External("mailto:a@b.c".into()))
+        );
+
+        let source = "# Plans\n```\n## Next step\n```\n## Next step\n";
+        assert_eq!(heading_line(source, "next step"), Some(4));
+        assert_eq!(heading_line(source, "next-step"), Some(4));
+        assert_eq!(heading_line(source, "later"), None);
+
+        assert_eq!(
+            new_note_path("New idea", Some(Path::new("work/plans.md"))),
+            Some(PathBuf::from("work/New idea.md"))
+        );
+        assert_eq!(
+            new_note_path("inbox/x.md", None),
+            Some(PathBuf::from("inbox/x.md"))
+        );
+        assert_eq!(new_note_path("../x", Some(Path::new("a.md"))), None);
+        assert_eq!(new_note_path("..", Some(Path::new("a.md"))), None);
+        assert_eq!(new_note_path("work/../../x.md", None), None);
+    }
+}
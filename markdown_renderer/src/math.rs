@@ -0,0 +1,1256 @@
+//! Layout engine for TeX math.
+//!
+//! Expressions are parsed into a [`MathNode`] tree and laid out on a grid of
+//! character cells ([`MathBox`]). The TUI draws the cells as text while the
+//! iced frontend scales them into vector glyphs, so both share one layout.
+
+use std::fmt;
+
+/// Errors produced while parsing a TeX expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathError {
+    /// A `\command` the engine does not know how to lay out.
+    UnsupportedCommand(String),
+    /// An environment passed to `\begin` that is not supported.
+    UnsupportedEnvironment(String),
+    /// A `{` without a matching `}` or the other way around.
+    UnbalancedBraces,
+    /// A command that is missing one of its arguments.
+    MissingArgument(String),
+    /// A `\left` without `\right` or a `\begin` without `\end`.
+    Unterminated(String),
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::UnsupportedCommand(cmd) => write!(f, "unsupported command `\\{cmd}`"),
+            MathError::UnsupportedEnvironment(env) => {
+                write!(f, "unsupported environment `{env}`")
+            }
+            MathError::UnbalancedBraces => write!(f, "unbalanced braces"),
+            MathError::MissingArgument(cmd) => write!(f, "missing argument for `\\{cmd}`"),
+            MathError::Unterminated(what) => write!(f, "unterminated `\\{what}`"),
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
+
+/// Parsed TeX math expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MathNode {
+    /// A single symbol such as a letter, digit or operator.
+    Glyph(char),
+    /// Upright text such as `\text{..}` or a function name like `sin`.
+    Text(String),
+    /// Horizontal space measured in cells.
+    Space(usize),
+    Row(Vec<MathNode>),
+    Frac(Box<MathNode>, Box<MathNode>),
+    Sqrt {
+        index: Option<Box<MathNode>>,
+        body: Box<MathNode>,
+    },
+    Scripts {
+        base: Box<MathNode>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    /// A large operator. With `limits` its scripts are stacked above and
+    /// below in display mode (`\sum`, `\lim`), otherwise placed to the right.
+    Operator {
+        symbol: String,
+        limits: bool,
+    },
+    Matrix(Vec<Vec<MathNode>>),
+    Fenced {
+        left: Option<char>,
+        right: Option<char>,
+        body: Box<MathNode>,
+    },
+}
+
+/// Where [`Parser::row`] stopped.
+#[derive(Debug, Clone, PartialEq)]
+enum Stop {
+    End,
+    Close,
+    Amp,
+    NewRow,
+    Right(Option<char>),
+    EndEnv(String),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(tex: &str) -> Self {
+        Self {
+            chars: tex.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Read a command name directly after a backslash.
+    fn command_name(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos > start {
+            return Some(self.chars[start..self.pos].iter().collect());
+        }
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c.to_string())
+    }
+
+    /// Read the raw contents of a `{..}` group, keeping whitespace.
+    fn raw_group(&mut self, cmd: &str) -> Result<String, MathError> {
+        self.skip_ws();
+        if self.peek() != Some('{') {
+            return Err(MathError::MissingArgument(cmd.to_string()));
+        }
+        self.pos += 1;
+        let mut depth = 1;
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(out);
+                    }
+                }
+                _ => {}
+            }
+            out.push(c);
+        }
+        Err(MathError::UnbalancedBraces)
+    }
+
+    /// Parse nodes until a terminator is reached.
+    fn row(&mut self) -> Result<(Vec<MathNode>, Stop), MathError> {
+        let mut nodes: Vec<MathNode> = Vec::new();
+        loop {
+            self.skip_ws();
+            let Some(c) = self.peek() else {
+                return Ok((nodes, Stop::End));
+            };
+            match c {
+                '}' => {
+                    self.pos += 1;
+                    return Ok((nodes, Stop::Close));
+                }
+                '&' => {
+                    self.pos += 1;
+                    return Ok((nodes, Stop::Amp));
+                }
+                '^' | '_' => {
+                    self.pos += 1;
+                    let script = self.atom("script")?;
+                    let base = nodes.pop().unwrap_or(MathNode::Row(Vec::new()));
+                    nodes.push(attach_script(base, script, c == '^'));
+                }
+                '\\' => {
+                    let save = self.pos;
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\\') => {
+                            self.pos += 1;
+                            return Ok((nodes, Stop::NewRow));
+                        }
+                        _ => {
+                            let name = self.command_name().unwrap_or_default();
+                            match name.as_str() {
+                                "right" => {
+                                    let delim = self.delimiter("right")?;
+                                    return Ok((nodes, Stop::Right(delim)));
+                                }
+                                "end" => {
+                                    let env = self.raw_group("end")?;
+                                    return Ok((nodes, Stop::EndEnv(env)));
+                                }
+                                _ => {
+                                    self.pos = save;
+                                    nodes.push(self.atom("")?);
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => nodes.push(self.atom("")?),
+            }
+        }
+    }
+
+    /// Parse a single argument: a group, a command or one character.
+    fn atom(&mut self, cmd: &str) -> Result<MathNode, MathError> {
+        self.skip_ws();
+        let Some(c) = self.peek() else {
+            return Err(MathError::MissingArgument(cmd.to_string()));
+        };
+        self.pos += 1;
+        match c {
+            '{' => {
+                let (nodes, stop) = self.row()?;
+                if stop != Stop::Close {
+                    return Err(MathError::UnbalancedBraces);
+                }
+                Ok(row_node(nodes))
+            }
+            '}' => Err(MathError::UnbalancedBraces),
+            '\\' => {
+                let name = self
+                    .command_name()
+                    .ok_or_else(|| MathError::MissingArgument(cmd.to_string()))?;
+                self.command(&name)
+            }
+            '\'' => Ok(MathNode::Glyph('′')),
+            '-' => Ok(MathNode::Glyph('−')),
+            '*' => Ok(MathNode::Glyph('∗')),
+            c => Ok(MathNode::Glyph(c)),
+        }
+    }
+
+    /// Read the delimiter following `\left` or `\right`. `.` means none.
+    fn delimiter(&mut self, cmd: &str) -> Result<Option<char>, MathError> {
+        self.skip_ws();
+        let c = self
+            .peek()
+            .ok_or_else(|| MathError::MissingArgument(cmd.to_string()))?;
+        self.pos += 1;
+        match c {
+            '.' => Ok(None),
+            '\\' => {
+                let name = self.command_name().unwrap_or_default();
+                match name.as_str() {
+                    "{" => Ok(Some('{')),
+                    "}" => Ok(Some('}')),
+                    "|" | "Vert" => Ok(Some('‖')),
+                    "vert" => Ok(Some('|')),
+                    "langle" => Ok(Some('⟨')),
+                    "rangle" => Ok(Some('⟩')),
+                    "lfloor" => Ok(Some('⌊')),
+                    "rfloor" => Ok(Some('⌋')),
+                    "lceil" => Ok(Some('⌈')),
+                    "rceil" => Ok(Some('⌉')),
+                    _ => Err(MathError::UnsupportedCommand(name)),
+                }
+            }
+            c => Ok(Some(c)),
+        }
+    }
+
+    fn command(&mut self, name: &str) -> Result<MathNode, MathError> {
+        if let Some(c) = symbol(name) {
+            return Ok(MathNode::Glyph(c));
+        }
+        if let Some((symbol, limits)) = operator(name) {
+            return Ok(MathNode::Operator {
+                symbol: symbol.to_string(),
+                limits,
+            });
+        }
+        match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let num = self.atom(name)?;
+                let den = self.atom(name)?;
+                Ok(MathNode::Frac(Box::new(num), Box::new(den)))
+            }
+            "binom" => {
+                let top = self.atom(name)?;
+                let bottom = self.atom(name)?;
+                Ok(MathNode::Fenced {
+                    left: Some('('),
+                    right: Some(')'),
+                    body: Box::new(MathNode::Matrix(vec![vec![top], vec![bottom]])),
+                })
+            }
+            "sqrt" => {
+                self.skip_ws();
+                let index = if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c != ']') {
+                        self.pos += 1;
+                    }
+                    if self.peek().is_none() {
+                        return Err(MathError::Unterminated("sqrt".into()));
+                    }
+                    let raw: String = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    Some(Box::new(parse(&raw)?))
+                } else {
+                    None
+                };
+                let body = self.atom(name)?;
+                Ok(MathNode::Sqrt {
+                    index,
+                    body: Box::new(body),
+                })
+            }
+            "text" | "textrm" | "mathrm" | "operatorname" | "textit" | "mbox" => {
+                Ok(MathNode::Text(self.raw_group(name)?))
+            }
+            "mathbf" | "mathit" | "boldsymbol" | "mathsf" | "mathtt" => self.atom(name),
+            "mathbb" => {
+                let raw = self.raw_group(name)?;
+                Ok(MathNode::Text(raw.chars().map(double_struck).collect()))
+            }
+            "left" => {
+                let left = self.delimiter(name)?;
+                let (nodes, stop) = self.row()?;
+                match stop {
+                    Stop::Right(right) => Ok(MathNode::Fenced {
+                        left,
+                        right,
+                        body: Box::new(row_node(nodes)),
+                    }),
+                    _ => Err(MathError::Unterminated("left".into())),
+                }
+            }
+            "begin" => {
+                let env = self.raw_group(name)?;
+                let (left, right) = match env.as_str() {
+                    "matrix" | "aligned" | "array" => (None, None),
+                    "pmatrix" => (Some('('), Some(')')),
+                    "bmatrix" => (Some('['), Some(']')),
+                    "Bmatrix" => (Some('{'), Some('}')),
+                    "vmatrix" => (Some('|'), Some('|')),
+                    "Vmatrix" => (Some('‖'), Some('‖')),
+                    "cases" => (Some('{'), None),
+                    _ => return Err(MathError::UnsupportedEnvironment(env)),
+                };
+                if env == "array" {
+                    // The column spec, such as `{c|cc}`, only aligns and
+                    // rules the columns and is not drawn.
+                    self.raw_group(&env)?;
+                }
+                let rows = self.matrix_rows(Some(&env))?;
+                let matrix = MathNode::Matrix(rows);
+                if left.is_none() && right.is_none() {
+                    Ok(matrix)
+                } else {
+                    Ok(MathNode::Fenced {
+                        left,
+                        right,
+                        body: Box::new(matrix),
+                    })
+                }
+            }
+            "," | ":" | ";" | " " => Ok(MathNode::Space(1)),
+            "!" => Ok(MathNode::Space(0)),
+            "quad" => Ok(MathNode::Space(2)),
+            "qquad" => Ok(MathNode::Space(4)),
+            "{" | "}" | "$" | "%" | "&" | "#" | "_" => {
+                Ok(MathNode::Glyph(name.chars().next().unwrap_or(' ')))
+            }
+            "|" => Ok(MathNode::Glyph('‖')),
+            _ => Err(MathError::UnsupportedCommand(name.to_string())),
+        }
+    }
+
+    /// Parse `&`/`\\` separated rows until `\end{env}`, or until the end of
+    /// input when `env` is `None`.
+    fn matrix_rows(&mut self, env: Option<&str>) -> Result<Vec<Vec<MathNode>>, MathError> {
+        let mut rows = Vec::new();
+        let mut current = Vec::new();
+        loop {
+            let (nodes, stop) = self.row()?;
+            current.push(row_node(nodes));
+            match stop {
+                Stop::Amp => {}
+                Stop::NewRow => rows.push(std::mem::take(&mut current)),
+                Stop::EndEnv(ref name) if Some(name.as_str()) == env => {
+                    // A trailing `\\` leaves an empty last row behind.
+                    let empty = current.len() == 1 && current[0] == MathNode::Row(Vec::new());
+                    if !empty {
+                        rows.push(current);
+                    }
+                    return Ok(rows);
+                }
+                Stop::End if env.is_none() => {
+                    rows.push(current);
+                    return Ok(rows);
+                }
+                Stop::Close => return Err(MathError::UnbalancedBraces),
+                _ => return Err(MathError::Unterminated("begin".into())),
+            }
+        }
+    }
+}
+
+fn row_node(mut nodes: Vec<MathNode>) -> MathNode {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        MathNode::Row(nodes)
+    }
+}
+
+fn attach_script(base: MathNode, script: MathNode, is_sup: bool) -> MathNode {
+    let (base, mut sub, mut sup) = match base {
+        MathNode::Scripts { base, sub, sup } => (base, sub, sup),
+        other => (Box::new(other), None, None),
+    };
+    if is_sup {
+        sup = Some(Box::new(script));
+    } else {
+        sub = Some(Box::new(script));
+    }
+    MathNode::Scripts { base, sub, sup }
+}
+
+fn symbol(name: &str) -> Option<char> {
+    Some(match name {
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        "cdot" => '·',
+        "times" => '×',
+        "div" => '÷',
+        "pm" => '±',
+        "mp" => '∓',
+        "ast" => '∗',
+        "circ" => '∘',
+        "leq" | "le" => '≤',
+        "geq" | "ge" => '≥',
+        "neq" | "ne" => '≠',
+        "approx" => '≈',
+        "equiv" => '≡',
+        "sim" => '∼',
+        "simeq" => '≃',
+        "propto" => '∝',
+        "ll" => '≪',
+        "gg" => '≫',
+        "infty" => '∞',
+        "partial" => '∂',
+        "nabla" => '∇',
+        "forall" => '∀',
+        "exists" => '∃',
+        "neg" | "lnot" => '¬',
+        "in" => '∈',
+        "notin" => '∉',
+        "ni" => '∋',
+        "subset" => '⊂',
+        "subseteq" => '⊆',
+        "supset" => '⊃',
+        "supseteq" => '⊇',
+        "cup" => '∪',
+        "cap" => '∩',
+        "emptyset" | "varnothing" => '∅',
+        "setminus" => '∖',
+        "wedge" | "land" => '∧',
+        "vee" | "lor" => '∨',
+        "oplus" => '⊕',
+        "otimes" => '⊗',
+        "to" | "rightarrow" => '→',
+        "leftarrow" | "gets" => '←',
+        "leftrightarrow" => '↔',
+        "Rightarrow" | "implies" => '⇒',
+        "Leftarrow" => '⇐',
+        "Leftrightarrow" | "iff" => '⇔',
+        "mapsto" => '↦',
+        "uparrow" => '↑',
+        "downarrow" => '↓',
+        "ldots" | "dots" => '…',
+        "cdots" => '⋯',
+        "vdots" => '⋮',
+        "ddots" => '⋱',
+        "prime" => '′',
+        "hbar" => 'ℏ',
+        "ell" => 'ℓ',
+        "Re" => 'ℜ',
+        "Im" => 'ℑ',
+        "aleph" => 'ℵ',
+        "angle" => '∠',
+        "perp" => '⊥',
+        "parallel" => '∥',
+        "mid" => '∣',
+        "langle" => '⟨',
+        "rangle" => '⟩',
+        "lfloor" => '⌊',
+        "rfloor" => '⌋',
+        "lceil" => '⌈',
+        "rceil" => '⌉',
+        "degree" => '°',
+        _ => return None,
+    })
+}
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "deg", "dim", "ker", "gcd", "arg", "Pr",
+];
+
+/// Large operators and function names as `(symbol, limits)`.
+fn operator(name: &str) -> Option<(&'static str, bool)> {
+    let big = match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        "lim" => ("lim", true),
+        "max" => ("max", true),
+        "min" => ("min", true),
+        "sup" => ("sup", true),
+        "inf" => ("inf", true),
+        "det" => ("det", true),
+        _ => return FUNCTIONS.iter().find(|f| **f == name).map(|f| (*f, false)),
+    };
+    Some(big)
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'R' => 'ℝ',
+        'N' => 'ℕ',
+        'Z' => 'ℤ',
+        'Q' => 'ℚ',
+        'C' => 'ℂ',
+        'P' => 'ℙ',
+        'H' => 'ℍ',
+        c => c,
+    }
+}
+
+fn superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '−' | '-' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        '′' => '′',
+        _ => return None,
+    })
+}
+
+fn subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '−' | '-' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    })
+}
+
+/// Convert a script to Unicode super- or subscript characters if every
+/// character has such a form.
+fn compact_script(node: &MathNode, map: fn(char) -> Option<char>) -> Option<String> {
+    match node {
+        MathNode::Glyph(c) => map(*c).map(String::from),
+        MathNode::Text(t) => t.chars().map(map).collect(),
+        MathNode::Row(nodes) => nodes.iter().map(|n| compact_script(n, map)).collect(),
+        _ => None,
+    }
+}
+
+fn is_binary(c: char) -> bool {
+    "+−=<>±∓×÷·≤≥≠≈≡∼≃∝≪≫∈∉∋⊂⊆⊃⊇∪∩∧∨⊕⊗→←↔⇒⇐⇔↦".contains(c)
+}
+
+/// Parse a TeX math expression.
+pub fn parse(tex: &str) -> Result<MathNode, MathError> {
+    let mut parser = Parser::new(tex);
+    let (nodes, stop) = parser.row()?;
+    match stop {
+        Stop::End => Ok(row_node(nodes)),
+        Stop::Close => Err(MathError::UnbalancedBraces),
+        Stop::Right(_) => Err(MathError::Unterminated("right".into())),
+        Stop::EndEnv(_) => Err(MathError::Unterminated("end".into())),
+        // Bare `&` and `\\` outside an environment lay out as a matrix.
+        Stop::Amp | Stop::NewRow => Ok(MathNode::Matrix(Parser::new(tex).matrix_rows(None)?)),
+    }
+}
+
+/// A single drawing instruction of a [`MathBox`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MathItem {
+    /// A glyph in the cell at `x`, `y`. `script` glyphs may be drawn smaller.
+    Glyph {
+        x: usize,
+        y: usize,
+        ch: char,
+        script: bool,
+    },
+    /// A horizontal rule such as a fraction bar or a radical's overline.
+    Rule { x: usize, y: usize, width: usize },
+}
+
+/// Laid out expression on a grid of character cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MathBox {
+    pub width: usize,
+    pub height: usize,
+    /// Row of the box that sits on the surrounding text baseline.
+    pub baseline: usize,
+    pub items: Vec<MathItem>,
+}
+
+impl MathBox {
+    fn empty(width: usize) -> Self {
+        Self {
+            width,
+            height: 1,
+            baseline: 0,
+            items: Vec::new(),
+        }
+    }
+
+    fn glyph(ch: char) -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            baseline: 0,
+            items: vec![MathItem::Glyph {
+                x: 0,
+                y: 0,
+                ch,
+                script: false,
+            }],
+        }
+    }
+
+    fn text(text: &str) -> Self {
+        let boxes = text.chars().map(Self::glyph).collect();
+        Self::hcat(boxes)
+    }
+
+    fn descent(&self) -> usize {
+        self.height - self.baseline - 1
+    }
+
+    fn blit(&mut self, other: &MathBox, dx: usize, dy: usize) {
+        for item in &other.items {
+            self.items.push(match *item {
+                MathItem::Glyph { x, y, ch, script } => MathItem::Glyph {
+                    x: x + dx,
+                    y: y + dy,
+                    ch,
+                    script,
+                },
+                MathItem::Rule { x, y, width } => MathItem::Rule {
+                    x: x + dx,
+                    y: y + dy,
+                    width,
+                },
+            });
+        }
+    }
+
+    fn into_script(mut self) -> Self {
+        for item in &mut self.items {
+            if let MathItem::Glyph { script, .. } = item {
+                *script = true;
+            }
+        }
+        self
+    }
+
+    fn hcat(boxes: Vec<MathBox>) -> Self {
+        let ascent = boxes.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let descent = boxes.iter().map(MathBox::descent).max().unwrap_or(0);
+        let mut out = MathBox {
+            width: 0,
+            height: ascent + 1 + descent,
+            baseline: ascent,
+            items: Vec::new(),
+        };
+        for b in &boxes {
+            out.blit(b, out.width, ascent - b.baseline);
+            out.width += b.width;
+        }
+        out
+    }
+
+    /// Rasterize the box into lines of text, drawing rules with `─`.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut grid = vec![vec![' '; self.width]; self.height];
+        for item in &self.items {
+            match *item {
+                MathItem::Glyph { x, y, ch, .. } => grid[y][x] = ch,
+                MathItem::Rule { x, y, width } => {
+                    for cell in &mut grid[y][x..x + width] {
+                        *cell = '─';
+                    }
+                }
+            }
+        }
+        grid.into_iter()
+            .map(|row| row.into_iter().collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    /// Draw the box as a standalone SVG element sized in `em`, one text
+    /// element per glyph. The SVG uses `currentColor` so it follows the
+    /// surrounding text colour.
+    pub fn to_svg(&self) -> String {
+        // Cell size in em, matching a monospace-like advance.
+        const CELL_W: f32 = 0.6;
+        const CELL_H: f32 = 1.2;
+        let (w, h) = (self.width as f32 * CELL_W, self.height as f32 * CELL_H);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.2}em\" height=\"{h:.2}em\" \
+             viewBox=\"0 0 {w:.2} {h:.2}\" style=\"vertical-align:-{:.2}em\" fill=\"currentColor\" \
+             stroke=\"currentColor\" font-size=\"1\" role=\"img\">",
+            (self.height - self.baseline - 1) as f32 * CELL_H + 0.25
+        );
+        for item in &self.items {
+            match *item {
+                MathItem::Glyph { ch: ' ', .. } => {}
+                MathItem::Glyph { x, y, ch, script } => {
+                    let size = if script { 0.7 } else { 1.0 };
+                    let ch = match ch {
+                        '&' => "&amp;".to_string(),
+                        '<' => "&lt;".to_string(),
+                        '>' => "&gt;".to_string(),
+                        c => c.to_string(),
+                    };
+                    out.push_str(&format!(
+                        "<text x=\"{:.2}\" y=\"{:.2}\" font-size=\"{size}\" stroke=\"none\" \
+                         text-anchor=\"middle\">{ch}</text>",
+                        (x as f32 + 0.5) * CELL_W,
+                        (y as f32 + 0.9) * CELL_H
+                    ));
+                }
+                MathItem::Rule { x, y, width } => {
+                    let mid = (y as f32 + 0.5) * CELL_H;
+                    out.push_str(&format!(
+                        "<line x1=\"{:.2}\" y1=\"{mid:.2}\" x2=\"{:.2}\" y2=\"{mid:.2}\" stroke-width=\"0.06\"/>",
+                        x as f32 * CELL_W,
+                        (x + width) as f32 * CELL_W
+                    ));
+                }
+            }
+        }
+        out.push_str("</svg>");
+        out
+    }
+}
+
+fn layout_row(nodes: &[MathNode], script: bool) -> MathBox {
+    let mut boxes = Vec::new();
+    let mut prev_operand = false;
+    for node in nodes {
+        match node {
+            // Pad binary operators and relations, but not a leading sign.
+            MathNode::Glyph(c) if is_binary(*c) && !script && prev_operand => {
+                boxes.push(MathBox::text(&format!(" {c} ")));
+                prev_operand = false;
+            }
+            _ => {
+                boxes.push(layout_node(node, script));
+                prev_operand = !matches!(node, MathNode::Glyph(c) if is_binary(*c) || *c == '(');
+            }
+        }
+    }
+    MathBox::hcat(boxes)
+}
+
+fn layout_node(node: &MathNode, script: bool) -> MathBox {
+    match node {
+        MathNode::Glyph(c) => MathBox::glyph(*c),
+        MathNode::Text(t) => MathBox::text(t),
+        MathNode::Space(n) => MathBox::empty(*n),
+        MathNode::Row(nodes) => layout_row(nodes, script),
+        MathNode::Operator { symbol, .. } => MathBox::text(symbol),
+        MathNode::Frac(num, den) if script => MathBox::text(&linear_frac(num, den)),
+        MathNode::Frac(num, den) => {
+            let num = layout_node(num, script);
+            let den = layout_node(den, script);
+            let width = num.width.max(den.width);
+            let mut out = MathBox {
+                width,
+                height: num.height + 1 + den.height,
+                baseline: num.height,
+                items: vec![MathItem::Rule {
+                    x: 0,
+                    y: num.height,
+                    width,
+                }],
+            };
+            out.blit(&num, (width - num.width) / 2, 0);
+            out.blit(&den, (width - den.width) / 2, num.height + 1);
+            out
+        }
+        MathNode::Sqrt { index, body } => {
+            let body = layout_node(body, script);
+            let prefix = index
+                .as_ref()
+                .map(|i| compact_script(i, superscript).unwrap_or_else(|| linear(i)))
+                .unwrap_or_default();
+            let px = prefix.chars().count();
+            let mut out = MathBox {
+                width: px + 1 + body.width,
+                height: body.height + 1,
+                baseline: body.baseline + 1,
+                items: vec![MathItem::Rule {
+                    x: px + 1,
+                    y: 0,
+                    width: body.width,
+                }],
+            };
+            let bottom = body.height;
+            out.blit(&MathBox::text(&prefix).into_script(), 0, bottom);
+            out.blit(&MathBox::glyph('√'), px, bottom);
+            for y in 1..bottom {
+                out.blit(&MathBox::glyph('│'), px, y);
+            }
+            out.blit(&body, px + 1, 1);
+            out
+        }
+        MathNode::Scripts { base, sub, sup } => layout_scripts(base, sub, sup, script),
+        MathNode::Matrix(rows) => layout_matrix(rows, script),
+        MathNode::Fenced { left, right, body } => {
+            let body = layout_node(body, script);
+            let mut parts = Vec::new();
+            if let Some(c) = left {
+                parts.push(delimiter_box(*c, body.height, body.baseline));
+            }
+            parts.push(body.clone());
+            if let Some(c) = right {
+                parts.push(delimiter_box(*c, body.height, body.baseline));
+            }
+            MathBox::hcat(parts)
+        }
+    }
+}
+
+fn layout_scripts(
+    base: &MathNode,
+    sub: &Option<Box<MathNode>>,
+    sup: &Option<Box<MathNode>>,
+    script: bool,
+) -> MathBox {
+    let limits = matches!(base, MathNode::Operator { limits: true, .. });
+    let base_box = layout_node(base, script);
+
+    let compact_sub = sub.as_ref().map(|s| compact_script(s, subscript));
+    let compact_sup = sup.as_ref().map(|s| compact_script(s, superscript));
+    let all_compact = compact_sub.as_ref().is_none_or(Option::is_some)
+        && compact_sup.as_ref().is_none_or(Option::is_some);
+    if !limits && all_compact {
+        let mut text = String::new();
+        text.extend(compact_sub.flatten());
+        text.extend(compact_sup.flatten());
+        return MathBox::hcat(vec![base_box, MathBox::text(&text)]);
+    }
+
+    let sub_box = sub.as_ref().map(|s| layout_node(s, true).into_script());
+    let sup_box = sup.as_ref().map(|s| layout_node(s, true).into_script());
+    let top = sup_box.as_ref().map_or(0, |b| b.height);
+    let sub_height = sub_box.as_ref().map_or(0, |b| b.height);
+    let mut out = MathBox {
+        width: 0,
+        height: top + base_box.height + sub_height,
+        baseline: top + base_box.baseline,
+        items: Vec::new(),
+    };
+    if limits && !script {
+        let width = [Some(&base_box), sub_box.as_ref(), sup_box.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|b| b.width)
+            .max()
+            .unwrap_or(0);
+        out.width = width;
+        if let Some(sup) = &sup_box {
+            out.blit(sup, (width - sup.width) / 2, 0);
+        }
+        out.blit(&base_box, (width - base_box.width) / 2, top);
+        if let Some(sub) = &sub_box {
+            out.blit(sub, (width - sub.width) / 2, top + base_box.height);
+        }
+    } else {
+        let script_width = [sub_box.as_ref(), sup_box.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|b| b.width)
+            .max()
+            .unwrap_or(0);
+        out.width = base_box.width + script_width;
+        if let Some(sup) = &sup_box {
+            out.blit(sup, base_box.width, 0);
+        }
+        out.blit(&base_box, 0, top);
+        if let Some(sub) = &sub_box {
+            out.blit(sub, base_box.width, top + base_box.height);
+        }
+    }
+    out
+}
+
+fn layout_matrix(rows: &[Vec<MathNode>], script: bool) -> MathBox {
+    const GAP: usize = 2;
+    let cells: Vec<Vec<MathBox>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| layout_node(c, script)).collect())
+        .collect();
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|j| {
+            cells
+                .iter()
+                .filter_map(|r| r.get(j))
+                .map(|b| b.width)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let width = widths.iter().sum::<usize>() + GAP * columns.saturating_sub(1);
+
+    let mut out = MathBox {
+        width,
+        height: 0,
+        baseline: 0,
+        items: Vec::new(),
+    };
+    for row in &cells {
+        let ascent = row.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let descent = row.iter().map(MathBox::descent).max().unwrap_or(0);
+        let mut x = 0;
+        for (j, cell) in row.iter().enumerate() {
+            let dx = x + (widths[j] - cell.width) / 2;
+            out.blit(cell, dx, out.height + ascent - cell.baseline);
+            x += widths[j] + GAP;
+        }
+        out.height += ascent + 1 + descent;
+    }
+    out.height = out.height.max(1);
+    out.baseline = (out.height - 1) / 2;
+    out
+}
+
+/// Build a delimiter of the given height, using the Unicode bracket pieces
+/// when it spans more than one row.
+fn delimiter_box(c: char, height: usize, baseline: usize) -> MathBox {
+    let (top, middle, bottom, centre) = match c {
+        '(' => ('⎛', '⎜', '⎝', None),
+        ')' => ('⎞', '⎟', '⎠', None),
+        '[' => ('⎡', '⎢', '⎣', None),
+        ']' => ('⎤', '⎥', '⎦', None),
+        '{' => ('⎧', '⎪', '⎩', Some('⎨')),
+        '}' => ('⎫', '⎪', '⎭', Some('⎬')),
+        '⌈' => ('⎡', '⎢', '⎢', None),
+        '⌉' => ('⎤', '⎥', '⎥', None),
+        '⌊' => ('⎢', '⎢', '⎣', None),
+        '⌋' => ('⎥', '⎥', '⎦', None),
+        '|' => ('│', '│', '│', None),
+        c => (c, c, c, None),
+    };
+    let mut out = MathBox {
+        width: 1,
+        height,
+        baseline,
+        items: Vec::new(),
+    };
+    for y in 0..height {
+        let ch = if height == 1 {
+            c
+        } else if y == 0 {
+            top
+        } else if y == height - 1 {
+            bottom
+        } else if y == (height - 1) / 2 {
+            centre.unwrap_or(middle)
+        } else {
+            middle
+        };
+        out.blit(&MathBox::glyph(ch), 0, y);
+    }
+    out
+}
+
+fn is_atomic(node: &MathNode) -> bool {
+    match node {
+        MathNode::Glyph(_) | MathNode::Text(_) | MathNode::Operator { .. } => true,
+        MathNode::Row(nodes) => nodes
+            .iter()
+            .all(|n| matches!(n, MathNode::Glyph(c) if c.is_alphanumeric())),
+        MathNode::Scripts { .. } | MathNode::Fenced { .. } => true,
+        _ => false,
+    }
+}
+
+fn wrapped(node: &MathNode) -> String {
+    if is_atomic(node) {
+        linear(node)
+    } else {
+        format!("({})", linear(node))
+    }
+}
+
+fn linear_frac(num: &MathNode, den: &MathNode) -> String {
+    format!("{}/{}", wrapped(num), wrapped(den))
+}
+
+/// Lay out an expression on a single line.
+fn linear(node: &MathNode) -> String {
+    match node {
+        MathNode::Glyph(c) => c.to_string(),
+        MathNode::Text(t) => t.clone(),
+        MathNode::Space(n) => " ".repeat(*n),
+        MathNode::Row(nodes) => {
+            let mut out = String::new();
+            let mut prev_operand = false;
+            for n in nodes {
+                match n {
+                    MathNode::Glyph(c) if is_binary(*c) && prev_operand => {
+                        out.push_str(&format!(" {c} "));
+                        prev_operand = false;
+                    }
+                    _ => {
+                        out.push_str(&linear(n));
+                        prev_operand =
+                            !matches!(n, MathNode::Glyph(c) if is_binary(*c) || *c == '(');
+                    }
+                }
+            }
+            out
+        }
+        MathNode::Frac(num, den) => linear_frac(num, den),
+        MathNode::Sqrt { index, body } => {
+            let prefix = index
+                .as_ref()
+                .map(|i| compact_script(i, superscript).unwrap_or_else(|| linear(i)))
+                .unwrap_or_default();
+            format!("{prefix}√{}", wrapped(body))
+        }
+        MathNode::Scripts { base, sub, sup } => {
+            let mut out = linear(base);
+            if let Some(sub) = sub {
+                match compact_script(sub, subscript) {
+                    Some(s) => out.push_str(&s),
+                    None => out.push_str(&format!("_{}", wrapped(sub))),
+                }
+            }
+            if let Some(sup) = sup {
+                match compact_script(sup, superscript) {
+                    Some(s) => out.push_str(&s),
+                    None => out.push_str(&format!("^{}", wrapped(sup))),
+                }
+            }
+            out
+        }
+        MathNode::Operator { symbol, .. } => symbol.clone(),
+        MathNode::Matrix(rows) => {
+            let rows: Vec<String> = rows
+                .iter()
+                .map(|r| r.iter().map(linear).collect::<Vec<_>>().join(" "))
+                .collect();
+            rows.join("; ")
+        }
+        MathNode::Fenced { left, right, body } => {
+            let mut out = String::new();
+            out.extend(*left);
+            out.push_str(&linear(body));
+            out.extend(*right);
+            out
+        }
+    }
+}
+
+/// Lay out `tex`. Display math (`$$..$$`) may span several rows, inline math
+/// (`$..$`) always stays on a single row so it can flow with text.
+pub fn layout(tex: &str, display: bool) -> Result<MathBox, MathError> {
+    let node = parse(tex)?;
+    if display {
+        Ok(layout_node(&node, false))
+    } else {
+        Ok(MathBox::text(&linear(&node)))
+    }
+}
+
+/// Render `tex` to lines of Unicode text.
+pub fn to_unicode(tex: &str, display: bool) -> Result<Vec<String>, MathError> {
+    Ok(layout(tex, display)?.to_lines())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_uses_unicode_scripts() {
+        assert_eq!(to_unicode(r"\pi r^2", false).unwrap(), vec!["πr²"]);
+        assert_eq!(to_unicode(r"x_1 + \alpha", false).unwrap(), vec!["x₁ + α"]);
+        assert_eq!(
+            to_unicode(r"\frac{a+b}{2}", false).unwrap(),
+            vec!["(a + b)/2"]
+        );
+    }
+
+    #[test]
+    fn svg_draws_glyphs_and_rules() {
+        let svg = layout(r"\frac{1}{x<y}", true).unwrap().to_svg();
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<text ").count(), 4);
+        assert_eq!(svg.matches("<line ").count(), 1);
+        assert!(svg.contains(">&lt;</text>"));
+    }
+
+    #[test]
+    fn display_fraction_stacks() {
+        let lines = to_unicode(r"\frac{a+b}{c}", true).unwrap();
+        assert_eq!(lines, vec!["a + b", "─────", "  c"]);
+    }
+
+    #[test]
+    fn display_sum_places_limits() {
+        let lines = to_unicode(r"\sum_{i=1}^{n} i", true).unwrap();
+        assert_eq!(lines, vec![" n", " ∑ i", "i=1"]);
+    }
+
+    #[test]
+    fn display_matrix_uses_tall_brackets() {
+        let lines = to_unicode(r"\begin{pmatrix} 1 & 0 \\ 0 & 1 \end{pmatrix}", true).unwrap();
+        assert_eq!(lines, vec!["⎛1  0⎞", "⎝0  1⎠"]);
+    }
+
+    #[test]
+    fn skips_the_column_spec_of_arrays() {
+        let lines = to_unicode(r"\begin{array}{c|c} 1 & 0 \\ 0 & 1 \end{array}", true).unwrap();
+        assert_eq!(lines, vec!["1  0", "0  1"]);
+        assert_eq!(
+            parse(r"\begin{array} 1 \end{array}").unwrap_err(),
+            MathError::MissingArgument("array".into())
+        );
+    }
+
+    #[test]
+    fn reports_unsupported_commands() {
+        let err = to_unicode(r"\foo{x}", true).unwrap_err();
+        assert_eq!(err, MathError::UnsupportedCommand("foo".into()));
+        assert_eq!(err.to_string(), "unsupported command `\\foo`");
+        assert_eq!(parse("{x").unwrap_err(), MathError::UnbalancedBraces);
+        assert_eq!(
+            parse(r"\begin{foo}x\end{foo}").unwrap_err(),
+            MathError::UnsupportedEnvironment("foo".into())
+        );
+    }
+}
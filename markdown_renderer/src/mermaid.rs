@@ -0,0 +1,395 @@
+//! Static SVG rendering of simple Mermaid flowcharts.
+//!
+//! Only `graph`/`flowchart` diagrams are understood: nodes with rectangle,
+//! round, circle and diamond shapes, and `-->`, `---`, `-.->` and `==>`
+//! edges with optional labels. Nodes are placed in ranks by their longest
+//! path from a source, which is enough for the small diagrams notes contain.
+
+use std::collections::HashMap;
+
+use crate::html::escape;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Rect,
+    Round,
+    Circle,
+    Diamond,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+    id: String,
+    label: String,
+    shape: Shape,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Edge {
+    from: usize,
+    to: usize,
+    label: Option<String>,
+    arrow: bool,
+    dashed: bool,
+}
+
+/// A parsed flowchart.
+#[derive(Debug, Default)]
+struct Flowchart {
+    horizontal: bool,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+const BRACKETS: [(&str, &str, Shape); 5] = [
+    ("((", "))", Shape::Circle),
+    ("([", "])", Shape::Round),
+    ("[", "]", Shape::Rect),
+    ("(", ")", Shape::Round),
+    ("{", "}", Shape::Diamond),
+];
+
+/// Edge operators, longest first so `-.->` is not read as `-`.
+const ARROWS: [(&str, bool, bool); 4] = [
+    ("-.->", true, true),
+    ("==>", true, false),
+    ("-->", true, false),
+    ("---", false, false),
+];
+
+impl Flowchart {
+    /// Index of the node `id`, declaring it with `label` and `shape` when
+    /// given.
+    fn node(&mut self, id: &str, shape: Option<(String, Shape)>) -> usize {
+        let i = match self.nodes.iter().position(|n| n.id == id) {
+            Some(i) => i,
+            None => {
+                self.nodes.push(Node {
+                    id: id.to_string(),
+                    label: id.to_string(),
+                    shape: Shape::Rect,
+                });
+                self.nodes.len() - 1
+            }
+        };
+        if let Some((label, shape)) = shape {
+            self.nodes[i].label = label;
+            self.nodes[i].shape = shape;
+        }
+        i
+    }
+
+    /// Parse a node reference such as `A`, `A[Label]` or `B{Choice?}` at
+    /// the start of `s`, returning its index and the rest of the input.
+    fn node_ref<'a>(&mut self, s: &'a str) -> Option<(usize, &'a str)> {
+        let s = s.trim_start();
+        let end = s
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(s.len());
+        if end == 0 {
+            return None;
+        }
+        let (id, rest) = s.split_at(end);
+        for (open, close, shape) in BRACKETS {
+            if let Some(inner) = rest.strip_prefix(open) {
+                let close_at = inner.find(close)?;
+                let label = inner[..close_at].trim().trim_matches('"').to_string();
+                let i = self.node(id, Some((label, shape)));
+                return Some((i, &inner[close_at + close.len()..]));
+            }
+        }
+        Some((self.node(id, None), rest))
+    }
+
+    fn statement(&mut self, line: &str) {
+        let Some((mut from, mut rest)) = self.node_ref(line) else {
+            return;
+        };
+        loop {
+            let trimmed = rest.trim_start();
+            // `A -- text --> B` carries the label before the arrow.
+            let (inline_label, trimmed) = match trimmed.strip_prefix("-- ") {
+                Some(after) => match after.find("-->") {
+                    Some(at) => (Some(after[..at].trim().to_string()), &after[at..]),
+                    None => (None, trimmed),
+                },
+                None => (None, trimmed),
+            };
+            let Some((op, arrow, dashed)) = ARROWS.iter().find(|(op, ..)| trimmed.starts_with(op))
+            else {
+                return;
+            };
+            let mut after = &trimmed[op.len()..];
+            let mut label = inline_label;
+            if let Some(piped) = after.strip_prefix('|')
+                && let Some(end) = piped.find('|')
+            {
+                label = Some(piped[..end].trim().to_string());
+                after = &piped[end + 1..];
+            }
+            let Some((to, next)) = self.node_ref(after) else {
+                return;
+            };
+            self.edges.push(Edge {
+                from,
+                to,
+                label,
+                arrow: *arrow,
+                dashed: *dashed,
+            });
+            from = to;
+            rest = next;
+        }
+    }
+
+    fn parse(src: &str) -> Option<Self> {
+        let mut lines = src
+            .lines()
+            .flat_map(|l| l.split(';'))
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with("%%"));
+        let mut header = lines.next()?.split_whitespace();
+        if !matches!(header.next()?, "graph" | "flowchart") {
+            return None;
+        }
+        let mut chart = Flowchart {
+            horizontal: matches!(header.next(), Some("LR" | "RL")),
+            ..Flowchart::default()
+        };
+        for line in lines {
+            let keyword = line.split_whitespace().next().unwrap_or_default();
+            if matches!(
+                keyword,
+                "subgraph"
+                    | "end"
+                    | "style"
+                    | "classDef"
+                    | "class"
+                    | "linkStyle"
+                    | "click"
+                    | "direction"
+            ) {
+                continue;
+            }
+            chart.statement(line);
+        }
+        Some(chart)
+    }
+
+    /// Edges that close a cycle, found by a depth-first search that starts
+    /// from the nodes in declaration order.
+    fn back_edges(&self) -> Vec<bool> {
+        fn visit(chart: &Flowchart, node: usize, state: &mut [u8], back: &mut [bool]) {
+            state[node] = 1;
+            for (i, e) in chart.edges.iter().enumerate() {
+                if e.from != node {
+                    continue;
+                }
+                match state[e.to] {
+                    0 => visit(chart, e.to, state, back),
+                    1 => back[i] = true,
+                    _ => {}
+                }
+            }
+            state[node] = 2;
+        }
+        let mut state = vec![0; self.nodes.len()];
+        let mut back = vec![false; self.edges.len()];
+        for node in 0..self.nodes.len() {
+            if state[node] == 0 {
+                visit(self, node, &mut state, &mut back);
+            }
+        }
+        back
+    }
+
+    /// Rank of every node: the longest path from a source, ignoring edges
+    /// that close a cycle.
+    fn ranks(&self) -> Vec<usize> {
+        let back = self.back_edges();
+        let mut rank = vec![0; self.nodes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (e, _) in self.edges.iter().zip(&back).filter(|(_, b)| !**b) {
+                if rank[e.to] < rank[e.from] + 1 {
+                    rank[e.to] = rank[e.from] + 1;
+                    changed = true;
+                }
+            }
+        }
+        rank
+    }
+}
+
+const CHAR_W: f32 = 8.0;
+const NODE_H: f32 = 40.0;
+const GAP: f32 = 50.0;
+
+/// Render a Mermaid flowchart to SVG. Returns `None` for other diagram
+/// types and for input without nodes.
+pub fn to_svg(src: &str) -> Option<String> {
+    let chart = Flowchart::parse(src)?;
+    if chart.nodes.is_empty() {
+        return None;
+    }
+    let ranks = chart.ranks();
+    let width = |n: &Node| {
+        let w = n.label.chars().count() as f32 * CHAR_W + 32.0;
+        if n.shape == Shape::Diamond {
+            w * 1.4
+        } else {
+            w
+        }
+    };
+    // Column of each node inside its rank, in order of first appearance.
+    let mut slots: HashMap<usize, usize> = HashMap::new();
+    let slot: Vec<usize> = ranks
+        .iter()
+        .map(|r| {
+            let s = slots.entry(*r).or_default();
+            *s += 1;
+            *s - 1
+        })
+        .collect();
+    let cell_w = chart.nodes.iter().map(width).fold(0.0, f32::max) + GAP;
+    let cell_h = NODE_H + GAP;
+    let center = |i: usize| {
+        let (along, across) = (ranks[i] as f32, slot[i] as f32);
+        if chart.horizontal {
+            (
+                along * cell_w + cell_w / 2.0,
+                across * cell_h + cell_h / 2.0,
+            )
+        } else {
+            (
+                across * cell_w + cell_w / 2.0,
+                along * cell_h + cell_h / 2.0,
+            )
+        }
+    };
+    let max_rank = ranks.iter().max().copied().unwrap_or(0) as f32 + 1.0;
+    let max_slot = slots.values().max().copied().unwrap_or(1) as f32;
+    let (w, h) = if chart.horizontal {
+        (max_rank * cell_w, max_slot * cell_h)
+    } else {
+        (max_slot * cell_w, max_rank * cell_h)
+    };
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" class=\"mermaid\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"14\">\
+         <defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" \
+         markerHeight=\"8\" orient=\"auto\"><path d=\"M0,0L10,5L0,10z\" fill=\"currentColor\"/></marker></defs>"
+    );
+    for e in &chart.edges {
+        let (x1, y1) = center(e.from);
+        let (x2, y2) = center(e.to);
+        // Stop at the target's border so the arrow head stays visible.
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let len = (dx * dx + dy * dy).sqrt().max(1.0);
+        let half = if chart.horizontal {
+            width(&chart.nodes[e.to]) / 2.0
+        } else {
+            NODE_H / 2.0
+        };
+        let shorten = (half / (if chart.horizontal { dx.abs() } else { dy.abs() } / len).max(0.3))
+            .min(len / 2.0);
+        let (x2, y2) = (x2 - dx / len * shorten, y2 - dy / len * shorten);
+        out.push_str(&format!(
+            "<line x1=\"{x1:.1}\" y1=\"{y1:.1}\" x2=\"{x2:.1}\" y2=\"{y2:.1}\" stroke=\"currentColor\"{}{}/>",
+            if e.dashed { " stroke-dasharray=\"4 3\"" } else { "" },
+            if e.arrow { " marker-end=\"url(#arrow)\"" } else { "" },
+        ));
+        if let Some(label) = &e.label {
+            out.push_str(&format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"currentColor\">{}</text>",
+                (x1 + x2) / 2.0,
+                (y1 + y2) / 2.0 - 4.0,
+                escape(label)
+            ));
+        }
+    }
+    for (i, node) in chart.nodes.iter().enumerate() {
+        let (cx, cy) = center(i);
+        let (nw, nh) = (width(node), NODE_H);
+        let style = "fill=\"none\" stroke=\"currentColor\"";
+        out.push_str(&match node.shape {
+            Shape::Rect => format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{nw:.1}\" height=\"{nh}\" {style}/>",
+                cx - nw / 2.0,
+                cy - nh / 2.0
+            ),
+            Shape::Round => format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{nw:.1}\" height=\"{nh}\" rx=\"{}\" {style}/>",
+                cx - nw / 2.0,
+                cy - nh / 2.0,
+                nh / 2.0
+            ),
+            Shape::Circle => format!(
+                "<ellipse cx=\"{cx:.1}\" cy=\"{cy:.1}\" rx=\"{:.1}\" ry=\"{}\" {style}/>",
+                nw / 2.0,
+                nh / 2.0
+            ),
+            Shape::Diamond => format!(
+                "<polygon points=\"{:.1},{cy:.1} {cx:.1},{:.1} {:.1},{cy:.1} {cx:.1},{:.1}\" {style}/>",
+                cx - nw / 2.0,
+                cy - nh / 2.0 - 6.0,
+                cx + nw / 2.0,
+                cy + nh / 2.0 + 6.0
+            ),
+        });
+        out.push_str(&format!(
+            "<text x=\"{cx:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"currentColor\">{}</text>",
+            cy + 5.0,
+            escape(&node.label)
+        ));
+    }
+    out.push_str("</svg>");
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nodes_and_edges() {
+        let chart = Flowchart::parse(
+            "graph LR\n  A[Start] --> B{Ok?}\n  B -->|yes| C((Done)); B -- no --> A\n  %% comment",
+        )
+        .unwrap();
+        assert!(chart.horizontal);
+        let labels: Vec<_> = chart
+            .nodes
+            .iter()
+            .map(|n| (n.label.as_str(), n.shape))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("Start", Shape::Rect),
+                ("Ok?", Shape::Diamond),
+                ("Done", Shape::Circle)
+            ]
+        );
+        let edges: Vec<_> = chart
+            .edges
+            .iter()
+            .map(|e| (e.from, e.to, e.label.as_deref()))
+            .collect();
+        assert_eq!(
+            edges,
+            [(0, 1, None), (1, 2, Some("yes")), (1, 0, Some("no"))]
+        );
+        assert_eq!(chart.ranks(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn renders_flowcharts_only() {
+        let svg = to_svg("flowchart TD\nA --> B --> C").unwrap();
+        assert_eq!(svg.matches("<rect ").count(), 3);
+        assert_eq!(svg.matches("<line ").count(), 2);
+        assert!(to_svg("sequenceDiagram\nA->>B: hi").is_none());
+    }
+}
@@ -0,0 +1,358 @@
+//! Image assets referenced from notes.
+//!
+//! Image paths are resolved relative to the note first and then to the vault
+//! root; images outside of the vault are never loaded. Decoded images are
+//! scaled down to thumbnails, cached under `<vault>/.notes2/thumbnails`.
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use markdown_renderer::links::percent_decode;
+use sha1::{Digest, Sha1};
+
+/// Errors produced while resolving or decoding an image.
+#[derive(Debug)]
+pub enum AssetError {
+    /// No file exists at any of the candidate locations.
+    Missing(PathBuf),
+    /// The URL points to a remote resource, which is never fetched.
+    Remote(String),
+    /// The file is outside of the vault, for example through `../` or a
+    /// symbolic link, and is not loaded.
+    Outside(PathBuf),
+    /// The file exists but could not be decoded.
+    Decode {
+        path: PathBuf,
+        message: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Missing(path) => write!(f, "image not found: {}", path.display()),
+            AssetError::Remote(url) => write!(f, "remote images are not loaded: {url}"),
+            AssetError::Outside(path) => {
+                write!(f, "image outside of the vault: {}", path.display())
+            }
+            AssetError::Decode { path, message } => {
+                write!(f, "cannot decode {}: {message}", path.display())
+            }
+            AssetError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl From<io::Error> for AssetError {
+    fn from(e: io::Error) -> Self {
+        AssetError::Io(e)
+    }
+}
+
+/// A decoded image scaled to fit the cache's maximum dimension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    /// Straight (not premultiplied) RGBA pixels, row by row.
+    pub rgba: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Encode the thumbnail as PNG.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut out = io::Cursor::new(Vec::new());
+        let _ = image::RgbaImage::from_raw(self.width, self.height, self.rgba.clone())
+            .map(|img| img.write_to(&mut out, image::ImageFormat::Png));
+        out.into_inner()
+    }
+
+    /// RGBA value of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [
+            self.rgba[i],
+            self.rgba[i + 1],
+            self.rgba[i + 2],
+            self.rgba[i + 3],
+        ]
+    }
+}
+
+/// Resolve an image `url` from a note at `note` inside `vault`.
+///
+/// Relative paths are looked up next to the note and then from the vault
+/// root, paths starting with `/` from the vault root and then as absolute
+/// paths. Files that are not inside the vault once symbolic links and `..`
+/// are resolved are refused with [`AssetError::Outside`].
+pub fn resolve(url: &str, note: Option<&Path>, vault: &Path) -> Result<PathBuf, AssetError> {
+    let url = url.trim();
+    let url = url.strip_prefix("file://").unwrap_or(url);
+    if url.contains("://") {
+        return Err(AssetError::Remote(url.to_string()));
+    }
+    let decoded = percent_decode(url);
+    let candidates: Vec<PathBuf> = match decoded.strip_prefix('/') {
+        Some(rooted) => vec![vault.join(rooted), PathBuf::from(&decoded)],
+        None => note
+            .and_then(Path::parent)
+            .map(|dir| dir.join(&decoded))
+            .into_iter()
+            .chain([vault.join(&decoded)])
+            .collect(),
+    };
+    let root = vault.canonicalize()?;
+    let mut outside = None;
+    for candidate in &candidates {
+        match candidate.canonicalize() {
+            Ok(real) if real.is_file() && real.starts_with(&root) => return Ok(candidate.clone()),
+            Ok(real) if real.is_file() => {
+                outside.get_or_insert(real);
+            }
+            _ => {}
+        }
+    }
+    Err(match outside {
+        Some(path) => AssetError::Outside(path),
+        None => AssetError::Missing(candidates[0].clone()),
+    })
+}
+
+fn decode_error(path: &Path, e: impl fmt::Display) -> AssetError {
+    AssetError::Decode {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    }
+}
+
+/// Decode the PNG, JPEG, GIF (first frame) or SVG image at `path` and scale
+/// it so neither side exceeds `max_dim`.
+pub fn decode(path: &Path, max_dim: u32) -> Result<Thumbnail, AssetError> {
+    let is_svg = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return decode_svg(path, max_dim);
+    }
+    let img = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .decode()
+        .map_err(|e| decode_error(path, e))?;
+    let img = if img.width() > max_dim || img.height() > max_dim {
+        img.thumbnail(max_dim, max_dim)
+    } else {
+        img
+    };
+    let rgba = img.to_rgba8();
+    Ok(Thumbnail {
+        width: rgba.width(),
+        height: rgba.height(),
+        rgba: rgba.into_raw(),
+    })
+}
+
+fn decode_svg(path: &Path, max_dim: u32) -> Result<Thumbnail, AssetError> {
+    use resvg::{tiny_skia, usvg};
+
+    let data = fs::read(path)?;
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+        .map_err(|e| decode_error(path, e))?;
+    let size = tree.size();
+    let scale = (max_dim as f32 / size.width().max(size.height())).min(1.0);
+    let width = ((size.width() * scale).ceil() as u32).max(1);
+    let height = ((size.height() * scale).ceil() as u32).max(1);
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or_else(|| decode_error(path, "empty image"))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    let rgba = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    Ok(Thumbnail {
+        width,
+        height,
+        rgba,
+    })
+}
+
+/// Thumbnail cache keyed by path and modification time.
+pub struct AssetCache {
+    dir: Option<PathBuf>,
+    max_dim: u32,
+    memory: HashMap<PathBuf, (SystemTime, Arc<Thumbnail>)>,
+}
+
+impl AssetCache {
+    /// Cache thumbnails of at most `max_dim` pixels in memory and under the
+    /// vault's cache directory.
+    pub fn new(vault: &Path, max_dim: u32) -> Self {
+        Self {
+            dir: Some(vault.join(".notes2").join("thumbnails")),
+            max_dim,
+            memory: HashMap::new(),
+        }
+    }
+
+    /// Cache thumbnails in memory only.
+    pub fn in_memory(max_dim: u32) -> Self {
+        Self {
+            dir: None,
+            max_dim,
+            memory: HashMap::new(),
+        }
+    }
+
+    fn disk_path(&self, path: &Path, mtime: SystemTime) -> Option<PathBuf> {
+        // A stable hash, so the cache keeps matching across builds.
+        let since = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut hasher = Sha1::new();
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(since.as_secs().to_le_bytes());
+        hasher.update(since.subsec_nanos().to_le_bytes());
+        hasher.update(self.max_dim.to_le_bytes());
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Some(self.dir.as_ref()?.join(format!("{hex}.png")))
+    }
+
+    /// Load the thumbnail for the image at `path`, decoding it only when
+    /// neither the memory nor the disk cache holds a current copy.
+    pub fn load(&mut self, path: &Path) -> Result<Arc<Thumbnail>, AssetError> {
+        let mtime = fs::metadata(path)
+            .map_err(|_| AssetError::Missing(path.to_path_buf()))?
+            .modified()?;
+        if let Some((cached, thumb)) = self.memory.get(path) {
+            if *cached == mtime {
+                return Ok(thumb.clone());
+            }
+        }
+        let disk = self.disk_path(path, mtime);
+        let thumb = match disk.as_deref().filter(|p| p.is_file()) {
+            Some(cached) => decode(cached, self.max_dim)?,
+            None => {
+                let thumb = decode(path, self.max_dim)?;
+                if let Some(disk) = &disk {
+                    // The disk cache is an optimisation only, failures are ignored.
+                    let _ = fs::create_dir_all(disk.parent().unwrap_or(disk))
+                        .and_then(|_| fs::write(disk, thumb.to_png()));
+                }
+                thumb
+            }
+        };
+        let thumb = Arc::new(thumb);
+        self.memory
+            .insert(path.to_path_buf(), (mtime, thumb.clone()));
+        Ok(thumb)
+    }
+
+    /// Resolve `url` like [`resolve`] and load its thumbnail.
+    pub fn load_url(
+        &mut self,
+        url: &str,
+        note: Option<&Path>,
+        vault: &Path,
+    ) -> Result<Arc<Thumbnail>, AssetError> {
+        let path = resolve(url, note, vault)?;
+        self.load(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_next_to_note_then_vault() {
+        let vault = tempfile::tempdir().unwrap();
+        let notes = vault.path().join("notes");
+        fs::create_dir_all(&notes).unwrap();
+        fs::write(notes.join("a b.png"), b"").unwrap();
+        fs::write(vault.path().join("shared.png"), b"").unwrap();
+        let note = notes.join("note.md");
+
+        let found = resolve("a%20b.png", Some(&note), vault.path()).unwrap();
+        assert_eq!(found, notes.join("a b.png"));
+        let found = resolve("shared.png", Some(&note), vault.path()).unwrap();
+        assert_eq!(found, vault.path().join("shared.png"));
+        let found = resolve("/notes/a b.png", None, vault.path()).unwrap();
+        assert_eq!(found, notes.join("a b.png"));
+        assert!(matches!(
+            resolve("gone.png", Some(&note), vault.path()),
+            Err(AssetError::Missing(p)) if p == notes.join("gone.png")
+        ));
+        assert!(matches!(
+            resolve("https://example.com/x.png", None, vault.path()),
+            Err(AssetError::Remote(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_images_outside_of_the_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        fs::create_dir_all(vault.join("notes")).unwrap();
+        fs::write(dir.path().join("secret.png"), b"").unwrap();
+        let secret = dir.path().join("secret.png").canonicalize().unwrap();
+        let note = vault.join("notes/note.md");
+
+        for url in [
+            "../../secret.png".to_string(),
+            secret.display().to_string(),
+            format!("file://{}", secret.display()),
+        ] {
+            assert!(
+                matches!(
+                    resolve(&url, Some(&note), &vault),
+                    Err(AssetError::Outside(_))
+                ),
+                "{url}"
+            );
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&secret, vault.join("link.png")).unwrap();
+            assert!(matches!(
+                resolve("link.png", Some(&note), &vault),
+                Err(AssetError::Outside(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn caches_scaled_thumbnails() {
+        let vault = tempfile::tempdir().unwrap();
+        let path = vault.path().join("img.png");
+        image::RgbaImage::from_pixel(64, 32, image::Rgba([255, 0, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let mut cache = AssetCache::new(vault.path(), 16);
+        let thumb = cache.load(&path).unwrap();
+        assert_eq!((thumb.width, thumb.height), (16, 8));
+        assert_eq!(thumb.pixel(0, 0), [255, 0, 0, 255]);
+        let cached = fs::read_dir(vault.path().join(".notes2/thumbnails"))
+            .unwrap()
+            .count();
+        assert_eq!(cached, 1);
+        assert!(Arc::ptr_eq(&thumb, &cache.load(&path).unwrap()));
+    }
+}
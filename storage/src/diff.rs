@@ -0,0 +1,204 @@
+//! Line based differences between two texts, and merging two texts
+//! changed from a common one.
+
+/// A line of the old or the new text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    /// In both texts.
+    Same(&'a str),
+    /// Only in the old text.
+    Removed(&'a str),
+    /// Only in the new text.
+    Added(&'a str),
+}
+
+/// Pairs of lines compared at most before giving up on finding common
+/// lines in the changed part.
+const MAX_WORK: usize = 16_000_000;
+
+/// Lines of `old` and `new` in order, telling which of them changed. Lines
+/// kept in both texts are found with a longest common subsequence, so a
+/// change shows up as few removed and added lines as possible.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut changes: Vec<Change> = old[..prefix].iter().map(|l| Change::Same(l)).collect();
+    if a.len() * b.len() > MAX_WORK {
+        changes.extend(a.iter().map(|l| Change::Removed(l)));
+        changes.extend(b.iter().map(|l| Change::Added(l)));
+    } else {
+        // common[i][j]: length of the longest common subsequence of a[i..]
+        // and b[j..].
+        let width = b.len() + 1;
+        let mut common = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i * width + j] = if a[i] == b[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                changes.push(Change::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                || (i < a.len() && common[(i + 1) * width + j] >= common[i * width + j + 1])
+            {
+                changes.push(Change::Removed(a[i]));
+                i += 1;
+            } else {
+                changes.push(Change::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    changes.extend(old[old.len() - suffix..].iter().map(|l| Change::Same(l)));
+    changes
+}
+
+/// Two texts merged line by line, see [`merge`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    pub lines: Vec<String>,
+    /// Number of places both sides changed differently. They are kept
+    /// between git-style conflict markers.
+    pub conflicts: usize,
+}
+
+/// Merge the changes `ours` and `theirs` made to `base`. Lines changed on
+/// one side only take that side's version; lines both sides changed
+/// differently become a conflict between `<<<<<<< ours`, `=======` and
+/// `>>>>>>> theirs` markers.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Merge {
+    let old: Vec<&str> = base.lines().collect();
+    let a: Vec<&str> = ours.lines().collect();
+    let b: Vec<&str> = theirs.lines().collect();
+    let in_a = matches(base, ours);
+    let in_b = matches(base, theirs);
+    let mut merge = Merge {
+        lines: Vec::new(),
+        conflicts: 0,
+    };
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        if i < old.len() && in_a[i] == Some(j) && in_b[i] == Some(k) {
+            merge.lines.push(old[i].to_string());
+            (i, j, k) = (i + 1, j + 1, k + 1);
+            continue;
+        }
+        // The next line of `base` both sides kept ends the changed part.
+        let next = (i..old.len()).find_map(|n| Some((n, in_a[n]?, in_b[n]?)));
+        let (n, m, l) = next.unwrap_or((old.len(), a.len(), b.len()));
+        let (old_part, a_part, b_part) = (&old[i..n], &a[j..m], &b[k..l]);
+        if a_part == old_part || a_part == b_part {
+            merge.lines.extend(b_part.iter().map(|l| l.to_string()));
+        } else if b_part == old_part {
+            merge.lines.extend(a_part.iter().map(|l| l.to_string()));
+        } else {
+            merge.conflicts += 1;
+            merge.lines.push("<<<<<<< ours".into());
+            merge.lines.extend(a_part.iter().map(|l| l.to_string()));
+            merge.lines.push("=======".into());
+            merge.lines.extend(b_part.iter().map(|l| l.to_string()));
+            merge.lines.push(">>>>>>> theirs".into());
+        }
+        if next.is_none() {
+            return merge;
+        }
+        (i, j, k) = (n, m, l);
+    }
+}
+
+/// For each line of `old`, the index of the same line in `new` when it was
+/// kept.
+fn matches(old: &str, new: &str) -> Vec<Option<usize>> {
+    let mut found = Vec::new();
+    let mut j = 0;
+    for change in lines(old, new) {
+        match change {
+            Change::Same(_) => {
+                found.push(Some(j));
+                j += 1;
+            }
+            Change::Removed(_) => found.push(None),
+            Change::Added(_) => j += 1,
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_changed_lines() {
+        let old = "title\none\ntwo\nthree\nend\n";
+        let new = "title\none\n2\nthree\nfour\nend\n";
+        assert_eq!(
+            lines(old, new),
+            [
+                Change::Same("title"),
+                Change::Same("one"),
+                Change::Removed("two"),
+                Change::Added("2"),
+                Change::Same("three"),
+                Change::Added("four"),
+                Change::Same("end"),
+            ]
+        );
+        assert_eq!(lines("", "a"), [Change::Added("a")]);
+        assert_eq!(
+            lines("a\nb", "b\na"),
+            [Change::Removed("a"), Change::Same("b"), Change::Added("a")]
+        );
+    }
+
+    #[test]
+    fn merges_changes_of_both_sides() {
+        let base = "title\none\ntwo\nthree\nend";
+        let ours = "title\nONE\ntwo\nthree\nend\nmore";
+        let theirs = "intro\ntitle\none\ntwo\n3\nend";
+        let merged = merge(base, ours, theirs);
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(
+            merged.lines,
+            ["intro", "title", "ONE", "two", "3", "end", "more"]
+        );
+
+        let ours = "title\none\n2\nthree\nend";
+        let theirs = "new title\none\nzwei\nthree\nend";
+        let merged = merge(base, ours, theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.lines,
+            [
+                "new title",
+                "one",
+                "<<<<<<< ours",
+                "2",
+                "=======",
+                "zwei",
+                ">>>>>>> theirs",
+                "three",
+                "end"
+            ]
+        );
+        assert_eq!(merge(base, base, base).lines.join("\n"), base);
+    }
+}
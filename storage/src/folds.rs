@@ -0,0 +1,175 @@
+//! Remembered open/closed state of collapsible blocks, per note.
+//!
+//! States live in `<vault>/.notes2/folds`, one `note<TAB>key<TAB>0|1` line
+//! per block whose state differs from the document default.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::note_file;
+
+/// Key identifying a collapsible block inside a note. Blocks are told apart
+/// by their summary and how many blocks with the same summary precede them,
+/// which survives edits elsewhere in the note.
+pub fn fold_key(summary: &str, occurrence: usize) -> String {
+    let summary: String = summary
+        .chars()
+        .map(|c| if c == '\t' || c == '\n' { ' ' } else { c })
+        .collect();
+    format!("{summary}#{occurrence}")
+}
+
+/// Keys of the collapsible blocks of a note, in document order. Counts the
+/// summaries met so far, so blocks sharing a summary get distinct keys.
+#[derive(Debug, Default)]
+pub struct FoldKeys(Vec<String>);
+
+impl FoldKeys {
+    /// Key of the next block with `summary`, inside the block whose key
+    /// followed by `/` is `parent`, or at the top level with an empty one.
+    pub fn next(&mut self, parent: &str, summary: &str) -> String {
+        let base = format!("{parent}{}", fold_key(summary, 0));
+        let n = self.0.iter().filter(|k| **k == base).count();
+        self.0.push(base);
+        format!("{parent}{}", fold_key(summary, n))
+    }
+}
+
+/// Fold states of all notes in a vault.
+#[derive(Debug, Default)]
+pub struct FoldStore {
+    file: Option<PathBuf>,
+    states: HashMap<PathBuf, HashMap<String, bool>>,
+}
+
+impl FoldStore {
+    /// Load the fold states of the vault at `vault`. A missing file yields an
+    /// empty store.
+    pub fn open(vault: &Path) -> io::Result<Self> {
+        let file = vault.join(".notes2").join("folds");
+        let mut store = Self {
+            file: Some(file.clone()),
+            states: HashMap::new(),
+        };
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(e),
+        };
+        for line in content.lines() {
+            let mut parts = line.splitn(3, '\t');
+            if let (Some(note), Some(key), Some(state)) = (parts.next(), parts.next(), parts.next())
+            {
+                store
+                    .states
+                    .entry(PathBuf::from(note))
+                    .or_default()
+                    .insert(key.to_string(), state == "1");
+            }
+        }
+        Ok(store)
+    }
+
+    /// A store that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Remembered state of the block `key` in `note`, if any.
+    pub fn get(&self, note: &Path, key: &str) -> Option<bool> {
+        self.states.get(note)?.get(key).copied()
+    }
+
+    /// Remember `open` for the block `key` in `note`. A state equal to the
+    /// block's `default` is forgotten instead.
+    pub fn set(&mut self, note: &Path, key: &str, open: bool, default: bool) {
+        if open != default {
+            self.states
+                .entry(note.to_path_buf())
+                .or_default()
+                .insert(key.to_string(), open);
+            return;
+        }
+        if let Some(keys) = self.states.get_mut(note) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.states.remove(note);
+            }
+        }
+    }
+
+    /// Flip the block `key` in `note`, starting from `default` when it has
+    /// no remembered state. Returns the new state.
+    pub fn toggle(&mut self, note: &Path, key: &str, default: bool) -> bool {
+        let open = !self.get(note, key).unwrap_or(default);
+        self.set(note, key, open, default);
+        open
+    }
+
+    /// Write all states back to the vault.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut notes: Vec<_> = self.states.iter().collect();
+        notes.sort_by(|a, b| a.0.cmp(b.0));
+        let mut out = String::new();
+        for (note, keys) in notes {
+            let mut keys: Vec<_> = keys.iter().collect();
+            keys.sort();
+            for (key, open) in keys {
+                out.push_str(&format!("{}\t{key}\t{}\n", note.display(), u8::from(*open)));
+            }
+        }
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        note_file::replace(file, &out).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persists_states_per_note() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut store = FoldStore::open(vault.path()).unwrap();
+        let key = fold_key("Setup\tsteps", 1);
+        assert_eq!(key, "Setup steps#1");
+        assert!(store.toggle(Path::new("a.md"), &key, false));
+        assert!(!store.toggle(Path::new("b.md"), &key, true));
+        store.save().unwrap();
+
+        let store = FoldStore::open(vault.path()).unwrap();
+        assert_eq!(store.get(Path::new("a.md"), &key), Some(true));
+        assert_eq!(store.get(Path::new("b.md"), &key), Some(false));
+        assert_eq!(store.get(Path::new("c.md"), &key), None);
+    }
+
+    #[test]
+    fn forgets_states_equal_to_the_default() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut store = FoldStore::open(vault.path()).unwrap();
+        let note = Path::new("a.md");
+        assert!(store.toggle(note, "Steps#0", false));
+        assert!(!store.toggle(note, "Steps#0", false));
+        assert_eq!(store.get(note, "Steps#0"), None);
+        store.save().unwrap();
+
+        let content = fs::read_to_string(vault.path().join(".notes2").join("folds")).unwrap();
+        assert_eq!(content, "");
+    }
+
+    #[test]
+    fn numbers_blocks_sharing_a_summary() {
+        let mut keys = FoldKeys::default();
+        assert_eq!(keys.next("", "Steps"), "Steps#0");
+        assert_eq!(keys.next("Steps#0/", "Steps"), "Steps#0/Steps#0");
+        assert_eq!(keys.next("", "Steps"), "Steps#1");
+    }
+}
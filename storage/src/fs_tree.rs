@@ -1,10 +1,22 @@
 use std::{
-    fs,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, fs,
     path::{Path, PathBuf},
-    sync::mpsc::{channel, Receiver},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+
+use crate::ignore::IgnoreRules;
 
 /// Represents a node in the file tree.
 #[derive(Debug, Clone)]
@@ -50,6 +62,73 @@ impl Node {
     pub fn children(&self) -> Option<&[Node]> {
         self.children.as_deref()
     }
+
+    /// Give this node and its loaded descendants the paths they have after
+    /// moving it to `path`.
+    fn move_to(&mut self, path: &Path) {
+        for child in self.children.iter_mut().flatten() {
+            if let Some(name) = child.path.file_name() {
+                child.move_to(&path.join(name));
+            }
+        }
+        self.path = path.to_path_buf();
+    }
+}
+
+/// A change of the files below the root of a [`FileTree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// The content or metadata of a file or folder changed.
+    Modified(PathBuf),
+}
+
+/// Entries of a directory sent at once by a background load.
+const BATCH: usize = 256;
+
+/// How long a rename seen as `From` and `To` events waits for its `Both`
+/// event. Backends without `Both` events would otherwise pile them up.
+const RENAME_WAIT: Duration = Duration::from_secs(2);
+
+/// How far a background load of a directory got, see
+/// [`FileTree::load_in_background`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// The directory the load started at.
+    pub dir: PathBuf,
+    /// Entries read so far, in all directories of the load.
+    pub entries: usize,
+    /// Entries found so far, in all directories of the load. Directories
+    /// are listed before their entries are read, so this runs ahead of
+    /// `entries`.
+    pub total: usize,
+    /// Paths of the entries read since the last poll.
+    pub added: Vec<PathBuf>,
+    pub done: bool,
+}
+
+/// Children read by a background load.
+struct Batch {
+    dir: PathBuf,
+    nodes: Vec<Node>,
+    /// Entries found in `dir`, with its first batch.
+    found: usize,
+    /// Whether these are the last children of `dir`.
+    last: bool,
+}
+
+/// A directory being read on a background thread.
+struct Load {
+    progress: Progress,
+    cancel: Arc<AtomicBool>,
+    rx: Receiver<Batch>,
+    /// Directories with some of their children added so far.
+    partial: HashSet<PathBuf>,
 }
 
 /// A lazy loading file tree that watches the filesystem for updates.
@@ -57,6 +136,30 @@ pub struct FileTree {
     root: Node,
     _watcher: RecommendedWatcher,
     rx: Receiver<notify::Result<Event>>, // receives filesystem events
+    subscribers: Vec<Sender<Change>>,
+    /// Sources of renames whose target was not seen yet, with the tracker
+    /// tying them to it.
+    moved_from: Vec<(Option<usize>, PathBuf)>,
+    /// Renames seen as `From` and `To` events, whose `Both` event is still
+    /// to come, with when they were seen.
+    renamed: Vec<(Instant, PathBuf, PathBuf)>,
+    loads: Vec<Load>,
+    /// Directories collapsed in a view, least recently collapsed first.
+    collapsed: VecDeque<PathBuf>,
+    /// Nodes kept loaded before the children of collapsed directories are
+    /// unloaded, `None` to keep everything.
+    node_limit: Option<usize>,
+    /// Entries left out of the tree, see [`crate::ignore`].
+    ignore: IgnoreRules,
+}
+
+impl fmt::Debug for FileTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileTree")
+            .field("root", &self.root.path)
+            .field("loads", &self.loads.len())
+            .finish_non_exhaustive()
+    }
 }
 
 impl FileTree {
@@ -76,6 +179,13 @@ impl FileTree {
             root,
             _watcher: watcher,
             rx,
+            subscribers: Vec::new(),
+            moved_from: Vec::new(),
+            renamed: Vec::new(),
+            loads: Vec::new(),
+            collapsed: VecDeque::new(),
+            node_limit: None,
+            ignore: IgnoreRules::load(&path),
         })
     }
 
@@ -89,54 +199,758 @@ impl FileTree {
         &mut self.root
     }
 
-    /// Expand the given node if it is a directory. Does nothing if already loaded.
-    pub fn expand(node: &mut Node) -> std::io::Result<()> {
+    /// The rules of the entries left out of the tree.
+    pub fn ignore(&self) -> &IgnoreRules {
+        &self.ignore
+    }
+
+    /// Show or leave out hidden entries. Loaded directories are read again
+    /// to add or drop them.
+    pub fn set_show_hidden(&mut self, show: bool) {
+        if self.ignore.show_hidden() != show {
+            self.ignore.set_show_hidden(show);
+            Self::reapply(&mut self.root, &self.ignore);
+        }
+    }
+
+    /// Expand the directory at `dir` if it is in the loaded part of the
+    /// tree. Does nothing if already loaded.
+    pub fn expand(&mut self, dir: &Path) -> std::io::Result<()> {
+        match Self::find(&mut self.root, dir) {
+            Some(node) => Self::expand_node(node, &self.ignore),
+            None => Ok(()),
+        }
+    }
+
+    fn expand_node(node: &mut Node, ignore: &IgnoreRules) -> std::io::Result<()> {
         if node.is_dir() && node.children.is_none() {
-            let mut children = Vec::new();
-            for entry in fs::read_dir(&node.path)? {
-                let entry = entry?;
-                let p = entry.path();
-                let md = fs::symlink_metadata(&p)?;
-                children.push(Node::new(p, md));
+            node.children = Some(read_children(&node.path, ignore)?);
+        }
+        Ok(())
+    }
+
+    /// Read the loaded directories from `node` down again after the rules
+    /// changed, keeping what is loaded below the entries that stay.
+    fn reapply(node: &mut Node, ignore: &IgnoreRules) {
+        let Some(old) = node.children.take() else {
+            return;
+        };
+        let Ok(fresh) = read_children(&node.path, ignore) else {
+            node.children = Some(old);
+            return;
+        };
+        let mut old: HashMap<PathBuf, Node> =
+            old.into_iter().map(|n| (n.path.clone(), n)).collect();
+        let children = node.children.insert(
+            fresh
+                .into_iter()
+                .map(|n| old.remove(&n.path).unwrap_or(n))
+                .collect(),
+        );
+        for child in children {
+            Self::reapply(child, ignore);
+        }
+    }
+
+    /// Paths of all files below the root, loading directories that are not
+    /// loaded yet. Hidden entries such as the `.notes2` folder are skipped,
+    /// even while they are shown.
+    pub fn files(&mut self) -> std::io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        Self::collect_files(&mut self.root, &self.ignore, &mut files)?;
+        Ok(files)
+    }
+
+    fn collect_files(
+        node: &mut Node,
+        ignore: &IgnoreRules,
+        files: &mut Vec<PathBuf>,
+    ) -> std::io::Result<()> {
+        Self::expand_node(node, ignore)?;
+        for child in node.children.iter_mut().flatten() {
+            if hidden(&child.path) {
+                continue;
+            }
+            if child.is_dir() {
+                Self::collect_files(child, ignore, files)?;
+            } else {
+                files.push(child.path.clone());
             }
-            node.children = Some(children);
         }
         Ok(())
     }
 
-    /// Poll the watcher for filesystem events and update cached nodes.
-    pub fn poll_events(&mut self) {
+    /// Paths of the files below the root that are loaded, skipping hidden
+    /// entries like [`FileTree::files`] without reading any directory.
+    pub fn loaded_files(&self) -> Vec<PathBuf> {
+        fn collect(node: &Node, files: &mut Vec<PathBuf>) {
+            for child in node.children.iter().flatten() {
+                if hidden(&child.path) {
+                    continue;
+                }
+                if child.is_dir() {
+                    collect(child, files);
+                } else {
+                    files.push(child.path.clone());
+                }
+            }
+        }
+        let mut files = Vec::new();
+        collect(&self.root, &mut files);
+        files
+    }
+
+    /// Read the children of `dir` on a background thread, and with
+    /// `recursive` those of its subdirectories that are not hidden.
+    /// Ignored entries are left out. The
+    /// children are added in batches by [`FileTree::poll_loads`], so a
+    /// directory can be shown while it is still being read. Directories
+    /// that are loaded already are read again.
+    pub fn load_in_background(&mut self, dir: &Path, recursive: bool) {
+        if self.loads.iter().any(|l| l.progress.dir == dir) {
+            return;
+        }
+        self.collapsed.retain(|d| d != dir);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = channel();
+        let stop = cancel.clone();
+        let start = dir.to_path_buf();
+        let ignore = self.ignore.clone();
+        thread::spawn(move || read_dirs(start, recursive, &ignore, &stop, &tx));
+        self.loads.push(Load {
+            progress: Progress {
+                dir: dir.to_path_buf(),
+                entries: 0,
+                total: 0,
+                added: Vec::new(),
+                done: false,
+            },
+            cancel,
+            rx,
+            partial: HashSet::new(),
+        });
+    }
+
+    /// Add the children read in the background since the last call and
+    /// return the progress of the loads. Finished loads are reported as
+    /// done once and then forgotten.
+    pub fn poll_loads(&mut self) -> Vec<Progress> {
+        let mut loads = std::mem::take(&mut self.loads);
+        for load in &mut loads {
+            load.progress.added.clear();
+            loop {
+                match load.rx.try_recv() {
+                    Ok(batch) => {
+                        load.progress.entries += batch.nodes.len();
+                        load.progress.total += batch.found;
+                        load.progress
+                            .added
+                            .extend(batch.nodes.iter().map(|n| n.path.clone()));
+                        self.add_children(batch, &mut load.partial);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // The thread is gone and everything it read was added.
+                    Err(TryRecvError::Disconnected) => {
+                        load.progress.done = true;
+                        break;
+                    }
+                }
+            }
+        }
+        let progress = loads.iter().map(|l| l.progress.clone()).collect();
+        loads.retain(|l| !l.progress.done);
+        self.loads.extend(loads);
+        self.trim();
+        progress
+    }
+
+    /// Whether directories are being read in the background.
+    pub fn loading(&self) -> bool {
+        !self.loads.is_empty()
+    }
+
+    /// The directories being read in the background, each where its load
+    /// started.
+    pub fn loading_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.loads.iter().map(|l| l.progress.dir.as_path())
+    }
+
+    /// Stop reading `dir` in the background. Directories it had read in
+    /// part are unloaded, so they are read in full the next time.
+    pub fn cancel(&mut self, dir: &Path) {
+        let Some(i) = self.loads.iter().position(|l| l.progress.dir == dir) else {
+            return;
+        };
+        let load = self.loads.remove(i);
+        load.cancel.store(true, Ordering::Relaxed);
+        let unload = load.partial.into_iter().chain([load.progress.dir]);
+        for dir in unload {
+            if let Some(node) = Self::find(&mut self.root, &dir) {
+                node.children = None;
+            }
+        }
+    }
+
+    fn add_children(&mut self, batch: Batch, partial: &mut HashSet<PathBuf>) {
+        let Some(node) = Self::find(&mut self.root, &batch.dir) else {
+            return;
+        };
+        // The first batch replaces what was loaded before.
+        let children = if partial.insert(batch.dir.clone()) {
+            node.children.insert(Vec::with_capacity(batch.nodes.len()))
+        } else {
+            node.children.get_or_insert_with(Vec::new)
+        };
+        // Entries may have been added by watcher events meanwhile.
+        let known: HashSet<PathBuf> = children.iter().map(|c| c.path.clone()).collect();
+        children.extend(batch.nodes.into_iter().filter(|n| !known.contains(&n.path)));
+        if batch.last {
+            partial.remove(&batch.dir);
+        }
+    }
+
+    /// Keep at most `limit` nodes loaded by unloading the children of
+    /// collapsed directories, see [`FileTree::collapse`]. `None` keeps
+    /// everything loaded.
+    pub fn set_node_limit(&mut self, limit: Option<usize>) {
+        self.node_limit = limit;
+        self.trim();
+    }
+
+    /// Note that `dir` was collapsed in a view. Its children may be
+    /// unloaded to stay below the node limit.
+    pub fn collapse(&mut self, dir: &Path) {
+        self.collapsed.retain(|d| d != dir);
+        self.collapsed.push_back(dir.to_path_buf());
+        self.trim();
+    }
+
+    /// Unload collapsed directories, least recently collapsed first, until
+    /// no more nodes than the limit are loaded.
+    fn trim(&mut self) {
+        let Some(limit) = self.node_limit else {
+            return;
+        };
+        let mut loaded = count(&self.root);
+        while loaded > limit {
+            let Some(dir) = self.collapsed.pop_front() else {
+                break;
+            };
+            if self.loads.iter().any(|l| l.progress.dir == dir) {
+                self.cancel(&dir);
+            }
+            if let Some(node) = Self::find(&mut self.root, &dir) {
+                loaded -= count(node) - 1;
+                node.children = None;
+            }
+        }
+    }
+
+    /// Receive the changes of the tree as they are found by
+    /// [`FileTree::poll_events`].
+    pub fn subscribe(&mut self) -> Receiver<Change> {
+        let (tx, rx) = channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Poll the watcher for filesystem events, patch the cached nodes and
+    /// return what changed. The changes are sent to subscribers as well.
+    /// Changes of ignored entries are left out, and a change of the ignore
+    /// file applies its rules to the loaded part of the tree.
+    pub fn poll_events(&mut self) -> Vec<Change> {
+        let mut changes = Vec::new();
         while let Ok(res) = self.rx.try_recv() {
             if let Ok(event) = res {
-                self.process_event(event);
+                self.process_event(event, &mut changes);
+            }
+        }
+        // The other half of these renames is outside the tree.
+        for (_, from) in std::mem::take(&mut self.moved_from) {
+            if fs::symlink_metadata(&from).is_err() {
+                self.remove(&from, &mut changes);
+            }
+        }
+        self.renamed.retain(|(at, ..)| at.elapsed() < RENAME_WAIT);
+        self.subscribers
+            .retain(|tx| changes.iter().all(|c| tx.send(c.clone()).is_ok()));
+        changes
+    }
+
+    fn process_event(&mut self, event: Event, changes: &mut Vec<Change>) {
+        if event.need_rescan() {
+            // Events were lost, so nothing cached can be trusted.
+            let dirs: Vec<PathBuf> = self.loads.iter().map(|l| l.progress.dir.clone()).collect();
+            for dir in dirs {
+                self.cancel(&dir);
+            }
+            self.root.children = None;
+            return;
+        }
+        if event.paths.iter().any(|p| self.ignore.is_rules_file(p)) {
+            let show = self.ignore.show_hidden();
+            self.ignore = IgnoreRules::load(&self.root.path);
+            self.ignore.set_show_hidden(show);
+            Self::reapply(&mut self.root, &self.ignore);
+        }
+        let tracker = event.attrs.tracker();
+        match event.kind {
+            EventKind::Access(_) => {}
+            EventKind::Create(_) => {
+                for path in event.paths {
+                    self.create(&path, changes);
+                }
+            }
+            EventKind::Remove(_) => {
+                for path in event.paths {
+                    self.remove(&path, changes);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                let (from, to) = (&event.paths[0], &event.paths[1]);
+                // Already seen as a `From` and `To` pair.
+                if let Some(i) = self
+                    .renamed
+                    .iter()
+                    .position(|(_, f, t)| f == from && t == to)
+                {
+                    self.renamed.swap_remove(i);
+                } else {
+                    self.rename(from, to, changes);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for path in event.paths {
+                    self.moved_from.push((tracker, path));
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for to in event.paths {
+                    let from = self
+                        .moved_from
+                        .iter()
+                        .position(|(t, _)| tracker.is_some() && *t == tracker)
+                        .map(|i| self.moved_from.remove(i).1);
+                    match from {
+                        Some(from) => {
+                            self.rename(&from, &to, changes);
+                            self.renamed.push((Instant::now(), from, to));
+                        }
+                        None => self.create(&to, changes),
+                    }
+                }
+            }
+            EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(_)) => {
+                for path in event.paths {
+                    self.modify(&path, changes);
+                }
+            }
+            // Events that do not tell what happened are checked against
+            // the disk.
+            _ => {
+                for path in event.paths {
+                    if fs::symlink_metadata(&path).is_err() {
+                        self.remove(&path, changes);
+                    } else if self.cached(&path) {
+                        self.modify(&path, changes);
+                    } else {
+                        self.create(&path, changes);
+                    }
+                }
             }
         }
     }
 
-    fn process_event(&mut self, event: Event) {
-        for path in event.paths {
-            self.invalidate(&path);
+    fn create(&mut self, path: &Path, changes: &mut Vec<Change>) {
+        let Ok(md) = fs::symlink_metadata(path) else {
+            return;
+        };
+        if self.ignore.ignores(path, md.is_dir()) {
+            return;
         }
+        if let Some(children) = self.loaded_children(path) {
+            match children.iter_mut().find(|c| c.path == path) {
+                Some(node) => node.metadata = md,
+                None => children.push(Node::new(path.to_path_buf(), md)),
+            }
+        }
+        changes.push(Change::Created(path.to_path_buf()));
     }
 
-    fn invalidate(&mut self, path: &Path) {
-        Self::invalidate_node(&mut self.root, path);
+    fn remove(&mut self, path: &Path, changes: &mut Vec<Change>) {
+        if self.take(path).is_none() && self.ignored(path) {
+            return;
+        }
+        changes.push(Change::Removed(path.to_path_buf()));
     }
 
-    fn invalidate_node(node: &mut Node, path: &Path) -> bool {
-        if path == node.path {
-            node.children = None;
-            return true;
+    fn rename(&mut self, from: &Path, to: &Path, changes: &mut Vec<Change>) {
+        match (self.ignored(from), self.ignored(to)) {
+            (true, true) => return,
+            (true, false) => return self.create(to, changes),
+            (false, true) => return self.remove(from, changes),
+            (false, false) => {}
         }
-        if let Some(children) = node.children.as_mut() {
-            for child in children {
-                if path.starts_with(&child.path) {
-                    if Self::invalidate_node(child, path) {
-                        return true;
-                    }
+        let moved = self.take(from);
+        if let (Some(children), Ok(md)) = (self.loaded_children(to), fs::symlink_metadata(to)) {
+            children.retain(|c| c.path != to);
+            let mut node = moved.unwrap_or_else(|| Node::new(to.to_path_buf(), md.clone()));
+            node.metadata = md;
+            node.move_to(to);
+            children.push(node);
+        }
+        changes.push(Change::Renamed {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+    }
+
+    fn modify(&mut self, path: &Path, changes: &mut Vec<Change>) {
+        if self.ignored(path) {
+            return;
+        }
+        let md = fs::symlink_metadata(path);
+        if let (Some(node), Ok(md)) = (Self::find(&mut self.root, path), md) {
+            node.metadata = md;
+        }
+        changes.push(Change::Modified(path.to_path_buf()));
+    }
+
+    /// Whether the rules leave out `path`. Paths that are gone count as
+    /// folders when that leaves them out.
+    fn ignored(&self, path: &Path) -> bool {
+        match fs::symlink_metadata(path) {
+            Ok(md) => self.ignore.ignores(path, md.is_dir()),
+            Err(_) => self.ignore.ignores(path, false) || self.ignore.ignores(path, true),
+        }
+    }
+
+    /// Whether `path` is in the loaded part of the tree.
+    fn cached(&mut self, path: &Path) -> bool {
+        Self::find(&mut self.root, path).is_some()
+    }
+
+    /// The loaded children of the directory that holds `path`.
+    fn loaded_children(&mut self, path: &Path) -> Option<&mut Vec<Node>> {
+        Self::find(&mut self.root, path.parent()?)?
+            .children
+            .as_mut()
+    }
+
+    /// Take the node at `path` out of the loaded part of the tree.
+    fn take(&mut self, path: &Path) -> Option<Node> {
+        let children = self.loaded_children(path)?;
+        let i = children.iter().position(|c| c.path == path)?;
+        Some(children.remove(i))
+    }
+
+    fn find<'a>(node: &'a mut Node, path: &Path) -> Option<&'a mut Node> {
+        if node.path == path {
+            return Some(node);
+        }
+        node.children
+            .as_mut()?
+            .iter_mut()
+            .find(|c| path.starts_with(&c.path))
+            .and_then(|c| Self::find(c, path))
+    }
+}
+
+/// Whether `path` names a hidden entry such as the `.notes2` folder.
+fn hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// The entries of `dir` that `ignore` does not leave out.
+fn read_children(dir: &Path, ignore: &IgnoreRules) -> std::io::Result<Vec<Node>> {
+    let mut children = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let p = entry?.path();
+        let md = fs::symlink_metadata(&p)?;
+        if !ignore.ignores(&p, md.is_dir()) {
+            children.push(Node::new(p, md));
+        }
+    }
+    Ok(children)
+}
+
+/// Number of loaded nodes from `node` down.
+fn count(node: &Node) -> usize {
+    1 + node.children.iter().flatten().map(count).sum::<usize>()
+}
+
+/// Read `start` and, when `recursive`, the directories below it breadth
+/// first, sending the children that `ignore` does not leave out in
+/// batches until `stop` is set.
+fn read_dirs(
+    start: PathBuf,
+    recursive: bool,
+    ignore: &IgnoreRules,
+    stop: &AtomicBool,
+    tx: &Sender<Batch>,
+) {
+    let mut queue = VecDeque::from([start]);
+    while let Some(dir) = queue.pop_front() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        // Listing comes with the type of the entries, so they are counted
+        // before their metadata is read.
+        let mut paths = Vec::new();
+        for entry in entries.flatten() {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let path = entry.path();
+            if !ignore.ignores(&path, entry.file_type().is_ok_and(|t| t.is_dir())) {
+                paths.push(path);
+            }
+        }
+        let mut found = paths.len();
+        let mut nodes = Vec::new();
+        for path in paths {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let Ok(md) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if recursive && md.is_dir() && !hidden(&path) {
+                queue.push_back(path.clone());
+            }
+            nodes.push(Node::new(path, md));
+            if nodes.len() == BATCH {
+                let batch = Batch {
+                    dir: dir.clone(),
+                    nodes: std::mem::take(&mut nodes),
+                    found: std::mem::take(&mut found),
+                    last: false,
+                };
+                if tx.send(batch).is_err() {
+                    return;
                 }
             }
         }
-        false
+        let batch = Batch {
+            dir,
+            nodes,
+            found,
+            last: true,
+        };
+        if tx.send(batch).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+
+    fn event(kind: EventKind, paths: &[&Path]) -> Event {
+        paths
+            .iter()
+            .fold(Event::new(kind), |e, p| e.add_path(p.to_path_buf()))
+    }
+
+    fn rename(mode: RenameMode, paths: &[&Path]) -> Event {
+        event(EventKind::Modify(ModifyKind::Name(mode)), paths)
+    }
+
+    #[test]
+    fn patches_the_cache_and_reports_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("a")).unwrap();
+        fs::write(root.join("a/x.md"), "x").unwrap();
+        fs::write(root.join("b.md"), "b").unwrap();
+        let mut tree = FileTree::new(root.to_path_buf()).unwrap();
+        tree.files().unwrap();
+        let mut changes = Vec::new();
+
+        fs::write(root.join("c.md"), "c").unwrap();
+        let created = event(EventKind::Create(CreateKind::File), &[&root.join("c.md")]);
+        tree.process_event(created, &mut changes);
+
+        fs::rename(root.join("a"), root.join("d")).unwrap();
+        let both = rename(RenameMode::Both, &[&root.join("a"), &root.join("d")]);
+        tree.process_event(both, &mut changes);
+
+        // Renames reported in parts, then as a whole, count once.
+        fs::rename(root.join("c.md"), root.join("e.md")).unwrap();
+        let (from, to) = (root.join("c.md"), root.join("e.md"));
+        tree.process_event(
+            rename(RenameMode::From, &[&from]).set_tracker(7),
+            &mut changes,
+        );
+        tree.process_event(rename(RenameMode::To, &[&to]).set_tracker(7), &mut changes);
+        tree.process_event(rename(RenameMode::Both, &[&from, &to]), &mut changes);
+
+        fs::remove_file(root.join("b.md")).unwrap();
+        let removed = event(EventKind::Remove(RemoveKind::File), &[&root.join("b.md")]);
+        tree.process_event(removed, &mut changes);
+        let modified = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        tree.process_event(event(modified, &[&root.join("e.md")]), &mut changes);
+
+        assert_eq!(
+            changes,
+            [
+                Change::Created(root.join("c.md")),
+                Change::Renamed {
+                    from: root.join("a"),
+                    to: root.join("d")
+                },
+                Change::Renamed { from, to },
+                Change::Removed(root.join("b.md")),
+                Change::Modified(root.join("e.md")),
+            ]
+        );
+        // The cache was patched rather than dropped.
+        let d = tree
+            .root()
+            .children()
+            .unwrap()
+            .iter()
+            .find(|n| n.path() == root.join("d"));
+        assert!(d.unwrap().children().is_some());
+        let mut files = tree.files().unwrap();
+        files.sort();
+        assert_eq!(files, [root.join("d/x.md"), root.join("e.md")]);
+    }
+
+    #[test]
+    fn waits_for_the_whole_rename_across_polls() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mut tree = FileTree::new(root.to_path_buf()).unwrap();
+        let (from, to) = (root.join("a.md"), root.join("b.md"));
+        let mut changes = Vec::new();
+
+        tree.process_event(
+            rename(RenameMode::From, &[&from]).set_tracker(3),
+            &mut changes,
+        );
+        tree.process_event(rename(RenameMode::To, &[&to]).set_tracker(3), &mut changes);
+        tree.poll_events();
+        tree.process_event(rename(RenameMode::Both, &[&from, &to]), &mut changes);
+        assert_eq!(changes, [Change::Renamed { from, to }]);
+        assert!(tree.renamed.is_empty());
+
+        // Renames whose `Both` event never comes are forgotten.
+        let (from, to) = (root.join("c.md"), root.join("d.md"));
+        tree.process_event(
+            rename(RenameMode::From, &[&from]).set_tracker(4),
+            &mut changes,
+        );
+        tree.process_event(rename(RenameMode::To, &[&to]).set_tracker(4), &mut changes);
+        tree.poll_events();
+        assert_eq!(tree.renamed.len(), 1);
+        if let Some(seen) = Instant::now().checked_sub(RENAME_WAIT) {
+            tree.renamed[0].0 = seen;
+            tree.poll_events();
+            assert!(tree.renamed.is_empty());
+        }
+    }
+
+    #[test]
+    fn leaves_out_ignored_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("target/out.md"), "").unwrap();
+        fs::write(root.join(".todo.md"), "").unwrap();
+        fs::write(root.join("a.md"), "").unwrap();
+        fs::write(root.join(".notesignore"), "target/\n").unwrap();
+        let mut tree = FileTree::new(root.to_path_buf()).unwrap();
+        let names = |tree: &FileTree| {
+            let mut names: Vec<_> = tree
+                .root()
+                .children()
+                .unwrap()
+                .iter()
+                .map(|n| n.path().file_name().unwrap().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        tree.expand(root).unwrap();
+        assert_eq!(names(&tree), ["a.md"]);
+
+        tree.set_show_hidden(true);
+        assert_eq!(names(&tree), [".notesignore", ".todo.md", "a.md"]);
+        assert_eq!(tree.files().unwrap(), [root.join("a.md")]);
+        tree.set_show_hidden(false);
+
+        let mut changes = Vec::new();
+        fs::write(root.join("a.md.swp"), "").unwrap();
+        let swap = event(
+            EventKind::Create(CreateKind::File),
+            &[&root.join("a.md.swp")],
+        );
+        tree.process_event(swap, &mut changes);
+        fs::rename(root.join("a.md.swp"), root.join("b.md")).unwrap();
+        let (swap, b) = (root.join("a.md.swp"), root.join("b.md"));
+        tree.process_event(rename(RenameMode::Both, &[&swap, &b]), &mut changes);
+        assert_eq!(changes, [Change::Created(b)]);
+
+        // Editing the ignore file applies its rules right away.
+        fs::write(root.join(".notesignore"), "*.md\n!b.md\n").unwrap();
+        let modified = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        tree.process_event(event(modified, &[&root.join(".notesignore")]), &mut changes);
+        assert_eq!(names(&tree), ["b.md", "target"]);
+    }
+
+    #[test]
+    fn loads_in_the_background_and_unloads_collapsed_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub/deep")).unwrap();
+        fs::create_dir(root.join(".hidden")).unwrap();
+        fs::write(root.join(".hidden/skipped.md"), "").unwrap();
+        for i in 0..600 {
+            fs::write(root.join(format!("sub/{i}.md")), "").unwrap();
+        }
+        fs::write(root.join("sub/deep/x.md"), "").unwrap();
+        let mut tree = FileTree::new(root.to_path_buf()).unwrap();
+
+        tree.load_in_background(root, true);
+        tree.cancel(root);
+        assert!(!tree.loading());
+        assert!(tree.root().children().is_none());
+
+        tree.load_in_background(root, true);
+        let mut last = None;
+        let mut added = 0;
+        for _ in 0..500 {
+            if let Some(progress) = tree.poll_loads().pop() {
+                added += progress.added.len();
+                last = Some(progress);
+            }
+            if !tree.loading() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let progress = last.unwrap();
+        assert!(progress.done);
+        // Both folders, 600 notes and `deep/x.md`, but not `.hidden`.
+        assert_eq!(progress.entries, 603);
+        assert_eq!(progress.total, 603);
+        assert_eq!(added, 603);
+        assert_eq!(tree.loaded_files().len(), 601);
+
+        tree.set_node_limit(Some(10));
+        tree.collapse(&root.join("sub"));
+        let sub = tree
+            .root()
+            .children()
+            .unwrap()
+            .iter()
+            .find(|n| n.path() == root.join("sub"));
+        assert!(sub.unwrap().children().is_none());
+        assert!(tree.loaded_files().is_empty());
     }
 }
@@ -0,0 +1,697 @@
+//! History of a vault kept in git.
+//!
+//! When the vault lies inside a git work tree, changed notes can be
+//! committed with generated messages and the log and blame of a note can be
+//! read back. Everything goes through the `git` command, so any plain local
+//! repository works. The `.notes2` cache folder is never committed.
+//!
+//! Notes with merge conflicts hold git's conflict markers; they are split
+//! into [`Region`]s so each conflict can be resolved by picking a [`Side`].
+//! Notes that still hold markers are never staged.
+//!
+//! Editors run git through a [`GitWorker`], which runs it on a background
+//! thread and commits saved notes as [`AutoCommit`] says.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Errors produced while running git.
+#[derive(Debug)]
+pub enum GitError {
+    /// git could not be started.
+    Io(io::Error),
+    /// git ran but failed.
+    Failed { command: String, message: String },
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::Io(e) => write!(f, "cannot run git: {e}"),
+            GitError::Failed { command, message } => write!(f, "git {command} failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
+
+impl From<io::Error> for GitError {
+    fn from(e: io::Error) -> Self {
+        GitError::Io(e)
+    }
+}
+
+/// A commit touching a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub id: String,
+    pub author: String,
+    pub at: SystemTime,
+    /// First line of the message.
+    pub summary: String,
+}
+
+/// A line of a note with the commit that last changed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// Commit id, all zeros for lines that are not committed yet.
+    pub id: String,
+    pub author: String,
+    pub at: SystemTime,
+    pub text: String,
+}
+
+/// Paths of a vault that are never committed: the app's own state and the
+/// `export` folder the interfaces write exported notes to.
+const EXCLUDE: [&str; 2] = [":(exclude).notes2", ":(exclude)export"];
+
+/// A vault inside a git work tree.
+#[derive(Debug, Clone)]
+pub struct GitVault {
+    vault: PathBuf,
+}
+
+impl GitVault {
+    /// The git work tree holding `vault`, `None` when it is not in one or
+    /// git is not installed.
+    pub fn open(vault: &Path) -> Option<Self> {
+        let git = Self {
+            vault: vault.to_path_buf(),
+        };
+        git.run(&["rev-parse", "--is-inside-work-tree"])
+            .ok()
+            .filter(|out| out.trim() == "true")
+            .map(|_| git)
+    }
+
+    /// Run git in the vault and return its output.
+    fn run(&self, args: &[&str]) -> Result<String, GitError> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.vault)
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            return Err(GitError::Failed {
+                command: args.first().copied().unwrap_or_default().to_string(),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Commit the changes of the vault-relative `note`. Returns the
+    /// message, or `None` when the note has no changes. During a merge the
+    /// note is only staged, see [`GitVault::commit_merge`].
+    pub fn commit_note(&self, note: &Path) -> Result<Option<String>, GitError> {
+        if self.merging() {
+            return self.commit_merge(Some(note));
+        }
+        let note = note.to_string_lossy();
+        self.commit(&["--", &note])
+    }
+
+    /// Commit the changes of all notes in the vault. Returns the message,
+    /// or `None` when nothing changed. During a merge only the notes whose
+    /// conflicts were resolved are staged.
+    pub fn commit_all(&self) -> Result<Option<String>, GitError> {
+        if self.merging() {
+            return self.commit_merge(None);
+        }
+        self.commit(&["--", ".", EXCLUDE[0], EXCLUDE[1]])
+    }
+
+    /// Whether a merge is waiting for its conflicts to be resolved.
+    pub fn merging(&self) -> bool {
+        self.run(&["rev-parse", "-q", "--verify", "MERGE_HEAD"])
+            .is_ok()
+    }
+
+    /// Stage `note`, or else every note with conflicts, unless it still
+    /// holds conflict markers, and commit the merge once no conflicts are
+    /// left.
+    fn commit_merge(&self, note: Option<&Path>) -> Result<Option<String>, GitError> {
+        let notes = match note {
+            Some(note) => vec![note.to_path_buf()],
+            None => self.conflicts()?,
+        };
+        for note in notes {
+            if !self.has_markers(&note) {
+                self.run(&["add", "-A", "--", &note.to_string_lossy()])?;
+            }
+        }
+        if !self.conflicts()?.is_empty() {
+            return Ok(None);
+        }
+        self.run(&["commit", "-q", "--no-edit"])?;
+        let message = self.run(&["log", "-1", "--format=%s"])?;
+        Ok(Some(message.trim().to_string()))
+    }
+
+    /// Whether the vault-relative `note` still holds conflict markers.
+    fn has_markers(&self, note: &Path) -> bool {
+        fs::read_to_string(self.vault.join(note))
+            .is_ok_and(|text| conflict_regions(&text).is_some())
+    }
+
+    /// Stage and commit `paths`.
+    fn commit(&self, paths: &[&str]) -> Result<Option<String>, GitError> {
+        self.run(&[&["add", "-A"], paths].concat())?;
+        let status = self.run(
+            &[
+                &[
+                    "diff",
+                    "--cached",
+                    "--name-status",
+                    "--no-renames",
+                    "--relative",
+                    "-z",
+                ],
+                paths,
+            ]
+            .concat(),
+        )?;
+        // With `-z` the status letters and paths are separate fields, and
+        // paths are not quoted.
+        let mut fields = status.split('\0').filter(|f| !f.is_empty());
+        let mut changes: Vec<(char, &str)> = Vec::new();
+        while let (Some(kind), Some(path)) = (fields.next(), fields.next()) {
+            changes.extend(kind.chars().next().map(|kind| (kind, path)));
+        }
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        let message = message(&changes);
+        self.run(&[&["commit", "-q", "-m", &message], paths].concat())?;
+        Ok(Some(message))
+    }
+
+    /// Commits changing the vault-relative `note`, newest first.
+    pub fn log(&self, note: &Path) -> Result<Vec<Commit>, GitError> {
+        let out = self.run(&[
+            "log",
+            "--follow",
+            "--format=%H%x09%an%x09%at%x09%s",
+            "--",
+            &note.to_string_lossy(),
+        ])?;
+        Ok(out
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                Some(Commit {
+                    id: fields.next()?.to_string(),
+                    author: fields.next()?.to_string(),
+                    at: time(fields.next()?),
+                    summary: fields.next().unwrap_or_default().to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// Lines of the vault-relative `note` as it is on disk, each with the
+    /// commit that last changed it.
+    pub fn blame(&self, note: &Path) -> Result<Vec<BlameLine>, GitError> {
+        let out = self.run(&["blame", "--line-porcelain", "--", &note.to_string_lossy()])?;
+        let mut lines = Vec::new();
+        let (mut id, mut author, mut at) = (String::new(), String::new(), UNIX_EPOCH);
+        for line in out.lines() {
+            if let Some(text) = line.strip_prefix('\t') {
+                lines.push(BlameLine {
+                    id: id.clone(),
+                    author: author.clone(),
+                    at,
+                    text: text.to_string(),
+                });
+            } else if let Some(name) = line.strip_prefix("author ") {
+                author = name.to_string();
+            } else if let Some(secs) = line.strip_prefix("author-time ") {
+                at = time(secs);
+            } else if let Some(header) = line
+                .split(' ')
+                .next()
+                .filter(|h| h.len() == 40 && h.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                id = header.to_string();
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Vault-relative paths of the notes with unresolved merge conflicts.
+    pub fn conflicts(&self) -> Result<Vec<PathBuf>, GitError> {
+        let out = self.run(&["diff", "--name-only", "--diff-filter=U", "--relative", "-z"])?;
+        let mut conflicts: Vec<PathBuf> = out
+            .split('\0')
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        // Each stage of a conflicted path is listed.
+        conflicts.dedup();
+        Ok(conflicts)
+    }
+}
+
+/// When saved notes are committed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoCommit {
+    #[default]
+    Off,
+    /// Every time a note is saved.
+    Save,
+    /// After no input for a while.
+    Idle,
+}
+
+/// Work for a [`GitWorker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Job {
+    /// Commit these vault-relative notes, see [`GitVault::commit_note`].
+    Commit(Vec<PathBuf>),
+    /// See [`GitVault::commit_all`].
+    CommitAll,
+    /// Stage the vault-relative note during a merge, so its resolved
+    /// conflicts count as resolved.
+    Resolved(PathBuf),
+    Log(PathBuf),
+    Blame(PathBuf),
+    Conflicts,
+}
+
+/// The outcome of a [`Job`].
+#[derive(Debug)]
+pub enum Done {
+    /// Messages of the commits made, empty when nothing was committed.
+    Committed(Result<Vec<String>, GitError>),
+    Log(PathBuf, Result<Vec<Commit>, GitError>),
+    Blame(PathBuf, Result<Vec<BlameLine>, GitError>),
+    Conflicts(Result<Vec<PathBuf>, GitError>),
+}
+
+/// Runs git for a vault on a background thread, one [`Job`] after the
+/// other, and commits saved notes as [`AutoCommit`] says.
+pub struct GitWorker {
+    jobs: Option<Sender<Job>>,
+    done: Receiver<Done>,
+    thread: Option<JoinHandle<()>>,
+    pub auto_commit: AutoCommit,
+    /// Time without input before an [`AutoCommit::Idle`] commit.
+    pub idle: Duration,
+    /// Notes were saved since the last commit.
+    pending: bool,
+    last_input: Instant,
+}
+
+impl GitWorker {
+    pub fn start(repo: GitVault, auto_commit: AutoCommit, idle: Duration) -> Self {
+        let (jobs, rx) = channel();
+        let (tx, done) = channel();
+        let thread = thread::spawn(move || {
+            for job in rx {
+                if let Some(done) = run(&repo, job) {
+                    if tx.send(done).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Self {
+            jobs: Some(jobs),
+            done,
+            thread: Some(thread),
+            auto_commit,
+            idle,
+            pending: false,
+            last_input: Instant::now(),
+        }
+    }
+
+    /// Queue `job`, its outcome is returned by [`GitWorker::poll`].
+    pub fn send(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(job);
+        }
+    }
+
+    /// Note that the user did something, which delays idle commits.
+    pub fn input(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    /// Commit the vault-relative `notes` that were just saved, right away
+    /// or once idle depending on [`GitWorker::auto_commit`]. During a merge
+    /// they are staged in any case, so saving a note with its conflicts
+    /// resolved marks them resolved.
+    pub fn saved(&mut self, notes: Vec<PathBuf>) {
+        if notes.is_empty() {
+            return;
+        }
+        if self.auto_commit == AutoCommit::Save {
+            self.send(Job::Commit(notes));
+            return;
+        }
+        self.pending |= self.auto_commit == AutoCommit::Idle;
+        for note in notes {
+            self.send(Job::Resolved(note));
+        }
+    }
+
+    /// Outcomes of the jobs finished since the last call. Starts the idle
+    /// commit when it is due.
+    pub fn poll(&mut self) -> Vec<Done> {
+        if self.pending && self.last_input.elapsed() >= self.idle {
+            self.pending = false;
+            self.send(Job::CommitAll);
+        }
+        self.done.try_iter().collect()
+    }
+
+    /// Commit what is pending and wait for all jobs to finish.
+    pub fn finish(&mut self) {
+        if std::mem::take(&mut self.pending) {
+            self.send(Job::CommitAll);
+        }
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for GitWorker {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Run `job`, `None` when there is nothing to report.
+fn run(repo: &GitVault, job: Job) -> Option<Done> {
+    Some(match job {
+        Job::Commit(notes) => Done::Committed(
+            notes
+                .iter()
+                .map(|note| repo.commit_note(note))
+                .filter_map(Result::transpose)
+                .collect(),
+        ),
+        Job::CommitAll => Done::Committed(repo.commit_all().map(|m| m.into_iter().collect())),
+        Job::Resolved(note) if repo.merging() => {
+            Done::Committed(repo.commit_note(&note).map(|m| m.into_iter().collect()))
+        }
+        Job::Resolved(_) => return None,
+        Job::Log(note) => {
+            let log = repo.log(&note);
+            Done::Log(note, log)
+        }
+        Job::Blame(note) => {
+            let blame = repo.blame(&note);
+            Done::Blame(note, blame)
+        }
+        Job::Conflicts => Done::Conflicts(repo.conflicts()),
+    })
+}
+
+/// Commit message for `changes`, pairs of git's status letter and path.
+fn message(changes: &[(char, &str)]) -> String {
+    let verb = if changes.iter().all(|(kind, _)| *kind == 'A') {
+        "Add"
+    } else if changes.iter().all(|(kind, _)| *kind == 'D') {
+        "Delete"
+    } else {
+        "Update"
+    };
+    match changes {
+        [(_, path)] => format!("{verb} {path}"),
+        _ if changes.len() <= 3 => {
+            let paths: Vec<&str> = changes.iter().map(|(_, path)| *path).collect();
+            format!("{verb} {}", paths.join(", "))
+        }
+        _ => format!("{verb} {} notes", changes.len()),
+    }
+}
+
+fn time(secs: &str) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs.trim().parse().unwrap_or(0))
+}
+
+/// A part of a note with merge conflicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Region {
+    /// Lines both sides agree on.
+    Same(Vec<String>),
+    /// Lines the sides changed differently.
+    Conflict {
+        ours: Vec<String>,
+        theirs: Vec<String>,
+    },
+}
+
+/// Which lines of a conflict to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+    /// Our lines followed by theirs.
+    Both,
+}
+
+/// Split a note with git's conflict markers into regions. Returns `None`
+/// when the note has no conflicts. The common ancestor's lines of a
+/// `diff3` style conflict are dropped.
+pub fn conflict_regions(text: &str) -> Option<Vec<Region>> {
+    enum State {
+        Same,
+        Ours,
+        Base,
+        Theirs,
+    }
+    let mut regions = Vec::new();
+    let mut same = Vec::new();
+    let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+    let mut state = State::Same;
+    for line in text.lines() {
+        let marker = |m: &str| {
+            line == m
+                || line
+                    .strip_prefix(m)
+                    .is_some_and(|rest| rest.starts_with(' '))
+        };
+        state = match state {
+            State::Same if marker("<<<<<<<") => {
+                if !same.is_empty() {
+                    regions.push(Region::Same(std::mem::take(&mut same)));
+                }
+                State::Ours
+            }
+            State::Ours if marker("|||||||") => State::Base,
+            State::Ours | State::Base if marker("=======") => State::Theirs,
+            State::Theirs if marker(">>>>>>>") => {
+                regions.push(Region::Conflict {
+                    ours: std::mem::take(&mut ours),
+                    theirs: std::mem::take(&mut theirs),
+                });
+                State::Same
+            }
+            State::Same => {
+                same.push(line.to_string());
+                State::Same
+            }
+            State::Ours => {
+                ours.push(line.to_string());
+                State::Ours
+            }
+            State::Base => State::Base,
+            State::Theirs => {
+                theirs.push(line.to_string());
+                State::Theirs
+            }
+        };
+    }
+    if !matches!(state, State::Same) {
+        return None;
+    }
+    if !same.is_empty() {
+        regions.push(Region::Same(same));
+    }
+    regions
+        .iter()
+        .any(|r| matches!(r, Region::Conflict { .. }))
+        .then_some(regions)
+}
+
+/// The lines of `regions` with the conflicts resolved by `sides`, one side
+/// per conflict in order.
+pub fn resolve(regions: &[Region], sides: &[Side]) -> Vec<String> {
+    let mut sides = sides.iter();
+    let mut lines = Vec::new();
+    for region in regions {
+        match region {
+            Region::Same(same) => lines.extend(same.iter().cloned()),
+            Region::Conflict { ours, theirs } => match sides.next() {
+                Some(Side::Ours) => lines.extend(ours.iter().cloned()),
+                Some(Side::Theirs) => lines.extend(theirs.iter().cloned()),
+                Some(Side::Both) | None => {
+                    lines.extend(ours.iter().cloned());
+                    lines.extend(theirs.iter().cloned());
+                }
+            },
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    }
+
+    fn repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "-q", "-b", "main"]);
+        git(dir.path(), &["config", "user.name", "Ann"]);
+        git(dir.path(), &["config", "user.email", "ann@example.com"]);
+        dir
+    }
+
+    #[test]
+    fn commits_notes_and_reads_their_history() {
+        let dir = repo();
+        let vault = dir.path().join("notes");
+        fs::create_dir_all(vault.join(".notes2")).unwrap();
+        fs::write(vault.join(".notes2/recent"), "a.md").unwrap();
+        fs::write(vault.join("a.md"), "one\n").unwrap();
+        fs::write(vault.join("b.md"), "b\n").unwrap();
+        fs::create_dir_all(vault.join("export")).unwrap();
+        fs::write(vault.join("export/a.pdf"), "%PDF").unwrap();
+        fs::write(vault.join("ü.md"), "u\n").unwrap();
+        let git = GitVault::open(&vault).unwrap();
+        assert_eq!(
+            git.commit_note(Path::new("ü.md")).unwrap().as_deref(),
+            Some("Add ü.md")
+        );
+
+        let message = git.commit_note(Path::new("a.md")).unwrap();
+        assert_eq!(message.as_deref(), Some("Add a.md"));
+        assert_eq!(git.commit_note(Path::new("a.md")).unwrap(), None);
+        fs::write(vault.join("a.md"), "one\ntwo\n").unwrap();
+        assert_eq!(
+            git.commit_all().unwrap().as_deref(),
+            Some("Update a.md, b.md")
+        );
+        let tracked = git.run(&["ls-files"]).unwrap();
+        assert!(!tracked.contains(".notes2"), "{tracked}");
+        assert!(!tracked.contains("export"), "{tracked}");
+
+        let log = git.log(Path::new("a.md")).unwrap();
+        let summaries: Vec<&str> = log.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, ["Update a.md, b.md", "Add a.md"]);
+        assert_eq!(log[0].author, "Ann");
+        let blame = git.blame(Path::new("a.md")).unwrap();
+        assert_eq!(blame.len(), 2);
+        assert_eq!(blame[0].id, log[1].id);
+        assert_eq!(blame[1].id, log[0].id);
+        assert_eq!(blame[1].text, "two");
+        assert!(git.conflicts().unwrap().is_empty());
+        assert!(GitVault::open(tempfile::tempdir().unwrap().path()).is_none());
+    }
+
+    #[test]
+    fn finds_and_resolves_conflicts() {
+        let dir = repo();
+        let git = GitVault::open(dir.path()).unwrap();
+        fs::write(dir.path().join("a.md"), "title\nline\nend\n").unwrap();
+        git.commit_all().unwrap();
+        git.run(&["checkout", "-q", "-b", "other"]).unwrap();
+        fs::write(dir.path().join("a.md"), "title\ntheirs\nend\n").unwrap();
+        git.commit_all().unwrap();
+        git.run(&["checkout", "-q", "main"]).unwrap();
+        fs::write(dir.path().join("a.md"), "title\nours\nend\n").unwrap();
+        git.commit_all().unwrap();
+        assert!(git.run(&["merge", "-q", "other"]).is_err());
+        assert_eq!(git.conflicts().unwrap(), [PathBuf::from("a.md")]);
+        // Notes that still hold markers are neither staged nor committed.
+        fs::write(dir.path().join("b.md"), "b\n").unwrap();
+        assert_eq!(git.commit_all().unwrap(), None);
+        assert_eq!(git.commit_note(Path::new("a.md")).unwrap(), None);
+        assert!(git.merging());
+        assert_eq!(git.conflicts().unwrap(), [PathBuf::from("a.md")]);
+
+        let text = fs::read_to_string(dir.path().join("a.md")).unwrap();
+        let regions = conflict_regions(&text).unwrap();
+        let lines = |l: &[&str]| l.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            regions,
+            [
+                Region::Same(lines(&["title"])),
+                Region::Conflict {
+                    ours: lines(&["ours"]),
+                    theirs: lines(&["theirs"])
+                },
+                Region::Same(lines(&["end"])),
+            ]
+        );
+        assert_eq!(
+            resolve(&regions, &[Side::Theirs]),
+            ["title", "theirs", "end"]
+        );
+        assert_eq!(
+            resolve(&regions, &[Side::Both]),
+            ["title", "ours", "theirs", "end"]
+        );
+        fs::write(dir.path().join("a.md"), "title\ntheirs\nend\n").unwrap();
+        let message = git.commit_note(Path::new("a.md")).unwrap();
+        assert_eq!(message.as_deref(), Some("Merge branch 'other'"));
+        assert!(!git.merging());
+
+        let diff3 = "<<<<<<< ours\na\n||||||| base\nb\n=======\nc\n>>>>>>> theirs\n";
+        assert_eq!(
+            resolve(&conflict_regions(diff3).unwrap(), &[Side::Ours]),
+            ["a"]
+        );
+        assert_eq!(conflict_regions("plain\n=======\n"), None);
+        assert_eq!(conflict_regions("<<<<<<< open\nnever closed\n"), None);
+    }
+
+    #[test]
+    fn runs_jobs_in_the_background() {
+        let dir = repo();
+        let repo = GitVault::open(dir.path()).unwrap();
+        fs::write(dir.path().join("a.md"), "a\n").unwrap();
+        let mut worker = GitWorker::start(repo.clone(), AutoCommit::Idle, Duration::ZERO);
+        worker.saved(vec![PathBuf::from("a.md")]);
+        // The idle commit starts with the first poll.
+        let mut done = worker.poll();
+        worker.send(Job::Log(PathBuf::from("a.md")));
+        while done.len() < 2 {
+            done.extend(worker.poll());
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(matches!(&done[0], Done::Committed(Ok(m)) if m == &["Add a.md"]));
+        assert!(matches!(&done[1], Done::Log(_, Ok(log)) if log.len() == 1));
+
+        fs::write(dir.path().join("a.md"), "b\n").unwrap();
+        worker.auto_commit = AutoCommit::Save;
+        worker.saved(vec![PathBuf::from("a.md")]);
+        worker.finish();
+        assert_eq!(repo.log(Path::new("a.md")).unwrap().len(), 2);
+    }
+}
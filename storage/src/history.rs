@@ -0,0 +1,663 @@
+//! Durable undo history, per note.
+//!
+//! Every edit of a note is appended to `<vault>/.notes2/history/<note>.log`,
+//! where `<note>` is the vault-relative path of the note. Each line is one
+//! entry with tab separated fields:
+//!
+//! - `n<TAB>ms<TAB>offset<TAB>removed<TAB>inserted<TAB>sha1` is an edit
+//!   starting a new undo step: at char `offset`, the text `removed` was
+//!   replaced by `inserted`. Tabs, newlines and backslashes in the texts are
+//!   escaped.
+//! - `e<TAB>...` is an edit with the same fields joining the current step.
+//! - `u<TAB>ms<TAB>sha1` and `r<TAB>ms<TAB>sha1` undo and redo a step.
+//!
+//! `ms` is the time of the entry in milliseconds since the Unix epoch and
+//! `sha1` the hash of the note's file after it. The editors record their
+//! text, which is written in the note's [`LineFormat`], so that is what is
+//! hashed.
+//! An edit after an undo drops the steps that could have been redone.
+//!
+//! Edits are recorded as they are typed, before they are saved. When a log
+//! is opened, the entries after the last one that left the note as it is on
+//! disk are dropped: they were never saved, or another program changed the
+//! note since. Logs are rewritten without dropped and dead entries.
+
+use std::{
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::note_file::{self, LineFormat};
+
+/// Edits further apart than this go into separate undo steps.
+const GROUP_GAP: Duration = Duration::from_millis(1000);
+
+/// Number of undo steps kept per note.
+const LIMIT: usize = 1000;
+
+/// A change of a note's text: `removed` at char `offset` was replaced by
+/// `inserted`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub offset: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    /// The edit turning `old` into `new`, `None` if they are equal. Only
+    /// the part between their common start and end is kept.
+    pub fn between(old: &str, new: &str) -> Option<Edit> {
+        if old == new {
+            return None;
+        }
+        let prefix = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old: Vec<char> = old.chars().collect();
+        let new: Vec<char> = new.chars().collect();
+        let max = old.len().min(new.len()) - prefix;
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(max)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(Edit {
+            offset: prefix,
+            removed: old[prefix..old.len() - suffix].iter().collect(),
+            inserted: new[prefix..new.len() - suffix].iter().collect(),
+        })
+    }
+
+    /// Apply the edit to `text`, checking that `text` holds `removed`.
+    fn apply(&self, text: &str) -> io::Result<String> {
+        splice(text, self.offset, &self.removed, &self.inserted)
+    }
+
+    /// Take the edit back from `text`, checking that `text` holds `inserted`.
+    fn revert(&self, text: &str) -> io::Result<String> {
+        splice(text, self.offset, &self.inserted, &self.removed)
+    }
+}
+
+/// Replace `old` at char `offset` of `text` by `new`.
+fn splice(text: &str, offset: usize, old: &str, new: &str) -> io::Result<String> {
+    let byte = |chars: usize| {
+        text.char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .nth(chars)
+    };
+    let start = byte(offset);
+    let end = byte(offset + old.chars().count());
+    match (start, end) {
+        (Some(start), Some(end)) if &text[start..end] == old => {
+            Ok(format!("{}{new}{}", &text[..start], &text[end..]))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the note was changed outside of its history",
+        )),
+    }
+}
+
+/// Edits undone and redone together.
+#[derive(Debug, Clone)]
+struct Step {
+    edits: Vec<(SystemTime, Edit)>,
+    /// Hash of the text after the step, when it is known.
+    hash: Option<String>,
+}
+
+impl Step {
+    /// Whether `edit` made at `at` continues typing or deleting the text of
+    /// this step. Steps end at pauses and at the start of a new word.
+    fn joins(&self, edit: &Edit, at: SystemTime) -> bool {
+        let Some((last_at, last)) = self.edits.last() else {
+            return false;
+        };
+        if at
+            .duration_since(*last_at)
+            .map_or(true, |gap| gap > GROUP_GAP)
+        {
+            return false;
+        }
+        let boundary = |before: Option<char>, after: Option<char>| {
+            before.is_some_and(char::is_whitespace) && after.is_some_and(|c| !c.is_whitespace())
+        };
+        let inserting = |e: &Edit| e.removed.is_empty() && !e.inserted.is_empty();
+        let deleting = |e: &Edit| e.inserted.is_empty() && !e.removed.is_empty();
+        if inserting(last) && inserting(edit) {
+            edit.offset == last.offset + last.inserted.chars().count()
+                && !boundary(last.inserted.chars().last(), edit.inserted.chars().next())
+        } else if deleting(last) && deleting(edit) {
+            if edit.offset + edit.removed.chars().count() == last.offset {
+                !boundary(edit.removed.chars().last(), last.removed.chars().next())
+            } else {
+                edit.offset == last.offset
+                    && !boundary(last.removed.chars().last(), edit.removed.chars().next())
+            }
+        } else {
+            false
+        }
+    }
+}
+
+/// An earlier text of a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// When the note got this text.
+    pub at: SystemTime,
+    pub text: String,
+}
+
+/// Undo history of one note.
+#[derive(Debug, Default)]
+pub struct NoteHistory {
+    file: Option<PathBuf>,
+    steps: Vec<Step>,
+    /// Hash of the text before the first step, when it is known.
+    base: Option<String>,
+    /// Number of steps applied to the text; the rest can be redone.
+    head: usize,
+    /// The next edit starts a new step.
+    sealed: bool,
+    /// How the note is written to disk.
+    format: LineFormat,
+}
+
+impl NoteHistory {
+    /// Load the history of the vault-relative `note` in the vault at
+    /// `vault`. A missing log yields an empty history. Entries after the
+    /// text of the note on disk are dropped.
+    pub fn open(vault: &Path, note: &Path) -> io::Result<Self> {
+        let mut file = vault
+            .join(".notes2")
+            .join("history")
+            .join(note)
+            .into_os_string();
+        file.push(".log");
+        let on_disk = fs::read_to_string(vault.join(note)).ok();
+        let mut history = Self {
+            file: Some(file.into()),
+            format: on_disk.as_deref().map(LineFormat::of).unwrap_or_default(),
+            ..Self::default()
+        };
+        let content = match fs::read_to_string(history.file.as_ref().unwrap()) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(e),
+        };
+        let entries: Vec<Entry> = content.lines().filter_map(Entry::parse).collect();
+        let on_disk = on_disk.map(|content| hash(&content));
+        let keep = entries
+            .iter()
+            .rposition(|entry| on_disk.is_some() && entry.hash == on_disk)
+            .map_or(0, |i| i + 1);
+        // Hash of the text after the entries replayed so far.
+        let mut current = None;
+        for entry in &entries[..keep] {
+            match &entry.kind {
+                Kind::Edit(edit, new_step) => {
+                    if *new_step && history.head == 0 {
+                        history.base = current.take();
+                    }
+                    history.push(edit.clone(), entry.at, *new_step, entry.hash.clone());
+                }
+                Kind::Undo => history.head = history.head.saturating_sub(1),
+                Kind::Redo => history.head = (history.head + 1).min(history.steps.len()),
+            }
+            current = entry.hash.clone();
+        }
+        history.sealed = true;
+        let live: usize = history.steps.iter().map(|s| s.edits.len()).sum();
+        if keep < entries.len() || keep > 2 * live + 100 {
+            history.compact()?;
+        }
+        Ok(history)
+    }
+
+    /// A history that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.head > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.head < self.steps.len()
+    }
+
+    /// End the current step, so the next edit starts a new one.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// Record the change of the note's text from `old` to `new`.
+    pub fn record(&mut self, old: &str, new: &str) -> io::Result<()> {
+        let Some(edit) = Edit::between(old, new) else {
+            return Ok(());
+        };
+        if self.head == 0 {
+            self.base = Some(self.hash(old));
+        }
+        self.add(edit, SystemTime::now(), Some(self.hash(new)))
+    }
+
+    /// Record `edit`, made at `at`, grouping it with the edits before it
+    /// when it continues them.
+    pub fn record_at(&mut self, edit: Edit, at: SystemTime) -> io::Result<()> {
+        self.add(edit, at, None)
+    }
+
+    /// Hash of the note's file holding `text`.
+    fn hash(&self, text: &str) -> String {
+        hash(&self.format.apply(text))
+    }
+
+    /// Record `edit` leaving a text with the hash `after`.
+    fn add(&mut self, edit: Edit, at: SystemTime, after: Option<String>) -> io::Result<()> {
+        let new_step = self.sealed
+            || self.head < self.steps.len()
+            || !self.steps.last().is_some_and(|s| s.joins(&edit, at));
+        let line = edit_line(new_step, at, &edit, after.as_deref());
+        self.push(edit, at, new_step, after);
+        self.sealed = false;
+        self.append(&line)
+    }
+
+    /// Undo the last step of `text`, the note's current text. Returns the
+    /// earlier text and the char offset of the change, or `None` when there
+    /// is nothing to undo.
+    pub fn undo(&mut self, text: &str) -> io::Result<Option<(String, usize)>> {
+        if self.head == 0 {
+            return Ok(None);
+        }
+        let step = &self.steps[self.head - 1];
+        let mut text = text.to_string();
+        for (_, edit) in step.edits.iter().rev() {
+            text = edit.revert(&text)?;
+        }
+        let cursor = step.edits[0].1.offset;
+        self.head -= 1;
+        self.sealed = true;
+        self.append(&format!(
+            "u\t{}\t{}",
+            millis(SystemTime::now()),
+            self.hash(&text)
+        ))?;
+        Ok(Some((text, cursor)))
+    }
+
+    /// Redo the last undone step of `text`, the note's current text.
+    /// Returns the later text and the char offset after the change, or
+    /// `None` when there is nothing to redo.
+    pub fn redo(&mut self, text: &str) -> io::Result<Option<(String, usize)>> {
+        let Some(step) = self.steps.get(self.head) else {
+            return Ok(None);
+        };
+        let mut text = text.to_string();
+        for (_, edit) in &step.edits {
+            text = edit.apply(&text)?;
+        }
+        let last = &step.edits[step.edits.len() - 1].1;
+        let cursor = last.offset + last.inserted.chars().count();
+        self.head += 1;
+        self.sealed = true;
+        self.append(&format!(
+            "r\t{}\t{}",
+            millis(SystemTime::now()),
+            self.hash(&text)
+        ))?;
+        Ok(Some((text, cursor)))
+    }
+
+    /// Earlier texts of the note, oldest first, down to `text`, its current
+    /// text. Stops at the first step that does not match, which happens when
+    /// the note was changed by another program.
+    pub fn versions(&self, text: &str) -> Vec<Version> {
+        let mut versions = Vec::new();
+        let mut text = text.to_string();
+        for step in self.steps[..self.head].iter().rev() {
+            let at = step.edits[step.edits.len() - 1].0;
+            let mut earlier = Ok(text.clone());
+            for (_, edit) in step.edits.iter().rev() {
+                earlier = earlier.and_then(|t| edit.revert(&t));
+            }
+            versions.push(Version { at, text });
+            match earlier {
+                Ok(earlier) => text = earlier,
+                Err(_) => {
+                    versions.reverse();
+                    return versions;
+                }
+            }
+        }
+        if let Some(first) = self.steps.first() {
+            versions.push(Version {
+                at: first.edits[0].0,
+                text,
+            });
+        }
+        versions.reverse();
+        versions
+    }
+
+    fn push(&mut self, edit: Edit, at: SystemTime, new_step: bool, hash: Option<String>) {
+        self.steps.truncate(self.head);
+        match self.steps.last_mut() {
+            Some(step) if !new_step => {
+                step.edits.push((at, edit));
+                step.hash = hash;
+            }
+            _ => {
+                self.steps.push(Step {
+                    edits: vec![(at, edit)],
+                    hash,
+                });
+                if self.steps.len() > LIMIT {
+                    self.base = self.steps.remove(0).hash;
+                }
+            }
+        }
+        self.head = self.steps.len();
+    }
+
+    fn append(&self, line: &str) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)?;
+        writeln!(log, "{line}")
+    }
+
+    /// Rewrite the log with only the steps that can still be undone or
+    /// redone.
+    fn compact(&self) -> io::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut out = String::new();
+        for step in &self.steps {
+            let last = step.edits.len() - 1;
+            for (i, (at, edit)) in step.edits.iter().enumerate() {
+                // Only the text after the whole step is known.
+                let hash = if i == last {
+                    step.hash.as_deref()
+                } else {
+                    None
+                };
+                out.push_str(&edit_line(i == 0, *at, edit, hash));
+                out.push('\n');
+            }
+        }
+        let now = millis(SystemTime::now());
+        for i in (self.head..self.steps.len()).rev() {
+            let before = match i {
+                0 => &self.base,
+                _ => &self.steps[i - 1].hash,
+            };
+            out.push_str(&format!("u\t{now}\t{}\n", before.as_deref().unwrap_or("")));
+        }
+        note_file::replace(file, &out).map(|_| ())
+    }
+}
+
+/// One line of a log.
+struct Entry {
+    at: SystemTime,
+    kind: Kind,
+    /// Hash of the text after the entry.
+    hash: Option<String>,
+}
+
+enum Kind {
+    /// An edit and whether it starts a new step.
+    Edit(Edit, bool),
+    Undo,
+    Redo,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let at = UNIX_EPOCH + Duration::from_millis(fields.get(1)?.parse().ok()?);
+        let (kind, hash) = match (fields[0], &fields[2..]) {
+            (kind @ ("n" | "e"), [offset, removed, inserted, hash @ ..]) => {
+                let edit = Edit {
+                    offset: offset.parse().ok()?,
+                    removed: unescape(removed),
+                    inserted: unescape(inserted),
+                };
+                (Kind::Edit(edit, kind == "n"), hash)
+            }
+            ("u", hash) => (Kind::Undo, hash),
+            ("r", hash) => (Kind::Redo, hash),
+            _ => return None,
+        };
+        Some(Self {
+            at,
+            kind,
+            hash: hash
+                .first()
+                .filter(|h| !h.is_empty())
+                .map(|h| h.to_string()),
+        })
+    }
+}
+
+/// The log line of `edit`, made at `at` and leaving a text with the hash
+/// `hash`.
+fn edit_line(new_step: bool, at: SystemTime, edit: &Edit, hash: Option<&str>) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        if new_step { "n" } else { "e" },
+        millis(at),
+        edit.offset,
+        escape(&edit.removed),
+        escape(&edit.inserted),
+        hash.unwrap_or("")
+    )
+}
+
+/// Hash of a note's text, as kept in the log.
+fn hash(text: &str) -> String {
+    format!("{:x}", Sha1::digest(text.as_bytes()))
+}
+
+fn millis(at: SystemTime) -> u128 {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type `text` one char at a time at the end of `note`, `gap` apart.
+    fn type_text(history: &mut NoteHistory, note: &mut String, text: &str, at: &mut SystemTime) {
+        for c in text.chars() {
+            let old = note.clone();
+            note.push(c);
+            history
+                .record_at(Edit::between(&old, note).unwrap(), *at)
+                .unwrap();
+            *at += Duration::from_millis(100);
+        }
+    }
+
+    #[test]
+    fn diffs_texts() {
+        let edit = Edit::between("hello world", "hello brave world").unwrap();
+        assert_eq!(edit.offset, 6);
+        assert_eq!(edit.removed, "");
+        assert_eq!(edit.inserted, "brave ");
+        let edit = Edit::between("aaa", "aa").unwrap();
+        assert_eq!((edit.offset, edit.removed.as_str()), (2, "a"));
+        assert_eq!(Edit::between("same", "same"), None);
+        assert_eq!(edit.revert("aa").unwrap(), "aaa");
+        assert!(edit.apply("bbb").is_err());
+    }
+
+    #[test]
+    fn groups_words_and_pauses() {
+        let mut history = NoteHistory::in_memory();
+        let mut note = String::new();
+        let mut at = UNIX_EPOCH + Duration::from_secs(1_000);
+        type_text(&mut history, &mut note, "hello wo", &mut at);
+        at += Duration::from_secs(5);
+        type_text(&mut history, &mut note, "rld", &mut at);
+        let old = note.clone();
+        note.pop();
+        history
+            .record_at(Edit::between(&old, &note).unwrap(), at)
+            .unwrap();
+
+        let mut undo = |note: &mut String| {
+            let (text, _) = history.undo(note).unwrap().unwrap();
+            *note = text;
+        };
+        undo(&mut note);
+        assert_eq!(note, "hello world");
+        undo(&mut note);
+        assert_eq!(note, "hello wo");
+        undo(&mut note);
+        assert_eq!(note, "hello ");
+        undo(&mut note);
+        assert_eq!(note, "");
+        assert!(!history.can_undo());
+        assert_eq!(history.redo(&note).unwrap(), Some(("hello ".into(), 6)));
+    }
+
+    #[test]
+    fn survives_reopening() {
+        let vault = tempfile::tempdir().unwrap();
+        let note = Path::new("dir/a.md");
+        let save = |text: &str| {
+            fs::create_dir_all(vault.path().join("dir")).unwrap();
+            fs::write(vault.path().join(note), format!("{text}\n")).unwrap();
+        };
+        let mut history = NoteHistory::open(vault.path(), note).unwrap();
+        history.record("", "one").unwrap();
+        history.record("one", "two\tone\nthree").unwrap();
+        let (text, cursor) = history.undo("two\tone\nthree").unwrap().unwrap();
+        assert_eq!((text.as_str(), cursor), ("one", 0));
+        assert!(vault.path().join(".notes2/history/dir/a.md.log").exists());
+        save("one");
+
+        let mut history = NoteHistory::open(vault.path(), note).unwrap();
+        assert!(history.can_redo());
+        let versions = history.versions("one");
+        let texts: Vec<&str> = versions.iter().map(|v| v.text.as_str()).collect();
+        assert_eq!(texts, ["", "one"]);
+        let (text, _) = history.redo("one").unwrap().unwrap();
+        assert_eq!(text, "two\tone\nthree");
+        history.undo(&text).unwrap();
+        history.record("one", "one!").unwrap();
+        save("one!");
+
+        let history = NoteHistory::open(vault.path(), note).unwrap();
+        assert!(!history.can_redo());
+        let versions = history.versions("one!");
+        let texts: Vec<&str> = versions.iter().map(|v| v.text.as_str()).collect();
+        assert_eq!(texts, ["", "one", "one!"]);
+        assert_eq!(history.versions("changed elsewhere").len(), 1);
+    }
+
+    #[test]
+    fn drops_edits_that_were_not_saved() {
+        let vault = tempfile::tempdir().unwrap();
+        let note = Path::new("a.md");
+        let file = vault.path().join(note);
+        fs::write(&file, "one\n").unwrap();
+        let mut history = NoteHistory::open(vault.path(), note).unwrap();
+        history.record("one", "one two").unwrap();
+        fs::write(&file, "one two\n").unwrap();
+        history.record("one two", "one two three").unwrap();
+
+        // Quit without saving the last edit.
+        let mut history = NoteHistory::open(vault.path(), note).unwrap();
+        assert!(!history.can_redo());
+        assert_eq!(history.undo("one two").unwrap(), Some(("one".into(), 3)));
+        assert!(!history.can_undo());
+        fs::write(&file, "one\n").unwrap();
+        let history = NoteHistory::open(vault.path(), note).unwrap();
+        assert!(!history.can_undo() && history.can_redo());
+
+        // Another program changed the note.
+        fs::write(&file, "other\n").unwrap();
+        let history = NoteHistory::open(vault.path(), note).unwrap();
+        assert!(!history.can_undo() && !history.can_redo());
+        let log = vault.path().join(".notes2/history/a.md.log");
+        assert_eq!(fs::read_to_string(log).unwrap(), "");
+    }
+
+    #[test]
+    fn hashes_notes_as_they_are_on_disk() {
+        let vault = tempfile::tempdir().unwrap();
+        let note = Path::new("a.md");
+        let file = vault.path().join(note);
+        for content in ["one\r\ntwo\r\n", "one\ntwo"] {
+            fs::write(&file, content).unwrap();
+            let format = LineFormat::of(content);
+            let mut history = NoteHistory::open(vault.path(), note).unwrap();
+            history.record("one\ntwo", "one\n2").unwrap();
+            fs::write(&file, format.apply("one\n2")).unwrap();
+
+            let mut history = NoteHistory::open(vault.path(), note).unwrap();
+            assert!(history.can_undo(), "{content:?}");
+            let (text, _) = history.undo("one\n2").unwrap().unwrap();
+            assert_eq!(text, "one\ntwo");
+        }
+    }
+}
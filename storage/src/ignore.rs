@@ -0,0 +1,298 @@
+//! Files of a vault that are left out of its tree, search and export.
+//!
+//! The rules come from a `.notesignore` file at the root of the vault,
+//! written like a `.gitignore`: one pattern per line, `#` for comments, `!`
+//! to include again what an earlier pattern left out, a trailing `/` for
+//! folders only and a `/` elsewhere to match from the root of the vault
+//! instead of anywhere. `*` and `?` match within one path component, `**`
+//! across several, and `[a-z]` one character of a class.
+//!
+//! Hidden entries, editor swap files and OS junk are left out by default,
+//! before the rules of the file apply. Hidden entries can be shown with
+//! [`IgnoreRules::set_show_hidden`], except for the `.git` folder and the
+//! `.notes2` cache of the vault.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Name of the ignore file at the root of a vault.
+pub const FILE_NAME: &str = ".notesignore";
+
+/// Left out unless hidden entries are shown.
+const HIDDEN: &[&str] = &[".*"];
+
+/// Always left out.
+const DEFAULTS: &[&str] = &[
+    ".git/",
+    ".notes2/",
+    "*~",
+    "*.swp",
+    "*.swo",
+    "*.tmp",
+    "\\#*#",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// The ignore rules of a vault.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    rules: Vec<Rule>,
+    show_hidden: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Vec<char>,
+    /// Whether matches are included again.
+    negate: bool,
+    dir_only: bool,
+    /// Whether the pattern matches the whole vault-relative path rather
+    /// than the file name.
+    anchored: bool,
+    /// Whether the rule is skipped while hidden entries are shown.
+    hidden: bool,
+}
+
+impl Rule {
+    /// The rule of one line of an ignore file, `None` for blank lines and
+    /// comments.
+    fn parse(line: &str) -> Option<Self> {
+        let mut line = line.trim_end_matches(['\r', '\n']);
+        if line.starts_with('#') {
+            return None;
+        }
+        // Trailing spaces count only when escaped.
+        while line.ends_with(' ') && !line[..line.len() - 1].ends_with('\\') {
+            line = &line[..line.len() - 1];
+        }
+        let negate = line.starts_with('!');
+        if negate {
+            line = &line[1..];
+        }
+        let dir_only = line.ends_with('/') && !line.ends_with("\\/");
+        if dir_only {
+            line = &line[..line.len() - 1];
+        }
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Self {
+            glob: line.chars().collect(),
+            negate,
+            dir_only,
+            anchored,
+            hidden: false,
+        })
+    }
+
+    fn matches(&self, path: &[char], name: &[char], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        glob(&self.glob, if self.anchored { path } else { name })
+    }
+}
+
+impl IgnoreRules {
+    /// The default rules of the vault at `root`, without reading its
+    /// ignore file.
+    pub fn new(root: &Path) -> Self {
+        let hidden = HIDDEN
+            .iter()
+            .filter_map(|p| Rule::parse(p))
+            .map(|rule| Rule {
+                hidden: true,
+                ..rule
+            });
+        Self {
+            root: root.to_path_buf(),
+            rules: hidden
+                .chain(DEFAULTS.iter().filter_map(|p| Rule::parse(p)))
+                .collect(),
+            show_hidden: false,
+        }
+    }
+
+    /// The rules of the vault at `root`: the defaults followed by those of
+    /// its ignore file. A missing or unreadable file leaves the defaults.
+    pub fn load(root: &Path) -> Self {
+        let mut rules = Self::new(root);
+        if let Ok(text) = fs::read_to_string(root.join(FILE_NAME)) {
+            rules.add(&text);
+        }
+        rules
+    }
+
+    /// Add the patterns of `text`, one per line, after the current ones.
+    pub fn add(&mut self, text: &str) {
+        self.rules.extend(text.lines().filter_map(Rule::parse));
+    }
+
+    /// Whether hidden entries are shown.
+    pub fn show_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
+    /// Show or leave out hidden entries.
+    pub fn set_show_hidden(&mut self, show: bool) {
+        self.show_hidden = show;
+    }
+
+    /// Whether `path` is the ignore file of the vault.
+    pub fn is_rules_file(&self, path: &Path) -> bool {
+        path == self.root.join(FILE_NAME)
+    }
+
+    /// Whether `path`, below the root of the vault or relative to it, is
+    /// left out. Everything inside a folder that is left out is too.
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        let rel = path.strip_prefix(&self.root).unwrap_or(path);
+        let names: Vec<Vec<char>> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().chars().collect()),
+                _ => None,
+            })
+            .collect();
+        let mut path = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            if i > 0 {
+                path.push('/');
+            }
+            path.extend(name);
+            let last = i + 1 == names.len();
+            if self.matched(&path, name, is_dir || !last) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether the last rule matching `path` leaves it out.
+    fn matched(&self, path: &[char], name: &[char], is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| !(rule.hidden && self.show_hidden))
+            .find(|rule| rule.matches(path, name, is_dir))
+            .is_some_and(|rule| !rule.negate)
+    }
+}
+
+/// Whether `text` matches the pattern `pattern`.
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // Any number of folders, including none.
+            glob(rest, text)
+                || (0..text.len()).any(|i| text[i] == '/' && glob(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob(rest, &text[i..])),
+        ['*', rest @ ..] => {
+            let within = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=within).any(|i| glob(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => text.first().is_some_and(|c| *c != '/') && glob(rest, &text[1..]),
+        ['[', class @ ..] => match (text.first(), class_end(class)) {
+            (Some(&c), Some(end)) => {
+                c != '/' && in_class(&class[..end], c) && glob(&class[end + 1..], &text[1..])
+            }
+            (None, Some(_)) => false,
+            // Without a closing bracket, `[` is an ordinary character.
+            (_, None) => text.first() == Some(&'[') && glob(class, &text[1..]),
+        },
+        ['\\', c, rest @ ..] | [c, rest @ ..] => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+/// Index of the `]` closing the character class `class` starts, which
+/// comes after the `[`.
+fn class_end(class: &[char]) -> Option<usize> {
+    let start = match class {
+        ['!' | '^', ..] => 1,
+        _ => 0,
+    };
+    // A `]` right at the start belongs to the class.
+    (start + 1..class.len()).find(|i| class[*i] == ']')
+}
+
+/// Whether `c` is in the character class `class`, without the brackets.
+fn in_class(class: &[char], c: char) -> bool {
+    let (negate, mut items) = match class {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    while let Some(&first) = items.first() {
+        match items {
+            [low, '-', high, rest @ ..] => {
+                found |= (*low..=*high).contains(&c);
+                items = rest;
+            }
+            _ => {
+                found |= first == c;
+                items = &items[1..];
+            }
+        }
+    }
+    found != negate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_gitignore_syntax() {
+        let root = Path::new("/vault");
+        let mut rules = IgnoreRules::new(root);
+        rules.add(
+            "# build output\n\
+             target/\n\
+             /drafts\n\
+             *.log\n\
+             !keep.log\n\
+             archive/**/old-*.md\n\
+             notes/[0-9][0-9].md\n\
+             !.github/\n",
+        );
+        let ignored = |path: &str, is_dir| rules.ignores(&root.join(path), is_dir);
+
+        assert!(ignored(".git", true));
+        assert!(ignored(".git/config", false));
+        assert!(ignored(".notes2/folds.json", false));
+        assert!(ignored("sub/.a.md.123.tmp", false));
+        assert!(ignored("sub/a.md.swp", false));
+        assert!(ignored("a.md~", false));
+        assert!(ignored("sub/.DS_Store", false));
+        assert!(!ignored(".github", true));
+
+        assert!(ignored("target", true));
+        assert!(ignored("sub/target/x.md", false));
+        assert!(!ignored("target", false));
+        assert!(ignored("drafts/a.md", false));
+        assert!(!ignored("sub/drafts/a.md", false));
+        assert!(ignored("sub/debug.log", false));
+        assert!(!ignored("sub/keep.log", false));
+        assert!(ignored("archive/old-a.md", false));
+        assert!(ignored("archive/2020/01/old-a.md", false));
+        assert!(!ignored("archive/new-a.md", false));
+        assert!(ignored("notes/42.md", false));
+        assert!(!ignored("notes/4a.md", false));
+        assert!(!ignored("notes/a.md", false));
+        assert!(!ignored("", true));
+
+        rules.set_show_hidden(true);
+        assert!(!rules.ignores(&root.join(".hidden/a.md"), false));
+        assert!(rules.ignores(&root.join(".git"), true));
+        assert!(rules.ignores(Path::new("sub/.DS_Store"), false));
+    }
+}
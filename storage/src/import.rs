@@ -0,0 +1,789 @@
+//! Import of notes written with other Markdown tools.
+//!
+//! [`import`] copies a foreign folder into the vault and rewrites what this
+//! app does not understand: `[[wikilinks]]` become `note://` links, `![[embeds]]`
+//! become `include-note` or `image` macros and front matter keys are mapped to
+//! the ones used here. Anything that cannot be converted faithfully is listed
+//! in the returned [`Report`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use markdown_renderer::links::{percent_decode, slash_path, slug};
+use serde_yaml::{Mapping, Value};
+
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "avif"];
+
+/// Something that was not converted faithfully.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Note the issue was found in, relative to the imported folder.
+    pub note: PathBuf,
+    /// 1-based line number, or 0 for the front matter.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Outcome of an import.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub notes: usize,
+    /// Attachments copied next to the notes.
+    pub files: usize,
+    pub links: usize,
+    pub embeds: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    fn issue(&mut self, note: &Path, line: usize, message: impl Into<String>) {
+        self.issues.push(Issue {
+            note: note.to_path_buf(),
+            line,
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Imported {} notes and {} files, converted {} links and {} embeds.",
+            self.notes, self.files, self.links, self.embeds
+        )?;
+        if self.issues.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "\n{} items need attention:\n", self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(
+                f,
+                "{}:{}: {}",
+                issue.note.display(),
+                issue.line,
+                issue.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn key(text: &str) -> String {
+    text.trim().to_lowercase().replace('\\', "/")
+}
+
+/// `to` relative to the directory `from`, both relative to the same root.
+fn relative(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to_parts: Vec<Component> = to.components().collect();
+    let common = from
+        .iter()
+        .zip(&to_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut out: PathBuf = std::iter::repeat_n("..", from.len() - common).collect();
+    out.extend(&to_parts[common..]);
+    out
+}
+
+/// Markdown link destination, in angle brackets when it would otherwise end
+/// early.
+fn destination(url: &str) -> String {
+    if url.contains([' ', '(', ')']) {
+        format!("<{url}>")
+    } else {
+        url.to_string()
+    }
+}
+
+fn is_image(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Where a link target points to.
+enum Target<'a> {
+    Found(&'a Path),
+    Missing,
+}
+
+/// Notes and attachments of the imported folder, by the names links use.
+#[derive(Default)]
+struct Index {
+    /// Lowercased path without extension and lowercased file stem or alias,
+    /// to the notes with that name.
+    notes: HashMap<String, Vec<PathBuf>>,
+    /// Lowercased path and lowercased file name of attachments.
+    files: HashMap<String, Vec<PathBuf>>,
+}
+
+impl Index {
+    fn add_note(&mut self, rel: &Path, aliases: &[String]) {
+        let path = key(&slash_path(&rel.with_extension("")));
+        let stem = rel.file_stem().unwrap_or_default().to_string_lossy();
+        let mut names = vec![path, key(&stem)];
+        names.extend(aliases.iter().map(|a| key(a)));
+        names.dedup();
+        for name in names {
+            let paths = self.notes.entry(name).or_default();
+            if !paths.iter().any(|p| p == rel) {
+                paths.push(rel.to_path_buf());
+            }
+        }
+    }
+
+    fn add_file(&mut self, rel: &Path) {
+        let name = rel.file_name().unwrap_or_default().to_string_lossy();
+        for name in [key(&slash_path(rel)), key(&name)] {
+            self.files.entry(name).or_default().push(rel.to_path_buf());
+        }
+    }
+
+    /// Pick among several matches like the tools that wrote them: a note in
+    /// the same folder first, then the one with the shortest path.
+    fn pick<'a>(
+        candidates: Option<&'a Vec<PathBuf>>,
+        target: &str,
+        note: &Path,
+        line: usize,
+        report: &mut Report,
+    ) -> Target<'a> {
+        let Some(candidates) = candidates.filter(|c| !c.is_empty()) else {
+            return Target::Missing;
+        };
+        if candidates.len() == 1 {
+            return Target::Found(&candidates[0]);
+        }
+        let dir = note.parent().unwrap_or(Path::new(""));
+        let best = candidates
+            .iter()
+            .find(|c| c.parent() == Some(dir))
+            .or_else(|| candidates.iter().min_by_key(|c| c.components().count()))
+            .expect("candidates are not empty");
+        report.issue(
+            note,
+            line,
+            format!(
+                "`{target}` is ambiguous ({} matches), linked to {}",
+                candidates.len(),
+                best.display()
+            ),
+        );
+        Target::Found(best)
+    }
+
+    fn note(&self, target: &str, from: &Path, line: usize, report: &mut Report) -> Target<'_> {
+        let name = key(target.trim_end_matches(".md"));
+        Self::pick(self.notes.get(&name), target, from, line, report)
+    }
+
+    fn file(&self, target: &str, from: &Path, line: usize, report: &mut Report) -> Target<'_> {
+        Self::pick(self.files.get(&key(target)), target, from, line, report)
+    }
+}
+
+/// Values of a front matter list, which other tools also write as a comma
+/// or space separated string.
+fn list(value: &Value, separators: &[char]) -> Vec<String> {
+    let items: Vec<String> = match value {
+        Value::Sequence(items) => items
+            .iter()
+            .filter_map(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Value::String(s) => s.split(separators).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    let mut out: Vec<String> = Vec::new();
+    for item in items {
+        let item = item.trim().trim_start_matches('#').to_string();
+        if !item.is_empty() && !out.contains(&item) {
+            out.push(item);
+        }
+    }
+    out
+}
+
+/// Front matter keys of other tools and the key used here.
+fn canonical_key(key: &str) -> Option<&'static str> {
+    Some(match key.to_lowercase().replace(['_', '-'], " ").as_str() {
+        "title" => "title",
+        "alias" | "aliases" => "aliases",
+        "tag" | "tags" | "keywords" => "tags",
+        "created" | "date" | "created at" | "creation date" | "date created" => "created",
+        "updated" | "modified" | "updated at" | "last modified" | "date modified" => "updated",
+        _ => return None,
+    })
+}
+
+/// Split `text` into its front matter, if any, and the body.
+fn split_front_matter(text: &str) -> (Option<&str>, &str) {
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return (None, text);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, text)
+}
+
+/// Front matter with the canonical keys first, in a fixed order, followed by
+/// the remaining keys as they were.
+struct FrontMatter {
+    fields: Mapping,
+    aliases: Vec<String>,
+}
+
+fn normalise_front_matter(yaml: &str, note: &Path, report: &mut Report) -> Option<FrontMatter> {
+    let parsed: Value = match serde_yaml::from_str(yaml) {
+        Ok(value) => value,
+        Err(err) => {
+            report.issue(
+                note,
+                0,
+                format!("front matter is not valid YAML, kept as is: {err}"),
+            );
+            return None;
+        }
+    };
+    let mapping = match parsed {
+        Value::Mapping(mapping) => mapping,
+        Value::Null => Mapping::new(),
+        _ => {
+            report.issue(note, 0, "front matter is not a mapping, kept as is");
+            return None;
+        }
+    };
+    let mut known: HashMap<&str, Value> = HashMap::new();
+    let mut rest = Mapping::new();
+    for (k, v) in mapping {
+        let canonical = k.as_str().and_then(canonical_key);
+        match canonical {
+            Some(name) if !known.contains_key(name) => {
+                let value = match name {
+                    "tags" => Value::from(list(&v, &[',', ' '])),
+                    "aliases" => Value::from(list(&v, &[','])),
+                    _ => v,
+                };
+                known.insert(name, value);
+            }
+            Some(name) => report.issue(
+                note,
+                0,
+                format!("duplicate `{name}` front matter key dropped"),
+            ),
+            None => {
+                rest.insert(k, v);
+            }
+        }
+    }
+    let aliases = known
+        .get("aliases")
+        .map(|v| list(v, &[',']))
+        .unwrap_or_default();
+    let mut fields = Mapping::new();
+    for name in ["title", "aliases", "tags", "created", "updated"] {
+        match known.remove(name) {
+            Some(Value::Sequence(items)) if items.is_empty() => {}
+            Some(value) => {
+                fields.insert(name.into(), value);
+            }
+            None => {}
+        }
+    }
+    fields.extend(rest);
+    Some(FrontMatter { fields, aliases })
+}
+
+/// Rewrites the notes of one import.
+struct Converter<'a> {
+    index: &'a Index,
+    /// Imported folder, relative to the vault.
+    into: &'a Path,
+    report: &'a mut Report,
+}
+
+impl Converter<'_> {
+    fn vault_path(&self, rel: &Path) -> String {
+        slash_path(&self.into.join(rel))
+    }
+
+    /// Markdown for `[[inner]]` found in `note`.
+    fn link(&mut self, inner: &str, note: &Path, line: usize) -> String {
+        let (target, alias) = match inner.split_once('|') {
+            Some((t, a)) => (t, Some(a.trim())),
+            None => (inner, None),
+        };
+        let (name, section) = match target.split_once('#') {
+            Some((n, s)) => (n.trim(), Some(s.trim())),
+            None => (target.trim(), None),
+        };
+        let text = match (alias, section) {
+            (Some(alias), _) => alias.to_string(),
+            (None, Some(heading)) if !heading.starts_with('^') => format!("{name} > {heading}"),
+            (None, _) => name.to_string(),
+        };
+        let anchor = match section {
+            Some(block) if block.starts_with('^') => {
+                self.report.issue(
+                    note,
+                    line,
+                    format!("block reference `{block}` in [[{inner}]] dropped, linked to the note"),
+                );
+                String::new()
+            }
+            Some(heading) => format!("#{}", slug(heading)),
+            None => String::new(),
+        };
+        let path = if name.is_empty() {
+            // `[[#Heading]]` points into the same note.
+            note.to_path_buf()
+        } else {
+            match self.index.note(name, note, line, self.report) {
+                Target::Found(path) => path.to_path_buf(),
+                Target::Missing => {
+                    self.report.issue(
+                        note,
+                        line,
+                        format!("link target `{name}` not found, linked to a new note"),
+                    );
+                    let dir = note.parent().unwrap_or(Path::new(""));
+                    dir.join(format!("{name}.md"))
+                }
+            }
+        };
+        self.report.links += 1;
+        let url = format!("note://{}{anchor}", self.vault_path(&path));
+        format!("[{text}]({})", destination(&url))
+    }
+
+    /// Markdown for `![[inner]]` found in `note`, or `None` when the embed is
+    /// left alone. Macros only work as whole paragraphs, so an `inline` embed,
+    /// such as one in a list item or table row, becomes an image or a link.
+    fn embed(&mut self, inner: &str, note: &Path, line: usize, inline: bool) -> Option<String> {
+        let target = inner.split('|').next().unwrap_or(inner).trim();
+        let (name, section) = match target.split_once('#') {
+            Some((n, s)) => (n.trim(), Some(s)),
+            None => (target, None),
+        };
+        let is_note = Path::new(name).extension().is_none_or(|e| e == "md");
+        if is_note {
+            let Target::Found(path) = self.index.note(name, note, line, self.report) else {
+                self.report.issue(
+                    note,
+                    line,
+                    format!("embedded note `{name}` not found, left as is"),
+                );
+                return None;
+            };
+            if let Some(section) = section {
+                self.report.issue(
+                    note,
+                    line,
+                    format!("embed of `#{section}` includes all of {}", path.display()),
+                );
+            }
+            let path = self.vault_path(path);
+            if inline {
+                self.report.issue(
+                    note,
+                    line,
+                    format!("`{name}` is embedded inside a line, linked instead"),
+                );
+                self.report.links += 1;
+                let url = format!("note://{path}");
+                return Some(format!("[{name}]({})", destination(&url)));
+            }
+            self.report.embeds += 1;
+            return Some(format!("{{% include-note {path} %}}"));
+        }
+        let Target::Found(path) = self.index.file(name, note, line, self.report) else {
+            self.report.issue(
+                note,
+                line,
+                format!("embedded file `{name}` not found, left as is"),
+            );
+            return None;
+        };
+        self.report.embeds += 1;
+        let dir = note.parent().unwrap_or(Path::new(""));
+        let url = slash_path(&relative(dir, path));
+        if is_image(name) && inline {
+            return Some(format!("![{name}]({})", destination(&url)));
+        }
+        if is_image(name) {
+            return Some(format!("{{% image {} %}}", self.vault_path(path)));
+        }
+        self.report.issue(
+            note,
+            line,
+            format!("`{name}` cannot be embedded, linked instead"),
+        );
+        Some(format!("[{name}]({})", destination(&url)))
+    }
+
+    /// Rewrite a relative link to a Markdown file into a `note://` link.
+    fn markdown_link(&mut self, url: &str, note: &Path) -> Option<String> {
+        let (path, anchor) = match url.split_once('#') {
+            Some((p, a)) => (p, format!("#{a}")),
+            None => (url, String::new()),
+        };
+        if path.contains("://") || !path.ends_with(".md") {
+            return None;
+        }
+        let decoded = percent_decode(path);
+        let dir = note.parent().unwrap_or(Path::new(""));
+        let mut resolved = PathBuf::new();
+        for part in dir.join(&decoded).components() {
+            match part {
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(part) => resolved.push(part),
+                _ => {}
+            }
+        }
+        self.report.links += 1;
+        Some(destination(&format!(
+            "note://{}{anchor}",
+            self.vault_path(&resolved)
+        )))
+    }
+
+    /// Convert one line outside of code blocks.
+    fn line(&mut self, text: &str, note: &Path, line: usize) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            if rest.starts_with('`') {
+                // Code spans are copied verbatim.
+                let ticks = rest.len() - rest.trim_start_matches('`').len();
+                let fence = &rest[..ticks];
+                let end = rest[ticks..]
+                    .find(fence)
+                    .map_or(rest.len(), |i| ticks + i + ticks);
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            let embed = rest.starts_with("![[");
+            if embed || rest.starts_with("[[") {
+                let open = if embed { 3 } else { 2 };
+                if let Some(close) = rest[open..].find("]]") {
+                    let inner = &rest[open..open + close];
+                    let whole = &rest[..open + close + 2];
+                    rest = &rest[open + close + 2..];
+                    if !embed {
+                        out.push_str(&self.link(inner, note, line));
+                        continue;
+                    }
+                    let inline = text.trim() != whole;
+                    match self.embed(inner, note, line, inline) {
+                        Some(m) => out.push_str(&m),
+                        None => out.push_str(whole),
+                    }
+                    continue;
+                }
+            }
+            if let Some(after) = rest.strip_prefix("](") {
+                let (url, len) = match after.strip_prefix('<') {
+                    Some(inner) => match inner.find('>') {
+                        Some(end) => (&inner[..end], end + 2),
+                        None => (after, after.len()),
+                    },
+                    None => {
+                        let end = after.find([')', ' ']).unwrap_or(after.len());
+                        (&after[..end], end)
+                    }
+                };
+                if let Some(url) = self.markdown_link(url, note) {
+                    out.push_str("](");
+                    out.push_str(&url);
+                    rest = &after[len..];
+                    continue;
+                }
+            }
+            let ch = rest.chars().next().expect("rest is not empty");
+            out.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+        out
+    }
+
+    /// Convert the body of a note.
+    fn body(&mut self, body: &str, note: &Path, first_line: usize) -> String {
+        let mut out = String::with_capacity(body.len());
+        let mut fence: Option<String> = None;
+        for (i, line) in body.split_inclusive('\n').enumerate() {
+            let trimmed = line.trim_start();
+            let marker: String = trimmed
+                .chars()
+                .take_while(|c| *c == '`' || *c == '~')
+                .collect();
+            match &fence {
+                Some(open) if trimmed.trim_end().starts_with(open.as_str()) => fence = None,
+                Some(_) => {}
+                None if marker.len() >= 3 => fence = Some(marker),
+                None => {
+                    let (text, newline) = match line.strip_suffix('\n') {
+                        Some(text) => (text, "\n"),
+                        None => (line, ""),
+                    };
+                    out.push_str(&self.line(text, note, first_line + i));
+                    out.push_str(newline);
+                    continue;
+                }
+            }
+            out.push_str(line);
+        }
+        out
+    }
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    notes: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+    report: &mut Report,
+) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(dir))?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        // Tool settings such as `.obsidian` and `.trash` are not imported.
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let rel = dir.join(&name);
+        let mut kind = entry.file_type()?;
+        // Links to files are copied as files, links to folders are left
+        // out as they may lead outside of the folder or back into it.
+        if kind.is_symlink() {
+            match fs::metadata(entry.path()) {
+                Ok(meta) if meta.is_file() => kind = meta.file_type(),
+                Ok(_) => {
+                    report.issue(&rel, 0, "link to a folder, not imported");
+                    continue;
+                }
+                Err(err) => {
+                    report.issue(&rel, 0, format!("broken link, not imported: {err}"));
+                    continue;
+                }
+            }
+        }
+        if kind.is_dir() {
+            walk(root, &rel, notes, files, report)?;
+        } else if rel.extension().is_some_and(|e| e == "md") {
+            notes.push(rel);
+        } else {
+            files.push(rel);
+        }
+    }
+    Ok(())
+}
+
+/// Copy the notes and attachments in `source` to the folder `into` of
+/// `vault`, converting links, embeds and front matter on the way.
+///
+/// Fails with [`io::ErrorKind::AlreadyExists`] if the target folder exists
+/// and is not empty, so earlier imports are never overwritten. The notes are
+/// written to a hidden folder next to it that is renamed into place once
+/// everything was imported, so a failed import leaves nothing behind.
+pub fn import(source: &Path, vault: &Path, into: &Path) -> io::Result<Report> {
+    let dest = vault.join(into);
+    let Some(name) = dest.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot import into {}", dest.display()),
+        ));
+    };
+    if dest.is_dir() && fs::read_dir(&dest)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists and is not empty", dest.display()),
+        ));
+    }
+    let temp = dest.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let imported = import_to(source, &temp, into).and_then(|report| {
+        if dest.is_dir() {
+            fs::remove_dir(&dest)?;
+        }
+        fs::rename(&temp, &dest)?;
+        Ok(report)
+    });
+    if imported.is_err() {
+        let _ = fs::remove_dir_all(&temp);
+    }
+    imported
+}
+
+/// Write the converted notes of `source` to `dest`, with links pointing
+/// into the folder `into` of the vault.
+fn import_to(source: &Path, dest: &Path, into: &Path) -> io::Result<Report> {
+    let mut report = Report::default();
+    let (mut notes, mut files) = (Vec::new(), Vec::new());
+    walk(source, Path::new(""), &mut notes, &mut files, &mut report)?;
+
+    fs::create_dir_all(dest)?;
+    let mut index = Index::default();
+    let mut parsed = Vec::with_capacity(notes.len());
+    for note in &notes {
+        let text = fs::read_to_string(source.join(note))?;
+        let (yaml, _) = split_front_matter(&text);
+        let front = yaml.and_then(|yaml| normalise_front_matter(yaml, note, &mut report));
+        let aliases = front.as_ref().map_or(&[][..], |f| f.aliases.as_slice());
+        index.add_note(note, aliases);
+        let keep_front = yaml.is_some() && front.is_none();
+        parsed.push((text, keep_front, front));
+    }
+    for file in &files {
+        index.add_file(file);
+    }
+
+    let mut converter = Converter {
+        index: &index,
+        into,
+        report: &mut report,
+    };
+    for (note, (text, keep_front, front)) in notes.iter().zip(parsed) {
+        let (yaml, body) = split_front_matter(&text);
+        let mut out = String::with_capacity(text.len());
+        let body_line = text[..text.len() - body.len()].lines().count() + 1;
+        match (front, yaml) {
+            (Some(front), _) if !front.fields.is_empty() => {
+                let yaml = serde_yaml::to_string(&front.fields).map_err(io::Error::other)?;
+                out.push_str(&format!("---\n{yaml}---\n"));
+            }
+            (_, Some(_)) if keep_front => out.push_str(&text[..text.len() - body.len()]),
+            _ => {}
+        }
+        out.push_str(&converter.body(body, note, body_line));
+        let target = dest.join(note);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(target, out)?;
+        converter.report.notes += 1;
+    }
+    for file in &files {
+        let target = dest.join(file);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(source.join(file), target)?;
+        report.files += 1;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_links_embeds_and_front_matter() {
+        let source = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let src = source.path();
+        fs::create_dir_all(src.join("a/img")).unwrap();
+        fs::create_dir_all(src.join("b")).unwrap();
+        fs::create_dir_all(src.join(".obsidian")).unwrap();
+        fs::write(src.join(".obsidian/app.json"), "{}").unwrap();
+        fs::write(src.join("a/img/pic.png"), b"png").unwrap();
+        fs::write(src.join("a/doc.pdf"), b"pdf").unwrap();
+        fs::write(
+            src.join("a/Home.md"),
+            "---\ntags: \"#work, ideas\"\ndate: 2024-01-02\ncssclass: wide\n---\n\
+             See [[Other Note#Big Idea|the idea]], [[Todo]] and [[Gone]].\n\
+             ![[pic.png|200]]\n\
+             Inline ![[Other Note]] embed and [old](../b/Other%20Note.md).\n\
+             ![[doc.pdf]] and [[Other Note#^abc]]\n\
+             - item ![[pic.png]] and [e](../b/Caf%C3%A9%20%281%29.md)\n\
+             `[[code]]`\n```\n[[fenced]]\n```\n",
+        )
+        .unwrap();
+        fs::write(
+            src.join("b/Other Note.md"),
+            "---\naliases: Other\n---\n# Big Idea\n",
+        )
+        .unwrap();
+        fs::write(src.join("a/Todo.md"), "todo").unwrap();
+        fs::write(src.join("b/Todo.md"), "todo").unwrap();
+
+        let report = import(src, vault.path(), Path::new("imported")).unwrap();
+        assert_eq!((report.notes, report.files), (4, 2));
+        let home = fs::read_to_string(vault.path().join("imported/a/Home.md")).unwrap();
+        assert_eq!(
+            home,
+            "---\ntags:\n- work\n- ideas\ncreated: 2024-01-02\ncssclass: wide\n---\n\
+             See [the idea](<note://imported/b/Other Note.md#big-idea>), \
+             [Todo](note://imported/a/Todo.md) and [Gone](note://imported/a/Gone.md).\n\
+             {% image imported/a/img/pic.png %}\n\
+             Inline [Other Note](<note://imported/b/Other Note.md>) embed and \
+             [old](<note://imported/b/Other Note.md>).\n\
+             [doc.pdf](doc.pdf) and [Other Note](<note://imported/b/Other Note.md>)\n\
+             - item ![pic.png](img/pic.png) and [e](<note://imported/b/Café (1).md>)\n\
+             `[[code]]`\n```\n[[fenced]]\n```\n"
+        );
+        let messages: Vec<&str> = report.issues.iter().map(|i| i.message.as_str()).collect();
+        assert!(
+            messages[0].starts_with("`Todo` is ambiguous (2 matches)"),
+            "{messages:?}"
+        );
+        assert!(messages[1].starts_with("link target `Gone` not found"));
+        assert!(messages[2].contains("embedded inside a line"));
+        assert_eq!(report.issues[2].line, 8);
+        assert!(messages[3].contains("cannot be embedded"));
+        assert!(messages[4].contains("block reference `^abc`"));
+        assert_eq!(report.issues[4].line, 9);
+        assert!(!vault.path().join("imported/.obsidian").exists());
+        assert!(vault.path().join("imported/a/img/pic.png").is_file());
+
+        let again = import(src, vault.path(), Path::new("imported"));
+        assert_eq!(again.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_links_to_folders_and_leaves_nothing_on_failure() {
+        let source = tempfile::tempdir().unwrap();
+        let vault = tempfile::tempdir().unwrap();
+        let src = source.path();
+        fs::create_dir(src.join("a")).unwrap();
+        fs::write(src.join("a/Note.md"), "text").unwrap();
+        std::os::unix::fs::symlink(src, src.join("a/loop")).unwrap();
+        std::os::unix::fs::symlink(src.join("a/Note.md"), src.join("Linked.md")).unwrap();
+
+        let report = import(src, vault.path(), Path::new("in")).unwrap();
+        assert_eq!(report.notes, 2);
+        assert_eq!(report.issues[0].note, Path::new("a/loop"));
+        assert!(vault.path().join("in/Linked.md").is_file());
+        assert!(!vault.path().join("in/a/loop").exists());
+
+        // A note that is not UTF-8 fails the import halfway.
+        fs::write(src.join("b.md"), b"\xff").unwrap();
+        assert!(import(src, vault.path(), Path::new("out")).is_err());
+        let names: Vec<_> = fs::read_dir(vault.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["in"]);
+    }
+}
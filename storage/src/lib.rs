@@ -1,3 +1,14 @@
+pub mod assets;
+pub mod diff;
+pub mod folds;
 pub mod fs_tree;
+pub mod git;
+pub mod history;
+pub mod ignore;
+pub mod import;
+pub mod note_file;
+pub mod note_watch;
+pub mod snapshots;
 
 pub use fs_tree::{FileTree, Node};
+pub use ignore::IgnoreRules;
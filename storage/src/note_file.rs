@@ -0,0 +1,328 @@
+//! Reading and writing notes without losing anyone's changes.
+//!
+//! A note is read together with a [`Stamp`] of its file. Writing it back
+//! checks the stamp against the file on disk, so changes made meanwhile by
+//! another program, a sync tool or `git pull` are reported as a
+//! [`WriteError::Conflict`] instead of being overwritten. The new content
+//! goes to a temporary file next to the note, is flushed to disk and then
+//! renamed over the note, so a crash leaves either the old or the new
+//! version behind, never a partial one.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use sha1::{Digest, Sha1};
+
+/// What the file of a note looked like when it was read or written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stamp {
+    /// `None` when there was no file.
+    file: Option<Seen>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Seen {
+    modified: Option<SystemTime>,
+    len: u64,
+    /// SHA-1 of the content.
+    hash: [u8; 20],
+}
+
+/// How the lines of a note end on disk. The editors show notes with `\n`
+/// line breaks and no final one, and write them back in this format, so a
+/// note that was not edited keeps its bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineFormat {
+    /// Lines end with `\r\n`.
+    pub crlf: bool,
+    /// The last line ends with a line break.
+    pub final_break: bool,
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        Self {
+            crlf: false,
+            final_break: true,
+        }
+    }
+}
+
+impl LineFormat {
+    /// Format of `content`, the note on disk. Its first line break tells
+    /// how lines end.
+    pub fn of(content: &str) -> Self {
+        Self {
+            crlf: content
+                .find('\n')
+                .is_some_and(|i| content[..i].ends_with('\r')),
+            final_break: content.ends_with('\n'),
+        }
+    }
+
+    /// `content` as the editors show it.
+    pub fn strip(&self, content: &str) -> String {
+        let ending = if self.crlf { "\r\n" } else { "\n" };
+        let content = match self.final_break {
+            true => content.strip_suffix(ending).unwrap_or(content),
+            false => content,
+        };
+        match self.crlf {
+            true => content.replace("\r\n", "\n"),
+            false => content.to_string(),
+        }
+    }
+
+    /// `text` from an editor as it is written to disk.
+    pub fn apply(&self, text: &str) -> String {
+        let mut content = match self.crlf {
+            true => text.replace('\n', "\r\n"),
+            false => text.to_string(),
+        };
+        if self.final_break {
+            content.push_str(if self.crlf { "\r\n" } else { "\n" });
+        }
+        content
+    }
+}
+
+/// Why a note could not be written.
+#[derive(Debug)]
+pub enum WriteError {
+    Io(io::Error),
+    /// The file was changed on disk since it was read.
+    Conflict {
+        /// The content that was to be written.
+        ours: String,
+        /// The content on disk.
+        theirs: String,
+    },
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Io(e) => write!(f, "{e}"),
+            WriteError::Conflict { .. } => write!(f, "the file was changed since it was read"),
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+impl From<io::Error> for WriteError {
+    fn from(e: io::Error) -> Self {
+        WriteError::Io(e)
+    }
+}
+
+impl Stamp {
+    /// Stamp of the file at `path` as it is now.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(content) => Ok(Self::read(path, &content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `content` is what the file held.
+    pub fn matches(&self, content: &str) -> bool {
+        self.file.as_ref().is_some_and(|seen| {
+            seen.len == content.len() as u64
+                && <[u8; 20]>::from(Sha1::digest(content.as_bytes())) == seen.hash
+        })
+    }
+
+    fn read(path: &Path, content: &[u8]) -> Self {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        Self {
+            file: Some(Seen {
+                modified,
+                len: content.len() as u64,
+                hash: Sha1::digest(content).into(),
+            }),
+        }
+    }
+
+    /// The content on disk when the file at `path` no longer looks like
+    /// this stamp. A file whose modification time changed still matches
+    /// when its content is the same.
+    fn changed(&self, path: &Path) -> io::Result<Option<String>> {
+        let meta = match fs::metadata(path) {
+            Ok(meta) => Some(meta),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let (seen, meta) = match (&self.file, meta) {
+            // A note that was deleted meanwhile is simply written again.
+            (_, None) => return Ok(None),
+            (None, Some(_)) => return Ok(Some(read_text(path)?)),
+            (Some(seen), Some(meta)) => (seen, meta),
+        };
+        if seen.modified.is_some()
+            && meta.modified().ok() == seen.modified
+            && meta.len() == seen.len
+        {
+            return Ok(None);
+        }
+        let content = fs::read(path)?;
+        if <[u8; 20]>::from(Sha1::digest(&content)) == seen.hash {
+            Ok(None)
+        } else {
+            Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+        }
+    }
+}
+
+/// Content of the note at `path` with a stamp to write it back with.
+pub fn read_note(path: &Path) -> io::Result<(String, Stamp)> {
+    let content = fs::read(path)?;
+    let stamp = Stamp::read(path, &content);
+    let text =
+        String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((text, stamp))
+}
+
+/// The note at `path` with a new stamp when it no longer holds what
+/// `loaded` stamps, because another program changed it. A note that was
+/// deleted counts as unchanged, as saving writes it again.
+pub fn reread(path: &Path, loaded: &Stamp) -> io::Result<Option<(String, Stamp)>> {
+    match read_note(path) {
+        Ok((text, _)) if loaded.matches(&text) => Ok(None),
+        Ok(read) => Ok(Some(read)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned())
+}
+
+/// Write `content` to the note at `path`, which was read with the stamp
+/// `loaded`. Fails with [`WriteError::Conflict`] when the file was changed
+/// since. Returns the stamp of the written file.
+pub fn write_note(path: &Path, content: &str, loaded: &Stamp) -> Result<Stamp, WriteError> {
+    match loaded.changed(path)? {
+        None => Ok(replace(path, content)?),
+        Some(theirs) if theirs == content => Ok(Stamp::of(path)?),
+        Some(theirs) => Err(WriteError::Conflict {
+            ours: content.to_string(),
+            theirs,
+        }),
+    }
+}
+
+/// Create the note at `path` with `content`, along with its folder. Fails
+/// with [`WriteError::Conflict`] when the file exists already.
+pub fn create_note(path: &Path, content: &str) -> Result<Stamp, WriteError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_note(path, content, &Stamp::default())
+}
+
+/// Write `content` to `path` whatever is on disk, see [`write_note`] for a
+/// checked write.
+pub fn replace(path: &Path, content: &str) -> io::Result<Stamp> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{name}.{}.tmp", std::process::id()));
+    let written =
+        write_synced(&temp, path, content.as_bytes()).and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    // Make the rename itself durable. Directories cannot be opened for
+    // syncing on every platform, so this is best effort.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(Stamp::read(path, content.as_bytes()))
+}
+
+/// Write `content` to `temp` and flush it to disk, with the permissions of
+/// the file at `original` when there is one.
+fn write_synced(temp: &Path, original: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = File::create(temp)?;
+    file.write_all(content)?;
+    if let Ok(meta) = fs::metadata(original) {
+        file.set_permissions(meta.permissions())?;
+    }
+    file.sync_all()
+}
+
+/// Temporary files left next to notes by writes that were interrupted.
+pub fn is_temp_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with('.') && name.ends_with(".tmp")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_unless_changed_meanwhile() {
+        let dir = tempfile::tempdir().unwrap();
+        let note = dir.path().join("sub/a.md");
+        let created = create_note(&note, "one\n").unwrap();
+        assert!(matches!(
+            create_note(&note, "again\n"),
+            Err(WriteError::Conflict { .. })
+        ));
+
+        let (text, stamp) = read_note(&note).unwrap();
+        assert_eq!(text, "one\n");
+        assert_eq!(stamp, created);
+        assert!(stamp.matches("one\n") && !stamp.matches("two\n"));
+        let stamp = write_note(&note, "two\n", &stamp).unwrap();
+        assert_eq!(fs::read_to_string(&note).unwrap(), "two\n");
+        let leftovers: Vec<_> = fs::read_dir(dir.path().join("sub"))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| is_temp_file(p))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        // Touching the file without changing it is not a conflict.
+        fs::write(&note, "two\n").unwrap();
+        let stamp = write_note(&note, "three\n", &stamp).unwrap();
+
+        fs::write(&note, "theirs\n").unwrap();
+        match write_note(&note, "mine\n", &stamp) {
+            Err(WriteError::Conflict { ours, theirs }) => {
+                assert_eq!(ours, "mine\n");
+                assert_eq!(theirs, "theirs\n");
+            }
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert_eq!(fs::read_to_string(&note).unwrap(), "theirs\n");
+        let (theirs, changed) = reread(&note, &stamp).unwrap().unwrap();
+        assert_eq!(theirs, "theirs\n");
+        assert!(reread(&note, &changed).unwrap().is_none());
+        assert!(write_note(&note, "theirs\n", &stamp).is_ok());
+    }
+
+    #[test]
+    fn keeps_the_line_format() {
+        for content in ["a\r\n\r\nb\r\n", "a\n\nb", "a\n\n", "", "a\r\nb"] {
+            let format = LineFormat::of(content);
+            let text = format.strip(content);
+            assert!(!text.contains('\r'), "{content:?}");
+            assert_eq!(format.apply(&text), content);
+        }
+        assert_eq!(LineFormat::of("a\r\nb\r\n").strip("a\r\nb\r\n"), "a\nb");
+        assert_eq!(LineFormat::default().apply("a\nb"), "a\nb\n");
+    }
+}
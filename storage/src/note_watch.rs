@@ -0,0 +1,100 @@
+//! Noticing when the note open in an editor is changed on disk.
+//!
+//! Only the folder of the note is watched, not the note itself: programs
+//! that save by renaming a new file over the old one, as
+//! [`note_file`](crate::note_file) does, replace the file a watch on the
+//! note would follow.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Watches the file of one note at a time.
+pub struct NoteWatcher {
+    watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+    note: Option<PathBuf>,
+}
+
+impl NoteWatcher {
+    /// A watcher that watches no note yet.
+    pub fn new() -> notify::Result<Self> {
+        let (tx, rx) = channel();
+        let watcher = notify::recommended_watcher(move |res| {
+            // Ignore send errors if receiver was dropped
+            let _ = tx.send(res);
+        })?;
+        Ok(Self {
+            watcher,
+            rx,
+            note: None,
+        })
+    }
+
+    /// Watch the note at `note` instead of the one watched so far, or no
+    /// note at all.
+    pub fn watch(&mut self, note: Option<&Path>) -> notify::Result<()> {
+        // Events name files by their canonical path on some platforms.
+        let note = note.map(|n| n.canonicalize().unwrap_or_else(|_| n.to_path_buf()));
+        if self.note == note {
+            return Ok(());
+        }
+        if let Some(dir) = self.note.take().as_deref().and_then(Path::parent) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        if let Some(dir) = note.as_deref().and_then(Path::parent) {
+            self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        // Events of the note watched before are of no interest any more.
+        while self.rx.try_recv().is_ok() {}
+        self.note = note;
+        Ok(())
+    }
+
+    /// Whether the watched note was written, replaced or removed since this
+    /// was last asked. Our own saves count as well, so callers compare the
+    /// content with what they wrote.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(res) = self.rx.try_recv() {
+            if let (Ok(event), Some(note)) = (res, &self.note) {
+                changed |= !event.kind.is_access() && event.paths.iter().any(|p| p == note);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread, time::Duration};
+
+    #[test]
+    fn notices_changes_of_the_watched_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let note = root.join("a.md");
+        fs::write(&note, "one\n").unwrap();
+        let mut watcher = NoteWatcher::new().unwrap();
+        watcher.watch(Some(&note)).unwrap();
+
+        fs::write(root.join("b.md"), "other\n").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(!watcher.changed());
+
+        crate::note_file::replace(&note, "two\n").unwrap();
+        let mut changed = false;
+        for _ in 0..50 {
+            thread::sleep(Duration::from_millis(20));
+            changed |= watcher.changed();
+            if changed {
+                break;
+            }
+        }
+        assert!(changed);
+    }
+}
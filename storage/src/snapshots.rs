@@ -0,0 +1,351 @@
+//! Saved versions of notes.
+//!
+//! Every save of a note can be kept as a snapshot under
+//! `<vault>/.notes2/snapshots`:
+//!
+//! - `objects/<ab>/<cdef...>` holds a version's content, named after the
+//!   SHA-1 of the content, so equal versions of any note are stored once.
+//! - `notes/<note>.log` lists the snapshots of the vault-relative `<note>`,
+//!   one `seconds<TAB>sha1` line per snapshot, oldest first, where
+//!   `seconds` is the time of the save since the Unix epoch.
+//!
+//! Older snapshots are thinned out by a [`Retention`] policy whenever a new
+//! one is taken, and contents no longer listed by any note are deleted.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::note_file;
+
+const HOUR: Duration = Duration::from_secs(3600);
+const DAY: Duration = Duration::from_secs(86_400);
+
+/// How long snapshots are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Every snapshot younger than this is kept.
+    pub all: Duration,
+    /// The newest snapshot of each hour is kept up to this age.
+    pub hourly: Duration,
+    /// The newest snapshot of each day is kept up to this age.
+    pub daily: Duration,
+}
+
+impl Default for Retention {
+    /// All snapshots of the last hour, hourly for a day and daily for a
+    /// month.
+    fn default() -> Self {
+        Self {
+            all: HOUR,
+            hourly: DAY,
+            daily: 30 * DAY,
+        }
+    }
+}
+
+/// A saved version of a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// SHA-1 of the content, in hex.
+    pub id: String,
+    /// When the note was saved, to the second.
+    pub at: SystemTime,
+}
+
+/// Snapshots of all notes in a vault.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    vault: PathBuf,
+    retention: Retention,
+}
+
+impl SnapshotStore {
+    /// The snapshots of the vault at `vault`, kept with the default
+    /// [`Retention`].
+    pub fn open(vault: &Path) -> Self {
+        Self {
+            vault: vault.to_path_buf(),
+            retention: Retention::default(),
+        }
+    }
+
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    fn root(&self) -> PathBuf {
+        self.vault.join(".notes2").join("snapshots")
+    }
+
+    fn object(&self, id: &str) -> PathBuf {
+        let (dir, name) = id.split_at(2.min(id.len()));
+        self.root().join("objects").join(dir).join(name)
+    }
+
+    fn index(&self, note: &Path) -> PathBuf {
+        let mut file = self.root().join("notes").join(note).into_os_string();
+        file.push(".log");
+        file.into()
+    }
+
+    /// Keep `content` as the newest version of the vault-relative `note`.
+    pub fn save(&self, note: &Path, content: &str) -> io::Result<Snapshot> {
+        self.save_at(note, content, SystemTime::now())
+    }
+
+    /// Keep `content` as the version of `note` saved at `at`. Nothing is
+    /// added when it equals the newest snapshot of the note.
+    pub fn save_at(&self, note: &Path, content: &str, at: SystemTime) -> io::Result<Snapshot> {
+        let id = format!("{:x}", Sha1::digest(content.as_bytes()));
+        let mut snapshots = self.list(note)?;
+        if let Some(last) = snapshots.last().filter(|s| s.id == id) {
+            return Ok(last.clone());
+        }
+        let object = self.object(&id);
+        if !object.exists() {
+            let dir = object.parent().unwrap();
+            fs::create_dir_all(dir)?;
+            // Written under another name first so a crash never leaves a
+            // partial object behind the real name.
+            let partial = dir.join(format!(".{}.partial", id));
+            fs::write(&partial, content)?;
+            fs::rename(&partial, &object)?;
+        }
+        let at = UNIX_EPOCH + Duration::from_secs(seconds(at));
+        let snapshot = Snapshot { id, at };
+        snapshots.push(snapshot.clone());
+        let kept = self.retention.apply(&snapshots, at);
+        self.write_index(note, &kept)?;
+        if kept.len() < snapshots.len() {
+            self.collect_garbage()?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Snapshots of the vault-relative `note`, oldest first.
+    pub fn list(&self, note: &Path) -> io::Result<Vec<Snapshot>> {
+        let content = match fs::read_to_string(self.index(note)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| {
+                let (secs, id) = line.split_once('\t')?;
+                Some(Snapshot {
+                    id: id.to_string(),
+                    at: UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?),
+                })
+            })
+            .collect())
+    }
+
+    /// The snapshot of `note` whose id starts with `prefix`, the newest one
+    /// when several snapshots have the same content.
+    pub fn find(&self, note: &Path, prefix: &str) -> io::Result<Snapshot> {
+        let snapshots = self.list(note)?;
+        let matching: Vec<&Snapshot> = snapshots
+            .iter()
+            .rev()
+            .filter(|s| !prefix.is_empty() && s.id.starts_with(prefix))
+            .collect();
+        match matching.as_slice() {
+            [] => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no snapshot {prefix} of {}", note.display()),
+            )),
+            [first, rest @ ..] if rest.iter().all(|s| s.id == first.id) => Ok((*first).clone()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{prefix} names several snapshots of {}", note.display()),
+            )),
+        }
+    }
+
+    /// Content of a snapshot.
+    pub fn read(&self, snapshot: &Snapshot) -> io::Result<String> {
+        fs::read_to_string(self.object(&snapshot.id))
+    }
+
+    /// Put `snapshot` back as the content of the vault-relative `note`. The
+    /// content it replaces is kept as a snapshot first.
+    pub fn restore(&self, note: &Path, snapshot: &Snapshot) -> io::Result<()> {
+        let content = self.read(snapshot)?;
+        let file = self.vault.join(note);
+        match fs::read_to_string(&file) {
+            Ok(current) => {
+                self.save(note, &current)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        note_file::replace(&file, &content)?;
+        self.save(note, &content)?;
+        Ok(())
+    }
+
+    fn write_index(&self, note: &Path, snapshots: &[Snapshot]) -> io::Result<()> {
+        let file = self.index(note);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let out: String = snapshots
+            .iter()
+            .map(|s| format!("{}\t{}\n", seconds(s.at), s.id))
+            .collect();
+        note_file::replace(&file, &out).map(|_| ())
+    }
+
+    /// Delete the contents no note lists any more.
+    fn collect_garbage(&self) -> io::Result<()> {
+        let mut indexes = Vec::new();
+        files(&self.root().join("notes"), &mut indexes)?;
+        let mut used = HashSet::new();
+        for index in indexes {
+            for line in fs::read_to_string(index)?.lines() {
+                if let Some((_, id)) = line.split_once('\t') {
+                    used.insert(id.to_string());
+                }
+            }
+        }
+        let mut objects = Vec::new();
+        files(&self.root().join("objects"), &mut objects)?;
+        for object in objects {
+            let dir = object.parent().and_then(Path::file_name);
+            let name = object.file_name();
+            if let (Some(dir), Some(name)) = (dir, name) {
+                let id = format!("{}{}", dir.to_string_lossy(), name.to_string_lossy());
+                if !used.contains(&id) {
+                    fs::remove_file(&object)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Retention {
+    /// The snapshots to keep at `now` out of `snapshots`, oldest first. The
+    /// newest snapshot is always kept.
+    fn apply(&self, snapshots: &[Snapshot], now: SystemTime) -> Vec<Snapshot> {
+        let mut buckets = HashSet::new();
+        let mut kept: Vec<Snapshot> = Vec::new();
+        for (i, snapshot) in snapshots.iter().enumerate().rev() {
+            let age = now.duration_since(snapshot.at).unwrap_or_default();
+            let secs = seconds(snapshot.at);
+            let keep = if i == snapshots.len() - 1 || age <= self.all {
+                true
+            } else if age <= self.hourly {
+                buckets.insert(('h', secs / HOUR.as_secs()))
+            } else if age <= self.daily {
+                buckets.insert(('d', secs / DAY.as_secs()))
+            } else {
+                false
+            };
+            if keep {
+                kept.push(snapshot.clone());
+            }
+        }
+        kept.reverse();
+        kept
+    }
+}
+
+fn seconds(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// All files below `dir`, which may not exist.
+fn files(dir: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            files(&entry.path(), out)?;
+        } else {
+            out.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_deduplicated_versions_and_restores_them() {
+        let vault = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::open(vault.path());
+        let note = Path::new("dir/a.md");
+        let first = store.save(note, "one\n").unwrap();
+        assert_eq!(store.save(note, "one\n").unwrap(), first);
+        store.save(note, "two\n").unwrap();
+        store.save(Path::new("b.md"), "one\n").unwrap();
+        let objects = vault.path().join(".notes2/snapshots/objects");
+        let mut stored = Vec::new();
+        files(&objects, &mut stored).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(store.list(note).unwrap().len(), 2);
+
+        fs::create_dir_all(vault.path().join("dir")).unwrap();
+        fs::write(vault.path().join(note), "overwritten\n").unwrap();
+        let found = store.find(note, &first.id[..6]).unwrap();
+        assert_eq!(store.read(&found).unwrap(), "one\n");
+        store.restore(note, &found).unwrap();
+        assert_eq!(
+            fs::read_to_string(vault.path().join(note)).unwrap(),
+            "one\n"
+        );
+        let ids: Vec<String> = store
+            .list(note)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(ids[3], first.id);
+        let overwritten = store.find(note, &ids[2]).unwrap();
+        assert_eq!(store.read(&overwritten).unwrap(), "overwritten\n");
+        assert!(store.find(note, "").is_err());
+    }
+
+    #[test]
+    fn thins_out_old_snapshots() {
+        let vault = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::open(vault.path());
+        let note = Path::new("a.md");
+        let start = UNIX_EPOCH + 1000 * DAY;
+        // A save every two hours for 40 days.
+        let saves = 40 * 12;
+        for i in 0..saves {
+            let at = start + i * 2 * HOUR;
+            store.save_at(note, &format!("version {i}"), at).unwrap();
+        }
+        let snapshots = store.list(note).unwrap();
+        // The newest one, one per hour for the rest of the day and one per
+        // day for the rest of the month.
+        assert!((40..=44).contains(&snapshots.len()), "{}", snapshots.len());
+        let newest = store.read(snapshots.last().unwrap()).unwrap();
+        assert_eq!(newest, format!("version {}", saves - 1));
+        let oldest = snapshots[0].at;
+        let now = snapshots.last().unwrap().at;
+        assert!(now.duration_since(oldest).unwrap() <= 30 * DAY);
+
+        let mut stored = Vec::new();
+        files(&vault.path().join(".notes2/snapshots/objects"), &mut stored).unwrap();
+        assert_eq!(stored.len(), snapshots.len());
+    }
+}
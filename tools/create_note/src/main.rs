@@ -1,6 +1,7 @@
 use std::fs;
 use std::path::PathBuf;
 use clap::{Parser, ValueEnum};
+use storage::note_file::{self, WriteError};
 
 /// Simple tool to create notes or folders
 #[derive(Parser, Debug)]
@@ -33,10 +34,11 @@ fn main() -> anyhow::Result<()> {
 
     match args.kind {
         ItemKind::Note => {
-            if let Some(parent) = target.parent() {
-                fs::create_dir_all(parent)?;
+            match note_file::create_note(&target, "") {
+                Ok(_) => {}
+                Err(WriteError::Conflict { .. }) => anyhow::bail!("{:?} already exists", target),
+                Err(err) => return Err(err.into()),
             }
-            fs::write(&target, "")?;
         }
         ItemKind::Folder => {
             fs::create_dir_all(&target)?;
@@ -0,0 +1,686 @@
+//! Export a note or a whole vault to static HTML or PDF.
+//!
+//! Every note becomes an `.html` file at the same relative path inside the
+//! output directory, next to a shared `style.css`. `note://` links point to
+//! the exported pages, included notes are inlined and referenced images are
+//! copied, so the result can be browsed offline. With `--format pdf` every
+//! note becomes a self-contained `.pdf` file instead, see [`pdf`].
+
+mod pdf;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use markdown_renderer::html::{self, HtmlContext};
+use markdown_renderer::links::{self, NoteIndex, Resolution};
+use storage::assets::{self, AssetCache, AssetError, Thumbnail};
+use storage::ignore::IgnoreRules;
+
+/// Export notes to static HTML or PDF
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Note or vault directory to export
+    input: PathBuf,
+
+    /// Output directory
+    #[arg(short, long, default_value = "export")]
+    out: PathBuf,
+
+    /// Vault used to resolve `note://` links, defaults to the input
+    /// directory or `ELEPHANT_NOTES_ORIGIN`
+    #[arg(long)]
+    vault: Option<PathBuf>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Html)]
+    format: Format,
+
+    /// Directory with the fonts embedded in PDF output, defaults to the
+    /// system fonts
+    #[arg(long)]
+    font_dir: Option<PathBuf>,
+
+    /// Built-in colour theme
+    #[arg(long, value_enum, default_value_t = Theme::Light)]
+    theme: Theme,
+
+    /// Stylesheet to use instead of the built-in theme
+    #[arg(long)]
+    css: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Format {
+    Html,
+    Pdf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn css(self) -> String {
+        let colours = match self {
+            Theme::Light => include_str!("themes/light.css"),
+            Theme::Dark => include_str!("themes/dark.css"),
+        };
+        format!("{colours}\n{}", include_str!("themes/base.css"))
+    }
+}
+
+/// `../` repeated once per directory of `page`, leading back to the output
+/// root.
+fn root_prefix(page: &Path) -> String {
+    let depth = page.parent().map_or(0, |p| p.components().count());
+    "../".repeat(depth)
+}
+
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|c| percent_encode(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Percent-encode everything but unreserved URL characters.
+fn percent_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// `path` with `.` and `..` resolved, `None` when it leaves the directory it
+/// is relative to.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => out.push(name),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::Prefix(_) => return None,
+        }
+    }
+    Some(out)
+}
+
+/// `#` and the id of the heading `anchor` links to.
+fn fragment(anchor: &str) -> String {
+    format!("#{}", links::slug(anchor))
+}
+
+/// Title of a note: its first level-one heading or its file name.
+fn title(source: &str, path: &Path) -> String {
+    source
+        .lines()
+        .find_map(|l| l.strip_prefix("# "))
+        .map(|t| t.trim().to_string())
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+}
+
+struct Exporter {
+    vault: PathBuf,
+    out: PathBuf,
+    /// Images copied so far, by source path, with their path in the output.
+    copied: HashMap<PathBuf, PathBuf>,
+    /// Path of the index page relative to the output root, when one is
+    /// written. Pages then link back to it.
+    index: Option<PathBuf>,
+    warnings: Vec<String>,
+    /// Decoded images for PDF output.
+    thumbnails: AssetCache,
+    /// Notes of the vault, for resolving wikilinks.
+    notes: NoteIndex,
+    /// Entries of the vault that are not exported.
+    ignore: IgnoreRules,
+}
+
+impl Exporter {
+    fn new(vault: PathBuf, out: PathBuf) -> Self {
+        let ignore = IgnoreRules::load(&vault);
+        Self {
+            notes: NoteIndex::scan_with(&vault, |path, is_dir| ignore.ignores(path, is_dir))
+                .unwrap_or_default(),
+            ignore,
+            vault,
+            out,
+            copied: HashMap::new(),
+            index: None,
+            warnings: Vec::new(),
+            thumbnails: AssetCache::in_memory(1600),
+        }
+    }
+
+    /// Resolve a `note://` target to a note path relative to the vault,
+    /// `None` when it points outside of the vault.
+    fn resolve_note(&self, target: &str, from: &Path) -> Option<PathBuf> {
+        let mut path = PathBuf::from(target.trim_start_matches('/'));
+        if path.extension().is_none() {
+            path.set_extension("md");
+        }
+        let direct = normalize(&path);
+        let sibling = normalize(&from.parent().unwrap_or(Path::new("")).join(&path));
+        let note = match (direct, sibling) {
+            (Some(direct), Some(sibling))
+                if !self.vault.join(&direct).is_file() && self.vault.join(&sibling).is_file() =>
+            {
+                sibling
+            }
+            (Some(direct), _) => direct,
+            (None, sibling) => sibling?,
+        };
+        // Symbolic links may lead out of the vault as well.
+        let inside = match (
+            self.vault.join(&note).canonicalize(),
+            self.vault.canonicalize(),
+        ) {
+            (Ok(real), Ok(vault)) => real.starts_with(vault),
+            _ => true,
+        };
+        inside.then_some(note)
+    }
+
+    /// Path and source of the note `target` included from the last note of
+    /// `including`, with a warning when it cannot be included.
+    fn include_source(&mut self, target: &str, including: &[PathBuf]) -> Option<(PathBuf, String)> {
+        let current = including
+            .last()
+            .map(PathBuf::as_path)
+            .unwrap_or(Path::new(""));
+        let Some(note) = self.resolve_note(target, current) else {
+            self.warnings.push(format!(
+                "{}: {target} is outside of the vault and not included",
+                current.display()
+            ));
+            return None;
+        };
+        if including.contains(&note) {
+            self.warnings.push(format!(
+                "{}: {} includes itself",
+                current.display(),
+                note.display()
+            ));
+            return None;
+        }
+        let source = fs::read_to_string(self.vault.join(&note)).ok()?;
+        Some((note, source))
+    }
+
+    /// Copy the image at `source` into the output once and return its path
+    /// relative to the output root.
+    fn copy_image(&mut self, source: &Path) -> Result<PathBuf> {
+        if let Some(done) = self.copied.get(source) {
+            return Ok(done.clone());
+        }
+        let target = match source.strip_prefix(&self.vault) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => {
+                let name = source.file_name().unwrap_or_default().to_string_lossy();
+                let n = self.copied.len();
+                PathBuf::from("assets").join(format!("{n}-{name}"))
+            }
+        };
+        let dest = self.out.join(&target);
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(source, &dest).with_context(|| format!("copying {}", source.display()))?;
+        self.copied.insert(source.to_path_buf(), target.clone());
+        Ok(target)
+    }
+
+    /// Export the note at `note`, relative to the vault, and return the
+    /// path of the page relative to the output root.
+    fn export_note(&mut self, note: &Path) -> Result<PathBuf> {
+        let source = fs::read_to_string(self.vault.join(note))
+            .with_context(|| format!("reading {}", note.display()))?;
+        let page = note.with_extension("html");
+        let mut ctx = Page {
+            exporter: self,
+            page: page.clone(),
+            notes: vec![note.to_path_buf()],
+        };
+        let body = html::to_html(&source, &mut ctx);
+        let prefix = root_prefix(&page);
+        let nav = match &self.index {
+            Some(index) => format!(
+                "<nav class=\"breadcrumbs\"><a href=\"{prefix}{}\">Index</a></nav>\n",
+                url_path(index)
+            ),
+            None => String::new(),
+        };
+        let html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{}</title>\n<link rel=\"stylesheet\" href=\"{prefix}style.css\">\n</head>\n\
+             <body>\n<main>\n{nav}{body}</main>\n</body>\n</html>\n",
+            html::escape(&title(&source, note)),
+        );
+        let dest = self.out.join(&page);
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&dest, html)?;
+        Ok(page)
+    }
+
+    /// Export the note at `note` as a PDF file and return its path relative
+    /// to the output root.
+    fn export_pdf(&mut self, note: &Path, fonts: &pdf::Fonts) -> Result<PathBuf> {
+        let source = fs::read_to_string(self.vault.join(note))
+            .with_context(|| format!("reading {}", note.display()))?;
+        let mut ctx = PdfNote {
+            exporter: self,
+            notes: vec![note.to_path_buf()],
+        };
+        let blocks = pdf::blocks(&source, &mut ctx);
+        for block in &blocks {
+            if let pdf::Block::Image(Err(err)) | pdf::Block::Math(Err(err)) = block {
+                self.warnings.push(format!("{}: {err}", note.display()));
+            }
+        }
+        let bytes = pdf::render(&blocks, &title(&source, note), fonts)
+            .with_context(|| format!("rendering {}", note.display()))?;
+        let file = note.with_extension("pdf");
+        let dest = self.out.join(&file);
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&dest, bytes)?;
+        Ok(file)
+    }
+
+    /// Export all notes of the vault and write an index page linking them.
+    /// The index is `index.html`, or `_index.html` when a note of the vault
+    /// root is exported to `index.html`.
+    fn export_vault(&mut self) -> Result<Vec<PathBuf>> {
+        let mut notes = Vec::new();
+        collect_notes(&self.vault, Path::new(""), &self.ignore, &mut notes)?;
+        let taken = notes
+            .iter()
+            .any(|n| n.with_extension("html") == Path::new("index.html"));
+        let index = PathBuf::from(if taken { "_index.html" } else { "index.html" });
+        self.index = Some(index.clone());
+        let mut items = String::new();
+        for note in &notes {
+            let page = self.export_note(note)?;
+            let source = fs::read_to_string(self.vault.join(note)).unwrap_or_default();
+            items.push_str(&format!(
+                "<li><a href=\"{}\">{}</a> <small>{}</small></li>\n",
+                url_path(&page),
+                html::escape(&title(&source, note)),
+                html::escape(&note.display().to_string()),
+            ));
+        }
+        let page = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Notes</title>\n\
+             <link rel=\"stylesheet\" href=\"style.css\">\n</head>\n<body>\n<main>\n<h1>Notes</h1>\n\
+             <ul>\n{items}</ul>\n</main>\n</body>\n</html>\n"
+        );
+        fs::write(self.out.join(index), page)?;
+        Ok(notes)
+    }
+}
+
+/// Markdown files below `dir`, skipping the entries `ignore` leaves out,
+/// such as `.notes2`.
+fn collect_notes(
+    root: &Path,
+    dir: &Path,
+    ignore: &IgnoreRules,
+    notes: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(dir))?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let rel = dir.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+        if ignore.ignores(&rel, is_dir) {
+            continue;
+        }
+        if is_dir {
+            collect_notes(root, &rel, ignore, notes)?;
+        } else if rel.extension().is_some_and(|e| e == "md") {
+            notes.push(rel);
+        }
+    }
+    Ok(())
+}
+
+/// Rendering state of one exported page.
+struct Page<'a> {
+    exporter: &'a mut Exporter,
+    /// Output path of the page, relative to the output root.
+    page: PathBuf,
+    /// The page's note followed by the notes currently being included.
+    notes: Vec<PathBuf>,
+}
+
+impl Page<'_> {
+    fn current(&self) -> &Path {
+        self.notes
+            .last()
+            .map(PathBuf::as_path)
+            .unwrap_or(Path::new(""))
+    }
+
+    fn href(&self, target: &Path) -> String {
+        format!("{}{}", root_prefix(&self.page), url_path(target))
+    }
+}
+
+impl HtmlContext for Page<'_> {
+    fn note_link(&mut self, target: &str) -> String {
+        let (target, anchor) = match target.split_once('#') {
+            Some((t, a)) => (t, fragment(a)),
+            None => (target, String::new()),
+        };
+        let Some(note) = self.exporter.resolve_note(target, self.current()) else {
+            self.exporter.warnings.push(format!(
+                "{}: link to {target} outside of the vault",
+                self.current().display()
+            ));
+            return "#".to_string();
+        };
+        if !self.exporter.vault.join(&note).is_file() {
+            self.exporter.warnings.push(format!(
+                "{}: link to missing note {}",
+                self.current().display(),
+                note.display()
+            ));
+        }
+        format!("{}{anchor}", self.href(&note.with_extension("html")))
+    }
+
+    fn wiki_link(&mut self, target: &str, anchor: Option<&str>) -> String {
+        let anchor = anchor.map_or(String::new(), fragment);
+        let note = match self.exporter.notes.resolve(target, Some(self.current())) {
+            Resolution::Found(note) => note,
+            Resolution::Ambiguous(candidates) => {
+                self.exporter.warnings.push(format!(
+                    "{}: [[{target}]] matches {} notes, linked to {}",
+                    self.current().display(),
+                    candidates.len(),
+                    candidates[0].display()
+                ));
+                candidates[0].clone()
+            }
+            Resolution::Missing => {
+                self.exporter.warnings.push(format!(
+                    "{}: link to missing note [[{target}]]",
+                    self.current().display()
+                ));
+                PathBuf::from(format!("{target}.md"))
+            }
+        };
+        format!("{}{anchor}", self.href(&note.with_extension("html")))
+    }
+
+    fn include(&mut self, target: &str) -> Option<String> {
+        let (note, source) = self.exporter.include_source(target, &self.notes)?;
+        self.notes.push(note);
+        let html = html::to_html(&source, self);
+        self.notes.pop();
+        Some(html)
+    }
+
+    fn image(&mut self, url: &str) -> String {
+        let note = self.exporter.vault.join(self.current());
+        let copied = assets::resolve(url, Some(&note), &self.exporter.vault)
+            .map_err(anyhow::Error::from)
+            .and_then(|path| self.exporter.copy_image(&path));
+        match copied {
+            Ok(target) => self.href(&target),
+            Err(err) => {
+                let kept = match err.downcast_ref::<AssetError>() {
+                    Some(AssetError::Remote(_)) => "remote image is not available offline",
+                    Some(AssetError::Outside(_)) => "image outside of the vault is not exported",
+                    _ => "image is missing",
+                };
+                self.exporter.warnings.push(format!(
+                    "{}: {kept}: {url} ({err})",
+                    self.current().display()
+                ));
+                url.to_string()
+            }
+        }
+    }
+}
+
+/// Layout state of one note exported as PDF.
+struct PdfNote<'a> {
+    exporter: &'a mut Exporter,
+    /// The exported note followed by the notes currently being included.
+    notes: Vec<PathBuf>,
+}
+
+impl PdfNote<'_> {
+    fn current(&self) -> &Path {
+        self.notes
+            .last()
+            .map(PathBuf::as_path)
+            .unwrap_or(Path::new(""))
+    }
+}
+
+impl pdf::Resources for PdfNote<'_> {
+    fn include(&mut self, target: &str) -> Option<String> {
+        let (note, source) = self.exporter.include_source(target, &self.notes)?;
+        self.notes.push(note);
+        Some(source)
+    }
+
+    fn leave(&mut self) {
+        self.notes.pop();
+    }
+
+    fn image(&mut self, url: &str) -> Result<Arc<Thumbnail>, String> {
+        let note = self.exporter.vault.join(self.current());
+        let exporter = &mut *self.exporter;
+        exporter
+            .thumbnails
+            .load_url(url, Some(&note), &exporter.vault)
+            .map_err(|err| err.to_string())
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let input = args
+        .input
+        .canonicalize()
+        .with_context(|| format!("{}", args.input.display()))?;
+    let vault = match &args.vault {
+        Some(vault) => vault.canonicalize()?,
+        None if input.is_dir() => input.clone(),
+        None => match std::env::var_os("ELEPHANT_NOTES_ORIGIN") {
+            Some(origin) => PathBuf::from(origin).canonicalize()?,
+            None => input.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        },
+    };
+    fs::create_dir_all(&args.out)?;
+    let mut exporter = Exporter::new(vault.clone(), args.out.clone());
+    if args.format == Format::Pdf {
+        let fonts = pdf::Fonts::load(args.font_dir.as_deref())?;
+        let notes = if input.is_dir() {
+            let mut notes = Vec::new();
+            collect_notes(&vault, Path::new(""), &exporter.ignore, &mut notes)?;
+            notes
+        } else {
+            vec![input
+                .strip_prefix(&vault)
+                .map(Path::to_path_buf)
+                .with_context(|| {
+                    format!(
+                        "{} is not inside the vault {}",
+                        input.display(),
+                        vault.display()
+                    )
+                })?]
+        };
+        for note in &notes {
+            let file = exporter.export_pdf(note, &fonts)?;
+            println!("Exported {}", args.out.join(file).display());
+        }
+    } else if input.is_dir() {
+        write_css(&args)?;
+        let notes = exporter.export_vault()?;
+        println!("Exported {} notes to {}", notes.len(), args.out.display());
+    } else {
+        write_css(&args)?;
+        let Ok(note) = input.strip_prefix(&vault) else {
+            bail!(
+                "{} is not inside the vault {}",
+                input.display(),
+                vault.display()
+            );
+        };
+        let page = exporter.export_note(note)?;
+        println!("Exported {}", args.out.join(page).display());
+    }
+    for warning in &exporter.warnings {
+        eprintln!("warning: {warning}");
+    }
+    Ok(())
+}
+
+fn write_css(args: &Args) -> Result<()> {
+    let css = match &args.css {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?
+        }
+        None => args.theme.css(),
+    };
+    fs::write(args.out.join("style.css"), css)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_vault_with_links_includes_and_images() {
+        let vault = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let root = vault.path();
+        fs::create_dir_all(root.join("sub/img")).unwrap();
+        fs::create_dir_all(root.join(".notes2")).unwrap();
+        fs::write(root.join(".notes2/hidden.md"), "").unwrap();
+        fs::create_dir_all(root.join("drafts")).unwrap();
+        fs::write(root.join("drafts/wip.md"), "").unwrap();
+        fs::write(root.join(".notesignore"), "drafts/\n").unwrap();
+        fs::write(root.join("sub/img/pic.png"), b"png").unwrap();
+        fs::write(
+            root.join("a.md"),
+            "# Home\n\nGo to [b](note://sub/b#top), [[B]] or [[B#Top Part|there]].\n\n{% include-note sub/part %}\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("sub/b.md"),
+            "# Top\n\n## Top Part\n\nBack to [a](note://a.md)\n",
+        )
+        .unwrap();
+        fs::write(root.join("sub/part.md"), "![pic](img/pic.png) and $x^2$\n").unwrap();
+
+        let mut exporter = Exporter::new(root.to_path_buf(), out.path().to_path_buf());
+        let notes = exporter.export_vault().unwrap();
+        assert_eq!(notes.len(), 3);
+        assert!(exporter.warnings.is_empty(), "{:?}", exporter.warnings);
+
+        let a = fs::read_to_string(out.path().join("a.html")).unwrap();
+        assert!(a.contains("<title>Home</title>"));
+        assert!(a.contains("href=\"sub/b.html#top\""));
+        assert!(a.contains("<a href=\"sub/b.html\">B</a>"), "{a}");
+        assert!(
+            a.contains("<a href=\"sub/b.html#top-part\">there</a>"),
+            "{a}"
+        );
+        assert!(
+            a.contains("<img src=\"sub/img/pic.png\" alt=\"pic\" />"),
+            "{a}"
+        );
+        assert!(a.contains("<svg"));
+        let b = fs::read_to_string(out.path().join("sub/b.html")).unwrap();
+        assert!(b.contains("href=\"../a.html\""));
+        assert!(b.contains("href=\"../style.css\""));
+        assert!(b.contains("<h1 id=\"top\">Top</h1>"));
+        assert!(b.contains("<h2 id=\"top-part\">Top Part</h2>"));
+        assert!(out.path().join("sub/img/pic.png").is_file());
+        assert!(out.path().join("index.html").is_file());
+        assert!(!out.path().join(".notes2").exists());
+    }
+
+    #[test]
+    fn names_the_index_after_a_root_index_note() {
+        let vault = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        fs::write(vault.path().join("index.md"), "# Welcome\n").unwrap();
+        fs::write(vault.path().join("other.md"), "# Other\n").unwrap();
+
+        let mut exporter = Exporter::new(vault.path().to_path_buf(), out.path().to_path_buf());
+        exporter.export_vault().unwrap();
+        let welcome = fs::read_to_string(out.path().join("index.html")).unwrap();
+        assert!(welcome.contains("<title>Welcome</title>"));
+        assert!(
+            welcome.contains("href=\"_index.html\">Index</a>"),
+            "{welcome}"
+        );
+        let listing = fs::read_to_string(out.path().join("_index.html")).unwrap();
+        assert!(
+            listing.contains("href=\"index.html\">Welcome</a>"),
+            "{listing}"
+        );
+        assert!(listing.contains("href=\"other.html\">Other</a>"));
+    }
+
+    #[test]
+    fn keeps_notes_outside_of_the_vault_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let root = dir.path().join("vault");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(dir.path().join("secret.md"), "password").unwrap();
+        fs::write(
+            root.join("sub/a.md"),
+            "[s](note://../../secret) [t](note://../../secret#x)\n\n\
+             {% include-note ../../secret %}\n",
+        )
+        .unwrap();
+
+        let mut exporter = Exporter::new(root.clone(), out.path().to_path_buf());
+        exporter.export_note(Path::new("sub/a.md")).unwrap();
+        let a = fs::read_to_string(out.path().join("sub/a.html")).unwrap();
+        assert!(!a.contains("password"), "{a}");
+        assert!(a.contains("<a href=\"#\">s</a>"), "{a}");
+        assert_eq!(exporter.warnings.len(), 3, "{:?}", exporter.warnings);
+        assert_eq!(
+            url_path(Path::new("a b/c#d?e%f/ü.html")),
+            "a%20b/c%23d%3Fe%25f/%C3%BC.html"
+        );
+    }
+}
@@ -0,0 +1,914 @@
+//! Paginated PDF output built from `markdown_renderer` widgets.
+//!
+//! A note is first turned into a list of [`Block`]s, one or more per
+//! top-level markdown block, which are then placed on A4 pages with a
+//! running header and a page-numbered footer. Fonts are taken from the
+//! installed system fonts and embedded in the file, so the PDF looks the
+//! same on every machine and no browser is involved.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use markdown_renderer::incremental::Document;
+use markdown_renderer::links;
+use markdown_renderer::math::{self, MathBox, MathItem};
+use markdown_renderer::{render_markdown, Widget};
+use printpdf::path::PaintMode;
+use printpdf::{
+    Color, ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Line, Mm,
+    PdfDocument, PdfDocumentReference, PdfLayerReference, Point, Px, Rect, Rgb,
+};
+use storage::assets::Thumbnail;
+
+const PAGE_W: f32 = 210.0;
+const PAGE_H: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const TOP: f32 = 25.0;
+const BOTTOM: f32 = PAGE_H - 22.0;
+const CONTENT_W: f32 = PAGE_W - 2.0 * MARGIN;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.0;
+const SMALL_SIZE: f32 = 8.0;
+const LINE_HEIGHT: f32 = 1.4;
+/// Millimetres per typographic point.
+const PT: f32 = 25.4 / 72.0;
+/// Resolution at which images are placed when they fit the page.
+const IMAGE_DPI: f32 = 96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+const STYLES: [FontStyle; 4] = [
+    FontStyle::Regular,
+    FontStyle::Bold,
+    FontStyle::Italic,
+    FontStyle::Mono,
+];
+
+/// The four font faces used in a document.
+pub struct Fonts {
+    faces: [Vec<u8>; 4],
+    metrics: [Metrics; 4],
+}
+
+/// Advance widths of the characters of a face, read once when the fonts
+/// are loaded.
+struct Metrics {
+    units_per_em: f32,
+    advances: HashMap<char, u16>,
+}
+
+impl Metrics {
+    fn read(data: &[u8]) -> Result<Self> {
+        let face = ttf_parser::Face::parse(data, 0)?;
+        let mut advances = HashMap::new();
+        let subtables = face
+            .tables()
+            .cmap
+            .into_iter()
+            .flat_map(|cmap| cmap.subtables);
+        for subtable in subtables.filter(|s| s.is_unicode()) {
+            subtable.codepoints(|code| {
+                let advance = subtable
+                    .glyph_index(code)
+                    .and_then(|g| face.glyph_hor_advance(g));
+                if let (Some(c), Some(advance)) = (char::from_u32(code), advance) {
+                    advances.entry(c).or_insert(advance);
+                }
+            });
+        }
+        Ok(Self {
+            units_per_em: f32::from(face.units_per_em()),
+            advances,
+        })
+    }
+}
+
+impl Fonts {
+    /// Pick sans-serif regular, bold and italic faces and a monospace face
+    /// among the fonts in `dir`, or among the system fonts.
+    pub fn load(dir: Option<&Path>) -> Result<Self> {
+        use fontdb::{Family, Query, Stretch, Style, Weight};
+
+        let mut db = fontdb::Database::new();
+        match dir {
+            Some(dir) => db.load_fonts_dir(dir),
+            None => db.load_system_fonts(),
+        }
+        let sans = [
+            Family::Name("DejaVu Sans"),
+            Family::Name("Noto Sans"),
+            Family::Name("Liberation Sans"),
+            Family::Name("Arial"),
+            Family::SansSerif,
+        ];
+        let mono = [
+            Family::Name("DejaVu Sans Mono"),
+            Family::Name("Noto Sans Mono"),
+            Family::Name("Liberation Mono"),
+            Family::Name("Courier New"),
+            Family::Monospace,
+        ];
+        let find = |families: &[Family], weight: Weight, style: Style| {
+            let query = Query {
+                families,
+                weight,
+                stretch: Stretch::Normal,
+                style,
+            };
+            // Font collections are skipped, the PDF writer embeds whole files.
+            let id = db
+                .query(&query)
+                .filter(|id| db.face(*id).is_some_and(|f| f.index == 0))
+                .or_else(|| db.faces().find(|f| f.index == 0).map(|f| f.id))?;
+            db.with_face_data(id, |data, _| data.to_vec())
+        };
+        let faces = [
+            find(&sans, Weight::NORMAL, Style::Normal),
+            find(&sans, Weight::BOLD, Style::Normal),
+            find(&sans, Weight::NORMAL, Style::Italic),
+            find(&mono, Weight::NORMAL, Style::Normal),
+        ];
+        let [Some(regular), Some(bold), Some(italic), Some(mono)] = faces else {
+            return Err(anyhow!(
+                "no usable fonts found{}",
+                dir.map_or(String::new(), |d| format!(" in {}", d.display()))
+            ));
+        };
+        let metrics = [
+            Metrics::read(&regular)?,
+            Metrics::read(&bold)?,
+            Metrics::read(&italic)?,
+            Metrics::read(&mono)?,
+        ];
+        Ok(Self {
+            faces: [regular, bold, italic, mono],
+            metrics,
+        })
+    }
+
+    fn data(&self, style: FontStyle) -> &[u8] {
+        &self.faces[style as usize]
+    }
+
+    /// Width of `text` set in `style` at `size` points, in millimetres.
+    fn width(&self, text: &str, style: FontStyle, size: f32) -> f32 {
+        let metrics = &self.metrics[style as usize];
+        let units = metrics.units_per_em;
+        let advance: f32 = text
+            .chars()
+            .map(|c| {
+                metrics
+                    .advances
+                    .get(&c)
+                    .map_or(units / 2.0, |a| f32::from(*a))
+            })
+            .sum();
+        advance / units * size * PT
+    }
+}
+
+/// A piece of text in one style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub style: FontStyle,
+}
+
+impl Run {
+    fn new(text: impl Into<String>, style: FontStyle) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+/// A unit of page layout.
+#[derive(Debug)]
+pub enum Block {
+    Heading(u32, String),
+    Paragraph {
+        runs: Vec<Run>,
+        /// Left indent in millimetres.
+        indent: f32,
+        /// List marker drawn in the indent.
+        bullet: Option<String>,
+    },
+    Code(String),
+    Table(Vec<Vec<String>>),
+    Image(Result<Arc<Thumbnail>, String>),
+    Math(Result<MathBox, String>),
+}
+
+/// Access to other files while a note is laid out.
+pub trait Resources {
+    /// Markdown of the note included with `include-note`. Images are
+    /// resolved from that note until [`Resources::leave`] is called.
+    fn include(&mut self, target: &str) -> Option<String>;
+    fn leave(&mut self);
+    fn image(&mut self, url: &str) -> Result<Arc<Thumbnail>, String>;
+}
+
+fn list_marker(line: &str) -> Option<(usize, String, &str)> {
+    let trimmed = line.trim_start();
+    let depth = (line.len() - trimmed.len()) / 2;
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(rest) = trimmed.strip_prefix(bullet) {
+            let (marker, rest) = match rest.strip_prefix("[ ] ").or(rest.strip_prefix("[x] ")) {
+                Some(task) => (
+                    if rest.starts_with("[x]") {
+                        "☑"
+                    } else {
+                        "☐"
+                    },
+                    task,
+                ),
+                None => ("•", rest),
+            };
+            return Some((depth, marker.to_string(), rest));
+        }
+    }
+    let digits = trimmed.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = trimmed[digits..]
+        .strip_prefix(". ")
+        .or(trimmed[digits..].strip_prefix(") "))?;
+    (digits > 0).then(|| (depth, format!("{}.", &trimmed[..digits]), rest))
+}
+
+/// Lay out the widgets of one markdown block.
+struct Builder<'a> {
+    resources: &'a mut dyn Resources,
+    blocks: Vec<Block>,
+    runs: Vec<Run>,
+    /// Whether the last run came from a plain text widget, which is how
+    /// soft line breaks show up.
+    after_text: bool,
+    depth: usize,
+}
+
+impl Builder<'_> {
+    fn flush(&mut self, indent: f32, bullet: Option<String>) {
+        if !self.runs.is_empty() || bullet.is_some() {
+            self.blocks.push(Block::Paragraph {
+                runs: std::mem::take(&mut self.runs),
+                indent,
+                bullet,
+            });
+        }
+        self.after_text = false;
+    }
+
+    fn run(&mut self, text: &str, style: FontStyle, plain: bool) {
+        let joins = self.runs.last().is_some_and(|r| {
+            !r.text.ends_with(char::is_whitespace) && !text.starts_with(char::is_whitespace)
+        });
+        if plain && self.after_text && joins {
+            self.runs.push(Run::new(" ", FontStyle::Regular));
+        }
+        self.runs.push(Run::new(text, style));
+        self.after_text = plain;
+    }
+
+    fn source(&mut self, source: &str) {
+        if self.depth > 8 {
+            return;
+        }
+        self.depth += 1;
+        for (text, widgets) in Document::new(source).blocks() {
+            let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+            if list_marker(first).is_some() {
+                self.list(text);
+            } else {
+                self.widgets(widgets, 0.0);
+                self.flush(0.0, None);
+            }
+        }
+        self.depth -= 1;
+    }
+
+    /// Lay out a list block item by item, since the widgets do not keep the
+    /// item boundaries.
+    fn list(&mut self, text: &str) {
+        let mut items: Vec<(usize, String, String)> = Vec::new();
+        for line in text.lines() {
+            match (list_marker(line), items.last_mut()) {
+                (Some((depth, marker, rest)), _) => items.push((depth, marker, rest.to_string())),
+                (None, Some(item)) if !line.trim().is_empty() => {
+                    item.2.push('\n');
+                    item.2.push_str(line.trim());
+                }
+                _ => {}
+            }
+        }
+        for (depth, marker, body) in items {
+            let indent = 6.0 * (depth + 1) as f32;
+            self.widgets(&render_markdown(&body), indent);
+            self.flush(indent, Some(marker));
+        }
+    }
+
+    fn widgets(&mut self, widgets: &[Widget], indent: f32) {
+        for widget in widgets {
+            match widget {
+                Widget::Text(t) => self.run(t, FontStyle::Regular, true),
+                Widget::Bold(t) => self.run(t, FontStyle::Bold, false),
+                Widget::Italic(t) => self.run(t, FontStyle::Italic, false),
+                Widget::Link { text, .. } => self.run(text, FontStyle::Regular, false),
+                Widget::NoteLink {
+                    target,
+                    alias,
+                    anchor,
+                } => {
+                    let target = target.trim_end_matches(".md");
+                    let text = links::label(target, alias.as_deref(), anchor.as_deref());
+                    self.run(&format!("→ {text}"), FontStyle::Italic, false);
+                }
+                Widget::Latex {
+                    tex,
+                    display: false,
+                } => match math::to_unicode(tex, false) {
+                    Ok(lines) => self.run(&lines.concat(), FontStyle::Italic, false),
+                    Err(err) => self.run(&format!("[math error: {err}]"), FontStyle::Mono, false),
+                },
+                Widget::Latex { tex, display: true } => {
+                    self.flush(indent, None);
+                    let layout =
+                        math::layout(tex, true).map_err(|e| format!("{e}: {}", tex.trim()));
+                    self.blocks.push(Block::Math(layout));
+                }
+                Widget::Header(level, text) => {
+                    self.flush(indent, None);
+                    self.blocks.push(Block::Heading(*level, text.clone()));
+                }
+                Widget::CodeBlock { code, .. } | Widget::Mermaid(code) => {
+                    self.flush(indent, None);
+                    self.blocks.push(Block::Code(code.clone()));
+                }
+                Widget::Table(rows) => {
+                    self.flush(indent, None);
+                    let rows = rows
+                        .iter()
+                        .filter_map(|row| match row {
+                            Widget::TableRow(cells) => Some(cells.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    self.blocks.push(Block::Table(rows));
+                }
+                Widget::TableRow(_) => {}
+                Widget::Image { url, .. } | Widget::CustomImage(url) => {
+                    self.flush(indent, None);
+                    let image = self.resources.image(url);
+                    self.blocks.push(Block::Image(image));
+                }
+                Widget::IncludeNote(target) => {
+                    self.flush(indent, None);
+                    match self.resources.include(target) {
+                        Some(source) => {
+                            self.source(&source);
+                            self.resources.leave();
+                        }
+                        None => {
+                            self.run(&format!("Missing note: {target}"), FontStyle::Italic, false);
+                            self.flush(indent, None);
+                        }
+                    }
+                }
+                Widget::Details {
+                    summary, children, ..
+                } => {
+                    self.flush(indent, None);
+                    self.run(&format!("▾ {summary}"), FontStyle::Bold, false);
+                    self.flush(indent, None);
+                    self.widgets(children, indent + 6.0);
+                    self.flush(indent + 6.0, None);
+                }
+            }
+        }
+    }
+}
+
+/// Turn the markdown `source` of a note into layout blocks.
+pub fn blocks(source: &str, resources: &mut dyn Resources) -> Vec<Block> {
+    let mut builder = Builder {
+        resources,
+        blocks: Vec::new(),
+        runs: Vec::new(),
+        after_text: false,
+        depth: 0,
+    };
+    builder.source(source);
+    builder.blocks
+}
+
+/// Break `runs` into lines at most `max` wide. `measure` gives the width of
+/// a piece of text in a style.
+fn wrap(runs: &[Run], max: f32, measure: &dyn Fn(&str, FontStyle) -> f32) -> Vec<Vec<Run>> {
+    let mut lines = vec![Vec::new()];
+    let mut width = 0.0;
+    let mut space = false;
+    for run in runs {
+        for (i, word) in run.text.split(char::is_whitespace).enumerate() {
+            space |= i > 0;
+            if word.is_empty() {
+                continue;
+            }
+            let line: &mut Vec<Run> = lines.last_mut().unwrap();
+            let gap = if space && !line.is_empty() {
+                measure(" ", run.style)
+            } else {
+                0.0
+            };
+            let mut word_w = measure(word, run.style);
+            let mut word = word.to_string();
+            if width + gap + word_w > max && !line.is_empty() {
+                lines.push(Vec::new());
+                width = 0.0;
+            } else if gap > 0.0 {
+                push_run(lines.last_mut().unwrap(), " ", run.style);
+                width += gap;
+            }
+            // Words wider than a whole line are cut wherever they overflow.
+            while word_w > max - width && word.chars().count() > 1 {
+                let mut cut = word.len();
+                while cut > 0 && measure(&word[..cut], run.style) > max - width {
+                    cut = word[..cut].char_indices().last().map_or(0, |(i, _)| i);
+                }
+                let cut = cut.max(word.chars().next().map_or(1, char::len_utf8));
+                push_run(lines.last_mut().unwrap(), &word[..cut], run.style);
+                lines.push(Vec::new());
+                width = 0.0;
+                word = word[cut..].to_string();
+                word_w = measure(&word, run.style);
+            }
+            push_run(lines.last_mut().unwrap(), &word, run.style);
+            width += word_w;
+            space = false;
+        }
+    }
+    lines
+}
+
+fn push_run(line: &mut Vec<Run>, text: &str, style: FontStyle) {
+    match line.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ => line.push(Run::new(text, style)),
+    }
+}
+
+fn gray(level: f32) -> Color {
+    Color::Rgb(Rgb::new(level, level, level, None))
+}
+
+/// Places blocks on pages, top to bottom.
+struct Pager<'a> {
+    doc: PdfDocumentReference,
+    metrics: &'a Fonts,
+    fonts: Vec<IndirectFontRef>,
+    pages: Vec<PdfLayerReference>,
+    /// Distance of the cursor from the top of the page, in millimetres.
+    y: f32,
+}
+
+impl Pager<'_> {
+    fn layer(&self) -> &PdfLayerReference {
+        self.pages
+            .last()
+            .expect("the first page is added on creation")
+    }
+
+    fn font(&self, style: FontStyle) -> &IndirectFontRef {
+        &self.fonts[style as usize]
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(Mm(PAGE_W), Mm(PAGE_H), "content");
+        self.pages.push(self.doc.get_page(page).get_layer(layer));
+        self.y = TOP;
+    }
+
+    /// Start a new page unless `height` millimetres still fit on this one.
+    fn ensure(&mut self, height: f32) {
+        if self.y + height > BOTTOM && self.y > TOP {
+            self.new_page();
+        }
+    }
+
+    fn text(&self, text: &str, style: FontStyle, size: f32, x: f32, baseline: f32) {
+        self.layer()
+            .use_text(text, size, Mm(x), Mm(PAGE_H - baseline), self.font(style));
+    }
+
+    fn line(&self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.layer().add_line(Line {
+            points: vec![
+                (Point::new(Mm(x1), Mm(PAGE_H - y1)), false),
+                (Point::new(Mm(x2), Mm(PAGE_H - y2)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn fill(&self, x: f32, y: f32, w: f32, h: f32, color: Color) {
+        let layer = self.layer();
+        layer.set_fill_color(color);
+        layer.add_rect(
+            Rect::new(Mm(x), Mm(PAGE_H - y - h), Mm(x + w), Mm(PAGE_H - y))
+                .with_mode(PaintMode::Fill),
+        );
+        layer.set_fill_color(gray(0.0));
+    }
+
+    fn runs(&mut self, runs: &[Run], size: f32, x: f32, width: f32) {
+        let metrics = self.metrics;
+        let lines = wrap(runs, width, &|t, s| metrics.width(t, s, size));
+        let height = size * LINE_HEIGHT * PT;
+        for line in lines {
+            self.ensure(height);
+            let mut cx = x;
+            for run in &line {
+                self.text(&run.text, run.style, size, cx, self.y + size * PT);
+                cx += metrics.width(&run.text, run.style, size);
+            }
+            self.y += height;
+        }
+    }
+
+    fn heading(&mut self, level: u32, text: &str) {
+        let size = match level {
+            1 => 20.0,
+            2 => 16.0,
+            3 => 13.0,
+            _ => BODY_SIZE,
+        };
+        // Keep the heading together with the first lines below it.
+        self.ensure(size * 1.6 * PT + 3.0 * BODY_SIZE * LINE_HEIGHT * PT);
+        self.y += size * 0.4 * PT;
+        self.runs(&[Run::new(text, FontStyle::Bold)], size, MARGIN, CONTENT_W);
+        if level == 1 {
+            self.line(MARGIN, self.y, PAGE_W - MARGIN, self.y);
+        }
+        self.y += size * 0.3 * PT;
+    }
+
+    fn code(&mut self, code: &str) {
+        let height = CODE_SIZE * LINE_HEIGHT * PT;
+        let pad = 2.0;
+        let metrics = self.metrics;
+        let runs: Vec<Vec<Run>> = code
+            .trim_end_matches('\n')
+            .lines()
+            .flat_map(|line| {
+                let line = line.replace('\t', "    ");
+                // Keep indentation, which whitespace based wrapping would drop.
+                let indent = line.len() - line.trim_start().len();
+                let measure = |t: &str, s| metrics.width(t, s, CODE_SIZE);
+                let mut wrapped = wrap(
+                    &[Run::new(line.trim_start(), FontStyle::Mono)],
+                    CONTENT_W - 2.0 * pad - measure(&line[..indent], FontStyle::Mono),
+                    &measure,
+                );
+                if let Some(first) = wrapped.first_mut().and_then(|l| l.first_mut()) {
+                    first.text.insert_str(0, &line[..indent]);
+                }
+                wrapped
+            })
+            .collect();
+        self.y += 1.0;
+        for line in runs {
+            self.ensure(height);
+            self.fill(MARGIN, self.y, CONTENT_W, height, gray(0.94));
+            if let Some(run) = line.first() {
+                self.text(
+                    &run.text,
+                    FontStyle::Mono,
+                    CODE_SIZE,
+                    MARGIN + pad,
+                    self.y + CODE_SIZE * PT,
+                );
+            }
+            self.y += height;
+        }
+        self.y += 1.0;
+    }
+
+    fn table(&mut self, rows: &[Vec<String>]) {
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        let pad = 1.5;
+        let size = BODY_SIZE - 1.0;
+        let metrics = self.metrics;
+        let natural: Vec<f32> = (0..columns)
+            .map(|c| {
+                rows.iter()
+                    .filter_map(|r| r.get(c))
+                    .map(|t| metrics.width(t, FontStyle::Bold, size) + 2.0 * pad)
+                    .fold(10.0, f32::max)
+            })
+            .collect();
+        let total: f32 = natural.iter().sum();
+        let scale = (CONTENT_W / total).min(1.0);
+        let widths: Vec<f32> = natural.iter().map(|w| w * scale).collect();
+        let line_h = size * LINE_HEIGHT * PT;
+        let layout = |row: &Vec<String>, style: FontStyle| -> Vec<Vec<Vec<Run>>> {
+            (0..columns)
+                .map(|c| {
+                    let text = row.get(c).map_or("", String::as_str);
+                    wrap(&[Run::new(text, style)], widths[c] - 2.0 * pad, &|t, s| {
+                        metrics.width(t, s, size)
+                    })
+                })
+                .collect()
+        };
+        let header = layout(&rows[0], FontStyle::Bold);
+        let draw = |pager: &mut Self, cells: &[Vec<Vec<Run>>], is_header: bool| {
+            let lines = cells.iter().map(Vec::len).max().unwrap_or(1);
+            let height = lines as f32 * line_h + 2.0 * pad;
+            let top = pager.y;
+            if is_header {
+                pager.fill(MARGIN, top, widths.iter().sum(), height, gray(0.92));
+            }
+            let mut x = MARGIN;
+            for (cell, width) in cells.iter().zip(&widths) {
+                for (i, line) in cell.iter().enumerate() {
+                    if let Some(run) = line.first() {
+                        let baseline = top + pad + i as f32 * line_h + size * PT;
+                        pager.text(&run.text, run.style, size, x + pad, baseline);
+                    }
+                }
+                pager.line(x, top, x, top + height);
+                x += width;
+            }
+            pager.line(x, top, x, top + height);
+            pager.line(MARGIN, top, x, top);
+            pager.line(MARGIN, top + height, x, top + height);
+            pager.y += height;
+        };
+        self.y += 1.0;
+        self.ensure(2.0 * (line_h + 2.0 * pad));
+        draw(self, &header, true);
+        for row in &rows[1..] {
+            let cells = layout(row, FontStyle::Regular);
+            let lines = cells.iter().map(Vec::len).max().unwrap_or(1);
+            let height = lines as f32 * line_h + 2.0 * pad;
+            if self.y + height > BOTTOM {
+                // Repeat the header on the next page.
+                self.new_page();
+                draw(self, &header, true);
+            }
+            draw(self, &cells, false);
+        }
+        self.y += 2.0;
+    }
+
+    fn image(&mut self, thumb: &Thumbnail) {
+        let natural_w = thumb.width as f32 * 25.4 / IMAGE_DPI;
+        let natural_h = thumb.height as f32 * 25.4 / IMAGE_DPI;
+        let scale = (CONTENT_W / natural_w).min(120.0 / natural_h).min(1.0);
+        let (w, h) = (natural_w * scale, natural_h * scale);
+        self.ensure(h + 2.0);
+        // PDF images have no alpha here, so blend transparent pixels onto white.
+        let rgb: Vec<u8> = thumb
+            .rgba
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let a = p[3] as u32;
+                [0, 1, 2].map(|i| ((p[i] as u32 * a + 255 * (255 - a)) / 255) as u8)
+            })
+            .collect();
+        let image = Image::from(ImageXObject {
+            width: Px(thumb.width as usize),
+            height: Px(thumb.height as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: rgb,
+            image_filter: None,
+            smask: None,
+            clipping_bbox: None,
+        });
+        self.y += 1.0;
+        image.add_to_layer(
+            self.layer().clone(),
+            ImageTransform {
+                translate_x: Some(Mm(MARGIN + (CONTENT_W - w) / 2.0)),
+                translate_y: Some(Mm(PAGE_H - self.y - h)),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                dpi: Some(IMAGE_DPI),
+                ..ImageTransform::default()
+            },
+        );
+        self.y += h + 1.0;
+    }
+
+    fn math(&mut self, layout: &MathBox) {
+        let size = BODY_SIZE + 1.0;
+        let (cell_w, cell_h) = (size * 0.6 * PT, size * 1.2 * PT);
+        let w = layout.width as f32 * cell_w;
+        let h = layout.height as f32 * cell_h;
+        self.ensure(h + 2.0);
+        let x0 = MARGIN + ((CONTENT_W - w) / 2.0).max(0.0);
+        let y0 = self.y + 1.0;
+        for item in &layout.items {
+            match *item {
+                MathItem::Glyph { ch: ' ', .. } => {}
+                MathItem::Glyph { x, y, ch, script } => {
+                    let glyph_size = if script { size * 0.7 } else { size };
+                    let text = ch.to_string();
+                    let advance = self.metrics.width(&text, FontStyle::Regular, glyph_size);
+                    let cx = x0 + (x as f32 + 0.5) * cell_w - advance / 2.0;
+                    let baseline = y0 + (y as f32 + 0.8) * cell_h;
+                    self.text(&text, FontStyle::Regular, glyph_size, cx, baseline);
+                }
+                MathItem::Rule { x, y, width } => {
+                    let mid = y0 + (y as f32 + 0.5) * cell_h;
+                    self.line(
+                        x0 + x as f32 * cell_w,
+                        mid,
+                        x0 + (x + width) as f32 * cell_w,
+                        mid,
+                    );
+                }
+            }
+        }
+        self.y += h + 2.0;
+    }
+
+    fn block(&mut self, block: &Block) {
+        match block {
+            Block::Heading(level, text) => self.heading(*level, text),
+            Block::Paragraph {
+                runs,
+                indent,
+                bullet,
+            } => {
+                self.y += 1.0;
+                if let Some(bullet) = bullet {
+                    self.ensure(BODY_SIZE * LINE_HEIGHT * PT);
+                    let x = MARGIN + indent - 4.5;
+                    self.text(
+                        bullet,
+                        FontStyle::Regular,
+                        BODY_SIZE,
+                        x,
+                        self.y + BODY_SIZE * PT,
+                    );
+                }
+                self.runs(runs, BODY_SIZE, MARGIN + indent, CONTENT_W - indent);
+                self.y += 1.0;
+            }
+            Block::Code(code) => self.code(code),
+            Block::Table(rows) => self.table(rows),
+            Block::Image(Ok(thumb)) => self.image(thumb),
+            Block::Math(Ok(layout)) => self.math(layout),
+            Block::Image(Err(err)) | Block::Math(Err(err)) => {
+                let runs = [Run::new(format!("[{err}]"), FontStyle::Italic)];
+                self.runs(&runs, BODY_SIZE, MARGIN, CONTENT_W);
+            }
+        }
+    }
+
+    /// Draw the title header and page number footer on every page.
+    fn decorate(&self, title: &str) {
+        let count = self.pages.len();
+        for (i, layer) in self.pages.iter().enumerate() {
+            layer.set_fill_color(gray(0.45));
+            layer.use_text(
+                title,
+                SMALL_SIZE,
+                Mm(MARGIN),
+                Mm(PAGE_H - 14.0),
+                self.font(FontStyle::Regular),
+            );
+            let footer = format!("Page {} / {count}", i + 1);
+            let w = self.metrics.width(&footer, FontStyle::Regular, SMALL_SIZE);
+            let x = (PAGE_W - w) / 2.0;
+            layer.use_text(
+                footer,
+                SMALL_SIZE,
+                Mm(x),
+                Mm(12.0),
+                self.font(FontStyle::Regular),
+            );
+            layer.set_fill_color(gray(0.0));
+        }
+    }
+}
+
+/// Render laid out blocks to the bytes of a PDF file titled `title`.
+pub fn render(blocks: &[Block], title: &str, fonts: &Fonts) -> Result<Vec<u8>> {
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_W), Mm(PAGE_H), "content");
+    let refs = STYLES
+        .iter()
+        .map(|style| doc.add_external_font(fonts.data(*style)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = doc.get_page(page).get_layer(layer);
+    let mut pager = Pager {
+        doc,
+        metrics: fonts,
+        fonts: refs,
+        pages: vec![first],
+        y: TOP,
+    };
+    for block in blocks {
+        pager.block(block);
+    }
+    pager.decorate(title);
+    Ok(pager.doc.save_to_bytes()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoFiles;
+
+    impl Resources for NoFiles {
+        fn include(&mut self, target: &str) -> Option<String> {
+            (target == "part").then(|| "Included **text**".to_string())
+        }
+
+        fn leave(&mut self) {}
+
+        fn image(&mut self, url: &str) -> Result<Arc<Thumbnail>, String> {
+            Ok(Arc::new(Thumbnail {
+                width: 2,
+                height: 1,
+                rgba: url.bytes().take(8).collect(),
+            }))
+        }
+    }
+
+    fn texts(runs: &[Run]) -> Vec<&str> {
+        runs.iter().map(|r| r.text.as_str()).collect()
+    }
+
+    #[test]
+    fn wraps_words_and_cuts_long_ones() {
+        let runs = [
+            Run::new("one two ", FontStyle::Regular),
+            Run::new("three", FontStyle::Bold),
+            Run::new(" abcdefghij", FontStyle::Regular),
+        ];
+        let lines = wrap(&runs, 8.0, &|t, _| t.chars().count() as f32);
+        let lines: Vec<Vec<&str>> = lines.iter().map(|l| texts(l)).collect();
+        assert_eq!(
+            lines,
+            [vec!["one two"], vec!["three"], vec!["abcdefgh"], vec!["ij"]]
+        );
+    }
+
+    #[test]
+    fn builds_blocks_from_widgets() {
+        let source = "# Notes\n\nfirst line\nsecond *line*\n\n- a\n  1. b\n\n\
+                      | h |\n|---|\n| c |\n\n{% include-note part %}\n\n![x](pixel)\n";
+        let blocks = blocks(source, &mut NoFiles);
+        assert!(matches!(&blocks[0], Block::Heading(1, t) if t == "Notes"));
+        let Block::Paragraph { runs, .. } = &blocks[1] else {
+            panic!("{blocks:?}");
+        };
+        assert_eq!(texts(runs), ["first line", " ", "second ", "line"]);
+        assert!(matches!(
+            &blocks[2..4],
+            [
+                Block::Paragraph { bullet: Some(a), indent: i1, .. },
+                Block::Paragraph { bullet: Some(b), indent: i2, .. },
+            ] if a == "•" && b == "1." && i1 < i2
+        ));
+        assert!(matches!(&blocks[4], Block::Table(rows) if rows == &[vec!["h"], vec!["c"]]));
+        assert!(matches!(&blocks[5], Block::Paragraph { runs, .. } if runs[1].text == "text"));
+        assert!(matches!(&blocks[6], Block::Image(Ok(_))));
+    }
+
+    #[test]
+    fn renders_pages_with_embedded_fonts() {
+        // A public domain font shipped for the tests, see its LICENSE.txt.
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fonts");
+        let fonts = Fonts::load(Some(&dir)).unwrap();
+        assert!(
+            fonts.width("WW", FontStyle::Regular, 12.0)
+                > fonts.width("ii", FontStyle::Regular, 12.0)
+        );
+        let mut source = String::from("# Report\n\n$$\\frac{a}{b}$$\n\n```\ncode\n```\n\n");
+        for i in 0..80 {
+            source.push_str(&format!("Paragraph {i} with some text.\n\n"));
+        }
+        let pdf = render(&blocks(&source, &mut NoFiles), "Report", &fonts).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with(b"%PDF"));
+        assert!(text.contains("/FontFile2"));
+        let pages = text.matches("/Type/Page").count() - text.matches("/Type/Pages").count();
+        assert!(pages >= 2, "{pages} pages");
+    }
+}
@@ -0,0 +1,65 @@
+//! Import a folder of notes written with another Markdown tool.
+//!
+//! The folder is copied into the vault with its links, embeds and front
+//! matter converted, see [`storage::import`]. A report of everything that
+//! could not be converted is printed and written next to the other vault
+//! metadata.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+/// Import notes from another Markdown vault
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Folder to import
+    source: PathBuf,
+
+    /// Vault to import into, defaults to `ELEPHANT_NOTES_ORIGIN`
+    #[arg(long)]
+    origin: Option<PathBuf>,
+
+    /// Folder inside the vault receiving the notes, defaults to the name of
+    /// the source folder
+    #[arg(long)]
+    into: Option<PathBuf>,
+
+    /// Where to write the report, defaults to `.notes2/import-report.txt`
+    /// in the vault
+    #[arg(long)]
+    report: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let source = args
+        .source
+        .canonicalize()
+        .with_context(|| format!("{}", args.source.display()))?;
+    let origin = args
+        .origin
+        .or_else(|| std::env::var_os("ELEPHANT_NOTES_ORIGIN").map(PathBuf::from))
+        .context("--origin or ELEPHANT_NOTES_ORIGIN required")?;
+    let into = match args.into {
+        Some(into) => into,
+        None => PathBuf::from(source.file_name().context("source has no folder name")?),
+    };
+
+    let report = storage::import::import(&source, &origin, &into)
+        .with_context(|| format!("importing {}", source.display()))?;
+    let report_path = args
+        .report
+        .unwrap_or_else(|| origin.join(".notes2").join("import-report.txt"));
+    if let Some(dir) = report_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&report_path, report.to_string())?;
+    print!("{report}");
+    if !report.issues.is_empty() {
+        println!("\nReport written to {}", report_path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! List, compare and restore the saved versions of a note.
+//!
+//! The editors keep a snapshot of a note every time it is saved, see
+//! [`storage::snapshots`]. Snapshots are named by the start of their id, as
+//! printed by `list`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use storage::diff::{self, Change};
+use storage::snapshots::SnapshotStore;
+
+/// Lines of unchanged text shown around a change.
+const CONTEXT: usize = 3;
+
+/// Saved versions of notes
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Vault of the note, defaults to `ELEPHANT_NOTES_ORIGIN`
+    #[arg(long)]
+    origin: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Cmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// List the snapshots of a note, oldest first
+    List { note: PathBuf },
+    /// Print a snapshot
+    Show { note: PathBuf, id: String },
+    /// Show the lines changed between two snapshots, or between a snapshot
+    /// and the note as it is now
+    Diff {
+        note: PathBuf,
+        from: String,
+        to: Option<String>,
+    },
+    /// Put a snapshot back as the content of the note
+    Restore { note: PathBuf, id: String },
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let origin = args
+        .origin
+        .or_else(|| std::env::var_os("ELEPHANT_NOTES_ORIGIN").map(PathBuf::from))
+        .context("--origin or ELEPHANT_NOTES_ORIGIN required")?;
+    let store = SnapshotStore::open(&origin);
+    match args.command {
+        Cmd::List { note } => {
+            let note = relative(&origin, &note);
+            let snapshots = store.list(&note)?;
+            if snapshots.is_empty() {
+                println!("No snapshots of {}", note.display());
+            }
+            for snapshot in snapshots {
+                println!("{}  {}", &snapshot.id[..8], timestamp(snapshot.at));
+            }
+        }
+        Cmd::Show { note, id } => {
+            let note = relative(&origin, &note);
+            print!("{}", store.read(&store.find(&note, &id)?)?);
+        }
+        Cmd::Diff { note, from, to } => {
+            let note = relative(&origin, &note);
+            let old = store.read(&store.find(&note, &from)?)?;
+            let (new, label) = match to {
+                Some(to) => (store.read(&store.find(&note, &to)?)?, to),
+                None => {
+                    let file = origin.join(&note);
+                    let text =
+                        fs::read_to_string(&file).with_context(|| format!("{}", file.display()))?;
+                    (text, note.display().to_string())
+                }
+            };
+            println!("--- {from}\n+++ {label}");
+            print!("{}", unified(&diff::lines(&old, &new)));
+        }
+        Cmd::Restore { note, id } => {
+            let note = relative(&origin, &note);
+            let snapshot = store.find(&note, &id)?;
+            store
+                .restore(&note, &snapshot)
+                .with_context(|| format!("restoring {}", note.display()))?;
+            println!(
+                "Restored {} as saved {}",
+                note.display(),
+                timestamp(snapshot.at)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `note` relative to the vault at `origin`. Paths that do not point into
+/// the vault are taken as vault-relative already.
+fn relative(origin: &Path, note: &Path) -> PathBuf {
+    let (Ok(origin), Ok(note_abs)) = (origin.canonicalize(), note.canonicalize()) else {
+        return note.to_path_buf();
+    };
+    note_abs
+        .strip_prefix(&origin)
+        .map_or_else(|_| note.to_path_buf(), Path::to_path_buf)
+}
+
+/// Changed lines with a few unchanged ones around them, in the unified diff
+/// format without line numbers.
+fn unified(changes: &[Change]) -> String {
+    let changed: Vec<usize> = changes
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !matches!(c, Change::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut out = String::new();
+    let mut shown_to = 0;
+    for (n, &i) in changed.iter().enumerate() {
+        let start = i.saturating_sub(CONTEXT).max(shown_to);
+        if start > shown_to {
+            out.push_str("@@\n");
+        }
+        let next = changed.get(n + 1).copied().unwrap_or(changes.len());
+        let end = if next <= i + 2 * CONTEXT + 1 {
+            next
+        } else {
+            (i + CONTEXT + 1).min(changes.len())
+        };
+        for change in &changes[start..end] {
+            let line = match change {
+                Change::Same(l) => format!(" {l}\n"),
+                Change::Removed(l) => format!("-{l}\n"),
+                Change::Added(l) => format!("+{l}\n"),
+            };
+            out.push_str(&line);
+        }
+        shown_to = end;
+    }
+    out
+}
+
+/// `at` as a UTC date and time.
+fn timestamp(at: SystemTime) -> String {
+    let secs = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's
+    // `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
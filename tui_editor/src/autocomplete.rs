@@ -0,0 +1,619 @@
+//! Link completion in the editor.
+//!
+//! Typing `note://`, `[[` or `{% include-note ` opens a popup listing the
+//! notes of the vault, fuzzy matched against their paths and titles. After a
+//! `#` it lists the headings of the chosen note instead. The popup only takes
+//! the keys it needs to move and accept, everything else goes to the editor,
+//! and the popup follows the query as it is typed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use core_notes::fuzzy;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use markdown_renderer::links::{self, NoteIndex, Resolution};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use storage::fs_tree::Change;
+use storage::FileTree;
+use tui_textarea::TextArea;
+
+use crate::input::Viewport;
+
+/// Number of entries shown at once.
+const VISIBLE: usize = 8;
+/// Number of matches kept for a query.
+const MAX_ENTRIES: usize = 50;
+
+/// Text that opens the popup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    NoteUrl,
+    WikiLink,
+    Include,
+}
+
+const MARKERS: [(Trigger, &str); 3] = [
+    (Trigger::NoteUrl, "note://"),
+    (Trigger::WikiLink, "[["),
+    (Trigger::Include, "{% include-note "),
+];
+
+/// The trigger in `line` before the char column `col`, with the column
+/// where its query starts, if the link is still being typed.
+pub fn detect(line: &str, col: usize) -> Option<(Trigger, usize)> {
+    let before: String = line.chars().take(col).collect();
+    let (trigger, start) = MARKERS
+        .iter()
+        .filter_map(|(t, marker)| before.rfind(marker).map(|i| (*t, i + marker.len())))
+        .max_by_key(|(_, start)| *start)?;
+    let query = &before[start..];
+    let open = match trigger {
+        Trigger::NoteUrl => !query.contains([' ', ')', '>']),
+        Trigger::WikiLink => !query.contains([']', '|']),
+        Trigger::Include => !query.contains('%'),
+    };
+    open.then(|| (trigger, before[..start].chars().count()))
+}
+
+/// One line of the popup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub label: String,
+    pub detail: String,
+    /// Text replacing the query when the entry is accepted.
+    pub insert: String,
+}
+
+/// The open popup.
+#[derive(Debug, Clone)]
+pub struct Popup {
+    pub trigger: Trigger,
+    pub row: usize,
+    /// Char column where the query starts.
+    pub start: usize,
+    /// Whether the entries are headings rather than notes.
+    pub headings: bool,
+    pub entries: Vec<Entry>,
+    pub selected: usize,
+}
+
+struct Note {
+    /// Path relative to the vault.
+    path: PathBuf,
+    title: Option<String>,
+}
+
+/// Title of a note read in the background, `None` when the note could not be
+/// read, e.g. because it was removed meanwhile.
+type Title = (PathBuf, Option<Option<String>>);
+
+/// Start a thread reading the titles of the notes sent to it.
+fn read_titles() -> (Sender<PathBuf>, Receiver<Title>) {
+    let (paths, rx) = mpsc::channel::<PathBuf>();
+    let (tx, titles) = mpsc::channel();
+    thread::spawn(move || {
+        for path in rx {
+            let title = fs::read_to_string(&path)
+                .ok()
+                .map(|source| links::note_title(&source));
+            if tx.send((path, title)).is_err() {
+                break;
+            }
+        }
+    });
+    (paths, titles)
+}
+
+fn is_note(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "md")
+}
+
+/// Completion state of the editor.
+pub struct Autocomplete {
+    vault: PathBuf,
+    tree: Option<FileTree>,
+    notes: Vec<Note>,
+    /// The notes by path and title, to resolve link targets.
+    index: NoteIndex,
+    /// Resolved note and headings of each link target completed since the
+    /// notes were last reloaded.
+    headings: HashMap<String, Option<(PathBuf, Vec<links::Heading>)>>,
+    /// Entries of the vault read so far while it is still being read.
+    reading: Option<usize>,
+    /// Notes whose title is to be read, and the titles read.
+    to_read: Sender<PathBuf>,
+    titles: Receiver<Title>,
+    pub popup: Option<Popup>,
+    /// Row, query start and query at which the popup was closed. It stays
+    /// closed until the query changes.
+    closed: Option<(usize, usize, String)>,
+}
+
+impl Autocomplete {
+    /// Completion for the notes of `vault`, which are read in the
+    /// background so a large vault does not hold up the editor.
+    pub fn new(vault: PathBuf) -> Self {
+        let mut tree = FileTree::new(vault.clone()).ok();
+        if let Some(tree) = &mut tree {
+            tree.load_in_background(&vault, true);
+        }
+        let (to_read, titles) = read_titles();
+        Self {
+            tree,
+            vault,
+            notes: Vec::new(),
+            index: NoteIndex::default(),
+            headings: HashMap::new(),
+            reading: None,
+            to_read,
+            titles,
+            popup: None,
+            closed: None,
+        }
+    }
+
+    /// Take the notes read and changed since the last call from the storage
+    /// tree. Their titles are read in the background and show up in a later
+    /// call.
+    fn refresh_notes(&mut self) {
+        let Some(tree) = self.tree.as_mut() else {
+            return;
+        };
+        let mut changed = false;
+        for change in tree.poll_events() {
+            match change {
+                Change::Created(path) if path.is_dir() => {
+                    tree.load_in_background(&path, true);
+                }
+                Change::Created(path) | Change::Modified(path) => {
+                    if is_note(&path) {
+                        let _ = self.to_read.send(path);
+                    }
+                }
+                Change::Removed(path) => {
+                    changed |= Self::remove(&mut self.notes, &self.vault, &path);
+                }
+                Change::Renamed { from, to } => {
+                    changed |= Self::remove(&mut self.notes, &self.vault, &from);
+                    if to.is_dir() {
+                        tree.load_in_background(&to, true);
+                    } else if is_note(&to) {
+                        let _ = self.to_read.send(to);
+                    }
+                }
+            }
+        }
+        if tree.root().children().is_none() && !tree.loading() {
+            // The watcher lost events, so the tree is read again.
+            changed |= !self.notes.is_empty();
+            self.notes.clear();
+            tree.load_in_background(&self.vault, true);
+        }
+        let loads = tree.poll_loads();
+        for path in loads.iter().flat_map(|p| &p.added) {
+            if is_note(path) {
+                let _ = self.to_read.send(path.clone());
+            }
+        }
+        self.reading = loads.iter().find(|p| !p.done).map(|p| p.entries);
+        while let Ok((file, title)) = self.titles.try_recv() {
+            let path = file
+                .strip_prefix(&self.vault)
+                .unwrap_or(&file)
+                .to_path_buf();
+            let i = self.notes.binary_search_by(|n| n.path.cmp(&path));
+            match (i, title) {
+                (Ok(i), Some(title)) => self.notes[i].title = title,
+                (Err(i), Some(title)) => self.notes.insert(i, Note { path, title }),
+                (Ok(i), None) => {
+                    self.notes.remove(i);
+                }
+                (Err(_), None) => continue,
+            }
+            changed = true;
+        }
+        if changed {
+            self.index = NoteIndex::default();
+            for note in &self.notes {
+                self.index.insert(note.path.clone(), note.title.as_deref());
+            }
+            self.headings.clear();
+        }
+    }
+
+    /// Remove the notes at or below `path`, returning whether there were any.
+    fn remove(notes: &mut Vec<Note>, vault: &Path, path: &Path) -> bool {
+        let path = path.strip_prefix(vault).unwrap_or(path);
+        let len = notes.len();
+        notes.retain(|n| !n.path.starts_with(path));
+        notes.len() != len
+    }
+
+    /// Update the popup for the editor text and cursor. `note` is the path
+    /// of the edited note, used to resolve relative links.
+    pub fn update(&mut self, lines: &[String], cursor: (usize, usize), note: Option<&Path>) {
+        self.refresh_notes();
+        let (row, col) = cursor;
+        let line = lines.get(row).map_or("", String::as_str);
+        let Some((trigger, start)) = detect(line, col) else {
+            self.popup = None;
+            self.closed = None;
+            return;
+        };
+        let query: String = line.chars().skip(start).take(col - start).collect();
+        if self.closed.as_ref() == Some(&(row, start, query.clone())) {
+            return;
+        }
+        self.closed = None;
+        let after: String = line.chars().skip(col).collect();
+        let note = note.map(|n| n.strip_prefix(&self.vault).unwrap_or(n));
+        let heading_query = query
+            .split_once('#')
+            .filter(|_| trigger != Trigger::Include);
+        let entries = match heading_query {
+            Some((target, heading)) => self.heading_entries(trigger, target, heading, &after, note),
+            None => self.note_entries(trigger, &query, &after),
+        };
+        self.popup = (!entries.is_empty()).then_some(Popup {
+            trigger,
+            row,
+            start,
+            headings: heading_query.is_some(),
+            entries,
+            selected: 0,
+        });
+    }
+
+    fn note_entries(&self, trigger: Trigger, query: &str, after: &str) -> Vec<Entry> {
+        let paths: Vec<String> = self
+            .notes
+            .iter()
+            .map(|n| links::slash_path(&n.path))
+            .collect();
+        let mut scored: Vec<(i64, usize)> = self
+            .notes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, note)| {
+                let by_path = fuzzy::fuzzy_match(query, &paths[i]).map(|m| m.score);
+                let by_title = note
+                    .title
+                    .as_deref()
+                    .and_then(|t| fuzzy::fuzzy_match(query, t))
+                    .map(|m| m.score);
+                Some((by_path.max(by_title)?, i))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        scored
+            .into_iter()
+            .take(MAX_ENTRIES)
+            .map(|(_, i)| {
+                let note = &self.notes[i];
+                let insert = match trigger {
+                    Trigger::NoteUrl => links::url_path(&note.path),
+                    Trigger::WikiLink => {
+                        // The file name is enough unless another note shares it.
+                        let stem = note.path.file_stem().unwrap_or_default();
+                        let shared = self
+                            .notes
+                            .iter()
+                            .filter(|n| n.path.file_stem() == Some(stem))
+                            .count();
+                        let name = if shared == 1 {
+                            stem.to_string_lossy().into_owned()
+                        } else {
+                            paths[i].trim_end_matches(".md").to_string()
+                        };
+                        let close = if after.starts_with("]]") { "" } else { "]]" };
+                        format!("{name}{close}")
+                    }
+                    Trigger::Include => {
+                        let close = if after.trim_start().starts_with("%}") {
+                            ""
+                        } else {
+                            " %}"
+                        };
+                        format!("{}{close}", paths[i])
+                    }
+                };
+                Entry {
+                    label: paths[i].clone(),
+                    detail: note.title.clone().unwrap_or_default(),
+                    insert,
+                }
+            })
+            .collect()
+    }
+
+    fn heading_entries(
+        &mut self,
+        trigger: Trigger,
+        target: &str,
+        query: &str,
+        after: &str,
+        from: Option<&Path>,
+    ) -> Vec<Entry> {
+        // The target is kept as typed, `note://` URLs are looked up decoded.
+        let wanted = match trigger {
+            Trigger::NoteUrl => links::percent_decode(target),
+            _ => target.to_string(),
+        };
+        let (index, vault) = (&self.index, &self.vault);
+        let found = self.headings.entry(wanted.clone()).or_insert_with(|| {
+            let Resolution::Found(path) = index.resolve(&wanted, from) else {
+                return None;
+            };
+            let source = fs::read_to_string(vault.join(&path)).unwrap_or_default();
+            Some((path, links::headings(&source)))
+        });
+        let Some((path, headings)) = found else {
+            return Vec::new();
+        };
+        fuzzy::rank(query, headings, |h| h.text.as_str())
+            .into_iter()
+            .take(MAX_ENTRIES)
+            .map(|(i, _)| {
+                let links::Heading { level, text, .. } = &headings[i];
+                let insert = match trigger {
+                    Trigger::WikiLink => {
+                        let close = if after.starts_with("]]") { "" } else { "]]" };
+                        format!("{target}#{text}{close}")
+                    }
+                    _ => format!("{target}#{}", links::slug(text)),
+                };
+                Entry {
+                    label: format!("{} {text}", "#".repeat(*level)),
+                    detail: links::slash_path(path),
+                    insert,
+                }
+            })
+            .collect()
+    }
+
+    /// Handle a key while the popup is open. Returns whether the key was
+    /// used, otherwise it belongs to the editor.
+    pub fn handle_key(&mut self, key: KeyEvent, editor: &mut TextArea) -> bool {
+        let Some(popup) = self.popup.as_mut() else {
+            return false;
+        };
+        let len = popup.entries.len();
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Up => popup.selected = (popup.selected + len - 1) % len,
+            KeyCode::Char('p') if ctrl => popup.selected = (popup.selected + len - 1) % len,
+            KeyCode::Down => popup.selected = (popup.selected + 1) % len,
+            KeyCode::Char('n') if ctrl => popup.selected = (popup.selected + 1) % len,
+            KeyCode::Tab | KeyCode::Enter => {
+                let (row, col) = editor.cursor();
+                if row == popup.row && col >= popup.start {
+                    for _ in popup.start..col {
+                        editor.delete_char();
+                    }
+                    let insert = &popup.entries[popup.selected].insert;
+                    editor.insert_str(insert);
+                    self.closed = Some((row, popup.start, insert.clone()));
+                }
+                self.popup = None;
+            }
+            KeyCode::Esc => {
+                let line = editor.lines().get(popup.row).map_or("", String::as_str);
+                let col = editor.cursor().1;
+                let query = line
+                    .chars()
+                    .skip(popup.start)
+                    .take(col.saturating_sub(popup.start));
+                self.closed = Some((popup.row, popup.start, query.collect()));
+                self.popup = None;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Draw the popup below the start of the query in the editor shown in
+    /// `viewport`, or above it when there is no room below. Nothing is
+    /// drawn while the query is scrolled out of view.
+    pub fn render(&self, f: &mut Frame, viewport: &Viewport) {
+        let Some(popup) = &self.popup else {
+            return;
+        };
+        let area = viewport.area;
+        let row = popup
+            .row
+            .checked_sub(viewport.top)
+            .filter(|row| *row < area.height as usize);
+        let col = popup.start.saturating_sub(viewport.left);
+        let anchor = row.zip(u16::try_from(col).ok()).and_then(|(row, col)| {
+            Some((area.x.checked_add(col)?, area.y.checked_add(row as u16)?))
+        });
+        let Some((x, y)) = anchor else {
+            return;
+        };
+        let bounds = f.area();
+        let first = popup.selected.saturating_sub(VISIBLE - 1);
+        let shown = &popup.entries[first..popup.entries.len().min(first + VISIBLE)];
+        let widest = shown
+            .iter()
+            .map(|e| e.label.chars().count() + e.detail.chars().count() + 2)
+            .max()
+            .unwrap_or(0);
+        let width = (widest.min(62) as u16 + 2).max(24).min(bounds.width);
+        let height = (shown.len() as u16 + 2).min(bounds.height);
+        let x = x.min(bounds.right().saturating_sub(width));
+        let below = y.saturating_add(1);
+        let y = if below.saturating_add(height) <= bounds.bottom() {
+            below
+        } else {
+            y.saturating_sub(height).max(bounds.y)
+        };
+        let lines: Vec<Line> = shown
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let mut style = Style::default();
+                if first + i == popup.selected {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                Line::from(vec![
+                    Span::styled(entry.label.clone(), style),
+                    Span::styled(format!("  {}", entry.detail), style.fg(Color::DarkGray)),
+                ])
+            })
+            .collect();
+        let title = match self.reading {
+            _ if popup.headings => "Headings".to_string(),
+            Some(entries) => format!("Notes (reading, {entries} files)"),
+            None => "Notes".to_string(),
+        };
+        let rect = Rect::new(x, y, width, height).intersection(bounds);
+        if rect.is_empty() {
+            return;
+        }
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            rect,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEventKind;
+    use tui_textarea::CursorMove;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+            kind: KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        }
+    }
+
+    fn type_line(complete: &mut Autocomplete, text: &str) -> TextArea<'static> {
+        let mut editor = TextArea::from([text.to_string()]);
+        editor.move_cursor(CursorMove::End);
+        complete.update(editor.lines(), editor.cursor(), None);
+        editor
+    }
+
+    #[test]
+    fn detects_open_links_only() {
+        assert_eq!(detect("see [[No", 8), Some((Trigger::WikiLink, 6)));
+        assert_eq!(detect("see [[No]] x", 12), None);
+        assert_eq!(detect("[a](note://b", 12), Some((Trigger::NoteUrl, 11)));
+        assert_eq!(
+            detect("{% include-note a", 17),
+            Some((Trigger::Include, 16))
+        );
+        assert_eq!(detect("plain", 5), None);
+    }
+
+    #[test]
+    fn completes_notes_and_headings() {
+        let vault = tempfile::tempdir().unwrap();
+        fs::create_dir_all(vault.path().join("work")).unwrap();
+        fs::write(
+            vault.path().join("alpha.md"),
+            "# Alpha Plans\n\n## Step one\n```\n# not a heading\n```\n",
+        )
+        .unwrap();
+        fs::write(vault.path().join("work/beta.md"), "# Beta").unwrap();
+        fs::write(
+            vault.path().join("work/to do (old).md"),
+            "# Gamma\n\n## Last",
+        )
+        .unwrap();
+        let mut complete = Autocomplete::new(vault.path().to_path_buf());
+        while complete.notes.len() < 3 {
+            complete.refresh_notes();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        let mut editor = type_line(&mut complete, "see [[plans");
+        let popup = complete.popup.as_ref().unwrap();
+        assert_eq!(popup.entries[0].label, "alpha.md");
+        assert!(complete.handle_key(key(KeyCode::Enter), &mut editor));
+        assert_eq!(editor.lines()[0], "see [[alpha]]");
+        complete.update(editor.lines(), editor.cursor(), None);
+        assert!(complete.popup.is_none());
+
+        let mut editor = type_line(&mut complete, "[x](note://alpha.md#st");
+        let popup = complete.popup.as_ref().unwrap();
+        assert!(popup.headings);
+        assert_eq!(popup.entries.len(), 1);
+        complete.handle_key(key(KeyCode::Tab), &mut editor);
+        assert_eq!(editor.lines()[0], "[x](note://alpha.md#step-one");
+
+        let mut editor = type_line(&mut complete, "[x](note://gamm");
+        complete.handle_key(key(KeyCode::Enter), &mut editor);
+        assert_eq!(editor.lines()[0], "[x](note://work/to%20do%20%28old%29.md");
+        editor.insert_str("#la");
+        complete.update(editor.lines(), editor.cursor(), None);
+        complete.handle_key(key(KeyCode::Enter), &mut editor);
+        assert_eq!(
+            editor.lines()[0],
+            "[x](note://work/to%20do%20%28old%29.md#last"
+        );
+
+        let mut editor = type_line(&mut complete, "{% include-note bet");
+        assert!(!complete.handle_key(key(KeyCode::Char('a')), &mut editor));
+        assert!(complete.handle_key(key(KeyCode::Esc), &mut editor));
+        complete.update(editor.lines(), editor.cursor(), None);
+        assert!(complete.popup.is_none());
+        editor.insert_char('a');
+        complete.update(editor.lines(), editor.cursor(), None);
+        complete.handle_key(key(KeyCode::Enter), &mut editor);
+        assert_eq!(editor.lines()[0], "{% include-note work/beta.md %}");
+    }
+
+    #[test]
+    fn draws_the_popup_at_the_scrolled_cursor() {
+        let mut complete = Autocomplete::new(PathBuf::from("/nonexistent"));
+        let entry = Entry {
+            label: "alpha.md".into(),
+            detail: String::new(),
+            insert: "alpha]]".into(),
+        };
+        let mut popup = Popup {
+            trigger: Trigger::WikiLink,
+            row: 120,
+            start: 2,
+            headings: false,
+            entries: vec![entry; 3],
+            selected: 0,
+        };
+        let mut viewport = Viewport::default();
+        viewport.area = Rect::new(1, 1, 30, 10);
+        viewport.top = 115;
+        let draw = |complete: &Autocomplete, viewport: &Viewport| {
+            let mut terminal = Terminal::new(backend::TestBackend::new(40, 12)).unwrap();
+            terminal.draw(|f| complete.render(f, viewport)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..12)
+                .position(|y| buffer[(4, y)].symbol() == "a")
+                .map(|y| y as u16)
+        };
+
+        complete.popup = Some(popup.clone());
+        // Row 120 is the sixth visible row, the entries start below its
+        // border.
+        assert_eq!(draw(&complete, &viewport), Some(8));
+        viewport.top = 112;
+        assert_eq!(draw(&complete, &viewport), Some(5));
+        viewport.top = 0;
+        assert_eq!(draw(&complete, &viewport), None);
+        popup.row = usize::MAX;
+        popup.start = usize::MAX;
+        viewport.top = usize::MAX - 1;
+        complete.popup = Some(popup);
+        draw(&complete, &viewport);
+    }
+}
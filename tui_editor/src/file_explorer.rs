@@ -1,9 +1,18 @@
-use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+use std::time::{Duration, Instant, SystemTime};
 
-use crossterm::event::{Event, KeyCode, MouseButton, MouseEventKind};
+use crossterm::event::{Event, KeyCode, KeyModifiers, MouseButton, MouseEventKind};
+use markdown_renderer::links::note_title;
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, List, ListItem, ListState};
+use storage::fs_tree::Progress;
+
+use crate::history::age;
 
 /// Message emitted by [`FileExplorer`] when user interacts with the tree.
 #[derive(Debug, Clone)]
@@ -22,15 +31,35 @@ pub struct FileNode {
     pub is_dir: bool,
     pub children: Vec<FileNode>,
     pub expanded: bool,
+    /// Modification and creation time as the vault's watcher last saw them,
+    /// `None` for entries not taken from the vault, which are read once.
+    pub modified: Option<SystemTime>,
+    pub created: Option<SystemTime>,
 }
 
 impl FileNode {
     pub fn new_file(name: impl Into<String>, path: PathBuf) -> Self {
-        Self { name: name.into(), path, is_dir: false, children: Vec::new(), expanded: false }
+        Self {
+            name: name.into(),
+            path,
+            is_dir: false,
+            children: Vec::new(),
+            expanded: false,
+            modified: None,
+            created: None,
+        }
     }
 
     pub fn new_dir(name: impl Into<String>, path: PathBuf) -> Self {
-        Self { name: name.into(), path, is_dir: true, children: Vec::new(), expanded: false }
+        Self {
+            name: name.into(),
+            path,
+            is_dir: true,
+            children: Vec::new(),
+            expanded: false,
+            modified: None,
+            created: None,
+        }
     }
 }
 
@@ -46,10 +75,100 @@ impl FileTree {
     }
 }
 
+/// Order of the entries of a folder. Folders always come before files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortMode {
+    /// By name, ignoring case.
+    #[default]
+    Name,
+    /// By name, comparing runs of digits by their value, so `note 2` comes
+    /// before `note 10`.
+    Natural,
+    /// Most recently modified first.
+    Modified,
+    /// Most recently created first.
+    Created,
+}
+
+impl SortMode {
+    /// The mode after this one, going round.
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Natural,
+            SortMode::Natural => SortMode::Modified,
+            SortMode::Modified => SortMode::Created,
+            SortMode::Created => SortMode::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Natural => "natural",
+            SortMode::Modified => "modified",
+            SortMode::Created => "created",
+        }
+    }
+}
+
+/// What the explorer read about an entry from disk.
+#[derive(Debug, Clone, Default)]
+struct NoteInfo {
+    modified: Option<SystemTime>,
+    created: Option<SystemTime>,
+    /// Whether the note itself was read for its title and words.
+    content: bool,
+    /// Front matter `title` or first heading of a note.
+    title: Option<String>,
+    /// Words of a note, without its front matter.
+    words: Option<usize>,
+}
+
+/// Info of entries that were not read.
+static NO_INFO: NoteInfo = NoteInfo {
+    modified: None,
+    created: None,
+    content: false,
+    title: None,
+    words: None,
+};
+
+impl NoteInfo {
+    /// Info of the entry `node`, reading the note itself only with
+    /// `content`. Its times are read from disk if the node has none.
+    fn read(node: &FileNode, content: bool) -> Self {
+        let (modified, created) = match node.modified {
+            Some(_) => (node.modified, node.created),
+            None => {
+                let meta = fs::metadata(&node.path).ok();
+                (
+                    meta.as_ref().and_then(|m| m.modified().ok()),
+                    meta.as_ref().and_then(|m| m.created().ok()),
+                )
+            }
+        };
+        let mut info = Self {
+            modified,
+            created,
+            content,
+            ..Self::default()
+        };
+        if content && !node.is_dir && node.path.extension().is_some_and(|e| e == "md") {
+            if let Ok(source) = fs::read_to_string(&node.path) {
+                info.title = note_title(&source);
+                info.words = Some(word_count(&source));
+            }
+        }
+        info
+    }
+}
+
 #[derive(Clone, Debug)]
 struct VisibleNode {
     path: Vec<usize>,
     name: String,
+    /// Modification time and word count, when details are shown.
+    detail: String,
     is_dir: bool,
     expanded: bool,
     indent: usize,
@@ -57,6 +176,12 @@ struct VisibleNode {
 }
 
 /// Interactive file explorer widget.
+///
+/// Typing filters the tree by name, `Backspace` and `Esc` edit and clear the
+/// filter. `Ctrl+S` cycles the [`SortMode`], `Ctrl+L` shows note titles
+/// instead of file names and `Ctrl+D` toggles the detail column. Folders of
+/// the vault being read show their progress in the title until they are
+/// read or [`FileExplorer::cancel_loads`] stops them.
 #[derive(Debug)]
 pub struct FileExplorer {
     tree: FileTree,
@@ -64,6 +189,18 @@ pub struct FileExplorer {
     visible: Vec<VisibleNode>,
     last_click: Option<(Instant, usize)>,
     press_start: Option<(Instant, usize)>,
+    sort: SortMode,
+    show_titles: bool,
+    show_details: bool,
+    filter: String,
+    /// Entries read from disk so far, by path.
+    info: HashMap<PathBuf, NoteInfo>,
+    /// Vault the tree is read from, see [`FileExplorer::for_vault`].
+    source: Option<storage::FileTree>,
+    /// Whether the tree is to be taken from the vault again.
+    stale: bool,
+    /// Folders of the vault still being read.
+    loads: Vec<Progress>,
 }
 
 impl FileExplorer {
@@ -74,31 +211,267 @@ impl FileExplorer {
             visible: Vec::new(),
             last_click: None,
             press_start: None,
+            sort: SortMode::default(),
+            show_titles: false,
+            show_details: false,
+            filter: String::new(),
+            info: HashMap::new(),
+            source: None,
+            stale: false,
+            loads: Vec::new(),
+        }
+    }
+
+    /// Explorer of the vault `tree` is rooted at. Folders are read in the
+    /// background when they are opened.
+    pub fn for_vault(mut tree: storage::FileTree) -> Self {
+        let root = tree.root().path().to_path_buf();
+        tree.load_in_background(&root, false);
+        let mut top = FileNode::new_dir(file_name(&root), root);
+        top.expanded = true;
+        let mut explorer = Self::new(FileTree::new(top));
+        explorer.source = Some(tree);
+        explorer
+    }
+
+    /// Keep at most `limit` entries of the vault loaded by unloading closed
+    /// folders, `None` to keep everything. Only applies to an explorer made
+    /// with [`FileExplorer::for_vault`].
+    pub fn set_node_limit(&mut self, limit: Option<usize>) {
+        if let Some(source) = &mut self.source {
+            source.set_node_limit(limit);
+            self.stale = true;
+        }
+    }
+
+    pub fn sort(&self) -> SortMode {
+        self.sort
+    }
+
+    pub fn set_sort(&mut self, sort: SortMode) {
+        self.sort = sort;
+    }
+
+    /// Show the title of notes instead of their file name.
+    pub fn set_show_titles(&mut self, show: bool) {
+        self.show_titles = show;
+    }
+
+    /// Show the modification time and word count next to each entry.
+    pub fn set_show_details(&mut self, show: bool) {
+        self.show_details = show;
+    }
+
+    /// Stop reading folders of the vault. Folders read in part are shown
+    /// empty and read again when they are opened next.
+    pub fn cancel_loads(&mut self) {
+        let Some(source) = &mut self.source else {
+            return;
+        };
+        let dirs: Vec<PathBuf> = source.loading_dirs().map(Path::to_path_buf).collect();
+        for dir in dirs {
+            source.cancel(&dir);
         }
+        self.loads.clear();
+        self.stale = true;
     }
 
-    fn collect(nodes: &[FileNode], prefix: &mut Vec<usize>, indent: usize, out: &mut Vec<VisibleNode>) {
-        for (i, node) in nodes.iter().enumerate() {
+    /// Show hidden entries of the vault, or leave them out again.
+    pub fn toggle_hidden(&mut self) {
+        if let Some(source) = &mut self.source {
+            source.set_show_hidden(!source.ignore().show_hidden());
+            self.stale = true;
+        }
+    }
+
+    /// Text the entries are filtered by, empty to show all of them.
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: impl Into<String>) {
+        self.filter = filter.into();
+        self.state.select(Some(0));
+    }
+
+    fn collect(
+        &self,
+        nodes: &[FileNode],
+        prefix: &mut Vec<usize>,
+        indent: usize,
+        out: &mut Vec<VisibleNode>,
+    ) {
+        let filtering = !self.filter.is_empty();
+        let mut order: Vec<usize> = (0..nodes.len()).collect();
+        order.sort_by(|a, b| self.compare(&nodes[*a], &nodes[*b]));
+        for i in order {
+            let node = &nodes[i];
+            // While filtering, folders are open to show what matches inside.
+            let open = node.is_dir && (node.expanded || filtering);
             prefix.push(i);
+            let start = out.len();
             out.push(VisibleNode {
                 path: prefix.clone(),
-                name: node.name.clone(),
+                name: self.label(node).to_string(),
+                detail: if self.show_details {
+                    self.detail(node)
+                } else {
+                    String::new()
+                },
                 is_dir: node.is_dir,
-                expanded: node.expanded,
+                expanded: open,
                 indent,
                 full_path: node.path.clone(),
             });
-            if node.is_dir && node.expanded {
-                Self::collect(&node.children, prefix, indent + 1, out);
+            if open {
+                self.collect(&node.children, prefix, indent + 1, out);
+            }
+            if filtering && out.len() == start + 1 && !self.matches(node) {
+                out.pop();
             }
             prefix.pop();
         }
     }
 
+    /// Take what was read and changed in the vault since the last call.
+    fn sync(&mut self) {
+        let Some(source) = &mut self.source else {
+            return;
+        };
+        let loads = source.poll_loads();
+        self.loads = loads.iter().filter(|p| !p.done).cloned().collect();
+        let changes = source.poll_events();
+        if !std::mem::take(&mut self.stale) && loads.is_empty() && changes.is_empty() {
+            return;
+        }
+        let mut expanded = HashSet::new();
+        expanded_paths(&self.tree.root, &mut expanded);
+        self.tree.root = from_node(source.root(), &expanded);
+    }
+
     fn refresh_visible(&mut self) {
-        self.visible.clear();
+        self.sync();
+        // Notes are only read for what is shown, and only their metadata to
+        // sort them by time.
+        let content = self.show_titles || self.show_details;
+        if content || matches!(self.sort, SortMode::Modified | SortMode::Created) {
+            Self::read_info(
+                &self.tree.root,
+                !self.filter.is_empty(),
+                content,
+                &mut self.info,
+            );
+        }
+        let mut visible = Vec::new();
         let mut prefix = Vec::new();
-        Self::collect(std::slice::from_ref(&self.tree.root), &mut prefix, 0, &mut self.visible);
+        self.collect(
+            std::slice::from_ref(&self.tree.root),
+            &mut prefix,
+            0,
+            &mut visible,
+        );
+        self.visible = visible;
+        if self
+            .state
+            .selected()
+            .is_some_and(|i| i >= self.visible.len())
+        {
+            self.state.select(self.visible.len().checked_sub(1));
+        }
+    }
+
+    /// Read the entries that can be shown, all of them with `all`, and the
+    /// notes themselves with `content`. Entries are only read again once
+    /// the watcher saw their modification time change.
+    fn read_info(node: &FileNode, all: bool, content: bool, info: &mut HashMap<PathBuf, NoteInfo>) {
+        let known = info.get(&node.path).is_some_and(|i| {
+            (node.modified.is_none() || i.modified == node.modified) && (i.content || !content)
+        });
+        if !known {
+            info.insert(node.path.clone(), NoteInfo::read(node, content));
+        }
+        if node.is_dir && (node.expanded || all) {
+            for child in &node.children {
+                Self::read_info(child, all, content, info);
+            }
+        }
+    }
+
+    fn info(&self, node: &FileNode) -> &NoteInfo {
+        self.info.get(&node.path).unwrap_or(&NO_INFO)
+    }
+
+    /// Name the entry is shown with.
+    fn label<'a>(&'a self, node: &'a FileNode) -> &'a str {
+        match &self.info(node).title {
+            Some(title) if self.show_titles && !node.is_dir => title,
+            _ => &node.name,
+        }
+    }
+
+    fn detail(&self, node: &FileNode) -> String {
+        let info = self.info(node);
+        let modified = info
+            .modified
+            .map(|at| age(SystemTime::now(), at))
+            .unwrap_or_default();
+        match info.words {
+            Some(words) => format!(
+                "{modified:>12}  {words:>5} {:<5}",
+                if words == 1 { "word" } else { "words" }
+            ),
+            None => format!("{modified:>12}  {:>11}", ""),
+        }
+    }
+
+    /// Whether the file name or shown name of `node` contains the filter.
+    fn matches(&self, node: &FileNode) -> bool {
+        let filter = self.filter.to_lowercase();
+        node.name.to_lowercase().contains(&filter)
+            || self.label(node).to_lowercase().contains(&filter)
+    }
+
+    fn compare(&self, a: &FileNode, b: &FileNode) -> Ordering {
+        let (info_a, info_b) = (self.info(a), self.info(b));
+        let by_mode = match self.sort {
+            SortMode::Name => self
+                .label(a)
+                .to_lowercase()
+                .cmp(&self.label(b).to_lowercase()),
+            SortMode::Natural => natural_cmp(self.label(a), self.label(b)),
+            // Entries without a time go last.
+            SortMode::Modified => info_b.modified.cmp(&info_a.modified),
+            SortMode::Created => info_b.created.cmp(&info_a.created),
+        };
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then(by_mode)
+            .then_with(|| a.name.cmp(&b.name))
+    }
+
+    /// Open or close the folder at `path` in the tree. Opened folders of the
+    /// vault are read if they are not loaded, closed ones may be unloaded.
+    fn set_expanded(&mut self, path: &[usize], expanded: bool) {
+        let Some(node) = self.get_mut(path) else {
+            return;
+        };
+        node.expanded = expanded;
+        let dir = node.path.clone();
+        let Some(source) = &mut self.source else {
+            return;
+        };
+        if !expanded {
+            source.collapse(&dir);
+            self.stale = true;
+        } else if find(source.root(), &dir).is_some_and(|n| n.children().is_none()) {
+            source.load_in_background(&dir, false);
+        }
+    }
+
+    fn toggle(&mut self, path: &[usize]) {
+        if let Some(open) = self.get_mut(path).map(|n| n.expanded) {
+            self.set_expanded(path, !open);
+        }
     }
 
     fn get_mut(&mut self, path: &[usize]) -> Option<&mut FileNode> {
@@ -112,23 +485,56 @@ impl FileExplorer {
     /// Draw the explorer in the given area.
     pub fn render(&mut self, f: &mut Frame<'_>, area: Rect) {
         self.refresh_visible();
+        let width = area.width.saturating_sub(2) as usize;
         let items: Vec<ListItem> = self
             .visible
             .iter()
             .map(|v| {
                 let indent = "  ".repeat(v.indent);
                 let icon = if v.is_dir {
-                    if v.expanded { "▾" } else { "▸" }
+                    if v.expanded {
+                        "▾"
+                    } else {
+                        "▸"
+                    }
                 } else {
                     " "
                 };
-                ListItem::new(format!("{}{} {}", indent, icon, v.name))
+                let name = format!("{}{} {}", indent, icon, v.name);
+                if v.detail.is_empty() {
+                    return ListItem::new(name);
+                }
+                // The detail column is right aligned, long names are cut.
+                let room = width.saturating_sub(v.detail.chars().count() + 1);
+                let name: String = name.chars().take(room).collect();
+                let pad = room - name.chars().count() + 1;
+                ListItem::new(Line::from(vec![
+                    Span::raw(name),
+                    Span::raw(" ".repeat(pad)),
+                    Span::styled(v.detail.clone(), Style::default().fg(Color::DarkGray)),
+                ]))
             })
             .collect();
-        let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Files"));
+        let list =
+            List::new(items).block(Block::default().borders(Borders::ALL).title(self.title()));
         f.render_stateful_widget(list, area, &mut self.state);
     }
 
+    /// Title of the explorer, with the filter and how far the folders being
+    /// read got.
+    fn title(&self) -> String {
+        let mut title = format!("Files by {}", self.sort.label());
+        if !self.filter.is_empty() {
+            title.push_str(&format!(" /{}", self.filter));
+        }
+        if !self.loads.is_empty() {
+            let read: usize = self.loads.iter().map(|p| p.entries).sum();
+            let found: usize = self.loads.iter().map(|p| p.total).sum();
+            title.push_str(&format!(" reading {read}/{found}"));
+        }
+        title
+    }
+
     /// Handle an input event. Returns a message if one was produced.
     pub fn handle_event(&mut self, ev: &Event) -> Option<ExplorerMessage> {
         match ev {
@@ -136,11 +542,13 @@ impl FileExplorer {
                 MouseEventKind::Down(MouseButton::Left) => {
                     let idx = self.state.selected().unwrap_or(0);
                     self.last_click = match self.last_click.take() {
-                        Some((inst, last_idx)) if last_idx == idx && inst.elapsed() < Duration::from_millis(500) => {
+                        Some((inst, last_idx))
+                            if last_idx == idx && inst.elapsed() < Duration::from_millis(500) =>
+                        {
                             if let Some(node) = self.visible.get(idx) {
                                 if node.is_dir {
                                     let path = node.path.clone();
-                                    if let Some(n) = self.get_mut(&path) { n.expanded = !n.expanded; }
+                                    self.toggle(&path);
                                 } else {
                                     return Some(ExplorerMessage::Open(node.full_path.clone()));
                                 }
@@ -164,7 +572,32 @@ impl FileExplorer {
                 }
                 _ => {}
             },
+            Event::Key(key) if key.modifiers.contains(KeyModifiers::CONTROL) => match key.code {
+                KeyCode::Char('s') => self.sort = self.sort.next(),
+                KeyCode::Char('l') => self.show_titles = !self.show_titles,
+                KeyCode::Char('d') => self.show_details = !self.show_details,
+                _ => {}
+            },
             Event::Key(key) => match key.code {
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.state.select(Some(0));
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.state.select(Some(0));
+                }
+                KeyCode::Esc => self.set_filter(""),
+                KeyCode::Enter => {
+                    if let Some(node) = self.state.selected().and_then(|i| self.visible.get(i)) {
+                        if node.is_dir {
+                            let path = node.path.clone();
+                            self.toggle(&path);
+                        } else {
+                            return Some(ExplorerMessage::Open(node.full_path.clone()));
+                        }
+                    }
+                }
                 KeyCode::Up => {
                     let i = self.state.selected().unwrap_or(0);
                     self.state.select(Some(i.saturating_sub(1)));
@@ -180,7 +613,7 @@ impl FileExplorer {
                         if let Some(node) = self.visible.get(idx) {
                             if node.is_dir && !node.expanded {
                                 let path = node.path.clone();
-                                if let Some(n) = self.get_mut(&path) { n.expanded = true; }
+                                self.set_expanded(&path, true);
                             }
                         }
                     }
@@ -190,7 +623,7 @@ impl FileExplorer {
                         if let Some(node) = self.visible.get(idx) {
                             if node.is_dir && node.expanded {
                                 let path = node.path.clone();
-                                if let Some(n) = self.get_mut(&path) { n.expanded = false; }
+                                self.set_expanded(&path, false);
                             }
                         }
                     }
@@ -203,3 +636,329 @@ impl FileExplorer {
     }
 }
 
+impl crate::sidebar::FileExplorer for FileExplorer {
+    fn go_home(&mut self) {
+        self.set_filter("");
+    }
+
+    fn new_note(&mut self) {}
+
+    fn search(&mut self) {}
+
+    fn go_back(&mut self) {}
+
+    fn settings(&mut self) {}
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into_owned(),
+    )
+}
+
+/// Paths of the open folders from `node` down.
+fn expanded_paths(node: &FileNode, out: &mut HashSet<PathBuf>) {
+    if node.expanded {
+        out.insert(node.path.clone());
+    }
+    for child in &node.children {
+        expanded_paths(child, out);
+    }
+}
+
+/// The loaded part of the vault from `node` down, with the folders in
+/// `expanded` open.
+fn from_node(node: &storage::Node, expanded: &HashSet<PathBuf>) -> FileNode {
+    FileNode {
+        name: file_name(node.path()),
+        path: node.path().to_path_buf(),
+        is_dir: node.is_dir(),
+        children: node
+            .children()
+            .unwrap_or_default()
+            .iter()
+            .map(|c| from_node(c, expanded))
+            .collect(),
+        expanded: expanded.contains(node.path()),
+        modified: node.metadata().modified().ok(),
+        created: node.metadata().created().ok(),
+    }
+}
+
+fn find<'a>(node: &'a storage::Node, path: &Path) -> Option<&'a storage::Node> {
+    if node.path() == path {
+        return Some(node);
+    }
+    node.children()?
+        .iter()
+        .filter(|c| path.starts_with(c.path()))
+        .find_map(|c| find(c, path))
+}
+
+/// Words of the note `source`, leaving out its front matter and markup
+/// such as heading, list and emphasis markers.
+fn word_count(source: &str) -> usize {
+    let body = source
+        .strip_prefix("---\n")
+        .and_then(|rest| rest.find("\n---").map(|end| &rest[end + 4..]))
+        .unwrap_or(source);
+    body.lines()
+        .map(|line| {
+            let mut words = line.split_whitespace().peekable();
+            while words.next_if(|w| is_marker(w)).is_some() {}
+            words
+                .filter(|w| w.chars().any(char::is_alphanumeric))
+                .count()
+        })
+        .sum()
+}
+
+/// Whether `word` starts a line as markup: a heading, quote or list marker,
+/// a numbered list marker like `1.` or a task box like `[x]`.
+fn is_marker(word: &str) -> bool {
+    let numbered = word
+        .strip_suffix(['.', ')'])
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    numbered || matches!(word, "[x]" | "[X]") || !word.chars().any(char::is_alphanumeric)
+}
+
+/// Compare names ignoring case, with runs of digits compared by value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ord = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                a.next();
+                b.next();
+                ord
+            }
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEvent;
+
+    fn press(explorer: &mut FileExplorer, code: KeyCode, modifiers: KeyModifiers) {
+        explorer.handle_event(&Event::Key(KeyEvent::new(code, modifiers)));
+        explorer.refresh_visible();
+    }
+
+    fn shown(explorer: &FileExplorer) -> Vec<&str> {
+        explorer.visible.iter().map(|v| v.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sorts_filters_and_shows_titles() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(
+            root.join("note 10.md"),
+            "---\ntitle: Ten\n---\nten words? no, four\n",
+        )
+        .unwrap();
+        fs::write(root.join("note 2.md"), "# Second\n\nsome text\n").unwrap();
+        fs::write(root.join("Zoo.md"), "").unwrap();
+        let file = |name: &str| FileNode::new_file(name, root.join(name));
+        let mut sub = FileNode::new_dir("sub", root.join("sub"));
+        sub.children.push(FileNode::new_file(
+            "inner 2.md",
+            root.join("sub/inner 2.md"),
+        ));
+        let mut top = FileNode::new_dir("vault", root.to_path_buf());
+        top.expanded = true;
+        top.children = vec![file("Zoo.md"), file("note 10.md"), sub, file("note 2.md")];
+        let mut explorer = FileExplorer::new(FileTree::new(top));
+
+        explorer.refresh_visible();
+        assert_eq!(
+            shown(&explorer),
+            ["vault", "sub", "note 10.md", "note 2.md", "Zoo.md"]
+        );
+        assert!(explorer.info.is_empty());
+        explorer.set_sort(SortMode::Natural);
+        explorer.refresh_visible();
+        assert_eq!(
+            shown(&explorer),
+            ["vault", "sub", "note 2.md", "note 10.md", "Zoo.md"]
+        );
+
+        press(&mut explorer, KeyCode::Char('l'), KeyModifiers::CONTROL);
+        press(&mut explorer, KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(
+            shown(&explorer),
+            ["vault", "sub", "Second", "Ten", "Zoo.md"]
+        );
+        assert!(explorer.visible[2].detail.ends_with("3 words"));
+        assert!(explorer.visible[3].detail.ends_with("4 words"));
+
+        // Matches inside collapsed folders show up along with the folder.
+        press(&mut explorer, KeyCode::Char('2'), KeyModifiers::NONE);
+        assert_eq!(shown(&explorer), ["vault", "sub", "inner 2.md", "Second"]);
+        press(&mut explorer, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut explorer, KeyCode::Down, KeyModifiers::NONE);
+        let opened = explorer.handle_event(&Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )));
+        assert!(
+            matches!(opened, Some(ExplorerMessage::Open(p)) if p == root.join("sub/inner 2.md"))
+        );
+        press(&mut explorer, KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(shown(&explorer).len(), 5);
+
+        assert_eq!(natural_cmp("a02b", "a2c"), Ordering::Less);
+        assert_eq!(natural_cmp("Note 9", "note 10"), Ordering::Less);
+        assert_eq!(word_count("---\ntitle: x\n---\none two\n"), 2);
+        assert_eq!(
+            word_count("# Title\n\n- **one** _two_\n1. three\n- [x] four *\n> 5 ---\n"),
+            6
+        );
+    }
+
+    #[test]
+    fn reads_and_unloads_folders_of_the_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.md"), "").unwrap();
+        fs::write(root.join("sub/b.md"), "").unwrap();
+        fs::write(root.join("c.md"), "").unwrap();
+        let mut explorer =
+            FileExplorer::for_vault(storage::FileTree::new(root.to_path_buf()).unwrap());
+        explorer.set_node_limit(Some(3));
+        let wait_for = |explorer: &mut FileExplorer, len: usize| {
+            for _ in 0..500 {
+                explorer.refresh_visible();
+                if explorer.visible.len() == len {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("got {:?}", shown(explorer));
+        };
+
+        wait_for(&mut explorer, 3);
+        assert_eq!(shown(&explorer)[1..], ["sub", "c.md"]);
+        press(&mut explorer, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut explorer, KeyCode::Right, KeyModifiers::NONE);
+        wait_for(&mut explorer, 5);
+        assert_eq!(shown(&explorer)[1..], ["sub", "a.md", "b.md", "c.md"]);
+
+        // Closing the folder goes over the limit, so its children are unloaded.
+        press(&mut explorer, KeyCode::Left, KeyModifiers::NONE);
+        let source = explorer.source.as_ref().unwrap();
+        assert!(find(source.root(), &root.join("sub"))
+            .unwrap()
+            .children()
+            .is_none());
+        press(&mut explorer, KeyCode::Right, KeyModifiers::NONE);
+        wait_for(&mut explorer, 5);
+    }
+
+    #[test]
+    fn shows_and_cancels_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for i in 0..200 {
+            fs::write(root.join(format!("{i}.md")), "").unwrap();
+        }
+        let mut explorer =
+            FileExplorer::for_vault(storage::FileTree::new(root.to_path_buf()).unwrap());
+        explorer.loads = vec![Progress {
+            dir: root.to_path_buf(),
+            entries: 40,
+            total: 200,
+            added: Vec::new(),
+            done: false,
+        }];
+        assert_eq!(explorer.title(), "Files by name reading 40/200");
+
+        explorer.cancel_loads();
+        assert!(!explorer.source.as_ref().unwrap().loading());
+        explorer.refresh_visible();
+        assert_eq!(explorer.title(), "Files by name");
+        assert_eq!(shown(&explorer).len(), 1);
+    }
+
+    #[test]
+    fn takes_note_times_from_the_watcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.md"), "one two\n").unwrap();
+        let mut explorer =
+            FileExplorer::for_vault(storage::FileTree::new(root.to_path_buf()).unwrap());
+        explorer.set_show_details(true);
+        let wait_for = |explorer: &mut FileExplorer, detail: &str| {
+            for _ in 0..500 {
+                explorer.refresh_visible();
+                if explorer
+                    .visible
+                    .get(1)
+                    .is_some_and(|v| v.detail.ends_with(detail))
+                {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("got {:?}", explorer.visible);
+        };
+
+        wait_for(&mut explorer, "2 words");
+        let modified = explorer.tree.root.children[0].modified;
+        assert!(modified.is_some());
+        assert_eq!(explorer.info[&root.join("a.md")].modified, modified);
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(root.join("a.md"), "one two three\n").unwrap();
+        wait_for(&mut explorer, "3 words");
+        assert!(explorer.tree.root.children[0].modified > modified);
+    }
+
+    #[test]
+    fn toggles_hidden_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".draft.md"), "").unwrap();
+        fs::write(root.join("a.md"), "").unwrap();
+        let mut explorer =
+            FileExplorer::for_vault(storage::FileTree::new(root.to_path_buf()).unwrap());
+        for _ in 0..500 {
+            explorer.refresh_visible();
+            if !explorer.source.as_ref().unwrap().loading() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(shown(&explorer)[1..], ["a.md"]);
+
+        explorer.toggle_hidden();
+        explorer.refresh_visible();
+        assert_eq!(shown(&explorer)[1..], [".draft.md", "a.md"]);
+        explorer.toggle_hidden();
+        explorer.refresh_visible();
+        assert_eq!(shown(&explorer)[1..], ["a.md"]);
+    }
+}
@@ -0,0 +1,409 @@
+//! Git integration of the TUI.
+//!
+//! When the vault is kept in git, saved notes are committed right away or
+//! once no key was pressed for a while, depending on [`AutoCommit`]. Git
+//! runs on a background thread and its results show up as they come. The log
+//! and blame of the current note are shown in place of the preview, and a
+//! note with merge conflicts opens in a side-by-side view where each
+//! conflict is resolved by picking our lines, theirs or both.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use config::keymap::Action;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+pub use storage::git::AutoCommit;
+use storage::git::{self, BlameLine, Commit, Done, GitVault, GitWorker, Job, Region, Side};
+use tui_textarea::{CursorMove, TextArea};
+
+use crate::history::age;
+
+/// Git state of the vault.
+pub struct Git {
+    worker: Option<GitWorker>,
+    view: Option<View>,
+}
+
+enum View {
+    /// Log or blame of a note.
+    Text {
+        title: String,
+        lines: Vec<Line<'static>>,
+        scroll: usize,
+    },
+    Resolve(Resolver),
+}
+
+/// Side-by-side view of a note with merge conflicts.
+struct Resolver {
+    regions: Vec<Region>,
+    /// Chosen side of each conflict.
+    sides: Vec<Option<Side>>,
+    /// Index of the selected conflict.
+    selected: usize,
+}
+
+impl Git {
+    /// Git state of the vault at `vault`; without a repository nothing is
+    /// committed.
+    pub fn new(vault: &Path, auto_commit: AutoCommit, idle: Duration) -> Self {
+        Self {
+            worker: GitVault::open(vault).map(|repo| GitWorker::start(repo, auto_commit, idle)),
+            view: None,
+        }
+    }
+
+    /// Commit saved notes as `auto_commit` says, waiting `idle` without
+    /// keys for [`AutoCommit::Idle`].
+    pub fn configure(&mut self, auto_commit: AutoCommit, idle: Duration) {
+        if let Some(worker) = &mut self.worker {
+            worker.auto_commit = auto_commit;
+            worker.idle = idle;
+        }
+    }
+
+    /// Look for notes with merge conflicts. They are named in the status
+    /// line by [`Git::tick`].
+    pub fn check_conflicts(&self) {
+        if let Some(worker) = &self.worker {
+            worker.send(Job::Conflicts);
+        }
+    }
+
+    /// Note that a key was pressed.
+    pub fn input(&mut self) {
+        if let Some(worker) = &mut self.worker {
+            worker.input();
+        }
+    }
+
+    /// Commit the vault-relative `saved` notes as configured.
+    pub fn saved(&mut self, saved: Vec<PathBuf>) {
+        if let Some(worker) = &mut self.worker {
+            worker.saved(saved);
+        }
+    }
+
+    /// Take what git finished in the background: show the log or blame
+    /// that was asked for and return a message for the status line.
+    pub fn tick(&mut self) -> Option<String> {
+        let mut message = None;
+        for done in self.worker.as_mut()?.poll() {
+            match done {
+                Done::Committed(Ok(messages)) if messages.is_empty() => {}
+                Done::Committed(Ok(messages)) => {
+                    message = Some(format!("Committed: {}", messages.join("; ")))
+                }
+                Done::Committed(Err(err)) => message = Some(format!("Cannot commit: {err}")),
+                Done::Log(note, Ok(commits)) => self.view = Some(log_view(&note, commits)),
+                Done::Blame(note, Ok(blame)) => self.view = Some(blame_view(&note, blame)),
+                Done::Log(_, Err(err)) | Done::Blame(_, Err(err)) => {
+                    message = Some(err.to_string())
+                }
+                Done::Conflicts(Ok(conflicts)) if !conflicts.is_empty() => {
+                    let names: Vec<String> =
+                        conflicts.iter().map(|p| p.display().to_string()).collect();
+                    message = Some(format!("Merge conflicts in {}", names.join(", ")));
+                }
+                Done::Conflicts(_) => {}
+            }
+        }
+        message
+    }
+
+    /// Commit the notes saved since the last commit and wait for git to
+    /// finish.
+    pub fn finish(&mut self) {
+        if let Some(worker) = &mut self.worker {
+            worker.finish();
+        }
+    }
+
+    /// Whether the log, blame or conflict view is shown.
+    pub fn showing(&self) -> bool {
+        self.view.is_some()
+    }
+
+    /// Close the log, blame or conflict view.
+    pub fn close(&mut self) {
+        self.view = None;
+    }
+
+    /// Show the commits of the vault-relative `note` once git read them.
+    /// Returns a message when they cannot be shown.
+    pub fn show_log(&mut self, note: Option<&Path>) -> Option<String> {
+        match (&self.worker, note) {
+            (Some(worker), Some(note)) => worker.send(Job::Log(note.to_path_buf())),
+            (None, _) => return Some("The vault is not kept in git".into()),
+            (_, None) => return Some("Only saved notes have a history".into()),
+        }
+        None
+    }
+
+    /// Show who last changed each line of the vault-relative `note` once
+    /// git read it. Returns a message when it cannot be shown.
+    pub fn show_blame(&mut self, note: Option<&Path>) -> Option<String> {
+        match (&self.worker, note) {
+            (Some(worker), Some(note)) => worker.send(Job::Blame(note.to_path_buf())),
+            (None, _) => return Some("The vault is not kept in git".into()),
+            (_, None) => return Some("Only saved notes can be blamed".into()),
+        }
+        None
+    }
+
+    /// Open the conflict view when the text of `editor` has merge
+    /// conflicts. Returns whether it has.
+    pub fn resolve(&mut self, editor: &TextArea) -> bool {
+        let Some(regions) = git::conflict_regions(&editor.lines().join("\n")) else {
+            return false;
+        };
+        let conflicts = regions
+            .iter()
+            .filter(|r| matches!(r, Region::Conflict { .. }))
+            .count();
+        self.view = Some(View::Resolve(Resolver {
+            regions,
+            sides: vec![None; conflicts],
+            selected: 0,
+        }));
+        true
+    }
+
+    /// Pick a side for the selected conflict: `o` ours, `t` theirs and `b`
+    /// both.
+    pub fn type_key(&mut self, key: KeyEvent) {
+        let Some(View::Resolve(resolver)) = &mut self.view else {
+            return;
+        };
+        let side = match key.code {
+            KeyCode::Char('o') => Side::Ours,
+            KeyCode::Char('t') => Side::Theirs,
+            KeyCode::Char('b') => Side::Both,
+            _ => return,
+        };
+        resolver.sides[resolver.selected] = Some(side);
+        resolver.selected = (resolver.selected + 1).min(resolver.sides.len() - 1);
+    }
+
+    /// Perform a palette action in the shown view. Accepting the conflict
+    /// view puts the resolved text into `editor`. Returns a message for the
+    /// status line.
+    pub fn act(&mut self, action: Action, editor: &mut TextArea) -> Option<String> {
+        match self.view.as_mut()? {
+            View::Text { scroll, lines, .. } => match action {
+                Action::SelectPrevious => *scroll = scroll.saturating_sub(1),
+                Action::SelectNext => *scroll = (*scroll + 1).min(lines.len().saturating_sub(1)),
+                Action::Accept | Action::Close => self.view = None,
+                _ => {}
+            },
+            View::Resolve(resolver) => match action {
+                Action::SelectPrevious => resolver.selected = resolver.selected.saturating_sub(1),
+                Action::SelectNext => {
+                    resolver.selected = (resolver.selected + 1).min(resolver.sides.len() - 1)
+                }
+                Action::Accept => {
+                    let left = resolver.sides.iter().filter(|s| s.is_none()).count();
+                    if left > 0 {
+                        return Some(format!("{left} conflicts left: o ours, t theirs, b both"));
+                    }
+                    let sides: Vec<Side> = resolver.sides.iter().flatten().copied().collect();
+                    let lines = git::resolve(&resolver.regions, &sides);
+                    editor.select_all();
+                    editor.insert_str(lines.join("\n"));
+                    editor.move_cursor(CursorMove::Top);
+                    self.view = None;
+                    return Some("Conflicts resolved, save the note to mark it resolved".into());
+                }
+                Action::Close => self.view = None,
+                _ => {}
+            },
+        }
+        None
+    }
+
+    /// Draw the log or blame over `preview`, or the conflict view over
+    /// `both`, the editor and the preview.
+    pub fn render(&self, f: &mut Frame, preview: Rect, both: Rect) {
+        match &self.view {
+            None => {}
+            Some(View::Text {
+                title,
+                lines,
+                scroll,
+            }) => {
+                f.render_widget(Clear, preview);
+                let text = Paragraph::new(lines.clone())
+                    .scroll((*scroll as u16, 0))
+                    .block(Block::default().borders(Borders::ALL).title(title.as_str()));
+                f.render_widget(text, preview);
+            }
+            Some(View::Resolve(resolver)) => resolver.render(f, both),
+        }
+    }
+}
+
+fn log_view(note: &Path, commits: Vec<Commit>) -> View {
+    let now = SystemTime::now();
+    let lines = commits
+        .into_iter()
+        .map(|c| {
+            Line::from(vec![
+                Span::styled(c.id[..8].to_string(), Style::default().fg(Color::Yellow)),
+                Span::raw(format!(" {:<14} ", age(now, c.at))),
+                Span::styled(
+                    format!("{} ", c.author),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(c.summary),
+            ])
+        })
+        .collect();
+    View::Text {
+        title: format!("Log of {}", note.display()),
+        lines,
+        scroll: 0,
+    }
+}
+
+fn blame_view(note: &Path, blame: Vec<BlameLine>) -> View {
+    let mut previous = String::new();
+    let lines = blame
+        .into_iter()
+        .map(|line| {
+            // Only the first of consecutive lines from one commit is
+            // labelled.
+            let label = if line.id == previous {
+                String::new()
+            } else if line.id.bytes().all(|b| b == b'0') {
+                "not committed".into()
+            } else {
+                format!("{} {}", &line.id[..8], line.author)
+            };
+            previous = line.id;
+            Line::from(vec![
+                Span::styled(
+                    format!("{label:<20.20}│"),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(line.text),
+            ])
+        })
+        .collect();
+    View::Text {
+        title: format!("Blame of {}", note.display()),
+        lines,
+        scroll: 0,
+    }
+}
+
+impl Resolver {
+    fn render(&self, f: &mut Frame, area: Rect) {
+        let (mut left, mut right) = (Vec::new(), Vec::new());
+        let mut selected_row = 0;
+        let mut conflict = 0;
+        let same = Style::default().fg(Color::DarkGray);
+        for region in &self.regions {
+            match region {
+                Region::Same(lines) => {
+                    for line in lines {
+                        left.push(Line::styled(line.clone(), same));
+                        right.push(Line::styled(line.clone(), same));
+                    }
+                }
+                Region::Conflict { ours, theirs } => {
+                    let mut header = Style::default().add_modifier(Modifier::BOLD);
+                    if conflict == self.selected {
+                        header = header.add_modifier(Modifier::REVERSED);
+                        selected_row = left.len();
+                    }
+                    let chosen = match self.sides[conflict] {
+                        None => "",
+                        Some(Side::Ours) => " ✓ ours",
+                        Some(Side::Theirs) => " ✓ theirs",
+                        Some(Side::Both) => " ✓ both",
+                    };
+                    let label =
+                        format!("conflict {} of {}{chosen}", conflict + 1, self.sides.len());
+                    left.push(Line::styled(label.clone(), header));
+                    right.push(Line::styled(label, header));
+                    let rows = ours.len().max(theirs.len());
+                    for i in 0..rows {
+                        let cell = |lines: &[String], color| match lines.get(i) {
+                            Some(line) => Line::styled(line.clone(), Style::default().fg(color)),
+                            None => Line::raw(""),
+                        };
+                        left.push(cell(ours, Color::Green));
+                        right.push(cell(theirs, Color::Blue));
+                    }
+                    conflict += 1;
+                }
+            }
+        }
+        let scroll = selected_row.saturating_sub(area.height as usize / 3) as u16;
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(area);
+        f.render_widget(Clear, area);
+        let panes = [
+            (left, "Ours (o)  Enter applies, Esc cancels"),
+            (right, "Theirs (t)  b keeps both"),
+        ];
+        for ((lines, title), half) in panes.into_iter().zip(halves.iter()) {
+            let pane = Paragraph::new(lines)
+                .scroll((scroll, 0))
+                .block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(pane, *half);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    #[test]
+    fn resolves_conflicts_side_by_side() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut git = Git::new(vault.path(), AutoCommit::Save, Duration::ZERO);
+        git.saved(vec![PathBuf::from("a.md")]);
+        assert_eq!(git.tick(), None);
+        assert!(git.show_log(Some(Path::new("a.md"))).is_some());
+
+        let mut editor = TextArea::from([
+            "# Plan",
+            "<<<<<<< HEAD",
+            "ours",
+            "=======",
+            "theirs",
+            ">>>>>>> other",
+            "middle",
+            "<<<<<<< HEAD",
+            "mine",
+            "=======",
+            "yours",
+            ">>>>>>> other",
+        ]);
+        assert!(git.resolve(&editor));
+        assert!(git.showing());
+        let press = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        git.type_key(press('t'));
+        assert!(git
+            .act(Action::Accept, &mut editor)
+            .unwrap()
+            .contains("1 conflicts left"));
+        git.act(Action::SelectPrevious, &mut editor);
+        git.type_key(press('b'));
+        git.type_key(press('o'));
+        git.act(Action::Accept, &mut editor);
+        assert!(!git.showing());
+        assert_eq!(
+            editor.lines(),
+            ["# Plan", "ours", "theirs", "middle", "mine"]
+        );
+        assert!(!git.resolve(&editor));
+    }
+}
@@ -0,0 +1,240 @@
+use std::env;
+
+use ratatui::prelude::*;
+use ratatui::text::{Line, Span};
+use storage::assets::Thumbnail;
+
+/// Terminal graphics protocol used to show images in the preview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Iterm,
+    Sixel,
+    /// Unicode half blocks with true colour, works in any modern terminal.
+    HalfBlocks,
+}
+
+impl Protocol {
+    /// Guess the best protocol from the environment. `NOTES2_GRAPHICS`
+    /// (`kitty`, `iterm`, `sixel` or `blocks`) overrides the detection.
+    pub fn detect() -> Self {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+        match var("NOTES2_GRAPHICS").as_str() {
+            "kitty" => return Protocol::Kitty,
+            "iterm" => return Protocol::Iterm,
+            "sixel" => return Protocol::Sixel,
+            "blocks" => return Protocol::HalfBlocks,
+            _ => {}
+        }
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        if !var("KITTY_WINDOW_ID").is_empty()
+            || term.contains("kitty")
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Protocol::Kitty
+        } else if program == "iTerm.app" || var("LC_TERMINAL") == "iTerm2" {
+            Protocol::Iterm
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || program == "mlterm"
+        {
+            Protocol::Sixel
+        } else {
+            Protocol::HalfBlocks
+        }
+    }
+}
+
+/// Number of terminal cells an image occupies when it is at most `max_cols`
+/// wide. Cells are assumed to be twice as tall as they are wide.
+pub fn cell_size(thumb: &Thumbnail, max_cols: u16) -> (u16, u16) {
+    let cols = (thumb.width.div_ceil(8) as u16).clamp(1, max_cols.max(1));
+    let rows = (cols as u32 * thumb.height).div_ceil(thumb.width.max(1) * 2);
+    (cols, rows.max(1) as u16)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Escape sequence that draws `thumb` at the cursor position, scaled to
+/// `cols` x `rows` cells. Returns `None` for [`Protocol::HalfBlocks`], which
+/// is drawn as text with [`half_blocks`] instead.
+pub fn encode(protocol: Protocol, thumb: &Thumbnail, cols: u16, rows: u16) -> Option<String> {
+    match protocol {
+        Protocol::Kitty => {
+            let data = base64(&thumb.rgba);
+            let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+            let mut out = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+                if i == 0 {
+                    out.push_str(&format!(
+                        "\x1b_Gf=32,a=T,q=2,s={},v={},c={cols},r={rows},m={more};{chunk}\x1b\\",
+                        thumb.width, thumb.height
+                    ));
+                } else {
+                    out.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\"));
+                }
+            }
+            Some(out)
+        }
+        Protocol::Iterm => Some(format!(
+            "\x1b]1337;File=inline=1;width={cols};height={rows};preserveAspectRatio=1:{}\x07",
+            base64(&thumb.to_png())
+        )),
+        Protocol::Sixel => Some(sixel(thumb)),
+        Protocol::HalfBlocks => None,
+    }
+}
+
+/// Encode `thumb` as sixel graphics using a 6x6x6 colour cube. Transparent
+/// pixels are left unpainted.
+fn sixel(thumb: &Thumbnail) -> String {
+    let level = |v: u8| (v as u16 * 5 + 127) / 255;
+    let index = |p: [u8; 4]| -> Option<u16> {
+        (p[3] >= 128).then(|| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+    };
+    let mut out = format!("\x1bP0;1q\"1;1;{};{}", thumb.width, thumb.height);
+    for i in 0..216u16 {
+        let pct = |l: u16| l * 100 / 5;
+        out.push_str(&format!(
+            "#{i};2;{};{};{}",
+            pct(i / 36),
+            pct(i / 6 % 6),
+            pct(i % 6)
+        ));
+    }
+    for band in (0..thumb.height).step_by(6) {
+        let mut colours: Vec<u16> = (band..(band + 6).min(thumb.height))
+            .flat_map(|y| (0..thumb.width).filter_map(move |x| index(thumb.pixel(x, y))))
+            .collect();
+        colours.sort_unstable();
+        colours.dedup();
+        for (n, colour) in colours.iter().enumerate() {
+            if n > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{colour}"));
+            let mut run: Option<(u8, usize)> = None;
+            let flush = |out: &mut String, run: Option<(u8, usize)>| {
+                if let Some((bits, len)) = run {
+                    let ch = (63 + bits) as char;
+                    if len > 3 {
+                        out.push_str(&format!("!{len}{ch}"));
+                    } else {
+                        out.extend(std::iter::repeat_n(ch, len));
+                    }
+                }
+            };
+            for x in 0..thumb.width {
+                let bits = (0..6u32)
+                    .filter(|dy| band + dy < thumb.height)
+                    .filter(|dy| index(thumb.pixel(x, band + dy)) == Some(*colour))
+                    .fold(0u8, |acc, dy| acc | 1 << dy);
+                run = match run {
+                    Some((b, len)) if b == bits => Some((b, len + 1)),
+                    other => {
+                        flush(&mut out, other);
+                        Some((bits, 1))
+                    }
+                };
+            }
+            flush(&mut out, run);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Draw `thumb` with `▀` characters: each cell shows two vertically stacked
+/// pixels through its foreground and background colours.
+pub fn half_blocks(thumb: &Thumbnail, cols: u16, rows: u16) -> Vec<Line<'static>> {
+    let sample = |cx: u32, py: u32| -> Color {
+        let x = (cx * thumb.width / cols as u32).min(thumb.width - 1);
+        let y = (py * thumb.height / (rows as u32 * 2)).min(thumb.height - 1);
+        let [r, g, b, a] = thumb.pixel(x, y);
+        if a < 128 {
+            Color::Reset
+        } else {
+            Color::Rgb(r, g, b)
+        }
+    };
+    (0..rows as u32)
+        .map(|row| {
+            let spans: Vec<Span<'static>> = (0..cols as u32)
+                .map(|col| {
+                    let style = Style::default()
+                        .fg(sample(col, row * 2))
+                        .bg(sample(col, row * 2 + 1));
+                    Span::styled("▀", style)
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumb(width: u32, height: u32) -> Thumbnail {
+        let rgba = (0..width * height).flat_map(|_| [255, 0, 0, 255]).collect();
+        Thumbnail {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+    }
+
+    #[test]
+    fn sizes_cells_by_aspect_ratio() {
+        assert_eq!(cell_size(&thumb(80, 40), 40), (10, 3));
+        assert_eq!(cell_size(&thumb(800, 400), 40), (40, 10));
+    }
+
+    #[test]
+    fn sixel_paints_every_column() {
+        let out = sixel(&thumb(4, 6));
+        assert!(out.starts_with("\x1bP0;1q\"1;1;4;6"));
+        assert!(out.ends_with("#180!4~-\x1b\\"));
+    }
+
+    #[test]
+    fn half_blocks_fill_the_requested_cells() {
+        let lines = half_blocks(&thumb(4, 4), 4, 2);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].spans.len(), 4);
+        assert_eq!(lines[0].spans[0].style.fg, Some(Color::Rgb(255, 0, 0)));
+    }
+}
@@ -0,0 +1,269 @@
+//! Durable undo of the note in the editor.
+//!
+//! Changes of the editor's text are recorded in the note's
+//! [`NoteHistory`], so undo and redo keep working after the TUI is
+//! restarted, up to the last save. The history view lists earlier versions of the note, shows
+//! the selected one in the preview and puts it back into the editor.
+
+use std::io;
+use std::time::SystemTime;
+
+use config::keymap::Action;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use storage::history::{NoteHistory, Version};
+use tui_textarea::{CursorMove, TextArea};
+
+use crate::preview::PreviewContext;
+
+/// Number of versions shown at once.
+const VISIBLE: usize = 12;
+
+/// History of the note in the editor.
+pub struct EditHistory {
+    history: NoteHistory,
+    /// Text of the editor when it was last looked at.
+    text: String,
+    /// The history view, while it is open.
+    view: Option<HistoryView>,
+}
+
+struct HistoryView {
+    /// Earlier versions, newest first.
+    versions: Vec<Version>,
+    selected: usize,
+}
+
+impl EditHistory {
+    /// History of the note shown in `editor`. The unsaved buffer gets a
+    /// history that is not kept.
+    pub fn open(editor: &TextArea, ctx: &PreviewContext) -> io::Result<Self> {
+        let history = match &ctx.note {
+            Some(note) => {
+                NoteHistory::open(&ctx.vault, note.strip_prefix(&ctx.vault).unwrap_or(note))?
+            }
+            None => NoteHistory::in_memory(),
+        };
+        Ok(Self {
+            history,
+            text: editor.lines().join("\n"),
+            view: None,
+        })
+    }
+
+    /// History of `editor` that is not kept, for text without a note.
+    pub fn unsaved(editor: &TextArea) -> Self {
+        Self {
+            history: NoteHistory::in_memory(),
+            text: editor.lines().join("\n"),
+            view: None,
+        }
+    }
+
+    /// Record what changed in `editor` since it was last looked at.
+    pub fn track(&mut self, editor: &TextArea) -> io::Result<()> {
+        let text = editor.lines().join("\n");
+        if text == self.text {
+            return Ok(());
+        }
+        let old = std::mem::replace(&mut self.text, text);
+        self.history.record(&old, &self.text)
+    }
+
+    /// Record what changed in `editor` since it was last looked at as a
+    /// step of its own, for Vim undoing each change as a whole.
+    pub fn track_change(&mut self, editor: &TextArea) -> io::Result<()> {
+        self.history.seal();
+        self.track(editor)
+    }
+
+    /// Undo the last change of `editor`. Returns `false` when there is
+    /// nothing to undo.
+    pub fn undo(&mut self, editor: &mut TextArea) -> io::Result<bool> {
+        self.track(editor)?;
+        let Some((text, cursor)) = self.history.undo(&self.text)? else {
+            return Ok(false);
+        };
+        self.replace(editor, text, cursor);
+        Ok(true)
+    }
+
+    /// Redo the last undone change of `editor`. Returns `false` when there
+    /// is nothing to redo.
+    pub fn redo(&mut self, editor: &mut TextArea) -> io::Result<bool> {
+        self.track(editor)?;
+        let Some((text, cursor)) = self.history.redo(&self.text)? else {
+            return Ok(false);
+        };
+        self.replace(editor, text, cursor);
+        Ok(true)
+    }
+
+    /// Put `text` into the editor with the cursor at the char `offset`.
+    fn replace(&mut self, editor: &mut TextArea, text: String, offset: usize) {
+        editor.select_all();
+        editor.insert_str(&text);
+        let mut rest = offset;
+        let mut row = 0;
+        for line in text.split('\n') {
+            let len = line.chars().count();
+            if rest <= len {
+                break;
+            }
+            rest -= len + 1;
+            row += 1;
+        }
+        editor.move_cursor(CursorMove::Jump(row as u16, rest as u16));
+        self.text = text;
+    }
+
+    /// Whether the history view is open.
+    pub fn showing(&self) -> bool {
+        self.view.is_some()
+    }
+
+    /// Open the history view on the versions of the editor's note.
+    pub fn show(&mut self, editor: &TextArea) -> io::Result<()> {
+        self.track(editor)?;
+        let mut versions = self.history.versions(&self.text);
+        versions.reverse();
+        self.view = Some(HistoryView {
+            versions,
+            selected: 0,
+        });
+        Ok(())
+    }
+
+    /// Perform a palette action in the history view: move through the
+    /// versions, put the selected one into the editor or close the view.
+    pub fn act(&mut self, action: Action, editor: &mut TextArea) -> io::Result<()> {
+        let Some(view) = &mut self.view else {
+            return Ok(());
+        };
+        let last = view.versions.len().saturating_sub(1);
+        match action {
+            Action::SelectPrevious => view.selected = view.selected.saturating_sub(1),
+            Action::SelectNext => view.selected = (view.selected + 1).min(last),
+            Action::Accept => {
+                let text = view.versions.get(view.selected).map(|v| v.text.clone());
+                self.view = None;
+                if let Some(text) = text {
+                    self.track(editor)?;
+                    editor.select_all();
+                    editor.insert_str(&text);
+                    editor.move_cursor(CursorMove::Top);
+                    self.track(editor)?;
+                }
+            }
+            Action::Close => self.view = None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Lines of the version selected in the history view.
+    pub fn preview(&self) -> Option<Vec<String>> {
+        let view = self.view.as_ref()?;
+        let version = view.versions.get(view.selected)?;
+        Some(version.text.split('\n').map(String::from).collect())
+    }
+
+    /// Draw the history view in the top right corner of `area`.
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let Some(view) = &self.view else {
+            return;
+        };
+        let width = area.width.min(32);
+        let height = area.height.min(VISIBLE as u16 + 2);
+        let rect = Rect::new(area.right() - width, area.y, width, height);
+        let now = SystemTime::now();
+        let first = view.selected.saturating_sub(VISIBLE - 1);
+        let lines: Vec<Line> = view
+            .versions
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE)
+            .map(|(i, version)| {
+                let label = match i {
+                    0 => "current".to_string(),
+                    _ => age(now, version.at),
+                };
+                let size = format!("{} chars", version.text.chars().count());
+                let style = if i == view.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![
+                    Span::styled(format!("{label:<14}"), style),
+                    Span::styled(size, style.fg(Color::DarkGray)),
+                ])
+            })
+            .collect();
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("History")),
+            rect,
+        );
+    }
+}
+
+/// How long before `now` the time `at` was, such as `5 min ago`.
+pub(crate) fn age(now: SystemTime, at: SystemTime) -> String {
+    let secs = now.duration_since(at).unwrap_or_default().as_secs();
+    match secs {
+        0..=9 => "just now".to_string(),
+        10..=59 => format!("{secs} s ago"),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn undoes_across_restarts_and_restores_versions() {
+        let vault = tempfile::tempdir().unwrap();
+        fs::write(vault.path().join("a.md"), "first\n").unwrap();
+        let mut ctx = PreviewContext::new(vault.path().to_path_buf());
+        ctx.note = Some(vault.path().join("a.md"));
+        let mut editor = TextArea::from(["first"]);
+        let mut history = EditHistory::open(&editor, &ctx).unwrap();
+        editor.move_cursor(CursorMove::End);
+        editor.insert_str("\nsecond");
+        history.track(&editor).unwrap();
+        editor.move_cursor(CursorMove::Head);
+        editor.delete_line_by_end();
+        history.track(&editor).unwrap();
+        assert_eq!(editor.lines(), ["first", ""]);
+        fs::write(vault.path().join("a.md"), "first\n\n").unwrap();
+
+        let mut history = EditHistory::open(&editor, &ctx).unwrap();
+        assert!(history.undo(&mut editor).unwrap());
+        assert_eq!(editor.lines(), ["first", "second"]);
+        assert_eq!(editor.cursor(), (1, 0));
+        assert!(history.redo(&mut editor).unwrap());
+        assert!(!history.redo(&mut editor).unwrap());
+
+        history.show(&editor).unwrap();
+        assert_eq!(history.preview().unwrap(), ["first", ""]);
+        history.act(Action::SelectNext, &mut editor).unwrap();
+        history.act(Action::SelectNext, &mut editor).unwrap();
+        history.act(Action::SelectNext, &mut editor).unwrap();
+        assert_eq!(history.preview().unwrap(), ["first"]);
+        history.act(Action::Accept, &mut editor).unwrap();
+        assert!(!history.showing());
+        assert_eq!(editor.lines(), ["first"]);
+        assert!(history.undo(&mut editor).unwrap());
+        assert_eq!(editor.lines(), ["first", ""]);
+
+        let now = SystemTime::now();
+        assert_eq!(age(now, now - Duration::from_secs(300)), "5 min ago");
+    }
+}
@@ -0,0 +1,221 @@
+//! Translation of terminal events into editor input.
+//!
+//! Key events keep all of their modifiers so the editor's own bindings
+//! work: Shift extends the selection, Ctrl and Alt move by words. Pasted
+//! text and mouse drags inside the editor are handled here as well.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+/// Editor input for a key event.
+pub fn to_input(ev: KeyEvent) -> Input {
+    let key = match ev.code {
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::F(n) => Key::F(n),
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Tab | KeyCode::BackTab => Key::Tab,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Esc => Key::Esc,
+        _ => Key::Null,
+    };
+    Input {
+        key,
+        ctrl: ev.modifiers.contains(KeyModifiers::CONTROL),
+        alt: ev.modifiers.contains(KeyModifiers::ALT),
+        // Terminals report Shift+Tab as a key of its own.
+        shift: ev.modifiers.contains(KeyModifiers::SHIFT) || ev.code == KeyCode::BackTab,
+    }
+}
+
+/// Insert text pasted into the terminal at the cursor, replacing the
+/// selection.
+pub fn paste(editor: &mut TextArea, text: &str) {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    editor.insert_str(text);
+}
+
+/// Where an editor is drawn and which part of its text is visible.
+///
+/// The [`TextArea`] widget scrolls to keep the cursor in view but does not
+/// tell where it scrolled to, so the viewport follows it the same way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    /// Inside of the editor's block.
+    pub area: Rect,
+    /// First visible row.
+    pub top: usize,
+    /// First visible char column.
+    pub left: usize,
+    /// A mouse drag started in the editor.
+    dragging: bool,
+}
+
+impl Viewport {
+    /// Follow the scrolling of the editor drawn in `area`, the inside of its
+    /// block.
+    pub fn update(&mut self, area: Rect, (row, col): (usize, usize)) {
+        self.area = area;
+        self.top = next_scroll_top(self.top, row, area.height as usize);
+        self.left = next_scroll_top(self.left, col, area.width as usize);
+    }
+
+    /// Row and column of the text under a screen cell. Cells outside of
+    /// the editor give the nearest visible position.
+    pub fn position(&self, column: u16, row: u16, lines: &[String]) -> (usize, usize) {
+        let area = self.area;
+        let y = row.clamp(area.y, area.bottom().saturating_sub(1).max(area.y));
+        let x = column.clamp(area.x, area.right().saturating_sub(1).max(area.x));
+        let row = (self.top + (y - area.y) as usize).min(lines.len().saturating_sub(1));
+        let len = lines.get(row).map_or(0, |l| l.chars().count());
+        (row, (self.left + (x - area.x) as usize).min(len))
+    }
+
+    /// Move the cursor with a click in the editor and select the text the
+    /// mouse is dragged over. Returns whether the event was for the editor.
+    pub fn mouse(&mut self, ev: &MouseEvent, editor: &mut TextArea) -> bool {
+        let inside = ev.column >= self.area.x
+            && ev.column < self.area.right()
+            && ev.row >= self.area.y
+            && ev.row < self.area.bottom();
+        match ev.kind {
+            MouseEventKind::Down(MouseButton::Left) if inside => {
+                editor.cancel_selection();
+                self.dragging = true;
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging => {
+                if !editor.is_selecting() {
+                    editor.start_selection();
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) if self.dragging => self.dragging = false,
+            _ => return false,
+        }
+        let (row, col) = self.position(ev.column, ev.row, editor.lines());
+        editor.move_cursor(CursorMove::Jump(row as u16, col as u16));
+        true
+    }
+}
+
+/// First visible row or column after scrolling to show `cursor`, the same
+/// way the [`TextArea`] widget does.
+fn next_scroll_top(top: usize, cursor: usize, len: usize) -> usize {
+    if cursor < top {
+        cursor
+    } else if top + len <= cursor {
+        cursor + 1 - len
+    } else {
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(key: Key, ctrl: bool, alt: bool, shift: bool) -> Input {
+        Input {
+            key,
+            ctrl,
+            alt,
+            shift,
+        }
+    }
+
+    #[test]
+    fn translates_every_key() {
+        let keys = [
+            (KeyCode::Char('a'), Key::Char('a')),
+            (KeyCode::F(5), Key::F(5)),
+            (KeyCode::Backspace, Key::Backspace),
+            (KeyCode::Enter, Key::Enter),
+            (KeyCode::Left, Key::Left),
+            (KeyCode::Right, Key::Right),
+            (KeyCode::Up, Key::Up),
+            (KeyCode::Down, Key::Down),
+            (KeyCode::Tab, Key::Tab),
+            (KeyCode::Delete, Key::Delete),
+            (KeyCode::Home, Key::Home),
+            (KeyCode::End, Key::End),
+            (KeyCode::PageUp, Key::PageUp),
+            (KeyCode::PageDown, Key::PageDown),
+            (KeyCode::Esc, Key::Esc),
+            (KeyCode::Insert, Key::Null),
+        ];
+        let modifiers = [
+            (KeyModifiers::NONE, (false, false, false)),
+            (KeyModifiers::CONTROL, (true, false, false)),
+            (KeyModifiers::ALT, (false, true, false)),
+            (KeyModifiers::SHIFT, (false, false, true)),
+            (KeyModifiers::all(), (true, true, true)),
+        ];
+        for (code, key) in keys {
+            for (m, (ctrl, alt, shift)) in modifiers {
+                let ev = KeyEvent::new(code, m);
+                assert_eq!(to_input(ev), input(key, ctrl, alt, shift), "{ev:?}");
+            }
+        }
+        let back_tab = KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT);
+        assert_eq!(to_input(back_tab), input(Key::Tab, false, false, true));
+        let back_tab = KeyEvent::new(KeyCode::BackTab, KeyModifiers::NONE);
+        assert_eq!(to_input(back_tab), input(Key::Tab, false, false, true));
+    }
+
+    #[test]
+    fn modifiers_reach_the_editor() {
+        let mut editor = TextArea::from(["one two", "three"]);
+        editor.input(to_input(KeyEvent::new(
+            KeyCode::Right,
+            KeyModifiers::CONTROL,
+        )));
+        assert_eq!(editor.cursor(), (0, 4));
+        editor.input(to_input(KeyEvent::new(KeyCode::End, KeyModifiers::SHIFT)));
+        assert_eq!(editor.selection_range(), Some(((0, 4), (0, 7))));
+        editor.input(to_input(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)));
+        assert_eq!(editor.lines()[0], "one     ");
+        editor.input(to_input(KeyEvent::new(
+            KeyCode::Left,
+            KeyModifiers::CONTROL,
+        )));
+        assert_eq!(editor.cursor(), (0, 0));
+        let ctrl_alt = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        editor.input(to_input(KeyEvent::new(KeyCode::Down, ctrl_alt)));
+        assert_eq!(editor.cursor().0, 1);
+    }
+
+    #[test]
+    fn selects_with_the_mouse_and_pastes() {
+        let mut editor = TextArea::from(["hello world", "second"]);
+        let mut viewport = Viewport::default();
+        viewport.update(Rect::new(1, 1, 20, 5), editor.cursor());
+        let mouse = |kind, column, row| MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        let left = MouseButton::Left;
+        assert!(viewport.mouse(&mouse(MouseEventKind::Down(left), 7, 1), &mut editor));
+        assert_eq!(editor.cursor(), (0, 6));
+        viewport.mouse(&mouse(MouseEventKind::Drag(left), 4, 2), &mut editor);
+        viewport.mouse(&mouse(MouseEventKind::Up(left), 4, 2), &mut editor);
+        assert_eq!(editor.selection_range(), Some(((0, 6), (1, 3))));
+        assert!(!viewport.mouse(&mouse(MouseEventKind::Down(left), 30, 1), &mut editor));
+
+        paste(&mut editor, "there\r\nmy");
+        assert_eq!(editor.lines(), ["hello there", "myond"]);
+
+        viewport.update(Rect::new(1, 1, 20, 5), (10, 0));
+        assert_eq!(viewport.top, 6);
+        assert_eq!(viewport.position(3, 40, &vec![String::new(); 12]), (10, 0));
+    }
+}
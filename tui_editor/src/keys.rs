@@ -0,0 +1,186 @@
+//! Key bindings of the TUI.
+//!
+//! Terminal key events are translated to [`config::keymap`] keys and fed
+//! through the keymap of the current mode. Keys that start a chord are held
+//! back until the chord completes; when it does not, they are handed back so
+//! they still reach the editor.
+
+use std::path::Path;
+
+use config::keymap::{self, Action, Key, Keymap, Lookup, Mode};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// The keymap key for a terminal key event, `None` for keys the keymap
+/// cannot bind.
+pub fn key_of(event: &KeyEvent) -> Option<Key> {
+    let code = match event.code {
+        KeyCode::Char(c) => keymap::KeyCode::Char(c),
+        KeyCode::F(n) => keymap::KeyCode::F(n),
+        KeyCode::Enter => keymap::KeyCode::Enter,
+        KeyCode::Esc => keymap::KeyCode::Esc,
+        KeyCode::Tab => keymap::KeyCode::Tab,
+        KeyCode::BackTab => keymap::KeyCode::BackTab,
+        KeyCode::Backspace => keymap::KeyCode::Backspace,
+        KeyCode::Delete => keymap::KeyCode::Delete,
+        KeyCode::Insert => keymap::KeyCode::Insert,
+        KeyCode::Up => keymap::KeyCode::Up,
+        KeyCode::Down => keymap::KeyCode::Down,
+        KeyCode::Left => keymap::KeyCode::Left,
+        KeyCode::Right => keymap::KeyCode::Right,
+        KeyCode::Home => keymap::KeyCode::Home,
+        KeyCode::End => keymap::KeyCode::End,
+        KeyCode::PageUp => keymap::KeyCode::PageUp,
+        KeyCode::PageDown => keymap::KeyCode::PageDown,
+        _ => return None,
+    };
+    let m = event.modifiers;
+    Some(Key::new(
+        code,
+        m.contains(KeyModifiers::CONTROL),
+        m.contains(KeyModifiers::ALT),
+        m.contains(KeyModifiers::SHIFT),
+    ))
+}
+
+/// What to do with the keys fed to [`Bindings`].
+#[derive(Debug, PartialEq)]
+pub enum Feed {
+    Action(Action),
+    /// A key without a binding, to be handled as input.
+    Key(KeyEvent),
+}
+
+/// The keymap with the keys of an unfinished chord.
+pub struct Bindings {
+    pub keymap: Keymap,
+    pending: Vec<KeyEvent>,
+    /// Keys of the chord that gave the last action.
+    chord: Vec<KeyEvent>,
+}
+
+impl Bindings {
+    pub fn new(keymap: Keymap) -> Self {
+        Self {
+            keymap,
+            pending: Vec::new(),
+            chord: Vec::new(),
+        }
+    }
+
+    /// Load the keymap file at `path` over the defaults, with messages for
+    /// the problems found in it. A missing file gives the defaults.
+    pub fn load(path: &Path) -> (Self, Vec<String>) {
+        if !path.exists() {
+            return (Self::new(Keymap::default()), Vec::new());
+        }
+        match keymap::load_keymap(path) {
+            Ok((keymap, issues)) => (
+                Self::new(keymap),
+                issues
+                    .iter()
+                    .map(|i| format!("{}: {i}", path.display()))
+                    .collect(),
+            ),
+            Err(err) => (
+                Self::new(Keymap::default()),
+                vec![format!("{}: {err}", path.display())],
+            ),
+        }
+    }
+
+    /// Keys of the unfinished chord.
+    pub fn pending(&self) -> &[KeyEvent] {
+        &self.pending
+    }
+
+    /// Keys of the chord that gave the last action, for actions that type
+    /// their keys when they cannot be carried out.
+    pub fn chord(&self) -> &[KeyEvent] {
+        &self.chord
+    }
+
+    /// Whether `event` on its own quits in `mode`. Such keys are not taken
+    /// as answers to questions.
+    pub fn quits(&self, mode: Mode, event: &KeyEvent) -> bool {
+        key_of(event)
+            .is_some_and(|key| self.keymap.lookup(mode, &[key]) == Lookup::Action(Action::Quit))
+    }
+
+    /// Feed the next key pressed in `mode`. Nothing is returned while a
+    /// chord is unfinished.
+    pub fn feed(&mut self, mode: Mode, event: KeyEvent) -> Vec<Feed> {
+        self.pending.push(event);
+        let keys: Option<Vec<Key>> = self.pending.iter().map(key_of).collect();
+        match keys.map_or(Lookup::Unbound, |keys| self.keymap.lookup(mode, &keys)) {
+            Lookup::Prefix => Vec::new(),
+            Lookup::Action(action) => {
+                self.chord = std::mem::take(&mut self.pending);
+                vec![Feed::Action(action)]
+            }
+            Lookup::Unbound if self.pending.len() > 1 => {
+                // The chord broke off: its keys are input, the last key may
+                // have a binding of its own.
+                let mut keys = std::mem::take(&mut self.pending);
+                let last = keys.pop().unwrap();
+                let mut out: Vec<Feed> = keys.into_iter().map(Feed::Key).collect();
+                out.extend(self.feed(mode, last));
+                out
+            }
+            Lookup::Unbound => self.pending.drain(..).map(Feed::Key).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn translates_key_events() {
+        let event = KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(key_of(&event), Some("G".parse().unwrap()));
+        let event = KeyEvent::new(KeyCode::Left, KeyModifiers::ALT);
+        assert_eq!(key_of(&event), Some("alt+left".parse().unwrap()));
+        let event = KeyEvent::new(KeyCode::Null, KeyModifiers::NONE);
+        assert_eq!(key_of(&event), None);
+    }
+
+    #[test]
+    fn holds_chords_and_replays_broken_ones() {
+        let mut keymap = Keymap::default();
+        keymap.bind(
+            Mode::Insert,
+            "g d".parse().unwrap(),
+            Some(Action::FollowLink),
+        );
+        let mut bindings = Bindings::new(keymap);
+        let input = |c| Feed::Key(key(c));
+        assert_eq!(bindings.feed(Mode::Insert, key('h')), [input('h')]);
+        assert_eq!(bindings.feed(Mode::Insert, key('g')), []);
+        assert_eq!(bindings.pending(), [key('g')]);
+        assert_eq!(
+            bindings.feed(Mode::Insert, key('d')),
+            [Feed::Action(Action::FollowLink)]
+        );
+        assert_eq!(bindings.chord(), [key('g'), key('d')]);
+        bindings.feed(Mode::Insert, key('g'));
+        assert_eq!(
+            bindings.feed(Mode::Insert, key('x')),
+            [input('g'), input('x')]
+        );
+        bindings.feed(Mode::Insert, key('g'));
+        assert_eq!(bindings.feed(Mode::Insert, key('g')), [input('g')]);
+        assert_eq!(bindings.pending(), [key('g')]);
+        let quit = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        assert_eq!(
+            bindings.feed(Mode::Insert, quit),
+            [input('g'), Feed::Action(Action::Quit)]
+        );
+        assert!(bindings.quits(Mode::Insert, &quit));
+        assert!(!bindings.quits(Mode::Insert, &key('q')));
+    }
+}
@@ -1,38 +1,67 @@
+pub mod autocomplete;
 pub mod file_explorer;
+pub mod git;
+pub mod graphics;
+pub mod history;
+pub mod input;
+pub mod keys;
+pub mod navigation;
+pub mod preview;
+pub mod quick_open;
 pub mod sidebar;
+pub mod snapshots;
+pub mod vim;
 
+use std::cell::RefCell;
+use std::env;
 use std::fs;
-use std::io::{self};
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
+use autocomplete::Autocomplete;
+use config::keymap::{Action, Mode};
+use core_notes::events::{EventSink, Message, SidebarAction};
+use core_notes::export;
+use core_notes::palette::{self, Command, Item};
+use crossterm::cursor::MoveTo;
 use crossterm::event::{
-    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers,
+    self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+    Event, KeyCode, KeyEvent, MouseButton, MouseEventKind,
 };
 use crossterm::execute;
+use crossterm::style::Print;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
+use file_explorer::{ExplorerMessage, FileExplorer};
+use git::{AutoCommit, Git};
+use graphics::Protocol;
+use history::EditHistory;
+use input::{to_input, Viewport};
+use keys::{Bindings, Feed};
+use navigation::Navigator;
+use preview::{FoldRegion, ImagePlacement, PreviewContext};
+use quick_open::QuickOpen;
 use ratatui::prelude::*;
-use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, Paragraph};
 use serde::{Deserialize, Serialize};
-use sidebar::{FileExplorer, Sidebar};
-use tui_textarea::{Input, Key, TextArea};
+use sidebar::Sidebar;
+use snapshots::Snapshots;
+use tui_textarea::TextArea;
+use vim::{Ex, Outcome, Vim, VimMode};
 
-struct LocalBus;
-
-impl core_notes::events::EventSink for LocalBus {
-    fn send(&self, _msg: core_notes::events::Message) {}
+/// Collects the messages of the sidebar until the event loop handles them.
+#[derive(Default)]
+struct LocalBus {
+    queue: RefCell<Vec<Message>>,
 }
 
-struct DummyExplorer;
-
-impl FileExplorer for DummyExplorer {
-    fn go_home(&mut self) {}
-    fn new_note(&mut self) {}
-    fn search(&mut self) {}
-    fn go_back(&mut self) {}
-    fn settings(&mut self) {}
+impl EventSink for LocalBus {
+    fn send(&self, msg: Message) {
+        self.queue.borrow_mut().push(msg);
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,6 +69,19 @@ struct Config {
     theme: String,
     #[serde(default)]
     auto_fold_ms: Option<u64>,
+    /// Command opening external links, `{url}` marks where the URL goes.
+    /// The desktop's default handler is used when unset.
+    #[serde(default)]
+    open_command: Option<String>,
+    /// Edit with Vim-style normal, insert and visual modes.
+    #[serde(default)]
+    vim: bool,
+    /// When to commit saved notes if the vault is kept in git.
+    #[serde(default)]
+    git: AutoCommit,
+    /// Seconds without keys before committing with `git: idle`.
+    #[serde(default)]
+    git_idle_secs: Option<u64>,
 }
 
 impl Default for Config {
@@ -47,6 +89,10 @@ impl Default for Config {
         Self {
             theme: "light".into(),
             auto_fold_ms: None,
+            open_command: None,
+            vim: false,
+            git: AutoCommit::Off,
+            git_idle_secs: None,
         }
     }
 }
@@ -65,122 +111,227 @@ fn save_config(cfg: &Config) {
     }
 }
 
-fn transform_heading(line: &str) -> (String, Option<Style>) {
-    let trimmed = line.trim_start();
-    let mut level = 0;
-    for ch in trimmed.chars() {
-        if ch == '#' {
-            level += 1;
+/// Editor for the settings, holding the configuration as YAML.
+fn settings_editor(cfg: &Config) -> TextArea<'static> {
+    let mut settings = TextArea::default();
+    settings.set_block(Block::default().borders(Borders::ALL).title("Settings"));
+    settings.insert_str(serde_yaml::to_string(cfg).unwrap());
+    settings
+}
+
+/// Hand a key without a binding to the editor, through the Vim layer when
+/// it is enabled. Returns the `:` command it completed.
+fn edit(
+    key: KeyEvent,
+    editor: &mut TextArea,
+    vim: Option<&mut Vim>,
+    history: &mut EditHistory,
+) -> Option<Ex> {
+    let Some(vim) = vim else {
+        let _ = editor.input(to_input(key));
+        return None;
+    };
+    let (count, redo) = match vim.handle_key(key, editor) {
+        Outcome::Input => {
+            let _ = editor.input(to_input(key));
+            return None;
+        }
+        Outcome::Handled => return None,
+        Outcome::Ex(ex) => return Some(ex),
+        Outcome::Undo(count) => (count, false),
+        Outcome::Redo(count) => (count, true),
+    };
+    for _ in 0..count {
+        let done = if redo {
+            history.redo(editor)
         } else {
-            break;
+            history.undo(editor)
+        };
+        let message = match done {
+            Ok(true) => continue,
+            Ok(false) if redo => "Already at newest change".to_string(),
+            Ok(false) => "Already at oldest change".to_string(),
+            Err(err) => format!("Cannot {}: {err}", if redo { "redo" } else { "undo" }),
+        };
+        vim.set_message(message);
+        break;
+    }
+    vim.settle_cursor(editor);
+    None
+}
+
+/// Record the changes of the editor in its history. With Vim, each change
+/// is one step, recorded once its text is typed.
+fn track(history: &mut EditHistory, editor: &TextArea, vim: Option<&Vim>) -> io::Result<()> {
+    match vim {
+        Some(vim) if vim.mode() == VimMode::Insert => Ok(()),
+        Some(_) => history.track_change(editor),
+        None => history.track(editor),
+    }
+}
+
+/// Draw the preview's images inside `area` with the terminal graphics
+/// protocol. Images are only sent again when their placement changes.
+fn draw_images<W: Write>(
+    out: &mut W,
+    protocol: Protocol,
+    area: Rect,
+    images: &[ImagePlacement],
+    drawn: &mut Vec<(u16, u16, usize)>,
+) -> io::Result<()> {
+    let visible: Vec<&ImagePlacement> = images
+        .iter()
+        .filter(|img| img.line + img.rows as usize <= area.height as usize)
+        .collect();
+    let key: Vec<(u16, u16, usize)> = visible
+        .iter()
+        .map(|img| {
+            (
+                area.x,
+                area.y + img.line as u16,
+                Arc::as_ptr(&img.thumbnail) as usize,
+            )
+        })
+        .collect();
+    if key == *drawn {
+        return Ok(());
+    }
+    if protocol == Protocol::Kitty {
+        // Kitty keeps images until they are deleted explicitly.
+        execute!(out, Print("\x1b_Ga=d,q=2\x1b\\"))?;
+    }
+    for (img, &(x, y, _)) in visible.iter().zip(&key) {
+        if let Some(seq) = graphics::encode(protocol, &img.thumbnail, img.cols, img.rows) {
+            execute!(out, MoveTo(x, y), Print(seq))?;
         }
     }
-    if level > 0 && trimmed.get(level..level + 1) == Some(" ") {
-        let text = trimmed[level + 1..].trim_start();
-        let style = Style::default().add_modifier(Modifier::BOLD);
-        (format!("H{}: {}", level, text), Some(style))
-    } else {
-        (line.to_string(), None)
+    out.flush()?;
+    *drawn = key;
+    Ok(())
+}
+
+/// Export the note in the editor to HTML with the `export` tool, returning
+/// a message for the status line.
+fn export_note(
+    editor: &mut TextArea,
+    ctx: &PreviewContext,
+    navigator: &mut Navigator,
+    theme: &str,
+) -> String {
+    let Some(note) = &ctx.note else {
+        return "Only notes in the vault can be exported".into();
+    };
+    if let Err(err) = navigator.save(editor, ctx) {
+        return format!("Cannot save note: {err}");
     }
+    let out = ctx.vault.join("export");
+    match export::export_note(note, &out, theme) {
+        Ok(()) => format!("Exported to {}", out.display()),
+        Err(err) => format!("Cannot export: {err}"),
+    }
+}
+
+/// Time without keys before saved notes are committed with
+/// [`AutoCommit::Idle`].
+fn idle_time(cfg: &Config) -> Duration {
+    Duration::from_secs(cfg.git_idle_secs.unwrap_or(60))
 }
 
-fn to_input(ev: crossterm::event::KeyEvent) -> Input {
-    match ev.code {
-        KeyCode::Backspace => Input {
-            key: Key::Backspace,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Enter => Input {
-            key: Key::Enter,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Left => Input {
-            key: Key::Left,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Right => Input {
-            key: Key::Right,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Up => Input {
-            key: Key::Up,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Down => Input {
-            key: Key::Down,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Home => Input {
-            key: Key::Home,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::End => Input {
-            key: Key::End,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Delete => Input {
-            key: Key::Delete,
-            ctrl: false,
-            alt: false,
-        },
-        KeyCode::Char(c) => Input {
-            key: Key::Char(c),
-            ctrl: ev.modifiers.contains(KeyModifiers::CONTROL),
-            alt: ev.modifiers.contains(KeyModifiers::ALT),
-        },
-        _ => Input {
-            key: Key::Null,
-            ctrl: false,
-            alt: false,
-        },
+/// Vault-relative path of the note in the editor.
+fn note_path(ctx: &PreviewContext) -> Option<&Path> {
+    let note = ctx.note.as_deref()?;
+    Some(note.strip_prefix(&ctx.vault).unwrap_or(note))
+}
+
+/// Keymap mode of the editor, following the mode of Vim when it is on.
+fn editing_mode(vim: Option<&Vim>) -> Mode {
+    if vim.is_some_and(|v| v.mode() != VimMode::Insert) {
+        Mode::Normal
+    } else {
+        Mode::Insert
     }
 }
 
 pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
 
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     let mut editor = TextArea::default();
-    editor.set_block(Block::default().borders(Borders::ALL).title("Edit"));
+    editor.set_block(navigation::editor_block(None));
     let mut cfg = load_config();
-    let mut show_palette = false;
-    let mut palette = TextArea::default();
-    palette.set_block(Block::default().borders(Borders::ALL).title("Settings"));
+    let mut show_settings = false;
+    let mut settings = settings_editor(&cfg);
 
     let mut sidebar = Sidebar::new(cfg.auto_fold_ms);
-    let bus = LocalBus;
-    let mut explorer = DummyExplorer;
-
-    if Path::new("config.yaml").exists() {
-        if let Ok(content) = fs::read_to_string("config.yaml") {
-            palette.insert_str(&content);
+    let bus = LocalBus::default();
+    let vault =
+        env::var_os("ELEPHANT_NOTES_ORIGIN").map_or_else(|| PathBuf::from("."), PathBuf::from);
+    let mut autocomplete = Autocomplete::new(vault.clone());
+    let mut preview_ctx = PreviewContext::new(vault);
+    let mut navigator = Navigator::new(cfg.open_command.clone());
+    let mut explorer = match storage::FileTree::new(preview_ctx.vault.clone()) {
+        Ok(tree) => FileExplorer::for_vault(tree),
+        Err(err) => {
+            navigator.status = Some(format!("Cannot read the notes folder: {err}"));
+            let top = file_explorer::FileNode::new_dir("notes", preview_ctx.vault.clone());
+            FileExplorer::new(file_explorer::FileTree::new(top))
         }
-    } else {
-        palette.insert_str(&serde_yaml::to_string(&cfg).unwrap());
+    };
+    let mut vim = cfg.vim.then(Vim::new);
+    let mut history = EditHistory::open(&editor, &preview_ctx)?;
+    let mut git = Git::new(&preview_ctx.vault, cfg.git, idle_time(&cfg));
+    let mut snapshots = Snapshots::default();
+    let mut quick = QuickOpen::new(&preview_ctx.vault);
+    let mut last_note = None;
+    let mut images: Vec<ImagePlacement> = Vec::new();
+    let mut folds: Vec<FoldRegion> = Vec::new();
+    let mut preview_area = Rect::default();
+    let mut viewport = Viewport::default();
+    let mut drawn_images = Vec::new();
+    let (mut bindings, issues) = Bindings::load(Path::new("keymap.yaml"));
+    if !issues.is_empty() {
+        navigator.status = Some(issues.join("; "));
     }
+    git.check_conflicts();
 
-    loop {
+    let mut quit = false;
+    // The last quit could not save the note, quitting again drops it.
+    let mut quit_unsaved = false;
+    while !quit {
         sidebar.tick();
+        if let Some(message) = git.tick() {
+            navigator.status = Some(message);
+        }
+        navigator.poll(&mut editor, &preview_ctx);
         terminal.draw(|f| {
-            let size = f.size();
-            if show_palette {
+            let mut size = f.size();
+            let status = match (&vim, &navigator.status) {
+                (Some(vim), Some(status)) => Some(format!("{}  {status}", vim.status())),
+                (Some(vim), None) => Some(vim.status()),
+                (None, status) => status.clone(),
+            };
+            if let Some(status) = &status {
+                let rows = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(0), Constraint::Length(1)])
+                    .split(size);
+                f.render_widget(Paragraph::new(status.as_str()), rows[1]);
+                size = rows[0];
+            }
+            if show_settings {
                 let area = size;
-                let widget = palette.widget();
-                f.render_widget(widget, area);
+                f.render_widget(&settings, area);
             } else {
-                let sidebar_width = if sidebar.open { 20 } else { 0 };
+                let sidebar_width = if sidebar.open { 32 } else { 0 };
                 let constraints = [
                     Constraint::Length(sidebar_width),
                     Constraint::Percentage(50),
@@ -191,35 +342,66 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
                     .constraints(constraints)
                     .split(size);
                 if sidebar.open {
-                    f.render_widget(sidebar.view(), chunks[0]);
+                    let parts = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                        .split(chunks[0]);
+                    explorer.render(f, parts[0]);
+                    f.render_widget(sidebar.view(), parts[1]);
+                }
+                f.render_widget(&editor, chunks[1]);
+                navigator.render_banner(f, chunks[1]);
+                viewport.update(chunks[1].inner(Margin::new(1, 1)), editor.cursor());
+                if let Some(vim) = &vim {
+                    vim.render_selection(f.buffer_mut(), &viewport, &editor);
                 }
-                f.render_widget(editor.widget(), chunks[1]);
+                autocomplete.render(f, &viewport);
 
-                let lines: Vec<Line> = editor
-                    .lines()
+                preview_area = chunks[2].inner(Margin::new(1, 1));
+                let version = history.preview();
+                let source = version.as_deref().unwrap_or(editor.lines());
+                let rendered = preview::render_cached(source, &mut preview_ctx, preview_area.width);
+                images = rendered.images.clone();
+                folds = rendered.folds.clone();
+                // Only the lines that fit are drawn.
+                let shown: Vec<Line> = rendered
+                    .lines
                     .iter()
-                    .map(|l| {
-                        let (text, style) = transform_heading(l);
-                        match style {
-                            Some(s) => Line::from(Span::styled(text, s)),
-                            None => Line::from(text),
-                        }
-                    })
+                    .take(preview_area.height as usize)
+                    .cloned()
                     .collect();
-                let preview = Paragraph::new(lines)
+                let preview = Paragraph::new(shown)
                     .block(Block::default().borders(Borders::ALL).title("Preview"));
                 f.render_widget(preview, chunks[2]);
+                history.render(f, chunks[1]);
+                git.render(f, chunks[2], chunks[1].union(chunks[2]));
+                snapshots.render(f, chunks[1], chunks[2]);
+                if quick.open {
+                    quick.render(f, size);
+                }
             }
         })?;
+        if show_settings || quick.open {
+            images.clear();
+        }
+        draw_images(
+            terminal.backend_mut(),
+            preview_ctx.protocol,
+            preview_area,
+            &images,
+            &mut drawn_images,
+        )?;
 
         if event::poll(std::time::Duration::from_millis(200))? {
             match event::read()? {
                 Event::Key(key) => {
-                    if show_palette {
+                    git.input();
+                    let drop_changes = std::mem::take(&mut quit_unsaved);
+                    if show_settings {
                         match key.code {
                             KeyCode::Esc => {
                                 if let Ok(new_cfg) = serde_yaml::from_str::<Config>(
-                                    &palette
+                                    &settings
                                         .lines()
                                         .iter()
                                         .cloned()
@@ -227,31 +409,292 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
                                         .join("\n"),
                                 ) {
                                     cfg = new_cfg;
+                                    navigator.open_command = cfg.open_command.clone();
+                                    git.configure(cfg.git, idle_time(&cfg));
+                                    if cfg.vim != vim.is_some() {
+                                        vim = cfg.vim.then(Vim::new);
+                                    }
                                     save_config(&cfg);
                                 }
-                                show_palette = false;
+                                show_settings = false;
                             }
                             _ => {
-                                let _ = palette.input(to_input(key));
+                                let _ = settings.input(to_input(key));
                             }
                         }
+                    } else if navigator
+                        .answers(&key, bindings.quits(editing_mode(vim.as_ref()), &key))
+                    {
+                        if navigator.answer(key, &mut editor, &mut preview_ctx)
+                            && git.resolve(&editor)
+                        {
+                            navigator.status =
+                                Some("The merge has conflicts: o ours, t theirs, b both".into());
+                        }
+                    } else if !quick.open && autocomplete.handle_key(key, &mut editor) {
                     } else {
-                        sidebar.handle_event(&Event::Key(key), &bus, &mut explorer);
-                        match key.code {
-                            KeyCode::Char('q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                                break
+                        let mode = if quick.open
+                            || history.showing()
+                            || git.showing()
+                            || snapshots.showing()
+                        {
+                            Mode::Palette
+                        } else if sidebar.open {
+                            Mode::Explorer
+                        } else {
+                            editing_mode(vim.as_ref())
+                        };
+                        if mode != Mode::Palette {
+                            navigator.status = None;
+                        }
+                        let mut picked = None;
+                        let mut ex = None;
+                        for feed in bindings.feed(mode, key) {
+                            let action = match feed {
+                                Feed::Action(action) => action,
+                                Feed::Key(key) => {
+                                    match mode {
+                                        Mode::Palette
+                                            if history.showing() || snapshots.showing() => {}
+                                        Mode::Palette if git.showing() => git.type_key(key),
+                                        Mode::Palette => quick.type_key(key),
+                                        Mode::Explorer => {
+                                            sidebar.touch();
+                                            if let Some(ExplorerMessage::Open(path)) =
+                                                explorer.handle_event(&Event::Key(key))
+                                            {
+                                                let path = path
+                                                    .strip_prefix(&preview_ctx.vault)
+                                                    .unwrap_or(&path);
+                                                picked = Some(Item::Note(path.to_path_buf()));
+                                            }
+                                        }
+                                        Mode::Normal | Mode::Insert => {
+                                            ex = edit(key, &mut editor, vim.as_mut(), &mut history);
+                                        }
+                                    }
+                                    continue;
+                                }
+                            };
+                            let sidebar_action = match action {
+                                Action::Quit => {
+                                    match navigator.save(&mut editor, &preview_ctx) {
+                                        Ok(()) => quit = true,
+                                        Err(_) if drop_changes => quit = true,
+                                        Err(err) => {
+                                            navigator.status = Some(format!(
+                                                "Cannot save note: {err}, quit again to drop the changes"
+                                            ));
+                                            quit_unsaved = true;
+                                        }
+                                    }
+                                    continue;
+                                }
+                                Action::Save => {
+                                    if let Err(err) = navigator.save(&mut editor, &preview_ctx) {
+                                        navigator.status = Some(format!("Cannot save note: {err}"));
+                                    }
+                                    continue;
+                                }
+                                Action::OpenPalette => {
+                                    preview_ctx.rescan_links();
+                                    quick.show(&preview_ctx.links);
+                                    continue;
+                                }
+                                Action::ToggleFold => {
+                                    let row = editor.cursor().0;
+                                    if let Some(fold) =
+                                        folds.iter().find(|f| f.source.contains(&row))
+                                    {
+                                        preview_ctx.toggle_fold(fold);
+                                    }
+                                    continue;
+                                }
+                                Action::FollowLink => {
+                                    // Without a link under the cursor the keys are typed.
+                                    if !navigator.follow(&mut editor, &mut preview_ctx) {
+                                        for &key in bindings.chord() {
+                                            ex = edit(key, &mut editor, vim.as_mut(), &mut history);
+                                        }
+                                    }
+                                    continue;
+                                }
+                                Action::SelectPrevious
+                                | Action::SelectNext
+                                | Action::Accept
+                                | Action::Close => {
+                                    if history.showing() {
+                                        if let Err(err) = history.act(action, &mut editor) {
+                                            navigator.status =
+                                                Some(format!("Cannot restore version: {err}"));
+                                        }
+                                    } else if git.showing() {
+                                        navigator.status = git.act(action, &mut editor);
+                                    } else if snapshots.showing() {
+                                        navigator.status = snapshots.act(action, &mut editor);
+                                    } else {
+                                        picked = quick.act(action);
+                                    }
+                                    continue;
+                                }
+                                Action::Undo | Action::Redo => {
+                                    let (done, what) = if action == Action::Undo {
+                                        (history.undo(&mut editor), "undo")
+                                    } else {
+                                        (history.redo(&mut editor), "redo")
+                                    };
+                                    navigator.status = match done {
+                                        Ok(true) => None,
+                                        Ok(false) => Some(format!("Nothing to {what}")),
+                                        Err(err) => Some(format!("Cannot {what}: {err}")),
+                                    };
+                                    continue;
+                                }
+                                Action::History => {
+                                    if let Err(err) = history.show(&editor) {
+                                        navigator.status =
+                                            Some(format!("Cannot read history: {err}"));
+                                    }
+                                    continue;
+                                }
+                                Action::CancelLoad => {
+                                    explorer.cancel_loads();
+                                    continue;
+                                }
+                                Action::ToggleHidden => {
+                                    explorer.toggle_hidden();
+                                    continue;
+                                }
+                                Action::Back => SidebarAction::Back,
+                                Action::Home => SidebarAction::Home,
+                                Action::Search => SidebarAction::Search,
+                                Action::NewNote => SidebarAction::Add,
+                                Action::Settings => SidebarAction::Settings,
+                                Action::ToggleSidebar => SidebarAction::Toggle(sidebar.open),
+                            };
+                            sidebar.perform(sidebar_action, &bus, &mut explorer);
+                        }
+                        for msg in bus.queue.take() {
+                            match msg {
+                                Message::SidebarAction(SidebarAction::Back) => {
+                                    navigator.back(&mut editor, &mut preview_ctx)
+                                }
+                                Message::SidebarAction(SidebarAction::Search) => {
+                                    preview_ctx.rescan_links();
+                                    quick.show(&preview_ctx.links);
+                                }
+                                Message::SidebarAction(SidebarAction::Add) => {
+                                    picked = Some(Item::Command(Command::NewNote))
+                                }
+                                Message::SidebarAction(SidebarAction::Settings) => {
+                                    picked = Some(Item::Command(Command::Settings))
+                                }
+                                _ => {}
+                            }
+                        }
+                        match ex {
+                            Some(Ex::Write | Ex::WriteQuit) => {
+                                match navigator.save(&mut editor, &preview_ctx) {
+                                    Ok(()) => quit = ex == Some(Ex::WriteQuit),
+                                    Err(err) => {
+                                        navigator.status = Some(format!("Cannot save note: {err}"))
+                                    }
+                                }
+                            }
+                            Some(Ex::Quit) if navigator.modified(&editor, &preview_ctx) => {
+                                if let Some(vim) = &mut vim {
+                                    vim.set_message(
+                                        "No write since last change (add ! to override)",
+                                    );
+                                }
+                            }
+                            Some(Ex::Quit | Ex::ForceQuit) => quit = true,
+                            None => {}
+                        }
+                        match picked {
+                            Some(Item::Note(path)) => {
+                                navigator.open(&path, &mut editor, &mut preview_ctx)
                             }
-                            KeyCode::Char('p') if key.modifiers.is_empty() => {
-                                palette = TextArea::default();
-                                palette.set_block(
-                                    Block::default().borders(Borders::ALL).title("Settings"),
+                            Some(Item::Command(Command::NewNote)) => {
+                                let path = palette::untitled(&preview_ctx.vault);
+                                navigator.create(&path, None, &mut editor, &mut preview_ctx);
+                            }
+                            Some(Item::Command(Command::ToggleSidebar)) => {
+                                sidebar.open = !sidebar.open;
+                            }
+                            Some(Item::Command(Command::SwitchTheme)) => {
+                                cfg.theme =
+                                    if cfg.theme == "dark" { "light" } else { "dark" }.into();
+                                save_config(&cfg);
+                                navigator.status = Some(format!("Theme: {}", cfg.theme));
+                            }
+                            Some(Item::Command(Command::Export)) => {
+                                navigator.status = Some(export_note(
+                                    &mut editor,
+                                    &preview_ctx,
+                                    &mut navigator,
+                                    &cfg.theme,
+                                ));
+                            }
+                            Some(Item::Command(Command::NoteLog)) => {
+                                navigator.status = git.show_log(note_path(&preview_ctx));
+                            }
+                            Some(Item::Command(Command::Blame)) => {
+                                navigator.status = git.show_blame(note_path(&preview_ctx));
+                            }
+                            Some(Item::Command(Command::ResolveConflicts))
+                                if !git.resolve(&editor) =>
+                            {
+                                navigator.status = Some("The note has no merge conflicts".into());
+                            }
+                            Some(Item::Command(Command::ResolveConflicts)) => {}
+                            Some(Item::Command(Command::Snapshots)) => {
+                                navigator.status = snapshots.show(
+                                    &preview_ctx.vault,
+                                    note_path(&preview_ctx),
+                                    &editor,
                                 );
-                                palette.insert_str(&serde_yaml::to_string(&cfg).unwrap());
-                                show_palette = true;
                             }
-                            _ => {
-                                let _ = editor.input(to_input(key));
+                            Some(Item::Command(Command::Settings)) => {
+                                settings = settings_editor(&cfg);
+                                show_settings = true;
                             }
+                            None => {}
+                        }
+                        if vim.as_ref().is_some_and(|v| v.mode() != VimMode::Insert) {
+                            autocomplete.popup = None;
+                        } else if !quick.open {
+                            autocomplete.update(
+                                editor.lines(),
+                                editor.cursor(),
+                                preview_ctx.note.as_deref(),
+                            );
+                        }
+                    }
+                }
+                Event::Paste(text) if show_settings => input::paste(&mut settings, &text),
+                Event::Paste(text) if quick.open => {
+                    for c in text.chars().filter(|c| !c.is_control()) {
+                        quick.type_key(KeyEvent::from(KeyCode::Char(c)));
+                    }
+                }
+                Event::Paste(text) if !sidebar.open => {
+                    input::paste(&mut editor, &text);
+                    autocomplete.update(
+                        editor.lines(),
+                        editor.cursor(),
+                        preview_ctx.note.as_deref(),
+                    );
+                }
+                Event::Mouse(mouse) if !show_settings && !quick.open => {
+                    let inside = mouse.column >= preview_area.x
+                        && mouse.column < preview_area.right()
+                        && mouse.row >= preview_area.y;
+                    if viewport.mouse(&mouse, &mut editor) {
+                    } else if mouse.kind == MouseEventKind::Down(MouseButton::Left) && inside {
+                        let line = (mouse.row - preview_area.y) as usize;
+                        if let Some(fold) = folds.iter().find(|f| f.line == line) {
+                            preview_ctx.toggle_fold(fold);
                         }
                     }
                 }
@@ -259,13 +702,39 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
                 _ => {}
             }
         }
+        if preview_ctx.note != last_note {
+            last_note = preview_ctx.note.clone();
+            match EditHistory::open(&editor, &preview_ctx) {
+                Ok(opened) => history = opened,
+                Err(err) => navigator.status = Some(format!("Cannot read history: {err}")),
+            }
+            viewport = Viewport::default();
+            if let Some(vim) = &mut vim {
+                vim.forget_changes();
+            }
+            if let Some(note) = &last_note {
+                quick.opened(note.strip_prefix(&preview_ctx.vault).unwrap_or(note));
+            }
+            git.close();
+            snapshots.close();
+            if git.resolve(&editor) {
+                navigator.status =
+                    Some("The note has merge conflicts: o ours, t theirs, b both".into());
+            }
+        } else if let Err(err) = track(&mut history, &editor, vim.as_ref()) {
+            navigator.status = Some(format!("Cannot record history: {err}"));
+        }
+        git.saved(std::mem::take(&mut navigator.saved));
     }
 
+    git.finish();
+
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
     Ok(())
@@ -0,0 +1,600 @@
+//! Following links from the editor.
+//!
+//! Enter on a link opens its note in the editor, at the linked heading if
+//! there is one, and remembers the place it was followed from so
+//! [`SidebarAction::Back`](core_notes::events::SidebarAction::Back) can
+//! return to it. A link to a missing note asks whether to create it, and
+//! links with a URL scheme are opened with the configured command.
+//!
+//! The navigator also loads and saves the notes, and watches the open
+//! note for changes other programs make. An unchanged editor takes them
+//! over, otherwise a banner asks whether to reload the note, keep the
+//! editor's text or merge both.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use core_notes::navigation::{self, History};
+use core_notes::palette;
+use crossterm::event::{KeyCode, KeyEvent};
+use markdown_renderer::links::{self, LinkTarget, Resolution};
+use ratatui::prelude::*;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::{Block, Borders};
+use storage::diff;
+use storage::note_file::{self, LineFormat, Stamp, WriteError};
+use storage::note_watch::NoteWatcher;
+use storage::snapshots::SnapshotStore;
+use tui_textarea::{CursorMove, TextArea};
+
+use crate::preview::PreviewContext;
+
+/// A place in the editor to go back to.
+struct Visit {
+    /// Vault-relative path of the note, `None` for the unsaved buffer.
+    note: Option<PathBuf>,
+    /// Text of the unsaved buffer, which cannot be read back from disk.
+    lines: Option<Vec<String>>,
+    cursor: (usize, usize),
+}
+
+/// A missing note waiting for the user to confirm its creation.
+struct Pending {
+    path: PathBuf,
+    anchor: Option<String>,
+}
+
+/// The note as the editor last read or wrote it.
+#[derive(Default)]
+struct Loaded {
+    /// The note as the editor shows it, see [`text`].
+    text: String,
+    stamp: Stamp,
+    format: LineFormat,
+}
+
+impl Loaded {
+    /// `content` of the note on disk, read with `stamp`.
+    fn new(content: &str, stamp: Stamp) -> Self {
+        let format = LineFormat::of(content);
+        Self {
+            text: format.strip(content),
+            stamp,
+            format,
+        }
+    }
+
+    /// Whether `text` from the editor is what the note holds.
+    fn matches(&self, text: &str) -> bool {
+        self.stamp.matches(&self.format.apply(text))
+    }
+}
+
+/// The note as another program saved it while the editor had changes.
+struct Outside {
+    question: String,
+    loaded: Loaded,
+}
+
+/// Link following state of the editor.
+#[derive(Default)]
+pub struct Navigator {
+    history: History<Visit>,
+    pending: Option<Pending>,
+    /// Message shown in the status line until the next key.
+    pub status: Option<String>,
+    /// Command opening external links, see [`navigation::open_command`].
+    pub open_command: Option<String>,
+    /// Vault-relative paths of the notes written since this was last
+    /// emptied.
+    pub saved: Vec<PathBuf>,
+    loaded: Loaded,
+    outside: Option<Outside>,
+    watcher: Option<NoteWatcher>,
+}
+
+/// Block drawn around the editor showing `note`.
+pub fn editor_block(note: Option<&Path>) -> Block<'static> {
+    let title = match note {
+        Some(note) => format!("Edit {}", note.display()),
+        None => "Edit".to_string(),
+    };
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+fn relative<'a>(ctx: &PreviewContext, path: &'a Path) -> &'a Path {
+    path.strip_prefix(&ctx.vault).unwrap_or(path)
+}
+
+impl Navigator {
+    pub fn new(open_command: Option<String>) -> Self {
+        Self {
+            open_command,
+            watcher: NoteWatcher::new().ok(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether a question is waiting for an answer.
+    pub fn asking(&self) -> bool {
+        self.pending.is_some() || self.outside.is_some()
+    }
+
+    /// Whether `key` goes to [`Navigator::answer`]. `quit` tells whether the
+    /// key quits: it and Esc are handled as usual while the question about
+    /// a note changed on disk stays open.
+    pub fn answers(&self, key: &KeyEvent, quit: bool) -> bool {
+        match &self.outside {
+            Some(_) => key.code != KeyCode::Esc && !quit,
+            None => self.pending.is_some(),
+        }
+    }
+
+    /// Follow the link under the cursor. Returns `false` when there is none,
+    /// leaving the key to the editor.
+    pub fn follow(&mut self, editor: &mut TextArea, ctx: &mut PreviewContext) -> bool {
+        let (row, col) = editor.cursor();
+        let Some(link) = editor.lines().get(row).and_then(|l| links::link_at(l, col)) else {
+            return false;
+        };
+        let (target, anchor) = match link {
+            LinkTarget::External(url) => {
+                if let Err(err) = navigation::open_external(&url, self.open_command.as_deref()) {
+                    self.status = Some(format!("Cannot open {url}: {err}"));
+                }
+                return true;
+            }
+            LinkTarget::Note { target, anchor } => (target, anchor),
+        };
+        let from = ctx.note.as_deref().map(|n| relative(ctx, n).to_path_buf());
+        match ctx.links.resolve(&target, from.as_deref()) {
+            Resolution::Found(path) => self.go(&path, anchor.as_deref(), editor, ctx),
+            Resolution::Ambiguous(paths) => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                self.status = Some(format!("{target} matches {}", paths.join(", ")));
+            }
+            Resolution::Missing => match links::new_note_path(&target, from.as_deref()) {
+                Some(path) => {
+                    self.status = Some(format!("Create {}? (y/n)", path.display()));
+                    self.pending = Some(Pending { path, anchor });
+                }
+                None => self.status = Some(format!("{target} is outside of the vault")),
+            },
+        }
+        true
+    }
+
+    /// Answer the pending question with `key`. For a missing note `y` or
+    /// Enter creates it, anything else cancels. For a note changed on disk
+    /// see [`Navigator::settle`]. Returns whether the editor was merged with
+    /// conflicts to resolve.
+    pub fn answer(
+        &mut self,
+        key: KeyEvent,
+        editor: &mut TextArea,
+        ctx: &mut PreviewContext,
+    ) -> bool {
+        if let Some(outside) = self.outside.take() {
+            return self.settle(key, outside, editor);
+        }
+        let Some(pending) = self.pending.take() else {
+            return false;
+        };
+        self.status = None;
+        if matches!(key.code, KeyCode::Char('y') | KeyCode::Enter) {
+            self.create(&pending.path, pending.anchor.as_deref(), editor, ctx);
+        }
+        false
+    }
+
+    /// Deal with the note changed on disk while the editor had changes:
+    /// `r` reloads it, `k` keeps the editor's text to be saved over it and
+    /// `m` merges the changes of both. Other keys leave the question open;
+    /// Esc and the quit key never get here, see [`Navigator::answers`].
+    fn settle(&mut self, key: KeyEvent, outside: Outside, editor: &mut TextArea) -> bool {
+        let mut conflicts = false;
+        match key.code {
+            KeyCode::Char('r') => replace(editor, &outside.loaded.text),
+            KeyCode::Char('k') => {}
+            KeyCode::Char('m') => {
+                let merged = diff::merge(&self.loaded.text, &text(editor), &outside.loaded.text);
+                replace(editor, &merged.lines.join("\n"));
+                conflicts = merged.conflicts > 0;
+            }
+            _ => {
+                self.outside = Some(outside);
+                return false;
+            }
+        }
+        self.loaded = outside.loaded;
+        conflicts
+    }
+
+    /// The question about a note changed on disk, if there is one.
+    pub fn banner(&self) -> Option<&str> {
+        self.outside.as_ref().map(|o| o.question.as_str())
+    }
+
+    /// Draw the question about a note changed on disk over the top line of
+    /// the editor at `area`.
+    pub fn render_banner(&self, f: &mut Frame, area: Rect) {
+        if let Some(question) = self.banner() {
+            let line = Rect::new(area.x, area.y, area.width, 1.min(area.height));
+            let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+            f.render_widget(Paragraph::new(question).style(style), line);
+        }
+    }
+
+    /// Take over changes other programs made to the note in the editor,
+    /// once its watcher noticed some.
+    pub fn poll(&mut self, editor: &mut TextArea, ctx: &PreviewContext) {
+        if self.watcher.as_mut().is_some_and(NoteWatcher::changed) {
+            self.check(editor, ctx);
+        }
+    }
+
+    /// Compare the note in the editor with the file on disk. A changed
+    /// file is reloaded, keeping the cursor, unless the editor was changed
+    /// too, which asks what to do.
+    pub fn check(&mut self, editor: &mut TextArea, ctx: &PreviewContext) {
+        let Some(note) = &ctx.note else {
+            return;
+        };
+        let (content, stamp) = match note_file::reread(note, &self.loaded.stamp) {
+            Ok(Some(read)) => read,
+            Ok(None) => return,
+            Err(err) => {
+                self.status = Some(format!("Cannot read {}: {err}", note.display()));
+                return;
+            }
+        };
+        let loaded = Loaded::new(&content, stamp);
+        if self.modified(editor, ctx) {
+            let question = format!(
+                " {} was changed on disk: r reload, k keep mine, m merge",
+                relative(ctx, note).display()
+            );
+            self.outside = Some(Outside { question, loaded });
+        } else {
+            replace(editor, &loaded.text);
+            self.loaded = loaded;
+        }
+    }
+
+    fn watch(&mut self, ctx: &PreviewContext) {
+        if let Some(watcher) = &mut self.watcher {
+            let _ = watcher.watch(ctx.note.as_deref());
+        }
+    }
+
+    /// Create the note at the vault-relative `path` with a heading naming
+    /// it, then open it.
+    pub fn create(
+        &mut self,
+        path: &Path,
+        anchor: Option<&str>,
+        editor: &mut TextArea,
+        ctx: &mut PreviewContext,
+    ) {
+        let file = ctx.vault.join(path);
+        match note_file::create_note(&file, &palette::new_note_text(path)) {
+            // A note created meanwhile is opened as it is.
+            Ok(_) | Err(WriteError::Conflict { .. }) => {
+                ctx.rescan_links();
+                self.go(path, anchor, editor, ctx);
+            }
+            Err(err) => self.status = Some(format!("Cannot create {}: {err}", file.display())),
+        }
+    }
+
+    /// Return to the place the last link was followed from.
+    pub fn back(&mut self, editor: &mut TextArea, ctx: &mut PreviewContext) {
+        let Some(visit) = self.history.back() else {
+            self.status = Some("Nothing to go back to".into());
+            return;
+        };
+        if let Err(err) = self.save(editor, ctx) {
+            self.status = Some(format!("Cannot save note: {err}"));
+            self.history.visit(visit);
+            return;
+        }
+        let lines = match (visit.lines, &visit.note) {
+            (Some(lines), _) => Ok((lines, Loaded::default())),
+            (None, Some(note)) => read_lines(&ctx.vault.join(note)),
+            (None, None) => Ok((Vec::new(), Loaded::default())),
+        };
+        match lines {
+            Ok((lines, loaded)) => {
+                load(editor, ctx, visit.note.as_deref(), lines);
+                self.loaded = loaded;
+                self.outside = None;
+                self.watch(ctx);
+                jump(editor, visit.cursor);
+            }
+            Err(err) => self.status = Some(format!("Cannot open note: {err}")),
+        }
+    }
+
+    /// Write the editor back to its note when it was changed, see
+    /// [`save`], and remember the note in [`Navigator::saved`]. When
+    /// another program changed the note meanwhile, the banner asks what to
+    /// do about it.
+    pub fn save(&mut self, editor: &mut TextArea, ctx: &PreviewContext) -> Result<(), WriteError> {
+        match save(editor, ctx, &mut self.loaded) {
+            Ok(true) => {
+                if let Some(note) = &ctx.note {
+                    self.saved.push(relative(ctx, note).to_path_buf());
+                }
+                Ok(())
+            }
+            Ok(false) => Ok(()),
+            Err(err) => {
+                if let WriteError::Conflict { .. } = err {
+                    self.check(editor, ctx);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Whether the editor was changed since its note was read or written.
+    pub fn modified(&self, editor: &TextArea, ctx: &PreviewContext) -> bool {
+        ctx.note.is_some() && !self.loaded.matches(&text(editor))
+    }
+
+    /// Open the vault-relative `path`, remembering the current place.
+    pub fn open(&mut self, path: &Path, editor: &mut TextArea, ctx: &mut PreviewContext) {
+        self.go(path, None, editor, ctx);
+    }
+
+    /// Open the vault-relative `path`, at the heading `anchor` if given.
+    fn go(
+        &mut self,
+        path: &Path,
+        anchor: Option<&str>,
+        editor: &mut TextArea,
+        ctx: &mut PreviewContext,
+    ) {
+        let (lines, loaded) = match read_lines(&ctx.vault.join(path)) {
+            Ok(read) => read,
+            Err(err) => {
+                self.status = Some(format!("Cannot open {}: {err}", path.display()));
+                return;
+            }
+        };
+        let current = ctx.note.as_deref().map(|n| relative(ctx, n).to_path_buf());
+        if let Err(err) = self.save(editor, ctx) {
+            self.status = Some(format!("Cannot save note: {err}"));
+            return;
+        }
+        let same = current.as_deref() == Some(path);
+        self.history.visit(Visit {
+            lines: current.is_none().then(|| editor.lines().to_vec()),
+            note: current,
+            cursor: editor.cursor(),
+        });
+        // Links to a heading of the same note only move the cursor.
+        if !same {
+            load(editor, ctx, Some(path), lines);
+            self.loaded = loaded;
+            self.outside = None;
+            self.watch(ctx);
+        }
+        let line = anchor
+            .map(|anchor| links::heading_line(&editor.lines().join("\n"), anchor).ok_or(anchor));
+        match line {
+            Some(Ok(line)) => jump(editor, (line, 0)),
+            Some(Err(anchor)) => self.status = Some(format!("No heading {anchor}")),
+            None => jump(editor, (0, 0)),
+        }
+    }
+}
+
+fn read_lines(path: &Path) -> io::Result<(Vec<String>, Loaded)> {
+    let (content, stamp) = note_file::read_note(path)?;
+    let loaded = Loaded::new(&content, stamp);
+    let lines = loaded.text.split('\n').map(String::from).collect();
+    Ok((lines, loaded))
+}
+
+/// Text of the editor, with `\n` line breaks and no final one. It is
+/// saved in the [`LineFormat`] of its note.
+fn text(editor: &TextArea) -> String {
+    editor.lines().join("\n")
+}
+
+/// Put `text` into the editor in place of its content, keeping the cursor
+/// where it was.
+fn replace(editor: &mut TextArea, text: &str) {
+    let cursor = editor.cursor();
+    editor.select_all();
+    editor.insert_str(text);
+    jump(editor, cursor);
+}
+
+/// Write the editor back to its note when it differs from `loaded`, the
+/// note as it was read, keeping its line format. Fails with
+/// [`WriteError::Conflict`] when the note was changed on disk meanwhile.
+/// The text on disk and the saved text are both kept as snapshots. Returns
+/// whether the note was written.
+fn save(editor: &TextArea, ctx: &PreviewContext, loaded: &mut Loaded) -> Result<bool, WriteError> {
+    let text = text(editor);
+    match &ctx.note {
+        Some(note) if !loaded.matches(&text) => {
+            let content = loaded.format.apply(&text);
+            let snapshots = SnapshotStore::open(&ctx.vault);
+            let name = relative(ctx, note);
+            if let Ok(old) = fs::read_to_string(note) {
+                snapshots.save(name, &old)?;
+            }
+            loaded.stamp = note_file::write_note(note, &content, &loaded.stamp)?;
+            loaded.text = text;
+            snapshots.save(name, &content)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn load(editor: &mut TextArea, ctx: &mut PreviewContext, note: Option<&Path>, lines: Vec<String>) {
+    *editor = TextArea::from(lines);
+    editor.set_block(editor_block(note));
+    ctx.note = note.map(|n| ctx.vault.join(n));
+}
+
+fn jump(editor: &mut TextArea, (row, col): (usize, usize)) {
+    editor.move_cursor(CursorMove::Jump(row as u16, col as u16));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn follows_links_and_goes_back() {
+        let vault = tempfile::tempdir().unwrap();
+        fs::write(vault.path().join("b.md"), "# B\n\ntext\n\n## Later part\n").unwrap();
+        let mut ctx = PreviewContext::new(vault.path().to_path_buf());
+        let mut nav = Navigator::default();
+        let mut editor = TextArea::from(["draft".to_string(), "see [[B#later part]]".to_string()]);
+        editor.move_cursor(CursorMove::Jump(1, 7));
+
+        assert!(nav.follow(&mut editor, &mut ctx));
+        assert_eq!(ctx.note, Some(vault.path().join("b.md")));
+        assert_eq!(editor.cursor(), (4, 0));
+
+        editor.move_cursor(CursorMove::Jump(2, 0));
+        editor.insert_str("[[New note]]");
+        editor.move_cursor(CursorMove::Jump(2, 3));
+        assert!(nav.follow(&mut editor, &mut ctx));
+        assert!(nav.asking());
+        nav.answer(press(KeyCode::Char('y')), &mut editor, &mut ctx);
+        assert_eq!(editor.lines()[0], "# New note");
+        assert!(fs::read_to_string(vault.path().join("b.md"))
+            .unwrap()
+            .contains("[[New note]]"));
+        let snapshots = SnapshotStore::open(vault.path()).list(Path::new("b.md"));
+        assert_eq!(snapshots.unwrap().len(), 2);
+        assert_eq!(nav.saved, [PathBuf::from("b.md")]);
+
+        nav.back(&mut editor, &mut ctx);
+        assert_eq!(editor.cursor(), (2, 3));
+        nav.back(&mut editor, &mut ctx);
+        assert_eq!(ctx.note, None);
+        assert_eq!(editor.lines()[1], "see [[B#later part]]");
+        editor.move_cursor(CursorMove::Jump(0, 2));
+        assert!(!nav.follow(&mut editor, &mut ctx));
+
+        nav.open(Path::new("b.md"), &mut editor, &mut ctx);
+        assert!(!nav.modified(&editor, &ctx));
+        fs::write(vault.path().join("b.md"), "# B\n\nchanged elsewhere\n").unwrap();
+        editor.insert_str("mine ");
+        assert!(nav.modified(&editor, &ctx));
+        assert!(matches!(
+            nav.save(&mut editor, &ctx),
+            Err(WriteError::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn keeps_line_endings_of_notes() {
+        let vault = tempfile::tempdir().unwrap();
+        let (a, b) = (vault.path().join("a.md"), vault.path().join("b.md"));
+        fs::write(&a, "# A\r\n\r\nsee [[B]]\r\n").unwrap();
+        fs::write(&b, "# B\n\nsee [[A]]").unwrap();
+        let mut ctx = PreviewContext::new(vault.path().to_path_buf());
+        let mut nav = Navigator::default();
+        let mut editor = TextArea::default();
+
+        nav.open(Path::new("a.md"), &mut editor, &mut ctx);
+        assert_eq!(editor.lines(), ["# A", "", "see [[B]]"]);
+        assert!(!nav.modified(&editor, &ctx));
+        editor.move_cursor(CursorMove::Jump(2, 6));
+        assert!(nav.follow(&mut editor, &mut ctx));
+        assert_eq!(ctx.note, Some(b.clone()));
+        assert!(!nav.modified(&editor, &ctx));
+        editor.move_cursor(CursorMove::Jump(2, 6));
+        assert!(nav.follow(&mut editor, &mut ctx));
+        nav.back(&mut editor, &mut ctx);
+        assert_eq!(fs::read(&a).unwrap(), b"# A\r\n\r\nsee [[B]]\r\n");
+        assert_eq!(fs::read(&b).unwrap(), b"# B\n\nsee [[A]]");
+
+        editor.insert_str("x");
+        nav.back(&mut editor, &mut ctx);
+        assert_eq!(fs::read_to_string(&b).unwrap(), "# B\n\nsee [[xA]]");
+        editor.insert_str("y");
+        nav.save(&mut editor, &ctx).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "# A\r\n\r\nsee [[yB]]\r\n");
+    }
+
+    #[test]
+    fn takes_over_changes_made_on_disk() {
+        let vault = tempfile::tempdir().unwrap();
+        let file = vault.path().join("a.md");
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+        let mut ctx = PreviewContext::new(vault.path().to_path_buf());
+        let mut nav = Navigator::default();
+        let mut editor = TextArea::default();
+        nav.open(Path::new("a.md"), &mut editor, &mut ctx);
+
+        editor.move_cursor(CursorMove::Jump(1, 1));
+        fs::write(&file, "one\ntwo\nthree\nfour\n").unwrap();
+        nav.check(&mut editor, &ctx);
+        assert!(!nav.asking());
+        assert_eq!(editor.lines(), ["one", "two", "three", "four"]);
+        assert_eq!(editor.cursor(), (1, 1));
+
+        editor.move_cursor(CursorMove::Jump(0, 0));
+        editor.insert_str("my ");
+        fs::write(&file, "one\ntwo\n3\nfour\n").unwrap();
+        nav.check(&mut editor, &ctx);
+        assert!(nav.banner().unwrap().contains("a.md was changed on disk"));
+        assert!(!nav.answer(press(KeyCode::Char('x')), &mut editor, &mut ctx));
+        assert!(nav.asking());
+        assert!(nav.answers(&press(KeyCode::Char('m')), false));
+        assert!(!nav.answers(&press(KeyCode::Char('q')), true));
+        assert!(!nav.answers(&press(KeyCode::Esc), false));
+        assert!(!nav.answer(press(KeyCode::Char('m')), &mut editor, &mut ctx));
+        assert_eq!(editor.lines(), ["my one", "two", "3", "four"]);
+        nav.save(&mut editor, &ctx).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "my one\ntwo\n3\nfour\n");
+
+        editor.insert_str("mine");
+        fs::write(&file, "theirs\n").unwrap();
+        assert!(nav.save(&mut editor, &ctx).is_err());
+        assert!(nav.asking());
+        nav.answer(press(KeyCode::Char('k')), &mut editor, &mut ctx);
+        nav.save(&mut editor, &ctx).unwrap();
+        let saved = fs::read_to_string(&file).unwrap();
+        assert!(saved.contains("mine") && !saved.contains("theirs"));
+    }
+
+    #[test]
+    fn reloads_unchanged_notes_in_any_line_format() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut ctx = PreviewContext::new(vault.path().to_path_buf());
+        let mut nav = Navigator::default();
+        let mut editor = TextArea::default();
+        for (name, before, after) in [
+            ("crlf.md", "one\r\ntwo\r\n", "one\r\n2\r\n"),
+            ("open.md", "one\ntwo", "one\n2"),
+        ] {
+            let file = vault.path().join(name);
+            fs::write(&file, before).unwrap();
+            nav.open(Path::new(name), &mut editor, &mut ctx);
+            fs::write(&file, after).unwrap();
+            nav.check(&mut editor, &ctx);
+            assert_eq!(nav.banner(), None, "{name}");
+            assert_eq!(editor.lines(), ["one", "2"]);
+            assert!(!nav.modified(&editor, &ctx));
+            nav.save(&mut editor, &ctx).unwrap();
+            assert_eq!(fs::read_to_string(&file).unwrap(), after);
+        }
+    }
+}
@@ -0,0 +1,567 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use markdown_renderer::inline_math::{self, InlineSpan};
+use markdown_renderer::links::{self, NoteIndex, Resolution};
+use markdown_renderer::math;
+use markdown_renderer::{render_markdown, Widget};
+use ratatui::prelude::*;
+use ratatui::text::{Line, Span};
+use storage::assets::{AssetCache, Thumbnail};
+use storage::folds::{FoldKeys, FoldStore};
+use storage::ignore::IgnoreRules;
+
+use crate::graphics::{self, Protocol};
+
+/// Largest side, in pixels, of the thumbnails shown in the preview.
+const THUMBNAIL_SIZE: u32 = 512;
+
+/// State the preview needs besides the buffer text.
+pub struct PreviewContext {
+    /// Root of the notes vault, used to resolve image paths.
+    pub vault: PathBuf,
+    /// Path of the note being edited, if it lives on disk.
+    pub note: Option<PathBuf>,
+    pub assets: AssetCache,
+    pub protocol: Protocol,
+    /// Remembered open/closed state of `<details>` blocks.
+    pub folds: FoldStore,
+    /// Notes of the vault, used to resolve links.
+    pub links: NoteIndex,
+    /// The last preview built by [`render_cached`].
+    cache: Option<Cached>,
+}
+
+/// A preview with the text and width it was built for.
+struct Cached {
+    source: Vec<String>,
+    width: u16,
+    note: Option<PathBuf>,
+    preview: Preview,
+}
+
+impl PreviewContext {
+    pub fn new(vault: PathBuf) -> Self {
+        Self {
+            assets: AssetCache::new(&vault, THUMBNAIL_SIZE),
+            folds: FoldStore::open(&vault).unwrap_or_else(|_| FoldStore::in_memory()),
+            links: scan_notes(&vault),
+            vault,
+            note: None,
+            protocol: Protocol::detect(),
+            cache: None,
+        }
+    }
+
+    /// Path under which fold states of the current note are remembered.
+    pub fn note_key(&self) -> &Path {
+        self.note.as_deref().unwrap_or(Path::new(""))
+    }
+
+    /// Scan the vault for notes again, after notes were added or renamed.
+    pub fn rescan_links(&mut self) {
+        self.links = scan_notes(&self.vault);
+        self.cache = None;
+    }
+
+    /// Flip a `<details>` block in the preview and remember its new state.
+    pub fn toggle_fold(&mut self, fold: &FoldRegion) {
+        let note = self.note_key().to_path_buf();
+        self.folds.toggle(&note, &fold.key, fold.default_open);
+        let _ = self.folds.save();
+        self.cache = None;
+    }
+
+    /// Span for a link to another note, flagged when its target does not
+    /// resolve to exactly one note.
+    fn link_span(
+        &self,
+        target: &str,
+        alias: Option<&str>,
+        anchor: Option<&str>,
+        style: Style,
+    ) -> Span<'static> {
+        let text = links::label(target, alias, anchor);
+        let style = style.fg(Color::Cyan).add_modifier(Modifier::UNDERLINED);
+        let from = self
+            .note
+            .as_deref()
+            .map(|n| n.strip_prefix(&self.vault).unwrap_or(n));
+        match self.links.resolve(target, from) {
+            Resolution::Found(_) => Span::styled(text, style),
+            Resolution::Ambiguous(notes) => Span::styled(
+                format!("{text} (ambiguous: {} notes)", notes.len()),
+                style.fg(Color::Yellow),
+            ),
+            Resolution::Missing => Span::styled(format!("{text} (missing)"), style.fg(Color::Red)),
+        }
+    }
+}
+
+/// Notes of the vault, leaving out those its ignore rules leave out.
+fn scan_notes(vault: &Path) -> NoteIndex {
+    let ignore = IgnoreRules::load(vault);
+    NoteIndex::scan_with(vault, |path, is_dir| ignore.ignores(path, is_dir)).unwrap_or_default()
+}
+
+/// An image drawn over blank preview lines with a terminal graphics protocol.
+#[derive(Debug, Clone)]
+pub struct ImagePlacement {
+    /// Index of the first preview line covered by the image.
+    pub line: usize,
+    pub cols: u16,
+    pub rows: u16,
+    pub thumbnail: Arc<Thumbnail>,
+}
+
+/// Header line of a `<details>` block in the preview.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldRegion {
+    /// Index of the preview line showing the summary.
+    pub line: usize,
+    /// Buffer lines of the outermost `<details>` block containing it.
+    pub source: Range<usize>,
+    pub key: String,
+    /// State used when nothing is remembered for the block.
+    pub default_open: bool,
+}
+
+/// Rendered preview pane.
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub lines: Vec<Line<'static>>,
+    pub images: Vec<ImagePlacement>,
+    pub folds: Vec<FoldRegion>,
+}
+
+pub(crate) fn transform_heading(line: &str) -> (String, Option<Style>) {
+    let trimmed = line.trim_start();
+    let mut level = 0;
+    for ch in trimmed.chars() {
+        if ch == '#' {
+            level += 1;
+        } else {
+            break;
+        }
+    }
+    if level > 0 && trimmed.get(level..level + 1) == Some(" ") {
+        let text = trimmed[level + 1..].trim_start();
+        let style = Style::default().add_modifier(Modifier::BOLD);
+        (format!("H{}: {}", level, text), Some(style))
+    } else {
+        (line.to_string(), None)
+    }
+}
+
+/// Render TeX to preview lines. Display math is indented as a block, errors
+/// are shown in place of the formula together with the offending source.
+fn math_lines(tex: &str, display: bool) -> Vec<Line<'static>> {
+    match math::to_unicode(tex, display) {
+        Ok(lines) if display => lines
+            .into_iter()
+            .map(|l| Line::from(format!("    {l}")))
+            .collect(),
+        Ok(lines) => lines.into_iter().map(Line::from).collect(),
+        Err(err) => vec![Line::from(vec![
+            Span::styled(
+                format!("math error: {err}"),
+                Style::default().fg(Color::Red),
+            ),
+            Span::raw(format!(" ({})", tex.trim())),
+        ])],
+    }
+}
+
+/// Lay out a line of running text, turning `$..$` spans into inline math
+/// and `[[..]]` into links.
+fn inline_line(text: &str, style: Style, ctx: &PreviewContext) -> Line<'static> {
+    let spans: Vec<Span<'static>> = inline_math::tokenize(text)
+        .into_iter()
+        .map(|span| match span {
+            InlineSpan::Text(t) => Span::styled(t.to_string(), style),
+            InlineSpan::Math { tex, .. } => match math::to_unicode(tex, false) {
+                Ok(lines) => Span::styled(lines.concat(), style.add_modifier(Modifier::ITALIC)),
+                Err(err) => Span::styled(format!("math error: {err}"), style.fg(Color::Red)),
+            },
+            InlineSpan::WikiLink(inner) => match links::wikilink(inner) {
+                Widget::NoteLink {
+                    target,
+                    alias,
+                    anchor,
+                } => ctx.link_span(&target, alias.as_deref(), anchor.as_deref(), style),
+                _ => Span::styled(format!("[[{inner}]]"), style),
+            },
+        })
+        .collect();
+    Line::from(spans)
+}
+
+/// Image on a line of its own, either `![alt](url)` or `{% image url %}`.
+fn image_ref(line: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = line.strip_prefix("![") {
+        let (alt, rest) = rest.split_once("](")?;
+        let target = rest.strip_suffix(')')?;
+        // Drop an optional title: `![alt](url "title")`.
+        let url = target.split_once(' ').map_or(target, |(url, _)| url);
+        return Some((alt, url));
+    }
+    let rest = line
+        .strip_prefix("{%")?
+        .trim_start()
+        .strip_prefix("image")?;
+    let url = rest.strip_suffix("%}")?.trim();
+    (!url.is_empty()).then_some(("", url))
+}
+
+fn push_image(
+    alt: &str,
+    url: &str,
+    ctx: &mut PreviewContext,
+    width: u16,
+    out: &mut Vec<Line<'static>>,
+    images: &mut Vec<ImagePlacement>,
+) {
+    match ctx.assets.load_url(url, ctx.note.as_deref(), &ctx.vault) {
+        Ok(thumbnail) => {
+            let (cols, rows) = graphics::cell_size(&thumbnail, width);
+            if ctx.protocol == Protocol::HalfBlocks {
+                out.extend(graphics::half_blocks(&thumbnail, cols, rows));
+            } else {
+                images.push(ImagePlacement {
+                    line: out.len(),
+                    cols,
+                    rows,
+                    thumbnail,
+                });
+                out.extend((0..rows).map(|_| Line::default()));
+            }
+        }
+        Err(err) => {
+            let label = if alt.is_empty() { "image" } else { alt };
+            out.push(Line::from(Span::styled(
+                format!("[{label}] {err}"),
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::ITALIC),
+            )));
+        }
+    }
+}
+
+/// Net change in `<details>` nesting caused by `line`.
+fn details_depth(line: &str) -> isize {
+    let lower = line.to_ascii_lowercase();
+    lower.matches("<details").count() as isize - lower.matches("</details>").count() as isize
+}
+
+/// End of the block starting at `row` that the markdown renderer lays out:
+/// a `<details>` element up to its closing tag, a `math` fence up to its
+/// closing fence or a paragraph starting with `$$` up to the next blank line.
+fn rendered_block_end(source: &[String], row: usize) -> Option<usize> {
+    let trimmed = source[row].trim();
+    if trimmed.to_ascii_lowercase().starts_with("<details") {
+        let mut depth = details_depth(trimmed);
+        let mut end = row + 1;
+        while depth > 0 && end < source.len() {
+            depth += details_depth(&source[end]);
+            end += 1;
+        }
+        return Some(end);
+    }
+    let rest = &source[row + 1..];
+    if trimmed.starts_with("$$") {
+        let blank = rest.iter().position(|l| l.trim().is_empty());
+        return Some(blank.map_or(source.len(), |i| row + 1 + i));
+    }
+    let fence = trimmed.strip_suffix("math")?.trim_end();
+    let ch = fence.bytes().next().filter(|c| *c == b'`' || *c == b'~')?;
+    if fence.len() < 3 || fence.bytes().any(|c| c != ch) {
+        return None;
+    }
+    let closes = |l: &String| {
+        let l = l.trim();
+        l.len() >= fence.len() && l.bytes().all(|c| c == ch)
+    };
+    Some(
+        rest.iter()
+            .position(closes)
+            .map_or(source.len(), |i| row + 2 + i),
+    )
+}
+
+struct FoldLayout<'a> {
+    ctx: &'a PreviewContext,
+    keys: FoldKeys,
+    source: Range<usize>,
+    lines: Vec<Line<'static>>,
+    folds: Vec<FoldRegion>,
+}
+
+impl FoldLayout<'_> {
+    fn push(&mut self, indent: usize, line: Line<'static>) {
+        let mut spans = vec![Span::raw(" ".repeat(indent))];
+        spans.extend(line.spans);
+        self.lines.push(Line::from(spans));
+    }
+
+    fn widgets(&mut self, widgets: &[Widget], indent: usize, parent: &str) {
+        for widget in widgets {
+            match widget {
+                Widget::Details {
+                    summary,
+                    open,
+                    children,
+                } => {
+                    let key = self.keys.next(parent, summary);
+                    let shown = self
+                        .ctx
+                        .folds
+                        .get(self.ctx.note_key(), &key)
+                        .unwrap_or(*open);
+                    self.folds.push(FoldRegion {
+                        line: self.lines.len(),
+                        source: self.source.clone(),
+                        key: key.clone(),
+                        default_open: *open,
+                    });
+                    let marker = if shown { "▾" } else { "▸" };
+                    let style = Style::default().add_modifier(Modifier::BOLD);
+                    self.push(
+                        indent,
+                        Line::from(Span::styled(format!("{marker} {summary}"), style)),
+                    );
+                    if shown {
+                        self.widgets(children, indent + 2, &format!("{key}/"));
+                    }
+                }
+                Widget::Header(level, text) => {
+                    let style = Style::default().add_modifier(Modifier::BOLD);
+                    self.push(
+                        indent,
+                        Line::from(Span::styled(format!("H{level}: {text}"), style)),
+                    );
+                }
+                Widget::Latex { tex, display } => {
+                    for line in math_lines(tex, *display) {
+                        self.push(indent, line);
+                    }
+                }
+                Widget::CodeBlock { code, .. } | Widget::Mermaid(code) => {
+                    for line in code.lines() {
+                        self.push(indent, Line::from(format!("  {line}")));
+                    }
+                }
+                Widget::Image { alt, url } => {
+                    let text = format!("[{}] {url}", if alt.is_empty() { "image" } else { alt });
+                    self.push(indent, Line::from(text));
+                }
+                Widget::Table(rows) => self.widgets(rows, indent, parent),
+                Widget::TableRow(cells) => self.push(indent, Line::from(cells.join(" │ "))),
+                Widget::NoteLink {
+                    target,
+                    alias,
+                    anchor,
+                } => {
+                    let span = self.ctx.link_span(
+                        target,
+                        alias.as_deref(),
+                        anchor.as_deref(),
+                        Style::default(),
+                    );
+                    self.push(indent, Line::from(span));
+                }
+                Widget::Link { text, .. } => {
+                    let style = Style::default()
+                        .fg(Color::Blue)
+                        .add_modifier(Modifier::UNDERLINED);
+                    self.push(indent, Line::from(Span::styled(text.clone(), style)));
+                }
+                Widget::Bold(text)
+                | Widget::Italic(text)
+                | Widget::Text(text)
+                | Widget::IncludeNote(text)
+                | Widget::CustomImage(text) => {
+                    for line in text.lines() {
+                        self.push(indent, inline_line(line, Style::default(), self.ctx));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Build the preview pane for the lines of the editor buffer, laid out for
+/// a pane `width` cells wide.
+pub fn render_preview(source: &[String], ctx: &mut PreviewContext, width: u16) -> Preview {
+    let mut out = Vec::new();
+    let mut images = Vec::new();
+    let mut folds = Vec::new();
+    let mut keys = FoldKeys::default();
+    let mut lines = source.iter().enumerate();
+    while let Some((row, line)) = lines.next() {
+        let trimmed = line.trim();
+        if let Some(end) = rendered_block_end(source, row) {
+            if end > row + 1 {
+                lines.nth(end - row - 2);
+            }
+            let mut layout = FoldLayout {
+                ctx,
+                keys,
+                source: row..end,
+                lines: out,
+                folds,
+            };
+            layout.widgets(&render_markdown(&source[row..end].join("\n")), 0, "");
+            FoldLayout {
+                keys,
+                lines: out,
+                folds,
+                ..
+            } = layout;
+        } else if let Some((alt, url)) = image_ref(trimmed) {
+            push_image(alt, url, ctx, width, &mut out, &mut images);
+        } else {
+            let (text, style) = transform_heading(line);
+            out.push(inline_line(&text, style.unwrap_or_default(), ctx));
+        }
+    }
+    Preview {
+        lines: out,
+        images,
+        folds,
+    }
+}
+
+/// The preview of [`render_preview`], built again only when the text, the
+/// width or the note changed, or links were scanned or folds toggled since.
+pub fn render_cached<'a>(
+    source: &[String],
+    ctx: &'a mut PreviewContext,
+    width: u16,
+) -> &'a Preview {
+    let fresh = ctx
+        .cache
+        .as_ref()
+        .is_some_and(|c| c.width == width && c.note == ctx.note && c.source == source);
+    if !fresh {
+        let preview = render_preview(source, ctx, width);
+        ctx.cache = Some(Cached {
+            source: source.to_vec(),
+            width,
+            note: ctx.note.clone(),
+            preview,
+        });
+    }
+    &ctx.cache.as_ref().unwrap().preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn flags_unresolved_links() {
+        let mut ctx = PreviewContext::new(PathBuf::from("."));
+        ctx.links = NoteIndex::default();
+        ctx.links.insert(PathBuf::from("a.md"), Some("Alpha"));
+        let source = vec!["see [[Alpha|the a]] and [[B#Top]]".to_string()];
+        let preview = render_preview(&source, &mut ctx, 40);
+        assert_eq!(text(&preview.lines[0]), "see the a and B > Top (missing)");
+        assert_eq!(preview.lines[0].spans[3].style.fg, Some(Color::Red));
+    }
+
+    #[test]
+    fn lays_out_display_math_with_the_renderer() {
+        let source: Vec<String> = [
+            "````math",
+            r"\begin{array}{cc} a & b \end{array}",
+            "````",
+            "$$",
+            r"x^2",
+            "$$",
+            "",
+            r"after $\alpha$",
+        ]
+        .map(String::from)
+        .to_vec();
+        let mut ctx = PreviewContext::new(PathBuf::from("."));
+        let preview = render_preview(&source, &mut ctx, 40);
+        let lines: Vec<String> = preview.lines.iter().map(text).collect();
+        assert_eq!(lines, ["    a  b", "    x²", "", "after α"]);
+    }
+
+    #[test]
+    fn folds_details_blocks() {
+        let source: Vec<String> = [
+            "intro",
+            "<details><summary>Steps</summary>",
+            "",
+            "hidden",
+            "",
+            "</details>",
+            "<details open><summary>Steps</summary>",
+            "",
+            "shown",
+            "",
+            "</details>",
+        ]
+        .map(String::from)
+        .to_vec();
+        let mut ctx = PreviewContext::new(PathBuf::from("."));
+        ctx.folds = FoldStore::in_memory();
+        let preview = render_preview(&source, &mut ctx, 40);
+        let lines: Vec<String> = preview.lines.iter().map(text).collect();
+        assert_eq!(lines, ["intro", "▸ Steps", "▾ Steps", "  shown"]);
+        assert_eq!(preview.folds[0].source, 1..6);
+        assert_eq!(preview.folds[1].key, "Steps#1");
+
+        let first = &preview.folds[0];
+        ctx.folds
+            .toggle(Path::new(""), &first.key, first.default_open);
+        let preview = render_preview(&source, &mut ctx, 40);
+        assert_eq!(text(&preview.lines[2]), "  hidden");
+    }
+
+    #[test]
+    fn builds_the_preview_again_only_when_needed() {
+        let mut source = vec!["see [[Alpha]]".to_string()];
+        let mut ctx = PreviewContext::new(PathBuf::from("."));
+        ctx.links = NoteIndex::default();
+        assert!(text(&render_cached(&source, &mut ctx, 40).lines[0]).ends_with("(missing)"));
+
+        // Links changed without a rescan keep the cached preview.
+        ctx.links.insert(PathBuf::from("Alpha.md"), None);
+        assert!(text(&render_cached(&source, &mut ctx, 40).lines[0]).ends_with("(missing)"));
+        assert_eq!(
+            text(&render_cached(&source, &mut ctx, 30).lines[0]),
+            "see Alpha"
+        );
+        source[0].push('!');
+        assert_eq!(
+            text(&render_cached(&source, &mut ctx, 30).lines[0]),
+            "see Alpha!"
+        );
+
+        ctx.folds = FoldStore::in_memory();
+        source = [
+            "<details><summary>Steps</summary>",
+            "",
+            "hidden",
+            "",
+            "</details>",
+        ]
+        .map(String::from)
+        .to_vec();
+        let fold = render_cached(&source, &mut ctx, 30).folds[0].clone();
+        ctx.toggle_fold(&fold);
+        assert_eq!(
+            text(&render_cached(&source, &mut ctx, 30).lines[1]),
+            "  hidden"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Quick open palette of the TUI.
+//!
+//! Draws the shared [`Palette`] model over the editor and feeds it the keys
+//! typed while it is open; moving and accepting go through the keymap. The
+//! recently opened notes are kept in the vault, see [`Palette::load_recent`].
+
+use std::path::{Path, PathBuf};
+
+use config::keymap::Action;
+use core_notes::palette::{Command, Item, Palette};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use markdown_renderer::links::NoteIndex;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+
+/// Number of results shown at once.
+const VISIBLE: usize = 12;
+
+pub struct QuickOpen {
+    pub palette: Palette,
+    pub open: bool,
+    vault: PathBuf,
+}
+
+impl QuickOpen {
+    pub fn new(vault: &Path) -> Self {
+        let mut palette = Palette::new();
+        for command in Command::ALL {
+            palette.register(command);
+        }
+        palette.load_recent(vault);
+        Self {
+            palette,
+            open: false,
+            vault: vault.to_path_buf(),
+        }
+    }
+
+    /// Open the palette with an empty query over the notes of `index`.
+    pub fn show(&mut self, index: &NoteIndex) {
+        self.palette.set_notes(
+            index
+                .notes()
+                .map(|(path, title)| (path.to_path_buf(), title.map(String::from))),
+        );
+        self.palette.set_query("");
+        self.open = true;
+    }
+
+    /// Remember that the vault-relative `path` was opened.
+    pub fn opened(&mut self, path: &Path) {
+        self.palette.opened(path);
+        let _ = self.palette.save_recent(&self.vault);
+    }
+
+    /// Perform a palette action bound in the keymap. Returns the chosen
+    /// item when the action accepted one.
+    pub fn act(&mut self, action: Action) -> Option<Item> {
+        match action {
+            Action::SelectPrevious => self.palette.move_selection(-1),
+            Action::SelectNext => self.palette.move_selection(1),
+            Action::Accept => {
+                self.open = false;
+                return self.palette.selected().map(|e| e.item.clone());
+            }
+            Action::Close => self.open = false,
+            _ => {}
+        }
+        None
+    }
+
+    /// Edit the query with a key that has no binding.
+    pub fn type_key(&mut self, key: KeyEvent) {
+        let mut query = self.palette.query().to_string();
+        match key.code {
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                query.push(c)
+            }
+            _ => return,
+        }
+        self.palette.set_query(&query);
+    }
+
+    /// Draw the palette centred near the top of `area`.
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let width = area.width.min(72);
+        let height = area.height.min(VISIBLE as u16 + 3);
+        let rect = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + area.height.saturating_sub(height).min(2),
+            width,
+            height,
+        );
+        let selected = self.palette.selected_index();
+        let first = selected.saturating_sub(VISIBLE - 1);
+        let mut lines = vec![Line::from(format!("> {}", self.palette.query()))];
+        for (i, entry) in self
+            .palette
+            .results()
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE)
+        {
+            let mut base = Style::default();
+            if i == selected {
+                base = base.add_modifier(Modifier::REVERSED);
+            }
+            let mut spans: Vec<Span> = entry
+                .label
+                .chars()
+                .enumerate()
+                .map(|(j, c)| {
+                    let style = if entry.positions.contains(&j) {
+                        base.fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                    } else {
+                        base
+                    };
+                    Span::styled(c.to_string(), style)
+                })
+                .collect();
+            if let Item::Command(_) = entry.item {
+                spans.push(Span::styled("  command", base.fg(Color::DarkGray)));
+            } else if !entry.detail.is_empty() {
+                spans.push(Span::styled(
+                    format!("  {}", entry.detail),
+                    base.fg(Color::DarkGray),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Open")),
+            rect,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn picks_notes_and_remembers_them() {
+        let vault = tempfile::tempdir().unwrap();
+        let mut index = NoteIndex::default();
+        index.insert("work/plans.md".into(), Some("Road map"));
+        index.insert("inbox.md".into(), None);
+        let mut quick = QuickOpen::new(vault.path());
+        quick.show(&index);
+        for c in "roadm".chars() {
+            quick.type_key(press(KeyCode::Char(c)));
+        }
+        quick.type_key(press(KeyCode::Backspace));
+        quick.act(Action::SelectNext);
+        quick.act(Action::SelectPrevious);
+        let item = quick.act(Action::Accept);
+        assert_eq!(item, Some(Item::Note("work/plans.md".into())));
+        assert!(!quick.open);
+
+        quick.opened(Path::new("work/plans.md"));
+        let quick = QuickOpen::new(vault.path());
+        assert_eq!(quick.palette.recent(), [PathBuf::from("work/plans.md")]);
+    }
+}
@@ -1,6 +1,5 @@
 use std::time::{Duration, Instant};
 
-use crossterm::event::{Event, KeyCode};
 use ratatui::prelude::*;
 use ratatui::text::{Line, Text};
 use ratatui::widgets::{Block, Borders, List, ListItem};
@@ -44,44 +43,46 @@ impl Sidebar {
         }
     }
 
-    /// Handle a key or other event. Emits [`Message::SidebarAction`] using the
-    /// provided [`EventSink`] whenever an action is triggered.
-    pub fn handle_event<E: EventSink, F: FileExplorer>(
+    /// Perform a sidebar action bound in the keymap. Emits
+    /// [`Message::SidebarAction`] using the provided [`EventSink`].
+    pub fn perform<E: EventSink, F: FileExplorer>(
         &mut self,
-        ev: &Event,
+        action: SidebarAction,
         sink: &E,
         explorer: &mut F,
     ) {
-        match ev {
-            Event::Key(k) => match k.code {
-                KeyCode::Char('h') => {
-                    explorer.go_home();
-                    sink.send(Message::SidebarAction(SidebarAction::Home));
-                }
-                KeyCode::Char('f') => {
-                    explorer.search();
-                    sink.send(Message::SidebarAction(SidebarAction::Search));
-                }
-                KeyCode::Char('n') => {
-                    explorer.new_note();
-                    sink.send(Message::SidebarAction(SidebarAction::Add));
-                }
-                KeyCode::Char('b') => {
-                    explorer.go_back();
-                    sink.send(Message::SidebarAction(SidebarAction::Back));
-                }
-                KeyCode::Char('s') => {
-                    explorer.settings();
-                    sink.send(Message::SidebarAction(SidebarAction::Settings));
-                }
-                KeyCode::Tab => {
-                    self.open = !self.open;
-                    sink.send(Message::SidebarAction(SidebarAction::Toggle(self.open)));
-                }
-                _ => {}
-            },
-            _ => {}
-        }
+        let action = match action {
+            SidebarAction::Home => {
+                explorer.go_home();
+                action
+            }
+            SidebarAction::Search => {
+                explorer.search();
+                action
+            }
+            SidebarAction::Add => {
+                explorer.new_note();
+                action
+            }
+            SidebarAction::Back => {
+                explorer.go_back();
+                action
+            }
+            SidebarAction::Settings => {
+                explorer.settings();
+                action
+            }
+            SidebarAction::Toggle(_) => {
+                self.open = !self.open;
+                SidebarAction::Toggle(self.open)
+            }
+        };
+        sink.send(Message::SidebarAction(action));
+        self.last_interaction = Instant::now();
+    }
+
+    /// Keep the sidebar open for now, as when one of its actions is used.
+    pub fn touch(&mut self) {
         self.last_interaction = Instant::now();
     }
 
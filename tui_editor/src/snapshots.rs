@@ -0,0 +1,233 @@
+//! Snapshots of the note in the editor.
+//!
+//! Every save keeps the note in the vault's [`SnapshotStore`]. The snapshot
+//! view lists them, shows what changed from the selected one to the text of
+//! the editor in place of the preview and puts it back into the editor.
+
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use config::keymap::Action;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use storage::diff::{self, Change};
+use storage::snapshots::{Snapshot, SnapshotStore};
+use tui_textarea::{CursorMove, TextArea};
+
+use crate::history::age;
+
+/// Number of snapshots listed at once.
+const VISIBLE: usize = 12;
+/// Unchanged lines kept above the first change when the diff is scrolled.
+const CONTEXT: usize = 3;
+
+/// The snapshot view, while it is open.
+#[derive(Default)]
+pub struct Snapshots {
+    view: Option<SnapshotView>,
+}
+
+struct SnapshotView {
+    /// Snapshots with their content, newest first.
+    snapshots: Vec<(Snapshot, String)>,
+    /// Text of the editor when the view was opened.
+    text: String,
+    selected: usize,
+    /// Changes from the selected snapshot to `text`.
+    diff: Vec<Line<'static>>,
+    /// Row of the first changed line in `diff`.
+    first_change: usize,
+}
+
+impl Snapshots {
+    /// Whether the snapshot view is open.
+    pub fn showing(&self) -> bool {
+        self.view.is_some()
+    }
+
+    /// Close the snapshot view.
+    pub fn close(&mut self) {
+        self.view = None;
+    }
+
+    /// Open the snapshot view on the vault-relative `note` of the vault at
+    /// `vault`. Returns a message when there is nothing to show.
+    pub fn show(&mut self, vault: &Path, note: Option<&Path>, editor: &TextArea) -> Option<String> {
+        let Some(note) = note else {
+            return Some("Only saved notes have snapshots".into());
+        };
+        let snapshots = match read(&SnapshotStore::open(vault), note) {
+            Ok(snapshots) if snapshots.is_empty() => {
+                return Some(format!("No snapshots of {}", note.display()));
+            }
+            Ok(snapshots) => snapshots,
+            Err(err) => return Some(format!("Cannot read snapshots: {err}")),
+        };
+        let mut view = SnapshotView {
+            snapshots,
+            text: editor.lines().join("\n"),
+            selected: 0,
+            diff: Vec::new(),
+            first_change: 0,
+        };
+        view.compare();
+        self.view = Some(view);
+        None
+    }
+
+    /// Perform a palette action in the snapshot view: move through the
+    /// snapshots, put the selected one into `editor` or close the view.
+    /// Returns a message for the status line.
+    pub fn act(&mut self, action: Action, editor: &mut TextArea) -> Option<String> {
+        let view = self.view.as_mut()?;
+        let last = view.snapshots.len() - 1;
+        match action {
+            Action::SelectPrevious => {
+                view.selected = view.selected.saturating_sub(1);
+                view.compare();
+            }
+            Action::SelectNext => {
+                view.selected = (view.selected + 1).min(last);
+                view.compare();
+            }
+            Action::Accept => {
+                let (snapshot, text) = &view.snapshots[view.selected];
+                let message = format!(
+                    "Restored the snapshot from {}, save the note to keep it",
+                    age(SystemTime::now(), snapshot.at)
+                );
+                editor.select_all();
+                editor.insert_str(text);
+                editor.move_cursor(CursorMove::Top);
+                self.view = None;
+                return Some(message);
+            }
+            Action::Close => self.view = None,
+            _ => {}
+        }
+        None
+    }
+
+    /// Draw the list of snapshots in the top right corner of `list` and
+    /// the changes of the selected one over `preview`.
+    pub fn render(&self, f: &mut Frame, list: Rect, preview: Rect) {
+        let Some(view) = &self.view else {
+            return;
+        };
+        f.render_widget(Clear, preview);
+        let scroll = view.first_change.saturating_sub(CONTEXT) as u16;
+        let changes = Paragraph::new(view.diff.clone()).scroll((scroll, 0)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Changes since the snapshot"),
+        );
+        f.render_widget(changes, preview);
+
+        let width = list.width.min(32);
+        let height = list.height.min(VISIBLE as u16 + 2);
+        let rect = Rect::new(list.right() - width, list.y, width, height);
+        let now = SystemTime::now();
+        let first = view.selected.saturating_sub(VISIBLE - 1);
+        let lines: Vec<Line> = view
+            .snapshots
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE)
+            .map(|(i, (snapshot, _))| {
+                let style = if i == view.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![
+                    Span::styled(format!("{:<14}", age(now, snapshot.at)), style),
+                    Span::styled(snapshot.id[..8].to_string(), style.fg(Color::DarkGray)),
+                ])
+            })
+            .collect();
+        f.render_widget(Clear, rect);
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Snapshots")),
+            rect,
+        );
+    }
+}
+
+impl SnapshotView {
+    /// Compare the selected snapshot with the text of the editor.
+    fn compare(&mut self) {
+        let old = &self.snapshots[self.selected].1;
+        let changes = diff::lines(old, &self.text);
+        self.first_change = changes
+            .iter()
+            .position(|c| !matches!(c, Change::Same(_)))
+            .unwrap_or(0);
+        self.diff = changes
+            .into_iter()
+            .map(|change| match change {
+                Change::Same(line) => {
+                    Line::styled(format!("  {line}"), Style::default().fg(Color::DarkGray))
+                }
+                Change::Removed(line) => {
+                    Line::styled(format!("- {line}"), Style::default().fg(Color::Red))
+                }
+                Change::Added(line) => {
+                    Line::styled(format!("+ {line}"), Style::default().fg(Color::Green))
+                }
+            })
+            .collect();
+    }
+}
+
+/// Snapshots of `note` with their content, newest first.
+fn read(store: &SnapshotStore, note: &Path) -> io::Result<Vec<(Snapshot, String)>> {
+    let mut snapshots = Vec::new();
+    for snapshot in store.list(note)?.into_iter().rev() {
+        let text = store.read(&snapshot)?;
+        snapshots.push((snapshot, text));
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn compares_and_restores_snapshots() {
+        let vault = tempfile::tempdir().unwrap();
+        let note = Path::new("plan.md");
+        let store = SnapshotStore::open(vault.path());
+        let day = |d: u64| UNIX_EPOCH + Duration::from_secs(86_400 * d);
+        store.save_at(note, "# Plan\nfirst", day(1)).unwrap();
+        store.save_at(note, "# Plan\nsecond", day(2)).unwrap();
+
+        let mut editor = TextArea::from(["# Plan", "third"]);
+        let mut snapshots = Snapshots::default();
+        assert!(snapshots.show(vault.path(), None, &editor).is_some());
+        assert!(snapshots
+            .show(vault.path(), Some(Path::new("other.md")), &editor)
+            .unwrap()
+            .contains("No snapshots"));
+        assert_eq!(snapshots.show(vault.path(), Some(note), &editor), None);
+
+        let text = |view: &SnapshotView| -> Vec<String> {
+            view.diff.iter().map(|l| l.to_string()).collect()
+        };
+        let view = snapshots.view.as_ref().unwrap();
+        assert_eq!(text(view), ["  # Plan", "- second", "+ third"]);
+        assert_eq!(view.first_change, 1);
+        snapshots.act(Action::SelectNext, &mut editor);
+        assert_eq!(
+            text(snapshots.view.as_ref().unwrap()),
+            ["  # Plan", "- first", "+ third"]
+        );
+
+        assert!(snapshots.act(Action::Accept, &mut editor).is_some());
+        assert!(!snapshots.showing());
+        assert_eq!(editor.lines(), ["# Plan", "first"]);
+    }
+}
@@ -0,0 +1,1326 @@
+//! Vim-style modal editing for the editor.
+//!
+//! The layer sits between the keymap and the [`TextArea`]: keys without a
+//! binding are handed to [`Vim::handle_key`]. In normal and visual mode the
+//! keys are parsed into commands made of an optional register, a count, an
+//! operator and a motion. In insert mode they are typed into the editor as
+//! usual. A change and the text typed after it are repeated as a whole, and
+//! undone as a whole by the editor's history, see [`Outcome::Undo`].
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::prelude::*;
+use tui_textarea::{CursorMove, TextArea};
+
+use crate::input::Viewport;
+
+/// Row and char column in the editor.
+type Pos = (usize, usize);
+
+/// Token of `Ctrl+R`, which redoes.
+const REDO: char = '\u{12}';
+
+/// Largest count, as in Vim. Larger counts are cut to it.
+const MAX_COUNT: usize = 999_999;
+
+/// Bytes a put or a repeated insert may add at once.
+const MAX_REPEAT: usize = 16 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl fmt::Display for VimMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VimMode::Normal => "NORMAL",
+            VimMode::Insert => "INSERT",
+            VimMode::Visual => "VISUAL",
+            VimMode::VisualLine => "VISUAL LINE",
+        })
+    }
+}
+
+/// Commands entered on the `:` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ex {
+    Write,
+    Quit,
+    /// `:q!`, quit without saving.
+    ForceQuit,
+    WriteQuit,
+}
+
+/// What became of a key.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Handled,
+    /// The key is typed into the editor.
+    Input,
+    Ex(Ex),
+    /// Undo this many changes. Changes are recorded by the editor's history
+    /// once a change ends, which is whenever the mode is not insert.
+    Undo(usize),
+    /// Redo this many undone changes.
+    Redo(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    /// `gg`, or the line of the count.
+    FirstLine,
+    /// `G`, or the line of the count.
+    LastLine,
+    MatchPair,
+    Find {
+        target: char,
+        backward: bool,
+        till: bool,
+    },
+    RepeatFind {
+        reverse: bool,
+    },
+}
+
+/// How far an operator reaches with a motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Extent {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    /// Whole lines, as in `dd`.
+    Lines,
+    /// The visual selection.
+    Selection,
+}
+
+/// Where insert mode starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    Below,
+    Above,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Move(Motion),
+    Operate(Operator, Target),
+    Put { before: bool },
+    Undo,
+    Redo,
+    Join,
+    Replace(char),
+    Insert(Entry),
+    Visual { linewise: bool },
+    CommandLine,
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cmd {
+    register: Option<char>,
+    count: Option<usize>,
+    kind: Kind,
+}
+
+impl Cmd {
+    fn changes(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Operate(Operator::Delete | Operator::Change, _)
+                | Kind::Put { .. }
+                | Kind::Join
+                | Kind::Replace(_)
+                | Kind::Insert(_)
+        )
+    }
+}
+
+enum Parse<T> {
+    Incomplete,
+    Invalid,
+    Done(T),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+/// State of the text typed since entering insert mode.
+struct InsertSession {
+    /// Text of the editor when typing started.
+    start: String,
+    count: usize,
+    entry: Option<Entry>,
+}
+
+pub struct Vim {
+    mode: VimMode,
+    /// Keys of the unfinished command.
+    pending: Vec<char>,
+    /// Text typed on the `:` line.
+    command: Option<String>,
+    /// Error shown in the status line until the next key.
+    message: Option<String>,
+    registers: HashMap<char, Register>,
+    anchor: Pos,
+    /// Column kept while moving up and down.
+    want_col: Option<usize>,
+    last_find: Option<Motion>,
+    last_change: Option<(Cmd, String)>,
+    insert: Option<InsertSession>,
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: VimMode::Normal,
+            pending: Vec::new(),
+            command: None,
+            message: None,
+            registers: HashMap::new(),
+            anchor: (0, 0),
+            want_col: None,
+            last_find: None,
+            last_change: None,
+            insert: None,
+        }
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    /// Forget the changes of the previous note when another one is loaded.
+    /// Registers are kept.
+    pub fn forget_changes(&mut self) {
+        self.mode = VimMode::Normal;
+        self.pending.clear();
+        self.command = None;
+        self.insert = None;
+    }
+
+    /// Show `message` in the status line until the next key.
+    pub fn set_message(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+    }
+
+    /// Text for the status line: the `:` line, a message or the mode with
+    /// the keys of an unfinished command.
+    pub fn status(&self) -> String {
+        if let Some(command) = &self.command {
+            return format!(":{command}");
+        }
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let pending: String = self.pending.iter().collect();
+        match self.mode {
+            VimMode::Normal if pending.is_empty() => "NORMAL".to_string(),
+            VimMode::Normal => format!("NORMAL {pending}"),
+            mode => format!("-- {mode} --"),
+        }
+    }
+
+    /// Handle a key without a binding in the keymap.
+    pub fn handle_key(&mut self, key: KeyEvent, editor: &mut TextArea) -> Outcome {
+        self.message = None;
+        if let Some(command) = &mut self.command {
+            match key.code {
+                KeyCode::Esc => self.command = None,
+                KeyCode::Backspace if command.is_empty() => self.command = None,
+                KeyCode::Backspace => {
+                    command.pop();
+                }
+                KeyCode::Enter => {
+                    let command = self.command.take().unwrap_or_default();
+                    match command.trim() {
+                        "w" | "write" => return Outcome::Ex(Ex::Write),
+                        "q" | "quit" => return Outcome::Ex(Ex::Quit),
+                        "q!" | "quit!" => return Outcome::Ex(Ex::ForceQuit),
+                        "wq" | "x" => return Outcome::Ex(Ex::WriteQuit),
+                        "" => {}
+                        other => self.message = Some(format!("Not an editor command: {other}")),
+                    }
+                }
+                KeyCode::Char(c) => command.push(c),
+                _ => {}
+            }
+            return Outcome::Handled;
+        }
+        if self.mode == VimMode::Insert {
+            if key.code != KeyCode::Esc {
+                return Outcome::Input;
+            }
+            self.leave_insert(editor);
+            return Outcome::Handled;
+        }
+        let Some(token) = token(&key) else {
+            if key.code == KeyCode::Esc && self.pending.is_empty() && self.visual() {
+                self.mode = VimMode::Normal;
+            }
+            self.pending.clear();
+            return Outcome::Handled;
+        };
+        self.pending.push(token);
+        match parse(&self.pending, self.visual()) {
+            Parse::Incomplete => {}
+            Parse::Invalid => self.pending.clear(),
+            Parse::Done(cmd) => {
+                self.pending.clear();
+                let count = cmd.count.unwrap_or(1);
+                match cmd.kind {
+                    Kind::Undo => return Outcome::Undo(count),
+                    Kind::Redo => return Outcome::Redo(count),
+                    _ => self.execute(cmd, editor, false),
+                }
+            }
+        }
+        Outcome::Handled
+    }
+
+    fn visual(&self) -> bool {
+        matches!(self.mode, VimMode::Visual | VimMode::VisualLine)
+    }
+
+    fn execute(&mut self, cmd: Cmd, editor: &mut TextArea, repeating: bool) {
+        let count = cmd.count.unwrap_or(1);
+        if cmd.changes() && !repeating && !self.visual() {
+            self.last_change = Some((cmd, String::new()));
+        }
+        if !matches!(cmd.kind, Kind::Move(Motion::Up | Motion::Down)) {
+            self.want_col = None;
+        }
+        match cmd.kind {
+            Kind::Move(motion) => {
+                let text = Text::new(editor.lines());
+                let cursor = editor.cursor();
+                if let Some((pos, _)) = self.target(&text, cursor, motion, cmd.count, false) {
+                    jump(editor, pos);
+                }
+            }
+            Kind::Operate(op, target) => self.operate(editor, cmd, op, target),
+            Kind::Put { before } => self.put(editor, cmd.register, count, before),
+            // Handed to the editor's history by `handle_key`.
+            Kind::Undo | Kind::Redo => {}
+            Kind::Join => {
+                let lines = editor.lines().len();
+                for _ in 0..count.saturating_sub(1).clamp(1, lines) {
+                    join(editor);
+                }
+            }
+            Kind::Replace(c) => {
+                let (row, col) = editor.cursor();
+                let end = col.saturating_add(count);
+                if end <= line_len(editor, row) {
+                    let with: String = std::iter::repeat_n(c, count).collect();
+                    splice(editor, (row, col), (row, end), &with);
+                    jump(editor, (row, end - 1));
+                }
+            }
+            Kind::Insert(entry) => {
+                let (row, col) = editor.cursor();
+                let len = line_len(editor, row);
+                match entry {
+                    Entry::Before => {}
+                    Entry::After => jump(editor, (row, (col + 1).min(len))),
+                    Entry::LineStart => jump(editor, (row, first_non_blank(editor, row))),
+                    Entry::LineEnd => jump(editor, (row, len)),
+                    Entry::Below => splice(editor, (row, len), (row, len), "\n"),
+                    Entry::Above => {
+                        splice(editor, (row, 0), (row, 0), "\n");
+                        jump(editor, (row, 0));
+                    }
+                }
+                self.start_insert(editor, count, Some(entry));
+            }
+            Kind::Visual { linewise } => {
+                let mode = if linewise {
+                    VimMode::VisualLine
+                } else {
+                    VimMode::Visual
+                };
+                if self.mode == mode {
+                    self.mode = VimMode::Normal;
+                } else {
+                    if !self.visual() {
+                        self.anchor = editor.cursor();
+                    }
+                    self.mode = mode;
+                }
+            }
+            Kind::CommandLine => self.command = Some(String::new()),
+            Kind::Repeat => {
+                if let Some((mut last, text)) = self.last_change.clone() {
+                    if cmd.count.is_some() {
+                        last.count = cmd.count;
+                    }
+                    self.execute(last, editor, true);
+                    if self.mode == VimMode::Insert {
+                        insert_text(editor, &text);
+                        self.leave_insert(editor);
+                    }
+                }
+            }
+        }
+        if self.mode != VimMode::Insert {
+            clamp(editor);
+        }
+    }
+
+    /// Where `motion` goes from `cursor`, with how far an operator reaches.
+    /// Operators may reach the end of a line, the cursor may not.
+    fn target(
+        &mut self,
+        text: &Text,
+        cursor: Pos,
+        motion: Motion,
+        count: Option<usize>,
+        operator: bool,
+    ) -> Option<(Pos, Extent)> {
+        let (row, col) = cursor;
+        let n = count.unwrap_or(1);
+        let len = text.line_len(row);
+        let last_row = text.starts.len() - 1;
+        let offset = text.offset(cursor);
+        let vertical = |vim: &mut Vim, row: usize| {
+            let want = *vim.want_col.get_or_insert(col);
+            ((row, want.min(text.line_len(row))), Extent::Linewise)
+        };
+        Some(match motion {
+            Motion::Left => ((row, col.saturating_sub(n)), Extent::Exclusive),
+            Motion::Right => {
+                let end = if operator { len } else { len.saturating_sub(1) };
+                ((row, col.saturating_add(n).min(end)), Extent::Exclusive)
+            }
+            Motion::Up => vertical(self, row.saturating_sub(n)),
+            Motion::Down => vertical(self, row.saturating_add(n).min(last_row)),
+            Motion::LineStart => ((row, 0), Extent::Exclusive),
+            Motion::FirstNonBlank => ((row, text.first_non_blank(row)), Extent::Exclusive),
+            Motion::LineEnd => {
+                let row = row.saturating_add(n - 1).min(last_row);
+                self.want_col = Some(usize::MAX);
+                (
+                    (row, text.line_len(row).saturating_sub(1)),
+                    Extent::Inclusive,
+                )
+            }
+            Motion::WordForward => {
+                let mut i = offset;
+                for _ in 0..n {
+                    i = next_word_start(&text.chars, i);
+                }
+                let mut pos = text.pos(i);
+                // Operators stop at the end of the line the word was on.
+                if operator && pos.0 > row {
+                    pos = (pos.0 - 1, text.line_len(pos.0 - 1));
+                }
+                (pos, Extent::Exclusive)
+            }
+            Motion::WordBackward => {
+                let mut i = offset;
+                for _ in 0..n {
+                    i = word_back(&text.chars, i);
+                }
+                (text.pos(i), Extent::Exclusive)
+            }
+            Motion::WordEnd => {
+                let mut i = offset;
+                for _ in 0..n {
+                    i = word_end(&text.chars, i);
+                }
+                (text.pos(i), Extent::Inclusive)
+            }
+            Motion::FirstLine | Motion::LastLine => {
+                let row = match count {
+                    Some(line) => (line - 1).min(last_row),
+                    None if motion == Motion::FirstLine => 0,
+                    None => last_row,
+                };
+                ((row, text.first_non_blank(row)), Extent::Linewise)
+            }
+            Motion::MatchPair => (text.pos(match_pair(text, cursor)?), Extent::Inclusive),
+            Motion::Find {
+                target,
+                backward,
+                till,
+            } => {
+                self.last_find = Some(motion);
+                let line = &text.chars[text.starts[row]..text.starts[row] + len];
+                let mut at = col;
+                for _ in 0..n {
+                    at = if backward {
+                        line[..at].iter().rposition(|&c| c == target)?
+                    } else {
+                        let skip = at + 1;
+                        skip + line.get(skip..)?.iter().position(|&c| c == target)?
+                    };
+                }
+                match (backward, till) {
+                    (false, false) => ((row, at), Extent::Inclusive),
+                    (false, true) => ((row, at - 1), Extent::Inclusive),
+                    (true, false) => ((row, at), Extent::Exclusive),
+                    (true, true) => ((row, at + 1), Extent::Exclusive),
+                }
+            }
+            Motion::RepeatFind { reverse } => {
+                let Some(Motion::Find {
+                    target,
+                    backward,
+                    till,
+                }) = self.last_find
+                else {
+                    return None;
+                };
+                let find = Motion::Find {
+                    target,
+                    backward: backward != reverse,
+                    till,
+                };
+                let found = self.target(text, cursor, find, count, operator);
+                self.last_find = Some(Motion::Find {
+                    target,
+                    backward,
+                    till,
+                });
+                found?
+            }
+        })
+    }
+
+    fn operate(&mut self, editor: &mut TextArea, cmd: Cmd, op: Operator, target: Target) {
+        let text = Text::new(editor.lines());
+        let cursor = editor.cursor();
+        let count = cmd.count.unwrap_or(1);
+        let region = match target {
+            Target::Lines => {
+                let last = cursor
+                    .0
+                    .saturating_add(count - 1)
+                    .min(text.starts.len() - 1);
+                Region::Lines(cursor.0, last)
+            }
+            Target::Selection => {
+                let (start, end) = ordered(self.anchor, cursor);
+                if self.mode == VimMode::VisualLine {
+                    Region::Lines(start.0, end.0)
+                } else {
+                    Region::Chars(text.offset(start), text.inclusive_end(end))
+                }
+            }
+            Target::Motion(motion) => {
+                // `cw` on a word changes to its end, like `ce`.
+                let on_word = text
+                    .chars
+                    .get(text.offset(cursor))
+                    .is_some_and(|c| !c.is_whitespace());
+                let motion = match motion {
+                    Motion::WordForward if op == Operator::Change && on_word => Motion::WordEnd,
+                    motion => motion,
+                };
+                let Some((pos, extent)) = self.target(&text, cursor, motion, cmd.count, true)
+                else {
+                    return;
+                };
+                let (start, end) = ordered(cursor, pos);
+                match extent {
+                    Extent::Linewise => Region::Lines(start.0, end.0),
+                    Extent::Inclusive => Region::Chars(text.offset(start), text.inclusive_end(end)),
+                    Extent::Exclusive => Region::Chars(text.offset(start), text.offset(end)),
+                }
+            }
+        };
+        self.mode = VimMode::Normal;
+        match region {
+            Region::Chars(from, to) if from == to => {}
+            Region::Chars(from, to) => {
+                let taken: String = text.chars[from..to].iter().collect();
+                self.store(cmd.register, taken, false, op == Operator::Yank);
+                let (start, end) = (text.pos(from), text.pos(to));
+                if op != Operator::Yank {
+                    splice(editor, start, end, "");
+                }
+                jump(editor, start);
+                if op == Operator::Change {
+                    self.start_insert(editor, 1, None);
+                }
+            }
+            Region::Lines(first, last) => {
+                let mut taken = editor.lines()[first..=last].join("\n");
+                taken.push('\n');
+                self.store(cmd.register, taken, true, op == Operator::Yank);
+                let last_row = text.starts.len() - 1;
+                let end = (last, text.line_len(last));
+                match op {
+                    Operator::Yank => jump(editor, (first, cursor.1.min(text.line_len(first)))),
+                    Operator::Change => {
+                        splice(editor, (first, 0), end, "");
+                        self.start_insert(editor, 1, None);
+                    }
+                    Operator::Delete => {
+                        if last < last_row {
+                            splice(editor, (first, 0), (last + 1, 0), "");
+                        } else if first > 0 {
+                            splice(editor, (first - 1, text.line_len(first - 1)), end, "");
+                        } else {
+                            splice(editor, (0, 0), end, "");
+                        }
+                        let row = first.min(editor.lines().len() - 1);
+                        jump(editor, (row, first_non_blank(editor, row)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keep deleted or yanked text in the unnamed register and in `register`.
+    /// Uppercase names append to the register, `_` discards the text.
+    fn store(&mut self, register: Option<char>, text: String, linewise: bool, yank: bool) {
+        let mut value = Register { text, linewise };
+        match register {
+            Some('_') => return,
+            Some(name) if name.is_ascii_uppercase() => {
+                let entry = self
+                    .registers
+                    .entry(name.to_ascii_lowercase())
+                    .or_insert(Register {
+                        text: String::new(),
+                        linewise,
+                    });
+                entry.text.push_str(&value.text);
+                entry.linewise |= linewise;
+                value = entry.clone();
+            }
+            Some(name) => {
+                self.registers.insert(name, value.clone());
+            }
+            None if yank => {
+                self.registers.insert('0', value.clone());
+            }
+            None => {}
+        }
+        self.registers.insert('"', value);
+    }
+
+    fn put(&mut self, editor: &mut TextArea, register: Option<char>, count: usize, before: bool) {
+        let name = register.unwrap_or('"').to_ascii_lowercase();
+        let Some(register) = self.registers.get(&name) else {
+            self.message = Some(format!("Nothing in register {name}"));
+            return;
+        };
+        if register.text.len().saturating_mul(count) > MAX_REPEAT {
+            self.message = Some("Too much text to put".into());
+            return;
+        }
+        let body = register.text.repeat(count);
+        let (row, col) = editor.cursor();
+        let len = line_len(editor, row);
+        if register.linewise {
+            let target = if before {
+                splice(editor, (row, 0), (row, 0), &body);
+                row
+            } else {
+                let body = format!("\n{}", body.strip_suffix('\n').unwrap_or(&body));
+                splice(editor, (row, len), (row, len), &body);
+                row + 1
+            };
+            jump(editor, (target, first_non_blank(editor, target)));
+        } else {
+            let at = if before || len == 0 { col } else { col + 1 };
+            splice(editor, (row, at), (row, at), &body);
+            // The cursor ends on the last char put.
+            editor.move_cursor(CursorMove::Back);
+        }
+    }
+
+    /// Keep the cursor on a char after the text was replaced, e.g. by an
+    /// undo, as it may not be past the end of a line outside insert mode.
+    pub fn settle_cursor(&self, editor: &mut TextArea) {
+        if self.mode != VimMode::Insert {
+            clamp(editor);
+        }
+    }
+
+    fn start_insert(&mut self, editor: &TextArea, count: usize, entry: Option<Entry>) {
+        self.mode = VimMode::Insert;
+        self.insert = Some(InsertSession {
+            start: editor.lines().join("\n"),
+            count,
+            entry,
+        });
+    }
+
+    /// Back to normal mode after typing. The typed text is inserted again
+    /// for a count and remembered for `.`.
+    fn leave_insert(&mut self, editor: &mut TextArea) {
+        self.mode = VimMode::Normal;
+        if let Some(session) = self.insert.take() {
+            let typed = inserted(&session.start, &editor.lines().join("\n"));
+            let repeat = session.count.saturating_sub(1);
+            if repeat > 0
+                && session.entry != Some(Entry::Above)
+                && typed.len().saturating_mul(repeat) <= MAX_REPEAT
+            {
+                insert_text(editor, &typed.repeat(repeat));
+            }
+            if let Some((_, text)) = &mut self.last_change {
+                *text = typed;
+            }
+        }
+        let (row, col) = editor.cursor();
+        if col > 0 {
+            jump(editor, (row, col - 1));
+        }
+    }
+
+    /// Highlight the visual selection over the editor drawn in `viewport`.
+    pub fn render_selection(&self, buf: &mut Buffer, viewport: &Viewport, editor: &TextArea) {
+        if !self.visual() {
+            return;
+        }
+        let (start, end) = ordered(self.anchor, editor.cursor());
+        let Viewport {
+            area, top, left, ..
+        } = *viewport;
+        let visible = top..top + area.height as usize;
+        for r in (start.0..=end.0).filter(|r| visible.contains(r)) {
+            let len = line_len(editor, r);
+            let (from, to) = if self.mode == VimMode::VisualLine {
+                (0, len.max(1))
+            } else {
+                let from = if r == start.0 { start.1 } else { 0 };
+                let to = if r == end.0 { end.1 + 1 } else { len + 1 };
+                (from, to)
+            };
+            let from = from.max(left);
+            let to = to.min(left + area.width as usize);
+            if from < to {
+                let rect = Rect::new(
+                    area.x + (from - left) as u16,
+                    area.y + (r - top) as u16,
+                    (to - from) as u16,
+                    1,
+                );
+                buf.set_style(rect, Style::default().bg(Color::DarkGray));
+            }
+        }
+    }
+}
+
+/// Part of the text an operator works on.
+enum Region {
+    /// Char offsets, the end excluded.
+    Chars(usize, usize),
+    /// First and last row.
+    Lines(usize, usize),
+}
+
+/// The editor text as chars with `\n` between lines.
+struct Text {
+    chars: Vec<char>,
+    /// Offset of the first char of each line.
+    starts: Vec<usize>,
+}
+
+impl Text {
+    fn new(lines: &[String]) -> Self {
+        let mut chars = Vec::new();
+        let mut starts = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                chars.push('\n');
+            }
+            starts.push(chars.len());
+            chars.extend(line.chars());
+        }
+        if starts.is_empty() {
+            starts.push(0);
+        }
+        Self { chars, starts }
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        let end = self
+            .starts
+            .get(row + 1)
+            .map_or(self.chars.len(), |next| next - 1);
+        end - self.starts[row]
+    }
+
+    fn first_non_blank(&self, row: usize) -> usize {
+        let start = self.starts[row];
+        let line = &self.chars[start..start + self.line_len(row)];
+        line.iter().position(|c| !c.is_whitespace()).unwrap_or(0)
+    }
+
+    fn offset(&self, (row, col): Pos) -> usize {
+        self.starts[row] + col.min(self.line_len(row))
+    }
+
+    fn pos(&self, offset: usize) -> Pos {
+        let row = self.starts.partition_point(|&s| s <= offset) - 1;
+        (row, offset - self.starts[row])
+    }
+
+    /// Offset after `pos` when it is included, not reaching past the line.
+    fn inclusive_end(&self, (row, col): Pos) -> usize {
+        self.offset((row, (col + 1).min(self.line_len(row))))
+    }
+}
+
+/// Command tokens of a key, `None` for keys that have none.
+fn token(key: &KeyEvent) -> Option<char> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    Some(match key.code {
+        KeyCode::Char('r') if ctrl => REDO,
+        KeyCode::Char(_) if ctrl || alt => return None,
+        KeyCode::Char(c) => c,
+        KeyCode::Left | KeyCode::Backspace => 'h',
+        KeyCode::Right => 'l',
+        KeyCode::Up => 'k',
+        KeyCode::Down | KeyCode::Enter => 'j',
+        KeyCode::Home => '0',
+        KeyCode::End => '$',
+        KeyCode::Delete => 'x',
+        _ => return None,
+    })
+}
+
+fn parse_count(tokens: &[char], i: &mut usize) -> Option<usize> {
+    let start = *i;
+    while tokens
+        .get(*i)
+        .is_some_and(|c| c.is_ascii_digit() && (*i > start || *c != '0'))
+    {
+        *i += 1;
+    }
+    let digits = tokens[start..*i].iter().filter_map(|c| c.to_digit(10));
+    let count = digits.fold(None, |count: Option<usize>, d| {
+        Some(
+            count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(d as usize),
+        )
+    });
+    count.map(|count| count.min(MAX_COUNT))
+}
+
+fn parse_motion(tokens: &[char]) -> Parse<Motion> {
+    let Some(&c) = tokens.first() else {
+        return Parse::Incomplete;
+    };
+    let arg = tokens.get(1).copied();
+    let motion = match c {
+        'h' => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'k' => Motion::Up,
+        'j' => Motion::Down,
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        'w' => Motion::WordForward,
+        'b' => Motion::WordBackward,
+        'e' => Motion::WordEnd,
+        'G' => Motion::LastLine,
+        '%' => Motion::MatchPair,
+        ';' => Motion::RepeatFind { reverse: false },
+        ',' => Motion::RepeatFind { reverse: true },
+        'g' | 'f' | 't' | 'F' | 'T' => {
+            let Some(arg) = arg else {
+                return Parse::Incomplete;
+            };
+            match c {
+                'g' if arg == 'g' => Motion::FirstLine,
+                'g' => return Parse::Invalid,
+                _ => Motion::Find {
+                    target: arg,
+                    backward: c.is_ascii_uppercase(),
+                    till: c.eq_ignore_ascii_case(&'t'),
+                },
+            }
+        }
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(motion)
+}
+
+/// Parse the keys of a command typed in normal or visual mode.
+fn parse(tokens: &[char], visual: bool) -> Parse<Cmd> {
+    let mut i = 0;
+    let mut register = None;
+    if tokens.first() == Some(&'"') {
+        match tokens.get(1) {
+            None => return Parse::Incomplete,
+            Some(&c) if c.is_ascii_alphanumeric() || c == '"' || c == '_' => register = Some(c),
+            Some(_) => return Parse::Invalid,
+        }
+        i = 2;
+    }
+    let mut count = parse_count(tokens, &mut i);
+    let Some(&c) = tokens.get(i) else {
+        return Parse::Incomplete;
+    };
+    i += 1;
+    let operator = |c: char| match c {
+        'd' | 'x' | 'X' | 'D' => Some(Operator::Delete),
+        'c' | 's' | 'C' | 'S' => Some(Operator::Change),
+        'y' | 'Y' => Some(Operator::Yank),
+        _ => None,
+    };
+    let kind = match c {
+        'd' | 'c' | 'y' if !visual => {
+            let op = operator(c).unwrap();
+            if let Some(more) = parse_count(tokens, &mut i) {
+                count = Some(count.unwrap_or(1).saturating_mul(more).min(MAX_COUNT));
+            }
+            match tokens.get(i) {
+                None => return Parse::Incomplete,
+                Some(&m) if m == c => Kind::Operate(op, Target::Lines),
+                Some(_) => match parse_motion(&tokens[i..]) {
+                    Parse::Incomplete => return Parse::Incomplete,
+                    Parse::Invalid => return Parse::Invalid,
+                    Parse::Done(motion) => Kind::Operate(op, Target::Motion(motion)),
+                },
+            }
+        }
+        c if visual && operator(c).is_some() => {
+            Kind::Operate(operator(c).unwrap(), Target::Selection)
+        }
+        'x' => Kind::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+        'X' => Kind::Operate(Operator::Delete, Target::Motion(Motion::Left)),
+        'D' => Kind::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        'C' => Kind::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        's' => Kind::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        'S' => Kind::Operate(Operator::Change, Target::Lines),
+        'Y' => Kind::Operate(Operator::Yank, Target::Lines),
+        'v' => Kind::Visual { linewise: false },
+        'V' => Kind::Visual { linewise: true },
+        _ if visual => match parse_motion(&tokens[i - 1..]) {
+            Parse::Incomplete => return Parse::Incomplete,
+            Parse::Invalid => return Parse::Invalid,
+            Parse::Done(motion) => Kind::Move(motion),
+        },
+        'p' => Kind::Put { before: false },
+        'P' => Kind::Put { before: true },
+        'u' => Kind::Undo,
+        REDO => Kind::Redo,
+        'J' => Kind::Join,
+        'r' => match tokens.get(i) {
+            None => return Parse::Incomplete,
+            Some(&c) => Kind::Replace(c),
+        },
+        'i' => Kind::Insert(Entry::Before),
+        'a' => Kind::Insert(Entry::After),
+        'I' => Kind::Insert(Entry::LineStart),
+        'A' => Kind::Insert(Entry::LineEnd),
+        'o' => Kind::Insert(Entry::Below),
+        'O' => Kind::Insert(Entry::Above),
+        ':' => Kind::CommandLine,
+        '.' => Kind::Repeat,
+        _ => match parse_motion(&tokens[i - 1..]) {
+            Parse::Incomplete => return Parse::Incomplete,
+            Parse::Invalid => return Parse::Invalid,
+            Parse::Done(motion) => Kind::Move(motion),
+        },
+    };
+    Parse::Done(Cmd {
+        register,
+        count,
+        kind,
+    })
+}
+
+/// 0 for blanks, 1 for word chars, 2 for other chars.
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Whether offset `i` starts an empty line.
+fn empty_line(chars: &[char], i: usize) -> bool {
+    i > 0 && chars[i - 1] == '\n' && chars.get(i).is_none_or(|&c| c == '\n')
+}
+
+fn next_word_start(chars: &[char], i: usize) -> usize {
+    let n = chars.len();
+    let mut j = i;
+    if j < n && class(chars[j]) != 0 {
+        let cls = class(chars[j]);
+        while j < n && class(chars[j]) == cls {
+            j += 1;
+        }
+    }
+    while j < n && class(chars[j]) == 0 {
+        if j > i && empty_line(chars, j) {
+            return j;
+        }
+        j += 1;
+    }
+    j
+}
+
+fn word_end(chars: &[char], i: usize) -> usize {
+    let n = chars.len();
+    let mut j = i + 1;
+    while j < n && class(chars[j]) == 0 {
+        j += 1;
+    }
+    if j >= n {
+        return n.saturating_sub(1).max(i);
+    }
+    let cls = class(chars[j]);
+    while j + 1 < n && class(chars[j + 1]) == cls {
+        j += 1;
+    }
+    j
+}
+
+fn word_back(chars: &[char], i: usize) -> usize {
+    if i == 0 {
+        return 0;
+    }
+    let mut j = i - 1;
+    while j > 0 && class(chars[j]) == 0 {
+        if empty_line(chars, j) {
+            return j;
+        }
+        j -= 1;
+    }
+    let cls = class(chars[j]);
+    while j > 0 && class(chars[j - 1]) == cls {
+        j -= 1;
+    }
+    j
+}
+
+/// Offset of the bracket matching the first one at or after the cursor on
+/// its line.
+fn match_pair(text: &Text, (row, col): Pos) -> Option<usize> {
+    const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+    let line_end = text.starts[row] + text.line_len(row);
+    let start = (text.offset((row, col))..line_end).find(|&i| "()[]{}".contains(text.chars[i]))?;
+    let c = text.chars[start];
+    let (open, close, forward) = PAIRS.iter().find_map(|&(open, close)| {
+        (c == open)
+            .then_some((open, close, true))
+            .or((c == close).then_some((open, close, false)))
+    })?;
+    let mut depth = 0usize;
+    let mut i = start;
+    loop {
+        match text.chars[i] {
+            ch if ch == open => {
+                depth = if forward {
+                    depth + 1
+                } else {
+                    depth.checked_sub(1)?
+                }
+            }
+            ch if ch == close => {
+                depth = if forward {
+                    depth.checked_sub(1)?
+                } else {
+                    depth + 1
+                }
+            }
+            _ => {}
+        }
+        if i != start && depth == 0 {
+            return Some(i);
+        }
+        i = if forward {
+            Some(i + 1).filter(|&i| i < text.chars.len())?
+        } else {
+            i.checked_sub(1)?
+        };
+    }
+}
+
+fn ordered(a: Pos, b: Pos) -> (Pos, Pos) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn line_len(editor: &TextArea, row: usize) -> usize {
+    editor.lines().get(row).map_or(0, |l| l.chars().count())
+}
+
+fn first_non_blank(editor: &TextArea, row: usize) -> usize {
+    let line = editor.lines().get(row).map_or("", String::as_str);
+    line.chars().position(|c| !c.is_whitespace()).unwrap_or(0)
+}
+
+fn jump(editor: &mut TextArea, (row, col): Pos) {
+    editor.move_cursor(CursorMove::Jump(row as u16, col as u16));
+}
+
+/// Keep the cursor on a char, as normal mode has no position after the
+/// end of a line.
+fn clamp(editor: &mut TextArea) {
+    let (row, col) = editor.cursor();
+    let len = line_len(editor, row);
+    if len > 0 && col >= len {
+        jump(editor, (row, len - 1));
+    }
+}
+
+fn insert_text(editor: &mut TextArea, text: &str) {
+    for (i, part) in text.split('\n').enumerate() {
+        if i > 0 {
+            editor.insert_newline();
+        }
+        if !part.is_empty() {
+            editor.insert_str(part);
+        }
+    }
+}
+
+/// Replace the text from `start` up to `end` with `text`, leaving the
+/// cursor after it.
+fn splice(editor: &mut TextArea, start: Pos, end: Pos, text: &str) {
+    let before = Text::new(editor.lines());
+    let mut remaining = before.offset(end) - before.offset(start);
+    jump(editor, start);
+    while remaining > 0 {
+        let (row, col) = editor.cursor();
+        let left = line_len(editor, row) - col;
+        if left == 0 {
+            editor.delete_next_char();
+            remaining -= 1;
+        } else if left <= remaining {
+            editor.delete_line_by_end();
+            remaining -= left;
+        } else {
+            for _ in 0..remaining {
+                editor.delete_next_char();
+            }
+            remaining = 0;
+        }
+    }
+    insert_text(editor, text);
+}
+
+/// Chars of `new` that are not in `old`, between their common start and end.
+fn inserted(old: &str, new: &str) -> String {
+    let (old, new): (Vec<char>, Vec<char>) = (old.chars().collect(), new.chars().collect());
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    new[prefix..new.len() - suffix].iter().collect()
+}
+
+/// Join the cursor line with the next one, separated by a space.
+fn join(editor: &mut TextArea) {
+    let row = editor.cursor().0;
+    let Some(next) = editor.lines().get(row + 1) else {
+        return;
+    };
+    let indent = next.chars().take_while(|c| c.is_whitespace()).count();
+    let sep = if next.trim().is_empty() || editor.lines()[row].ends_with(' ') {
+        ""
+    } else {
+        " "
+    };
+    let len = line_len(editor, row);
+    splice(editor, (row, len), (row + 1, indent), sep);
+    jump(editor, (row, len));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::EditHistory;
+
+    /// Type `keys` into the editor as the TUI does, `\x1b` being Escape.
+    fn feed(vim: &mut Vim, editor: &mut TextArea, keys: &str) -> Option<Ex> {
+        feed_with(vim, editor, &mut EditHistory::unsaved(editor), keys)
+    }
+
+    fn feed_with(
+        vim: &mut Vim,
+        editor: &mut TextArea,
+        history: &mut EditHistory,
+        keys: &str,
+    ) -> Option<Ex> {
+        let mut ex = None;
+        for c in keys.chars() {
+            let key = match c {
+                '\x1b' => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+                '\n' => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+                REDO => KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+                c => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+            };
+            ex = crate::edit(key, editor, Some(&mut *vim), history).or(ex);
+            crate::track(history, editor, Some(vim)).unwrap();
+        }
+        ex
+    }
+
+    fn editor(lines: &[&str]) -> TextArea<'static> {
+        TextArea::from(lines.iter().map(|l| l.to_string()))
+    }
+
+    #[test]
+    fn motions_and_operators_with_counts() {
+        let mut vim = Vim::new();
+        let mut ed = editor(&["one two three four", "  (a [b] c) end", "last"]);
+        feed(&mut vim, &mut ed, "d2w");
+        assert_eq!(ed.lines()[0], "three four");
+        feed(&mut vim, &mut ed, "cwTHREE\x1b");
+        assert_eq!(ed.lines()[0], "THREE four");
+        assert_eq!(vim.mode(), VimMode::Normal);
+        feed(&mut vim, &mut ed, "j^%");
+        assert_eq!(ed.cursor(), (1, 10));
+        feed(&mut vim, &mut ed, "%lldt]");
+        assert_eq!(ed.lines()[1], "  (a] c) end");
+        feed(&mut vim, &mut ed, "$F(D");
+        assert_eq!(ed.lines()[1], "  ");
+        feed(&mut vim, &mut ed, "Gdgg");
+        assert_eq!(ed.lines(), [""]);
+        assert_eq!(vim.status(), "NORMAL");
+        feed(&mut vim, &mut ed, "2");
+        assert_eq!(vim.status(), "NORMAL 2");
+    }
+
+    #[test]
+    fn registers_put_repeat_and_undo() {
+        let mut vim = Vim::new();
+        let mut ed = editor(&["alpha", "beta", "gamma"]);
+        let mut history = EditHistory::unsaved(&ed);
+        let mut feed =
+            |vim: &mut Vim, ed: &mut TextArea, keys| feed_with(vim, ed, &mut history, keys);
+        feed(&mut vim, &mut ed, "\"ayyjdd\"ap");
+        assert_eq!(ed.lines(), ["alpha", "gamma", "alpha"]);
+        feed(&mut vim, &mut ed, "P");
+        assert_eq!(ed.lines(), ["alpha", "gamma", "beta", "alpha"]);
+        feed(&mut vim, &mut ed, "ggA!\x1bj.");
+        assert_eq!(ed.lines(), ["alpha!", "gamma!", "beta", "alpha"]);
+        feed(&mut vim, &mut ed, "u");
+        assert_eq!(ed.lines(), ["alpha!", "gamma", "beta", "alpha"]);
+        feed(&mut vim, &mut ed, "uu");
+        assert_eq!(ed.lines(), ["alpha", "gamma", "alpha"]);
+        feed(&mut vim, &mut ed, "\u{12}");
+        assert_eq!(ed.lines(), ["alpha", "gamma", "beta", "alpha"]);
+        feed(&mut vim, &mut ed, "gg3x\"_xx.");
+        assert_eq!(ed.lines()[0], "");
+        feed(&mut vim, &mut ed, "p");
+        assert_eq!(ed.lines()[0], "a");
+        feed(&mut vim, &mut ed, "jJ");
+        assert_eq!(ed.lines()[1], "gamma beta");
+        feed(&mut vim, &mut ed, "u");
+        assert_eq!(ed.lines()[1], "gamma");
+        feed(&mut vim, &mut ed, "uuuuuuuu");
+        assert_eq!(vim.status(), "Already at oldest change");
+        feed(&mut vim, &mut ed, "gg  x");
+        assert_eq!(ed.lines()[0], "alha");
+    }
+
+    #[test]
+    fn visual_mode_and_commands() {
+        let mut vim = Vim::new();
+        let mut ed = editor(&["one two", "three", "four"]);
+        feed(&mut vim, &mut ed, "wvjy");
+        assert_eq!(vim.registers[&'"'].text, "two\nthree");
+        assert_eq!(vim.mode(), VimMode::Normal);
+        feed(&mut vim, &mut ed, "jVjd");
+        assert_eq!(ed.lines(), ["one two"]);
+        feed(&mut vim, &mut ed, "v$c1\x1b");
+        assert_eq!(ed.lines(), ["1"]);
+
+        assert_eq!(feed(&mut vim, &mut ed, ":w\n"), Some(Ex::Write));
+        assert_eq!(feed(&mut vim, &mut ed, ":q!\n"), Some(Ex::ForceQuit));
+        assert_eq!(feed(&mut vim, &mut ed, ":wq\n"), Some(Ex::WriteQuit));
+        feed(&mut vim, &mut ed, ":foo");
+        assert_eq!(vim.status(), ":foo");
+        feed(&mut vim, &mut ed, "\n");
+        assert_eq!(vim.status(), "Not an editor command: foo");
+        feed(&mut vim, &mut ed, "i");
+        assert_eq!(vim.status(), "-- INSERT --");
+    }
+
+    #[test]
+    fn caps_huge_counts() {
+        let huge = "99999999999999999999999";
+        let tokens: Vec<char> = format!("2d{huge}j").chars().collect();
+        assert!(matches!(
+            parse(&tokens, false),
+            Parse::Done(Cmd {
+                count: Some(MAX_COUNT),
+                ..
+            })
+        ));
+        let mut vim = Vim::new();
+        let mut ed = editor(&["ab", "cd", "ef"]);
+        feed(&mut vim, &mut ed, &format!("{huge}j{huge}l"));
+        assert_eq!(ed.cursor(), (2, 1));
+        feed(&mut vim, &mut ed, &format!("gg{huge}rx{huge}$"));
+        assert_eq!(ed.lines(), ["ab", "cd", "ef"]);
+        feed(&mut vim, &mut ed, &format!("gg{huge}J"));
+        assert_eq!(ed.lines(), ["ab cd ef"]);
+        feed(&mut vim, &mut ed, &format!("0yl{huge}p"));
+        assert_eq!(ed.lines()[0].len(), 8 + MAX_COUNT);
+        feed(&mut vim, &mut ed, &format!("0y$2{huge}p"));
+        assert_eq!(vim.status(), "Too much text to put");
+        feed(&mut vim, &mut ed, &format!("{huge}d{huge}d"));
+        assert_eq!(ed.lines(), [""]);
+        let typed = "a".repeat(20);
+        feed(&mut vim, &mut ed, &format!("{huge}i{typed}\x1b"));
+        assert_eq!(ed.lines(), [typed]);
+    }
+}
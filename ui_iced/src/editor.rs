@@ -1,61 +1,1191 @@
-use iced::{widget::{Column, Text, Button, Checkbox}, Element};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use core_notes::navigation::{self, History};
+use core_notes::{export, palette};
+use iced::keyboard::{self, Key};
+use iced::widget::scrollable::{self, RelativeOffset};
+use iced::{
+    Element, Length, Subscription,
+    widget::{Button, Checkbox, Column, Container, Row, Scrollable, Text, slider},
+};
+use markdown_renderer::Widget;
+use markdown_renderer::incremental::{Document, Edit};
+use markdown_renderer::links::{self, LinkTarget, NoteIndex, Resolution};
+use storage::diff::{self, Change};
+use storage::folds::{FoldKeys, FoldStore};
+use storage::git::{self as vault_git, Done, GitVault, GitWorker, Job, Region, Side};
+use storage::history::{NoteHistory, Version};
+use storage::ignore::IgnoreRules;
+use storage::note_file::{self, LineFormat, Stamp, WriteError};
+use storage::note_watch::NoteWatcher;
+use storage::snapshots::{Snapshot, SnapshotStore};
+
+use crate::images::{self, ImageLoader};
+use crate::math;
+use crate::settings::Settings;
 
 #[derive(Debug, Clone)]
 pub enum InteractiveTag {
-    Link(String),
+    Link {
+        label: String,
+        target: LinkTarget,
+    },
+    Heading(u32, String),
     Checkbox(bool),
     Text(String),
+    Math {
+        tex: String,
+        display: bool,
+    },
+    Image {
+        alt: String,
+        image: Result<iced::widget::image::Handle, String>,
+    },
+    /// A `<details>` block; `key` identifies it in the note's fold state and
+    /// `default_open` is its state in the markdown.
+    Details {
+        key: String,
+        summary: String,
+        open: bool,
+        default_open: bool,
+        children: Vec<InteractiveTag>,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum EditorMessage {
-    Toggle(usize),
+    /// Tick or untick the checkbox at the given path, see [`tag_at`].
+    Toggle(Vec<usize>),
+    ToggleFold(String),
+    /// Open a link, see [`Editor::follow`].
+    Follow(LinkTarget),
+    /// Return to the note the last link was followed from.
+    Back,
+    /// Create the missing note a link pointed at.
+    Create,
+    Cancel,
+    /// Undo the last change of the note, see [`NoteHistory`].
+    Undo,
+    Redo,
+    /// Show the earlier versions of the note.
+    ShowHistory,
+    /// Preview the version at this index of the history.
+    Scrub(usize),
+    /// Write the previewed version back to the note.
+    Restore,
+    CloseHistory,
+    /// Show the commits of the note, see [`GitVault::log`].
+    ShowLog,
+    /// Show the commit that last changed each line of the note.
+    ShowBlame,
+    /// Compare the sides of the note's merge conflicts.
+    ShowConflicts,
+    /// Keep this side of the conflict at the index.
+    PickSide(usize, Side),
+    /// Write the note with the picked sides of its conflicts.
+    Resolve,
+    ClosePanel,
+    /// Compare the saved snapshots of the note with its text.
+    ShowSnapshots,
+    /// Compare the snapshot at this index, oldest first.
+    PickSnapshot(usize),
+    /// Write the compared snapshot back to the note.
+    RestoreSnapshot,
+    /// The note was exported to this folder, see [`Editor::export`].
+    Exported(Result<PathBuf, String>),
+    /// Show hidden notes of the vault, or leave them out again.
+    ToggleHidden,
+}
+
+/// Earlier versions of the note, one of them previewed.
+struct Scrub {
+    /// Oldest first, ending with the current text.
+    versions: Vec<Version>,
+    selected: usize,
+}
+
+/// What git or the snapshots know about the note, shown in place of its
+/// content.
+enum Panel {
+    Lines {
+        title: String,
+        lines: Vec<String>,
+    },
+    /// The note's merge conflicts with the side picked for each of them.
+    Conflicts {
+        regions: Vec<Region>,
+        sides: Vec<Side>,
+    },
+    /// Snapshots of the note with their content, oldest first, one of them
+    /// compared with the note's `text`.
+    Snapshots {
+        snapshots: Vec<(Snapshot, String)>,
+        selected: usize,
+        text: String,
+    },
+}
+
+/// A missing note the user is asked to create.
+struct Pending {
+    path: PathBuf,
+    anchor: Option<String>,
 }
 
-#[derive(Default)]
 pub struct Editor {
     tags: Vec<InteractiveTag>,
+    /// The shown text, parsed block by block so changes only rebuild the
+    /// tags of the blocks they touch.
+    document: Document,
+    /// Number of tags made from each top level widget of `document`.
+    tag_counts: Vec<usize>,
+    folds: FoldStore,
+    /// Vault-relative path of the shown note.
+    note: PathBuf,
+    vault: PathBuf,
+    /// The note's file as it was last read or written.
+    stamp: Stamp,
+    /// Notices other programs changing the note.
+    watcher: Option<NoteWatcher>,
+    loader: ImageLoader,
+    /// Leaves files of the vault out of `notes`.
+    ignore: IgnoreRules,
+    notes: NoteIndex,
+    history: History<PathBuf>,
+    /// Undo history of the shown note.
+    edits: NoteHistory,
+    scrub: Option<Scrub>,
+    /// Runs git in the vault, when it is kept in git.
+    git: Option<GitWorker>,
+    /// Command opening external links, see [`navigation::open_command`].
+    open_command: Option<String>,
+    panel: Option<Panel>,
+    pending: Option<Pending>,
+    status: Option<String>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn scroll_id() -> scrollable::Id {
+    scrollable::Id::new("editor")
 }
 
 impl Editor {
     pub fn new() -> Self {
-        Self { tags: render_viewport() }
+        let vault: PathBuf =
+            std::env::var_os("ELEPHANT_NOTES_ORIGIN").map_or_else(|| ".".into(), Into::into);
+        let folds = FoldStore::open(&vault).unwrap_or_else(|_| FoldStore::in_memory());
+        let note = PathBuf::from(SAMPLE_NOTE);
+        let loader = ImageLoader::new(vault.clone());
+        let ignore = IgnoreRules::load(&vault);
+        let notes = NoteIndex::scan_with(&vault, |path, is_dir| ignore.ignores(path, is_dir))
+            .unwrap_or_default();
+        let edits = NoteHistory::open(&vault, &note).unwrap_or_else(|_| NoteHistory::in_memory());
+        let settings = Settings::load();
+        let git = GitVault::open(&vault)
+            .map(|repo| GitWorker::start(repo, settings.git, settings.idle_time()));
+        if let Some(git) = &git {
+            git.send(Job::Conflicts);
+        }
+        let stamp = Stamp::of(&vault.join(&note)).unwrap_or_default();
+        let mut watcher = NoteWatcher::new().ok();
+        if let Some(watcher) = &mut watcher {
+            let _ = watcher.watch(Some(&vault.join(&note)));
+        }
+        let mut editor = Self {
+            tags: Vec::new(),
+            document: Document::default(),
+            tag_counts: Vec::new(),
+            folds,
+            note,
+            vault,
+            stamp,
+            watcher,
+            loader,
+            ignore,
+            notes,
+            history: History::new(),
+            edits,
+            scrub: None,
+            git,
+            open_command: settings.open_command,
+            panel: None,
+            pending: None,
+            status: None,
+        };
+        editor.show_sample();
+        editor
+    }
+
+    /// Vault-relative path of the shown note.
+    pub fn note(&self) -> &Path {
+        &self.note
+    }
+
+    /// Root folder of the vault.
+    pub fn vault(&self) -> &Path {
+        &self.vault
+    }
+
+    /// Notes of the vault.
+    pub fn notes(&self) -> &NoteIndex {
+        &self.notes
+    }
+
+    /// Show the vault-relative `path`, remembering the current note for
+    /// [`EditorMessage::Back`].
+    pub fn open_note(&mut self, path: PathBuf) -> iced::Task<EditorMessage> {
+        let previous = self.note.clone();
+        let task = self.open(path, None);
+        if self.note != previous {
+            self.history.visit(previous);
+        }
+        task
+    }
+
+    /// Create the note at the vault-relative `path` with a heading naming
+    /// it and show it.
+    pub fn create_note(
+        &mut self,
+        path: PathBuf,
+        anchor: Option<&str>,
+    ) -> iced::Task<EditorMessage> {
+        let file = self.vault.join(&path);
+        let stem = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        match note_file::create_note(&file, &palette::new_note_text(&path)) {
+            // A note created meanwhile is shown as it is.
+            Ok(_) | Err(WriteError::Conflict { .. }) => {
+                self.notes.insert(path.clone(), Some(&stem));
+                let previous = self.note.clone();
+                let task = self.open(path, anchor);
+                self.history.visit(previous);
+                task
+            }
+            Err(err) => {
+                self.status = Some(format!("Cannot create {}: {err}", file.display()));
+                iced::Task::none()
+            }
+        }
+    }
+
+    /// Export the shown note to HTML in the `export` folder of the vault,
+    /// styled with `theme`, without holding up the interface.
+    pub fn export(&mut self, theme: &str) -> iced::Task<EditorMessage> {
+        let note = self.vault.join(&self.note);
+        let out = self.vault.join("export");
+        let theme = theme.to_string();
+        self.status = Some("Exporting…".into());
+        iced::Task::perform(
+            async move {
+                export::export_note(&note, &out, &theme)
+                    .map(|()| out)
+                    .map_err(|err| err.to_string())
+            },
+            EditorMessage::Exported,
+        )
+    }
+
+    /// Follow a link: notes open in the editor, scrolled to the linked
+    /// heading, missing notes ask to be created and anything else goes to
+    /// the system's URL handler.
+    fn follow(&mut self, target: LinkTarget) -> iced::Task<EditorMessage> {
+        let (target, anchor) = match target {
+            LinkTarget::External(url) => {
+                if let Err(err) = navigation::open_external(&url, self.open_command.as_deref()) {
+                    self.status = Some(format!("Cannot open {url}: {err}"));
+                }
+                return iced::Task::none();
+            }
+            LinkTarget::Note { target, anchor } => (target, anchor),
+        };
+        match self.notes.resolve(&target, Some(&self.note)) {
+            Resolution::Found(path) => {
+                let previous = self.note.clone();
+                let task = self.open(path, anchor.as_deref());
+                if self.note != previous {
+                    self.history.visit(previous);
+                }
+                task
+            }
+            Resolution::Ambiguous(paths) => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                self.status = Some(format!("{target} matches {}", paths.join(", ")));
+                iced::Task::none()
+            }
+            Resolution::Missing => {
+                match links::new_note_path(&target, Some(&self.note)) {
+                    Some(path) => {
+                        self.status = Some(format!("{} does not exist yet.", path.display()));
+                        self.pending = Some(Pending { path, anchor });
+                    }
+                    None => self.status = Some(format!("{target} is outside of the vault")),
+                }
+                iced::Task::none()
+            }
+        }
+    }
+
+    /// Show the vault-relative `path`, scrolled to the heading `anchor`.
+    fn open(&mut self, path: PathBuf, anchor: Option<&str>) -> iced::Task<EditorMessage> {
+        let (document, stamp) =
+            if path == Path::new(SAMPLE_NOTE) && !self.vault.join(&path).exists() {
+                (None, Stamp::default())
+            } else {
+                match note_file::read_note(&self.vault.join(&path)) {
+                    Ok((source, stamp)) => (Some(Document::new(source)), stamp),
+                    Err(err) => {
+                        self.status = Some(format!("Cannot open {}: {err}", path.display()));
+                        return iced::Task::none();
+                    }
+                }
+            };
+        self.stamp = stamp;
+        if let Some(watcher) = &mut self.watcher {
+            let _ = watcher.watch(Some(&self.vault.join(&path)));
+        }
+        self.edits =
+            NoteHistory::open(&self.vault, &path).unwrap_or_else(|_| NoteHistory::in_memory());
+        self.scrub = None;
+        self.panel = None;
+        self.note = path;
+        match document {
+            Some(document) => self.show_document(document),
+            None => self.show_sample(),
+        }
+        self.status = None;
+        self.show_conflicts();
+        // Headings are approximated by their position among the top level tags.
+        let position = anchor.and_then(|anchor| {
+            let wanted = links::slug(anchor);
+            self.tags.iter().position(|tag| {
+                matches!(tag, InteractiveTag::Heading(_, text)
+                    if text.eq_ignore_ascii_case(anchor.trim()) || links::slug(text) == wanted)
+            })
+        });
+        if let (Some(anchor), None) = (anchor, position) {
+            self.status = Some(format!("No heading {anchor}"));
+        }
+        let y = position.map_or(0.0, |i| i as f32 / self.tags.len().max(1) as f32);
+        scrollable::snap_to(scroll_id(), RelativeOffset { x: 0.0, y })
+    }
+
+    /// Show the note again when another program changed it, keeping the
+    /// scroll position. Views of the old content are closed. Shows what
+    /// git finished in the background.
+    pub fn tick(&mut self) {
+        let done = self.git.as_mut().map(GitWorker::poll).unwrap_or_default();
+        for done in done {
+            self.git_done(done);
+        }
+        if !self.watcher.as_mut().is_some_and(NoteWatcher::changed) {
+            return;
+        }
+        let file = self.vault.join(&self.note);
+        match note_file::reread(&file, &self.stamp) {
+            Ok(Some((text, stamp))) => {
+                self.stamp = stamp;
+                self.show_text(&text);
+                if self.scrub.take().is_some() || self.panel.take().is_some() {
+                    self.status = Some(format!("{} was changed on disk", self.note.display()));
+                }
+                self.show_conflicts();
+            }
+            Ok(None) => {}
+            Err(err) => self.status = Some(format!("Cannot open {}: {err}", self.note.display())),
+        }
+    }
+
+    /// Text of the shown note as the editors see it, with `\n` line breaks
+    /// and no final one, see [`LineFormat`].
+    fn text(&mut self) -> io::Result<String> {
+        let (content, stamp) = note_file::read_note(&self.vault.join(&self.note))?;
+        self.stamp = stamp;
+        Ok(LineFormat::of(&content).strip(&content))
+    }
+
+    /// Save `text` as the content of the note in the line format it has on
+    /// disk, keeping snapshots of the old and the new content. Fails with
+    /// [`WriteError::Conflict`] when the note was changed on disk since it
+    /// was last read.
+    fn write(&mut self, text: &str) -> Result<(), WriteError> {
+        let snapshots = SnapshotStore::open(&self.vault);
+        let file = self.vault.join(&self.note);
+        let mut format = LineFormat::default();
+        if let Ok(old) = fs::read_to_string(&file) {
+            format = LineFormat::of(&old);
+            snapshots.save(&self.note, &old)?;
+        }
+        let content = format.apply(text);
+        self.stamp = note_file::write_note(&file, &content, &self.stamp)?;
+        snapshots.save(&self.note, &content)?;
+        if let Some(git) = &mut self.git {
+            git.saved(vec![self.note.clone()]);
+        }
+        Ok(())
+    }
+
+    /// Show `text` in place of the note's content. Only the tags of the
+    /// widgets that changed are made again, unless `<details>` blocks were
+    /// added or removed: the fold keys of later blocks depend on them.
+    fn show_text(&mut self, text: &str) {
+        let diff = self
+            .document
+            .apply(&Edit::between(self.document.text(), text));
+        let start: usize = self.tag_counts[..diff.start].iter().sum();
+        let end = start
+            + self.tag_counts[diff.start..diff.start + diff.removed]
+                .iter()
+                .sum::<usize>();
+        let folds_moved = self.tags[start..end]
+            .iter()
+            .any(|t| matches!(t, InteractiveTag::Details { .. }))
+            || diff
+                .inserted
+                .iter()
+                .any(|w| matches!(w, Widget::Details { .. }));
+        if folds_moved {
+            let document = std::mem::take(&mut self.document);
+            self.show_document(document);
+            return;
+        }
+        let mut tags = Vec::new();
+        let mut counts = Vec::new();
+        for widget in diff.inserted {
+            let made = widget_tags(
+                vec![widget],
+                "",
+                &mut FoldKeys::default(),
+                &self.folds,
+                &self.note,
+                &mut self.loader,
+            );
+            counts.push(made.len());
+            tags.extend(made);
+        }
+        self.tags.splice(start..end, tags);
+        self.tag_counts
+            .splice(diff.start..diff.start + diff.removed, counts);
+    }
+
+    /// Show the parsed `document`, making the tags of all its widgets.
+    fn show_document(&mut self, document: Document) {
+        let mut keys = FoldKeys::default();
+        self.tags.clear();
+        self.tag_counts.clear();
+        for widget in document.widgets() {
+            let made = widget_tags(
+                vec![widget.clone()],
+                "",
+                &mut keys,
+                &self.folds,
+                &self.note,
+                &mut self.loader,
+            );
+            self.tag_counts.push(made.len());
+            self.tags.extend(made);
+        }
+        self.document = document;
+    }
+
+    /// Show the sample note, followed by a few tags markdown cannot make.
+    fn show_sample(&mut self) {
+        self.show_document(Document::new(SAMPLE));
+        self.tags.extend([
+            InteractiveTag::Link {
+                label: "https://example.com".into(),
+                target: LinkTarget::External("https://example.com".into()),
+            },
+            InteractiveTag::Checkbox(false),
+            InteractiveTag::Image {
+                alt: "logo".into(),
+                image: self.loader.load("assets/logo.png", None),
+            },
+        ]);
+    }
+
+    /// Undo or redo the last change of the note on disk.
+    fn step(&mut self, undo: bool) {
+        let what = if undo { "undo" } else { "redo" };
+        let stepped = self.text().and_then(|text| {
+            if undo {
+                self.edits.undo(&text)
+            } else {
+                self.edits.redo(&text)
+            }
+        });
+        let written = match stepped {
+            Ok(Some((text, _))) => self.write(&text).map(|_| text),
+            Ok(None) => {
+                self.status = Some(format!("Nothing to {what}"));
+                return;
+            }
+            Err(err) => Err(err.into()),
+        };
+        match written {
+            Ok(text) => {
+                self.show_text(&text);
+                self.status = None;
+            }
+            Err(err) => self.status = Some(format!("Cannot {what}: {err}")),
+        }
+    }
+
+    /// Write the previewed version back to the note, as a change that can
+    /// be undone.
+    fn restore(&mut self) -> Result<(), WriteError> {
+        let Some(scrub) = self.scrub.take() else {
+            return Ok(());
+        };
+        let text = scrub.versions[scrub.selected].text.clone();
+        let current = self.text()?;
+        self.write(&text)?;
+        self.edits.record(&current, &text)?;
+        Ok(())
+    }
+
+    /// Open the conflict view when the note has merge conflicts.
+    fn show_conflicts(&mut self) -> bool {
+        let regions = self
+            .text()
+            .ok()
+            .as_deref()
+            .and_then(vault_git::conflict_regions);
+        let Some(regions) = regions else {
+            return false;
+        };
+        let count = regions
+            .iter()
+            .filter(|r| matches!(r, Region::Conflict { .. }))
+            .count();
+        self.panel = Some(Panel::Conflicts {
+            regions,
+            sides: vec![Side::Ours; count],
+        });
+        true
+    }
+
+    /// Open the snapshot view on the newest snapshot of the note.
+    fn show_snapshots(&mut self) -> io::Result<()> {
+        let store = SnapshotStore::open(&self.vault);
+        let mut snapshots = Vec::new();
+        for snapshot in store.list(&self.note)? {
+            let content = store.read(&snapshot)?;
+            snapshots.push((snapshot, content));
+        }
+        if snapshots.is_empty() {
+            self.status = Some(format!("No snapshots of {}", self.note.display()));
+            return Ok(());
+        }
+        let text = self.text()?;
+        let selected = snapshots.len() - 1;
+        self.panel = Some(Panel::Snapshots {
+            snapshots,
+            selected,
+            text,
+        });
+        Ok(())
+    }
+
+    /// Write the compared snapshot back to the note, as a change that can
+    /// be undone.
+    fn restore_snapshot(&mut self) -> Result<(), WriteError> {
+        let Some(Panel::Snapshots {
+            snapshots,
+            selected,
+            ..
+        }) = self.panel.take()
+        else {
+            return Ok(());
+        };
+        let content = &snapshots[selected].1;
+        let text = content.strip_suffix('\n').unwrap_or(content);
+        let current = self.text()?;
+        self.write(text)?;
+        self.edits.record(&current, text)?;
+        self.show_text(text);
+        Ok(())
+    }
+
+    /// Ask git for the commits of the note or the commit of each of its
+    /// lines, shown once git is done.
+    fn show_git(&mut self, blame: bool) {
+        let Some(git) = &self.git else {
+            self.status = Some("The vault is not kept in git".into());
+            return;
+        };
+        let note = self.note.clone();
+        git.send(if blame {
+            Job::Blame(note)
+        } else {
+            Job::Log(note)
+        });
+    }
+
+    /// Show what git finished. Views of notes no longer shown are dropped.
+    fn git_done(&mut self, done: Done) {
+        match done {
+            Done::Committed(Ok(messages)) if messages.is_empty() => {}
+            Done::Committed(Ok(messages)) => {
+                self.status = Some(format!("Committed: {}", messages.join("; ")))
+            }
+            Done::Committed(Err(err)) => self.status = Some(format!("Cannot commit: {err}")),
+            Done::Blame(note, Ok(lines)) if note == self.note => {
+                let lines = lines
+                    .into_iter()
+                    .map(|l| format!("{} {:<12} {}", &l.id[..8], l.author, l.text))
+                    .collect();
+                self.panel = Some(Panel::Lines {
+                    title: format!("Blame of {}", note.display()),
+                    lines,
+                });
+            }
+            Done::Log(note, Ok(commits)) if note == self.note => {
+                let lines = commits
+                    .into_iter()
+                    .map(|c| format!("{} {} {}", &c.id[..8], c.author, c.summary))
+                    .collect();
+                self.panel = Some(Panel::Lines {
+                    title: format!("Log of {}", note.display()),
+                    lines,
+                });
+            }
+            Done::Log(note, Err(err)) | Done::Blame(note, Err(err)) if note == self.note => {
+                self.status = Some(err.to_string())
+            }
+            Done::Log(..) | Done::Blame(..) => {}
+            Done::Conflicts(Ok(conflicts)) if !conflicts.is_empty() => {
+                let names: Vec<String> =
+                    conflicts.iter().map(|p| p.display().to_string()).collect();
+                self.status = Some(format!("Merge conflicts in {}", names.join(", ")));
+            }
+            Done::Conflicts(_) => {}
+        }
+    }
+
+    /// Write the note with the picked sides of its conflicts. During a
+    /// merge the note is then staged, and the merge committed once no
+    /// conflicts are left.
+    fn resolve(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(Panel::Conflicts { regions, sides }) = self.panel.take() else {
+            return Ok(());
+        };
+        let text = vault_git::resolve(&regions, &sides).join("\n");
+        let current = self.text()?;
+        self.write(&text)?;
+        self.edits.record(&current, &text)?;
+        self.show_text(&text);
+        Ok(())
     }
 
     pub fn update(&mut self, message: EditorMessage) -> iced::Task<EditorMessage> {
+        if let Some(git) = &mut self.git {
+            git.input();
+        }
         match message {
-            EditorMessage::Toggle(i) => {
-                if let Some(InteractiveTag::Checkbox(value)) = self.tags.get_mut(i) {
+            EditorMessage::Toggle(path) => {
+                if let Some(InteractiveTag::Checkbox(value)) = tag_at(&mut self.tags, &path) {
                     *value = !*value;
                 }
             }
+            EditorMessage::ToggleFold(key) => {
+                if let Some((open, default_open)) = find_fold(&mut self.tags, &key) {
+                    *open = !*open;
+                    self.folds.set(&self.note, &key, *open, default_open);
+                    let _ = self.folds.save();
+                }
+            }
+            EditorMessage::Follow(target) => return self.follow(target),
+            EditorMessage::Back => match self.history.back() {
+                Some(note) => return self.open(note, None),
+                None => self.status = Some("Nothing to go back to".into()),
+            },
+            EditorMessage::Create => {
+                if let Some(Pending { path, anchor }) = self.pending.take() {
+                    return self.create_note(path, anchor.as_deref());
+                }
+            }
+            EditorMessage::Cancel => {
+                self.pending = None;
+                self.status = None;
+            }
+            EditorMessage::Undo => self.step(true),
+            EditorMessage::Redo => self.step(false),
+            EditorMessage::ShowHistory => match self.text() {
+                Ok(text) => {
+                    let versions = self.edits.versions(&text);
+                    if versions.is_empty() {
+                        self.status = Some("No earlier versions".into());
+                    } else {
+                        let selected = versions.len() - 1;
+                        self.scrub = Some(Scrub { versions, selected });
+                    }
+                }
+                Err(err) => self.status = Some(format!("Cannot read history: {err}")),
+            },
+            EditorMessage::Scrub(i) => {
+                if let Some(scrub) = &mut self.scrub {
+                    scrub.selected = i.min(scrub.versions.len() - 1);
+                    let text = scrub.versions[scrub.selected].text.clone();
+                    self.show_text(&text);
+                }
+            }
+            EditorMessage::Restore => {
+                if let Err(err) = self.restore() {
+                    self.status = Some(format!("Cannot restore version: {err}"));
+                }
+            }
+            EditorMessage::CloseHistory => {
+                if self.scrub.take().is_some() {
+                    match self.text() {
+                        Ok(text) => self.show_text(&text),
+                        Err(err) => {
+                            self.status =
+                                Some(format!("Cannot open {}: {err}", self.note.display()))
+                        }
+                    }
+                }
+            }
+            EditorMessage::ShowLog => self.show_git(false),
+            EditorMessage::ShowBlame => self.show_git(true),
+            EditorMessage::Exported(Ok(out)) => {
+                self.status = Some(format!("Exported to {}", out.display()))
+            }
+            EditorMessage::Exported(Err(err)) => {
+                self.status = Some(format!("Cannot export: {err}"))
+            }
+            EditorMessage::ShowConflicts => {
+                if !self.show_conflicts() {
+                    self.status = Some("The note has no merge conflicts".into());
+                }
+            }
+            EditorMessage::PickSide(i, side) => {
+                if let Some(Panel::Conflicts { sides, .. }) = &mut self.panel
+                    && let Some(picked) = sides.get_mut(i)
+                {
+                    *picked = side;
+                }
+            }
+            EditorMessage::Resolve => match self.resolve() {
+                Ok(()) => self.status = None,
+                Err(err) => self.status = Some(format!("Cannot resolve conflicts: {err}")),
+            },
+            EditorMessage::ClosePanel => self.panel = None,
+            EditorMessage::ShowSnapshots => {
+                if let Err(err) = self.show_snapshots() {
+                    self.status = Some(format!("Cannot read snapshots: {err}"));
+                }
+            }
+            EditorMessage::PickSnapshot(i) => {
+                if let Some(Panel::Snapshots {
+                    snapshots,
+                    selected,
+                    ..
+                }) = &mut self.panel
+                {
+                    *selected = i.min(snapshots.len() - 1);
+                }
+            }
+            EditorMessage::RestoreSnapshot => match self.restore_snapshot() {
+                Ok(()) => self.status = None,
+                Err(err) => self.status = Some(format!("Cannot restore snapshot: {err}")),
+            },
+            EditorMessage::ToggleHidden => {
+                let show = !self.ignore.show_hidden();
+                self.ignore.set_show_hidden(show);
+                let ignore = &self.ignore;
+                match NoteIndex::scan_with(&self.vault, |path, is_dir| ignore.ignores(path, is_dir))
+                {
+                    Ok(notes) => {
+                        self.notes = notes;
+                        self.status = Some(
+                            if show {
+                                "Showing hidden notes"
+                            } else {
+                                "Hiding hidden notes"
+                            }
+                            .into(),
+                        );
+                    }
+                    Err(err) => self.status = Some(format!("Cannot list notes: {err}")),
+                }
+            }
         }
         iced::Task::none()
     }
 
+    /// Shortcuts of the editor: `Ctrl+.` shows or hides hidden notes.
+    pub fn subscription(&self) -> Subscription<EditorMessage> {
+        keyboard::on_key_press(|key, modifiers| match key.as_ref() {
+            Key::Character(".") if modifiers.command() => Some(EditorMessage::ToggleHidden),
+            _ => None,
+        })
+    }
+
     pub fn view(&self) -> Element<EditorMessage> {
         let mut col = Column::new();
-        for (i, tag) in self.tags.iter().enumerate() {
-            match tag {
-                InteractiveTag::Link(url) => {
-                    col = col.push(Button::new(Text::new(url)).on_press(EditorMessage::Toggle(i)));
-                }
-                InteractiveTag::Checkbox(value) => {
-                    col = col.push(Checkbox::new("", *value).on_toggle(move |_| EditorMessage::Toggle(i)));
+        let toolbar = match &self.scrub {
+            Some(scrub) => {
+                let last = (scrub.versions.len() - 1) as u32;
+                Row::new()
+                    .spacing(8)
+                    .push(
+                        slider(0..=last, scrub.selected as u32, |i| {
+                            EditorMessage::Scrub(i as usize)
+                        })
+                        .width(Length::Fill),
+                    )
+                    .push(Text::new(format!(
+                        "Version {} of {}",
+                        scrub.selected + 1,
+                        scrub.versions.len()
+                    )))
+                    .push(Button::new(Text::new("Restore")).on_press(EditorMessage::Restore))
+                    .push(Button::new(Text::new("Close")).on_press(EditorMessage::CloseHistory))
+            }
+            None => Row::new()
+                .spacing(8)
+                .push(
+                    Button::new(Text::new("Undo"))
+                        .on_press_maybe(self.edits.can_undo().then_some(EditorMessage::Undo)),
+                )
+                .push(
+                    Button::new(Text::new("Redo"))
+                        .on_press_maybe(self.edits.can_redo().then_some(EditorMessage::Redo)),
+                )
+                .push(Button::new(Text::new("History")).on_press(EditorMessage::ShowHistory))
+                .push(Button::new(Text::new("Snapshots")).on_press(EditorMessage::ShowSnapshots))
+                .push(
+                    Button::new(Text::new("Log"))
+                        .on_press_maybe(self.git.is_some().then_some(EditorMessage::ShowLog)),
+                )
+                .push(
+                    Button::new(Text::new("Blame"))
+                        .on_press_maybe(self.git.is_some().then_some(EditorMessage::ShowBlame)),
+                ),
+        };
+        col = col.push(toolbar);
+        if let Some(status) = &self.status {
+            let mut row = Row::new().spacing(8).push(Text::new(status));
+            if self.pending.is_some() {
+                row = row
+                    .push(Button::new(Text::new("Create")).on_press(EditorMessage::Create))
+                    .push(Button::new(Text::new("Cancel")).on_press(EditorMessage::Cancel));
+            }
+            col = col.push(row);
+        }
+        let content = match &self.panel {
+            Some(panel) => panel_view(panel),
+            None => tags_view(&self.tags, &[]),
+        };
+        col.push(
+            Scrollable::new(content)
+                .id(scroll_id())
+                .height(Length::Fill),
+        )
+        .into()
+    }
+}
+
+/// Git's view of the note, with the conflicts' sides next to each other,
+/// or the changes since a snapshot.
+fn panel_view(panel: &Panel) -> Element<'_, EditorMessage> {
+    let lines = |lines: &[String]| {
+        lines
+            .iter()
+            .fold(Column::new(), |col, line| col.push(Text::new(line.clone())))
+    };
+    let mut col = Column::new().spacing(8);
+    match panel {
+        Panel::Lines {
+            title,
+            lines: shown,
+        } => {
+            col = col
+                .push(
+                    Row::new()
+                        .spacing(8)
+                        .push(Text::new(title.clone()).size(20))
+                        .push(Button::new(Text::new("Close")).on_press(EditorMessage::ClosePanel)),
+                )
+                .push(lines(shown));
+        }
+        Panel::Conflicts { regions, sides } => {
+            col = col.push(
+                Row::new()
+                    .spacing(8)
+                    .push(Text::new("Merge conflicts").size(20))
+                    .push(Button::new(Text::new("Apply")).on_press(EditorMessage::Resolve))
+                    .push(Button::new(Text::new("Cancel")).on_press(EditorMessage::ClosePanel)),
+            );
+            let mut conflict = 0;
+            for region in regions {
+                match region {
+                    Region::Same(same) => col = col.push(lines(same)),
+                    Region::Conflict { ours, theirs } => {
+                        let i = conflict;
+                        conflict += 1;
+                        let pick = |side: Side, label: &str| {
+                            let label = if sides[i] == side {
+                                format!("[{label}]")
+                            } else {
+                                label.to_string()
+                            };
+                            Button::new(Text::new(label)).on_press(EditorMessage::PickSide(i, side))
+                        };
+                        col = col
+                            .push(
+                                Row::new()
+                                    .spacing(16)
+                                    .push(
+                                        Column::new()
+                                            .push(Text::new("Ours"))
+                                            .push(lines(ours))
+                                            .width(Length::FillPortion(1)),
+                                    )
+                                    .push(
+                                        Column::new()
+                                            .push(Text::new("Theirs"))
+                                            .push(lines(theirs))
+                                            .width(Length::FillPortion(1)),
+                                    ),
+                            )
+                            .push(
+                                Row::new()
+                                    .spacing(8)
+                                    .push(pick(Side::Ours, "Use ours"))
+                                    .push(pick(Side::Theirs, "Use theirs"))
+                                    .push(pick(Side::Both, "Keep both")),
+                            );
+                    }
                 }
-                InteractiveTag::Text(t) => {
-                    col = col.push(Text::new(t));
+            }
+        }
+        Panel::Snapshots {
+            snapshots,
+            selected,
+            text,
+        } => {
+            let (snapshot, content) = &snapshots[*selected];
+            let last = (snapshots.len() - 1) as u32;
+            col = col
+                .push(
+                    Row::new()
+                        .spacing(8)
+                        .push(Text::new("Changes since the snapshot").size(20))
+                        .push(
+                            Button::new(Text::new("Restore"))
+                                .on_press(EditorMessage::RestoreSnapshot),
+                        )
+                        .push(Button::new(Text::new("Close")).on_press(EditorMessage::ClosePanel)),
+                )
+                .push(
+                    Row::new()
+                        .spacing(8)
+                        .push(
+                            slider(0..=last, *selected as u32, |i| {
+                                EditorMessage::PickSnapshot(i as usize)
+                            })
+                            .width(Length::Fill),
+                        )
+                        .push(Text::new(format!(
+                            "Snapshot {} of {} ({})",
+                            selected + 1,
+                            snapshots.len(),
+                            &snapshot.id[..8]
+                        ))),
+                );
+            let changes: Vec<String> = diff::lines(content, text)
+                .into_iter()
+                .map(|change| match change {
+                    Change::Same(line) => format!("  {line}"),
+                    Change::Removed(line) => format!("- {line}"),
+                    Change::Added(line) => format!("+ {line}"),
+                })
+                .collect();
+            col = col.push(lines(&changes));
+        }
+    }
+    col.into()
+}
+
+/// View of `tags`, which are at `path` in the tags of the note.
+fn tags_view<'a>(tags: &'a [InteractiveTag], path: &[usize]) -> Element<'a, EditorMessage> {
+    let mut col = Column::new();
+    for (i, tag) in tags.iter().enumerate() {
+        let path = [path, &[i]].concat();
+        match tag {
+            InteractiveTag::Link { label, target } => {
+                col = col.push(
+                    Button::new(Text::new(label)).on_press(EditorMessage::Follow(target.clone())),
+                );
+            }
+            InteractiveTag::Heading(level, text) => {
+                col = col.push(Text::new(text).size(28.0 - 2.0 * *level as f32));
+            }
+            InteractiveTag::Checkbox(value) => {
+                col = col.push(
+                    Checkbox::new("", *value)
+                        .on_toggle(move |_| EditorMessage::Toggle(path.clone())),
+                );
+            }
+            InteractiveTag::Text(t) => {
+                col = col.push(Text::new(t));
+            }
+            InteractiveTag::Math { tex, display: true } => {
+                col = col.push(
+                    Container::new(math::view(tex, true))
+                        .width(Length::Fill)
+                        .center_x(Length::Fill),
+                );
+            }
+            InteractiveTag::Math {
+                tex,
+                display: false,
+            } => {
+                col = col.push(math::view(tex, false));
+            }
+            InteractiveTag::Image { alt, image } => {
+                col = col.push(images::view(alt, image));
+            }
+            InteractiveTag::Details {
+                key,
+                summary,
+                open,
+                children,
+                ..
+            } => {
+                let marker = if *open { "▾" } else { "▸" };
+                col = col.push(
+                    Button::new(Text::new(format!("{marker} {summary}")))
+                        .style(iced::widget::button::text)
+                        .on_press(EditorMessage::ToggleFold(key.clone())),
+                );
+                if *open {
+                    col = col.push(Container::new(tags_view(children, &path)).padding([0, 16]));
                 }
             }
         }
-        col.into()
     }
+    col.into()
 }
 
-fn render_viewport() -> Vec<InteractiveTag> {
-    vec![
-        InteractiveTag::Text("Example note".into()),
-        InteractiveTag::Link("https://example.com".into()),
-        InteractiveTag::Checkbox(false),
-    ]
+/// The tag at `path`: its index, after those of the `<details>` tags it is
+/// nested in.
+fn tag_at<'a>(tags: &'a mut [InteractiveTag], path: &[usize]) -> Option<&'a mut InteractiveTag> {
+    let (first, rest) = path.split_first()?;
+    let tag = tags.get_mut(*first)?;
+    if rest.is_empty() {
+        return Some(tag);
+    }
+    match tag {
+        InteractiveTag::Details { children, .. } => tag_at(children, rest),
+        _ => None,
+    }
+}
+
+/// Open flag of the `<details>` tag identified by `key`.
+fn find_fold<'a>(tags: &'a mut [InteractiveTag], key: &str) -> Option<(&'a mut bool, bool)> {
+    for tag in tags {
+        if let InteractiveTag::Details {
+            key: k,
+            open,
+            default_open,
+            children,
+            ..
+        } = tag
+        {
+            if k == key {
+                return Some((open, *default_open));
+            }
+            if let Some(found) = find_fold(children, key) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+/// Note shown until the editor is connected to the vault.
+const SAMPLE_NOTE: &str = "example.md";
+
+const SAMPLE: &str = r#"Example note
+
+<details>
+<summary>Formulas</summary>
+
+Area of a circle: $\pi r^2$
+
+$$\sum_{i=1}^{n} i = \frac{n(n+1)}{2}$$
+
+</details>
+"#;
+
+/// Convert rendered markdown into tags, applying remembered fold states.
+/// `keys` tells blocks with equal summaries apart.
+fn widget_tags(
+    widgets: Vec<Widget>,
+    parent: &str,
+    keys: &mut FoldKeys,
+    folds: &FoldStore,
+    note: &Path,
+    loader: &mut ImageLoader,
+) -> Vec<InteractiveTag> {
+    let mut tags = Vec::new();
+    for widget in widgets {
+        match widget {
+            Widget::Details {
+                summary,
+                open,
+                children,
+            } => {
+                let key = keys.next(parent, &summary);
+                let children = widget_tags(children, &format!("{key}/"), keys, folds, note, loader);
+                let default_open = open;
+                let open = folds.get(note, &key).unwrap_or(default_open);
+                tags.push(InteractiveTag::Details {
+                    key,
+                    summary,
+                    open,
+                    default_open,
+                    children,
+                });
+            }
+            Widget::Latex { tex, display } => tags.push(InteractiveTag::Math { tex, display }),
+            Widget::Image { alt, url } => {
+                let image = loader.load(&url, Some(note));
+                tags.push(InteractiveTag::Image { alt, image });
+            }
+            Widget::Text(t) if t.trim().is_empty() => {}
+            Widget::Header(level, t) => tags.push(InteractiveTag::Heading(level, t)),
+            Widget::Text(t) | Widget::Bold(t) | Widget::Italic(t) => {
+                tags.push(InteractiveTag::Text(t));
+            }
+            Widget::NoteLink {
+                target,
+                alias,
+                anchor,
+            } => {
+                let label = links::label(&target, alias.as_deref(), anchor.as_deref());
+                tags.push(InteractiveTag::Link {
+                    label,
+                    target: LinkTarget::Note { target, anchor },
+                });
+            }
+            Widget::Link { text, url } => tags.push(InteractiveTag::Link {
+                label: text,
+                target: LinkTarget::External(url),
+            }),
+            _ => {}
+        }
+    }
+    tags
 }
@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use iced::widget::image::Handle;
+use iced::widget::{Container, Image, Text, container};
+use iced::{Border, Color, Element, Length};
+use storage::assets::AssetCache;
+
+/// Largest side, in pixels, of the thumbnails shown in the editor.
+const THUMBNAIL_SIZE: u32 = 1024;
+
+/// Loads note images through the shared thumbnail cache.
+pub struct ImageLoader {
+    vault: PathBuf,
+    cache: AssetCache,
+}
+
+impl ImageLoader {
+    pub fn new(vault: PathBuf) -> Self {
+        Self {
+            cache: AssetCache::new(&vault, THUMBNAIL_SIZE),
+            vault,
+        }
+    }
+
+    /// Resolve `url` from `note` and turn it into an image handle, or a
+    /// message describing why it cannot be shown.
+    pub fn load(&mut self, url: &str, note: Option<&Path>) -> Result<Handle, String> {
+        let thumb = self
+            .cache
+            .load_url(url, note, &self.vault)
+            .map_err(|e| e.to_string())?;
+        Ok(Handle::from_rgba(
+            thumb.width,
+            thumb.height,
+            thumb.rgba.clone(),
+        ))
+    }
+}
+
+/// Show a loaded image, or a framed placeholder naming the problem.
+pub fn view<'a, Message: 'a>(alt: &str, image: &Result<Handle, String>) -> Element<'a, Message> {
+    match image {
+        Ok(handle) => Image::new(handle.clone()).into(),
+        Err(err) => {
+            let label = if alt.is_empty() { "image" } else { alt };
+            Container::new(Text::new(format!("[{label}] {err}")))
+                .padding(8)
+                .width(Length::Shrink)
+                .style(|_| container::Style {
+                    text_color: Some(Color::from_rgb(0.5, 0.5, 0.5)),
+                    border: Border {
+                        color: Color::from_rgb(0.7, 0.7, 0.7),
+                        width: 1.0,
+                        radius: 4.0.into(),
+                    },
+                    ..container::Style::default()
+                })
+                .into()
+        }
+    }
+}
@@ -1,9 +1,16 @@
 mod editor;
+mod images;
+mod math;
+mod quick_open;
+mod settings;
 mod sidebar;
 
+use core_notes::events::SidebarAction;
+use core_notes::palette::{self, Command, Item};
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use editor::{Editor, EditorMessage};
-use iced::{Subscription, Task, application, time, widget::Column};
+use iced::{Subscription, Task, Theme, application, time, widget::Column};
+use quick_open::{PaletteMessage, QuickOpen};
 use sidebar::{Sidebar, SidebarMessage};
 
 #[derive(Debug, Clone)]
@@ -25,6 +32,8 @@ impl EventBus {
 struct App {
     sidebar: Sidebar,
     editor: Editor,
+    quick_open: QuickOpen,
+    dark: bool,
     event_bus: EventBus,
     event_rx: Receiver<CoreEvent>,
 }
@@ -33,15 +42,33 @@ struct App {
 enum Message {
     Sidebar(SidebarMessage),
     Editor(EditorMessage),
+    Palette(PaletteMessage),
     Tick,
 }
 
 fn init() -> (App, Task<Message>) {
     let (bus, rx) = EventBus::new();
+    let editor = Editor::new();
+    let mut quick_open = QuickOpen::default();
+    quick_open.palette.load_recent(editor.vault());
+    for command in [
+        Command::NewNote,
+        Command::ToggleSidebar,
+        Command::SwitchTheme,
+        Command::Export,
+        Command::NoteLog,
+        Command::Blame,
+        Command::ResolveConflicts,
+        Command::Snapshots,
+    ] {
+        quick_open.palette.register(command);
+    }
     (
         App {
             sidebar: Sidebar::new(),
-            editor: Editor::new(),
+            editor,
+            quick_open,
+            dark: false,
             event_bus: bus,
             event_rx: rx,
         },
@@ -50,11 +77,57 @@ fn init() -> (App, Task<Message>) {
 }
 
 fn update(app: &mut App, message: Message) -> Task<Message> {
+    let note = app.editor.note().to_path_buf();
+    let task = dispatch(app, message);
+    if app.editor.note() != note {
+        app.quick_open.palette.opened(app.editor.note());
+        let _ = app.quick_open.palette.save_recent(app.editor.vault());
+    }
+    task
+}
+
+/// Carry out what was picked in the quick open palette.
+fn pick(app: &mut App, item: Item) -> Task<Message> {
+    match item {
+        Item::Note(path) => app.editor.open_note(path).map(Message::Editor),
+        Item::Command(Command::NewNote) => {
+            let path = palette::untitled(app.editor.vault());
+            app.editor.create_note(path, None).map(Message::Editor)
+        }
+        Item::Command(Command::ToggleSidebar) => app.sidebar.update(SidebarMessage::Toggle).map(Message::Sidebar),
+        Item::Command(Command::SwitchTheme) => {
+            app.dark = !app.dark;
+            Task::none()
+        }
+        Item::Command(Command::Export) => {
+            let theme = if app.dark { "dark" } else { "light" };
+            app.editor.export(theme).map(Message::Editor)
+        }
+        Item::Command(Command::NoteLog) => app.editor.update(EditorMessage::ShowLog).map(Message::Editor),
+        Item::Command(Command::Blame) => app.editor.update(EditorMessage::ShowBlame).map(Message::Editor),
+        Item::Command(Command::ResolveConflicts) => {
+            app.editor.update(EditorMessage::ShowConflicts).map(Message::Editor)
+        }
+        Item::Command(Command::Snapshots) => app.editor.update(EditorMessage::ShowSnapshots).map(Message::Editor),
+        Item::Command(_) => Task::none(),
+    }
+}
+
+fn dispatch(app: &mut App, message: Message) -> Task<Message> {
     match message {
+        Message::Palette(PaletteMessage::Show) => app.quick_open.show(app.editor.notes()).map(Message::Palette),
+        Message::Palette(msg) => match app.quick_open.update(msg) {
+            Some(item) => pick(app, item),
+            None => Task::none(),
+        },
+        Message::Sidebar(SidebarMessage::Action(SidebarAction::Back)) => {
+            app.editor.update(EditorMessage::Back).map(Message::Editor)
+        }
         Message::Sidebar(msg) => app.sidebar.update(msg).map(Message::Sidebar),
         Message::Editor(msg) => app.editor.update(msg).map(Message::Editor),
         Message::Tick => {
             app.sidebar.tick();
+            app.editor.tick();
             while let Ok(event) = app.event_rx.try_recv() {
                 match event {
                     CoreEvent::Dummy => {}
@@ -66,6 +139,9 @@ fn update(app: &mut App, message: Message) -> Task<Message> {
 }
 
 fn view(app: &App) -> Column<Message> {
+    if app.quick_open.open {
+        return Column::new().push(app.quick_open.view().map(Message::Palette));
+    }
     Column::new()
         .push(app.sidebar.view().map(Message::Sidebar))
         .push(app.editor.view().map(Message::Editor))
@@ -73,11 +149,17 @@ fn view(app: &App) -> Column<Message> {
 
 fn subscription(app: &App) -> Subscription<Message> {
     let tick = time::every(std::time::Duration::from_millis(16)).map(|_| Message::Tick);
-    Subscription::batch(vec![tick, app.sidebar.subscription().map(Message::Sidebar)])
+    Subscription::batch(vec![
+        tick,
+        app.sidebar.subscription().map(Message::Sidebar),
+        app.quick_open.subscription().map(Message::Palette),
+        app.editor.subscription().map(Message::Editor),
+    ])
 }
 
 pub fn run() -> iced::Result {
     application("notes2", update, view)
         .subscription(subscription)
+        .theme(|app| if app.dark { Theme::Dark } else { Theme::Light })
         .run_with(init)
 }
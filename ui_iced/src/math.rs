@@ -0,0 +1,73 @@
+use iced::widget::canvas::{self, Canvas, Frame, Geometry, Path, Stroke};
+use iced::widget::Text;
+use iced::{Color, Element, Length, Point, Rectangle, Renderer, Theme, mouse};
+use markdown_renderer::math::{self, MathBox, MathItem};
+
+const FONT_SIZE: f32 = 18.0;
+const CELL_WIDTH: f32 = FONT_SIZE * 0.6;
+const CELL_HEIGHT: f32 = FONT_SIZE * 1.2;
+const SCRIPT_SCALE: f32 = 0.7;
+
+/// Draws a laid out formula with one vector glyph per layout cell.
+struct Formula {
+    layout: MathBox,
+}
+
+impl<Message> canvas::Program<Message> for Formula {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &(),
+        renderer: &Renderer,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let color = theme.palette().text;
+        let mut frame = Frame::new(renderer, bounds.size());
+        for item in &self.layout.items {
+            match *item {
+                MathItem::Glyph { x, y, ch, script } => {
+                    let size = if script { FONT_SIZE * SCRIPT_SCALE } else { FONT_SIZE };
+                    // Scripts hug the row they belong to rather than floating mid-cell.
+                    let y_offset = if script { CELL_HEIGHT - size * 1.2 } else { 0.0 };
+                    frame.fill_text(canvas::Text {
+                        content: ch.to_string(),
+                        position: Point::new(x as f32 * CELL_WIDTH, y as f32 * CELL_HEIGHT + y_offset),
+                        size: size.into(),
+                        color,
+                        ..canvas::Text::default()
+                    });
+                }
+                MathItem::Rule { x, y, width } => {
+                    let mid = (y as f32 + 0.5) * CELL_HEIGHT;
+                    let line = Path::line(
+                        Point::new(x as f32 * CELL_WIDTH, mid),
+                        Point::new((x + width) as f32 * CELL_WIDTH, mid),
+                    );
+                    frame.stroke(&line, Stroke::default().with_width(1.0).with_color(color));
+                }
+            }
+        }
+        vec![frame.into_geometry()]
+    }
+}
+
+/// Lay out `tex` and show it as a canvas, or an error message when the
+/// formula uses unsupported TeX.
+pub fn view<'a, Message: 'a>(tex: &str, display: bool) -> Element<'a, Message> {
+    match math::layout(tex, display) {
+        Ok(layout) => {
+            let width = layout.width as f32 * CELL_WIDTH;
+            let height = layout.height as f32 * CELL_HEIGHT;
+            Canvas::new(Formula { layout })
+                .width(Length::Fixed(width))
+                .height(Length::Fixed(height))
+                .into()
+        }
+        Err(err) => Text::new(format!("math error: {err} ({})", tex.trim()))
+            .color(Color::from_rgb(0.8, 0.1, 0.1))
+            .into(),
+    }
+}
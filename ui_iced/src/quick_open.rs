@@ -0,0 +1,91 @@
+use core_notes::palette::{Item, Palette};
+use iced::keyboard::{self, Key, key::Named};
+use iced::widget::{Button, Column, Container, Row, Text, text_input};
+use iced::{Element, Length, Subscription, Task};
+use markdown_renderer::links::NoteIndex;
+
+/// Number of results shown at once.
+const VISIBLE: usize = 12;
+
+#[derive(Debug, Clone)]
+pub enum PaletteMessage {
+    Show,
+    Hide,
+    Query(String),
+    Move(isize),
+    Submit,
+    Pick(usize),
+}
+
+fn input_id() -> text_input::Id {
+    text_input::Id::new("quick-open")
+}
+
+/// Quick open palette over the shared [`Palette`] model.
+#[derive(Default)]
+pub struct QuickOpen {
+    pub palette: Palette,
+    pub open: bool,
+}
+
+impl QuickOpen {
+    /// Open the palette over the notes of `index`.
+    pub fn show(&mut self, index: &NoteIndex) -> Task<PaletteMessage> {
+        self.palette.set_notes(index.notes().map(|(path, title)| (path.to_path_buf(), title.map(String::from))));
+        self.palette.set_query("");
+        self.open = true;
+        text_input::focus(input_id())
+    }
+
+    /// Handle a message, returning the item it picked, if any.
+    pub fn update(&mut self, msg: PaletteMessage) -> Option<Item> {
+        match msg {
+            PaletteMessage::Show => {}
+            PaletteMessage::Hide => self.open = false,
+            PaletteMessage::Query(query) => self.palette.set_query(&query),
+            PaletteMessage::Move(delta) => self.palette.move_selection(delta),
+            PaletteMessage::Submit => {
+                self.open = false;
+                return self.palette.selected().map(|e| e.item.clone());
+            }
+            PaletteMessage::Pick(i) => {
+                self.open = false;
+                return self.palette.results().get(i).map(|e| e.item.clone());
+            }
+        }
+        None
+    }
+
+    pub fn view(&self) -> Element<'_, PaletteMessage> {
+        let mut col = Column::new().spacing(2).push(
+            text_input("Search notes, > for commands", self.palette.query())
+                .id(input_id())
+                .on_input(PaletteMessage::Query)
+                .on_submit(PaletteMessage::Submit),
+        );
+        let selected = self.palette.selected_index();
+        let first = selected.saturating_sub(VISIBLE - 1);
+        for (i, entry) in self.palette.results().iter().enumerate().skip(first).take(VISIBLE) {
+            let kind = match entry.item {
+                Item::Command(_) => "command",
+                Item::Note(_) => entry.detail.as_str(),
+            };
+            let row = Row::new().spacing(12).push(Text::new(entry.label.as_str())).push(Text::new(kind).size(12));
+            let style = if i == selected { iced::widget::button::primary } else { iced::widget::button::text };
+            col = col.push(Button::new(row).width(Length::Fill).style(style).on_press(PaletteMessage::Pick(i)));
+        }
+        Container::new(col).padding(8).max_width(640).into()
+    }
+
+    /// Ctrl+P opens the palette, the arrows and Escape work while it is
+    /// open.
+    pub fn subscription(&self) -> Subscription<PaletteMessage> {
+        keyboard::on_key_press(|key, modifiers| match key.as_ref() {
+            Key::Character("p") if modifiers.command() => Some(PaletteMessage::Show),
+            Key::Named(Named::Escape) => Some(PaletteMessage::Hide),
+            Key::Named(Named::ArrowUp) => Some(PaletteMessage::Move(-1)),
+            Key::Named(Named::ArrowDown) => Some(PaletteMessage::Move(1)),
+            _ => None,
+        })
+    }
+}
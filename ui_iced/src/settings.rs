@@ -0,0 +1,33 @@
+use std::fs;
+use std::time::Duration;
+
+use serde::Deserialize;
+use storage::git::AutoCommit;
+
+/// Settings shared with the terminal editor, read from its `config.yaml`.
+/// Entries the terminal editor alone uses are skipped.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// When to commit saved notes if the vault is kept in git.
+    pub git: AutoCommit,
+    /// Seconds without input before committing with `git: idle`.
+    pub git_idle_secs: Option<u64>,
+    /// Command opening external links, `{url}` marks where the URL goes.
+    /// The desktop's default handler is used when unset.
+    pub open_command: Option<String>,
+}
+
+impl Settings {
+    /// The settings in `config.yaml` of the working directory, the defaults
+    /// when it is missing or invalid.
+    pub fn load() -> Self {
+        fs::read_to_string("config.yaml").ok().and_then(|data| serde_yaml::from_str(&data).ok()).unwrap_or_default()
+    }
+
+    /// Time without input before saved notes are committed with
+    /// [`AutoCommit::Idle`].
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_secs(self.git_idle_secs.unwrap_or(60))
+    }
+}
@@ -1,4 +1,5 @@
-use iced::{widget::{Container, Column, Text}, Length, Element, Subscription, event, Event, touch};
+use core_notes::events::SidebarAction;
+use iced::{widget::{Button, Container, Column, Text}, Length, Element, Subscription, event, Event, touch};
 
 const WIDTH: u16 = 220;
 const SPEED: f32 = 0.1;
@@ -8,6 +9,8 @@ pub enum SidebarMessage {
     Toggle,
     SwipeLeft,
     SwipeRight,
+    /// An action for the rest of the application.
+    Action(SidebarAction),
 }
 
 #[derive(Default)]
@@ -32,6 +35,7 @@ impl Sidebar {
             SidebarMessage::SwipeRight => {
                 self.open = true;
             }
+            SidebarMessage::Action(_) => {}
         }
         iced::Task::none()
     }
@@ -48,7 +52,9 @@ impl Sidebar {
     }
 
     pub fn view(&self) -> Element<SidebarMessage> {
-        let content = Column::new().push(Text::new("Sidebar"));
+        let content = Column::new()
+            .push(Text::new("Sidebar"))
+            .push(Button::new(Text::new("Back")).on_press(SidebarMessage::Action(SidebarAction::Back)));
         Container::new(content)
             .width(Length::Fixed(WIDTH as f32 * self.progress))
             .height(Length::Fill)